@@ -0,0 +1,943 @@
+//! Assembly of AVM1 code from text.
+//!
+//! The text format is the one written by `avm1::disassemble`: one action per line, named in
+//! any case and followed by its operands separated by commas. Labels are defined by `name:`
+//! and targeted by `if name` and `jump name`, and the bodies of functions, `With` and `Try`
+//! actions are written inside braces:
+//!
+//! ```text
+//! definefunction2 add(r:1='a', r:2='b') {
+//!     push r:1, r:2
+//!     add2
+//!     return
+//! }
+//! loop:
+//!     push "counter", 1.5, null
+//!     try {
+//!         throw
+//!     } catch 'e' {
+//!         jump loop
+//!     } finally {
+//!         stop
+//!     }
+//! ```
+//!
+//! Comments start with `;`. The number of registers of a `definefunction2` may be omitted, in
+//! which case it is set from the registers that the function uses.
+use avm1::optimize::{use_constant_pool, visit_bodies};
+use avm1::read::MAX_NESTING_DEPTH;
+use avm1::types::*;
+use avm1::write::Writer;
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind, Result};
+use std::iter::Peekable;
+use std::str::{Chars, FromStr};
+
+/// Assembles the text of AVM1 code into a list of actions.
+///
+/// If the code neither contains a `constantpool` action nor pushes references to the pool of
+/// earlier code, one is added at its start with the strings that it pushes, and the pushed
+/// strings are replaced by indices into the pool.
+pub fn assemble(text: &str) -> Result<ActionList> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        position: 0,
+        labels: HashMap::new(),
+        defined_labels: HashSet::new(),
+        branches: vec![],
+        depth: 0,
+    };
+    let mut actions = parser.parse_block(false)?;
+    for &(ref name, line) in &parser.branches {
+        if !parser.defined_labels.contains(&parser.labels[name]) {
+            return Err(error(line, &format!("Undefined label `{}`", name)));
+        }
+    }
+    build_constant_pool(&mut actions);
+    Ok(actions)
+}
+
+/// Assembles and writes AVM1 code, as for the data of a `DoAction` tag.
+pub fn assemble_code(text: &str, swf_version: u8) -> Result<Vec<u8>> {
+    let actions = assemble(text)?;
+    let mut action_data = vec![];
+    Writer::new(&mut action_data, swf_version).write_action_list(&actions)?;
+    Ok(action_data)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Symbol(char),
+    Newline,
+}
+
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>> {
+    let mut tokens = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let mut chars = line.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            let token = match c {
+                ';' => break,
+                '"' | '\'' => {
+                    chars.next();
+                    let string = read_string(&mut chars, c)
+                        .map_err(|message| error(line_number, message))?;
+                    Token::Str(string)
+                }
+                ',' | '(' | ')' | '{' | '}' | '=' | ':' => {
+                    chars.next();
+                    Token::Symbol(c)
+                }
+                _ if c.is_whitespace() => {
+                    chars.next();
+                    continue;
+                }
+                _ if is_word_char(c) => {
+                    let mut word = String::new();
+                    while let Some(&c) = chars.peek() {
+                        if !is_word_char(c) {
+                            break;
+                        }
+                        word.push(c);
+                        chars.next();
+                    }
+                    Token::Word(word)
+                }
+                _ => return Err(error(line_number, &format!("Unexpected character {:?}", c))),
+            };
+            tokens.push((token, line_number));
+        }
+        tokens.push((Token::Newline, line_number));
+    }
+    Ok(tokens)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$' || c == '.' || c == '+' || c == '-'
+}
+
+/// Reads the rest of a string after its opening quote, replacing its escape sequences.
+fn read_string(
+    chars: &mut Peekable<Chars>,
+    quote: char,
+) -> ::std::result::Result<String, &'static str> {
+    let mut string = String::new();
+    loop {
+        let c = match chars.next() {
+            Some(c) if c == quote => return Ok(string),
+            Some('\\') => match chars.next() {
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('t') => '\t',
+                Some('u') => {
+                    if chars.next() != Some('{') {
+                        return Err("Invalid escape sequence");
+                    }
+                    let mut code = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => code.push(c),
+                            None => return Err("Invalid escape sequence"),
+                        }
+                    }
+                    u32::from_str_radix(&code, 16)
+                        .ok()
+                        .and_then(::std::char::from_u32)
+                        .ok_or("Invalid escape sequence")?
+                }
+                Some(c) if c == '\\' || c == '"' || c == '\'' => c,
+                _ => return Err("Invalid escape sequence"),
+            },
+            Some(c) => c,
+            None => return Err("Unterminated string"),
+        };
+        string.push(c);
+    }
+}
+
+fn error(line: usize, message: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Line {}: {}", line, message),
+    )
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    labels: HashMap<String, Label>,
+    defined_labels: HashSet<Label>,
+    /// The labels targeted by branches, with the lines of the branches.
+    branches: Vec<(String, usize)>,
+    /// The number of bodies that contain the actions being parsed.
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .map(|(token, _)| token.clone());
+        if token.is_some() {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn error(&self, message: &str) -> Error {
+        let line = match self.tokens.get(self.position) {
+            Some(&(_, line)) => line,
+            None => self.tokens.last().map_or(1, |&(_, line)| line),
+        };
+        error(line, message)
+    }
+
+    fn is_symbol(&self, symbol: char) -> bool {
+        self.peek() == Some(&Token::Symbol(symbol))
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<()> {
+        if !self.is_symbol(symbol) {
+            return Err(self.error(&format!("Expected `{}`", symbol)));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    /// Whether the next token ends an action.
+    fn is_action_end(&self) -> bool {
+        matches!(
+            self.peek(),
+            None | Some(&Token::Newline) | Some(&Token::Symbol('}'))
+        )
+    }
+
+    /// Parses the actions of the code, or of a body up to its closing brace.
+    fn parse_block(&mut self, is_body: bool) -> Result<ActionList> {
+        if is_body {
+            if self.depth >= MAX_NESTING_DEPTH {
+                return Err(self.error("Actions are nested too deeply"));
+            }
+            self.depth += 1;
+        }
+        let mut actions = vec![];
+        loop {
+            match self.peek() {
+                Some(&Token::Newline) => self.position += 1,
+                Some(&Token::Symbol('}')) if is_body => {
+                    self.position += 1;
+                    self.depth -= 1;
+                    return Ok(actions);
+                }
+                None if is_body => return Err(self.error("Expected `}`")),
+                None => return Ok(actions),
+                _ => self.parse_line(&mut actions)?,
+            }
+        }
+    }
+
+    fn parse_line(&mut self, actions: &mut ActionList) -> Result<()> {
+        let word = self.parse_word()?;
+        if self.is_symbol(':') {
+            self.position += 1;
+            let label = self.define_label(&word)?;
+            actions.push(Action::Label(label));
+            return Ok(());
+        }
+        if self.is_symbol('=') {
+            self.position += 1;
+            let offset = self.parse_number()?;
+            let hidden_actions = if self.is_symbol('{') {
+                self.position += 1;
+                self.parse_block(true)?
+            } else {
+                vec![]
+            };
+            let label = self.define_label(&word)?;
+            actions.push(Action::OffsetLabel(OffsetLabel {
+                label,
+                offset,
+                actions: hidden_actions,
+            }));
+        } else {
+            let action = self.parse_action(&word.to_lowercase())?;
+            actions.push(action);
+        }
+        if !self.is_action_end() {
+            return Err(self.error("Expected the end of the line"));
+        }
+        Ok(())
+    }
+
+    fn parse_action(&mut self, name: &str) -> Result<Action> {
+        if let Some(action) = simple_action(name) {
+            return Ok(action);
+        }
+        let action = match name {
+            "constantpool" => Action::ConstantPool(self.parse_list(Parser::parse_string)?),
+            "definefunction" => {
+                let name = self.parse_function_name()?;
+                let params = self.parse_params(Parser::parse_string)?;
+                self.expect('{')?;
+                Action::DefineFunction {
+                    name,
+                    params,
+                    actions: self.parse_block(true)?,
+                }
+            }
+            "definefunction2" => self.parse_define_function_2()?,
+            "geturl" => {
+                let url = self.parse_string()?;
+                self.expect(',')?;
+                Action::GetUrl {
+                    url,
+                    target: self.parse_string()?,
+                }
+            }
+            "geturl2" => {
+                let send_vars_method = match &self.parse_word()?.to_lowercase()[..] {
+                    "none" => SendVarsMethod::None,
+                    "get" => SendVarsMethod::Get,
+                    "post" => SendVarsMethod::Post,
+                    _ => return Err(self.error("Expected `none`, `get` or `post`")),
+                };
+                let mut is_target_sprite = false;
+                let mut is_load_vars = false;
+                while self.is_symbol(',') {
+                    self.position += 1;
+                    match &self.parse_word()?.to_lowercase()[..] {
+                        "sprite" => is_target_sprite = true,
+                        "loadvars" => is_load_vars = true,
+                        _ => return Err(self.error("Expected `sprite` or `loadvars`")),
+                    }
+                }
+                Action::GetUrl2 {
+                    send_vars_method,
+                    is_target_sprite,
+                    is_load_vars,
+                }
+            }
+            "gotoframe" => Action::GotoFrame(self.parse_number()?),
+            "gotoframe2" => {
+                let set_playing = match &self.parse_word()?.to_lowercase()[..] {
+                    "play" => true,
+                    "stop" => false,
+                    _ => return Err(self.error("Expected `play` or `stop`")),
+                };
+                let scene_offset = if self.is_symbol(',') {
+                    self.position += 1;
+                    self.parse_number()?
+                } else {
+                    0
+                };
+                Action::GotoFrame2 {
+                    set_playing,
+                    scene_offset,
+                }
+            }
+            "gotolabel" => Action::GotoLabel(self.parse_string()?),
+            "if" => Action::If {
+                target: self.parse_branch_target()?,
+            },
+            "jump" => Action::Jump {
+                target: self.parse_branch_target()?,
+            },
+            "push" => Action::Push(self.parse_list(Parser::parse_value)?),
+            "settarget" => Action::SetTarget(self.parse_string()?),
+            "storeregister" => Action::StoreRegister(self.parse_register()?),
+            "try" => {
+                self.expect('{')?;
+                let try = self.parse_block(true)?;
+                let catch = if self.peek() == Some(&Token::Word("catch".to_string())) {
+                    self.position += 1;
+                    let catch_var = if let Some(&Token::Str(_)) = self.peek() {
+                        CatchVar::Var(self.parse_string()?)
+                    } else {
+                        CatchVar::Register(self.parse_register()?)
+                    };
+                    self.expect('{')?;
+                    Some((catch_var, self.parse_block(true)?))
+                } else {
+                    None
+                };
+                let finally = if self.peek() == Some(&Token::Word("finally".to_string())) {
+                    self.position += 1;
+                    self.expect('{')?;
+                    Some(self.parse_block(true)?)
+                } else {
+                    None
+                };
+                Action::Try(TryBlock {
+                    try,
+                    catch,
+                    finally,
+                })
+            }
+            "unknown" => {
+                let opcode = self.parse_hex_byte()?;
+                let mut data = vec![];
+                while !self.is_action_end() {
+                    data.push(self.parse_hex_byte()?);
+                }
+                Action::Unknown { opcode, data }
+            }
+            "waitforframe" => {
+                let frame = self.parse_number()?;
+                self.expect(',')?;
+                Action::WaitForFrame {
+                    frame,
+                    num_actions_to_skip: self.parse_number()?,
+                }
+            }
+            "waitforframe2" => Action::WaitForFrame2 {
+                num_actions_to_skip: self.parse_number()?,
+            },
+            "with" => {
+                self.expect('{')?;
+                Action::With {
+                    actions: self.parse_block(true)?,
+                }
+            }
+            _ => return Err(self.error(&format!("Unknown action `{}`", name))),
+        };
+        Ok(action)
+    }
+
+    fn parse_define_function_2(&mut self) -> Result<Action> {
+        let name = self.parse_function_name()?;
+        let params = self.parse_params(|parser| {
+            let register_index = if let Some(&Token::Str(_)) = parser.peek() {
+                None
+            } else {
+                let register = parser.parse_register()?;
+                parser.expect('=')?;
+                Some(register)
+            };
+            Ok(FunctionParam {
+                name: parser.parse_string()?,
+                register_index,
+            })
+        })?;
+        let mut num_registers = None;
+        let mut flags = HashSet::new();
+        while !self.is_symbol('{') {
+            let word = self.parse_word()?.to_lowercase();
+            if word == "registers" {
+                self.expect(':')?;
+                num_registers = Some(self.parse_number()?);
+            } else {
+                flags.insert(word);
+            }
+        }
+        self.position += 1;
+        let mut function = Function {
+            name,
+            params,
+            num_registers: 0,
+            preload_parent: flags.remove("preload_parent"),
+            preload_root: flags.remove("preload_root"),
+            suppress_super: flags.remove("suppress_super"),
+            preload_super: flags.remove("preload_super"),
+            suppress_arguments: flags.remove("suppress_arguments"),
+            preload_arguments: flags.remove("preload_arguments"),
+            suppress_this: flags.remove("suppress_this"),
+            preload_this: flags.remove("preload_this"),
+            preload_global: flags.remove("preload_global"),
+            actions: vec![],
+        };
+        if let Some(flag) = flags.into_iter().next() {
+            return Err(self.error(&format!("Unknown function flag `{}`", flag)));
+        }
+        function.actions = self.parse_block(true)?;
+        function.num_registers = match num_registers {
+            Some(num_registers) => num_registers,
+            None => count_registers(&function),
+        };
+        Ok(Action::DefineFunction2(function))
+    }
+
+    /// Parses the optional name of a function, which is an identifier or a string.
+    fn parse_function_name(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(&Token::Word(_)) => self.parse_word(),
+            Some(&Token::Str(_)) => self.parse_string(),
+            _ => Ok(String::new()),
+        }
+    }
+
+    /// Parses a parenthesized list of function parameters.
+    fn parse_params<T, F>(&mut self, mut parse: F) -> Result<Vec<T>>
+    where
+        F: FnMut(&mut Parser) -> Result<T>,
+    {
+        self.expect('(')?;
+        let mut params = vec![];
+        if self.is_symbol(')') {
+            self.position += 1;
+            return Ok(params);
+        }
+        loop {
+            params.push(parse(self)?);
+            if self.is_symbol(')') {
+                self.position += 1;
+                return Ok(params);
+            }
+            self.expect(',')?;
+        }
+    }
+
+    /// Parses a comma-separated list of operands, up to the end of the action.
+    fn parse_list<T, F>(&mut self, mut parse: F) -> Result<Vec<T>>
+    where
+        F: FnMut(&mut Parser) -> Result<T>,
+    {
+        let mut items = vec![];
+        if self.is_action_end() {
+            return Ok(items);
+        }
+        loop {
+            items.push(parse(self)?);
+            if !self.is_symbol(',') {
+                return Ok(items);
+            }
+            self.position += 1;
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value> {
+        if let Some(&Token::Str(_)) = self.peek() {
+            return Ok(Value::Str(self.parse_string()?));
+        }
+        let word = self.parse_word()?;
+        if self.is_symbol(':') {
+            self.position += 1;
+            return match &word[..] {
+                "r" => Ok(Value::Register(self.parse_number()?)),
+                "c" => {
+                    let index = self.parse_number()?;
+                    // The string of a constant is only written as a comment.
+                    if let Some(&Token::Str(_)) = self.peek() {
+                        self.position += 1;
+                    }
+                    Ok(Value::ConstantPool(index))
+                }
+                "f" => Ok(Value::Float(self.parse_number()?)),
+                _ => Err(self.error(&format!("Unknown value prefix `{}`", word))),
+            };
+        }
+        let value = match &word[..] {
+            "undefined" => Value::Undefined,
+            "null" => Value::Null,
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => {
+                if let Ok(value) = word.parse::<i32>() {
+                    Value::Int(value as u32)
+                } else if let Ok(value) = word.parse::<u32>() {
+                    Value::Int(value)
+                } else if let Ok(value) = word.parse::<f64>() {
+                    Value::Double(value)
+                } else {
+                    self.position -= 1;
+                    return Err(self.error(&format!("Invalid value `{}`", word)));
+                }
+            }
+        };
+        Ok(value)
+    }
+
+    /// Parses a register, written as `r:1`, or as a number.
+    fn parse_register(&mut self) -> Result<u8> {
+        if self.peek() == Some(&Token::Word("r".to_string())) {
+            self.position += 1;
+            self.expect(':')?;
+        }
+        self.parse_number()
+    }
+
+    fn parse_branch_target(&mut self) -> Result<Label> {
+        let line = self.tokens[self.position].1;
+        let name = self.parse_word()?;
+        let label = self.label(&name);
+        self.branches.push((name, line));
+        Ok(label)
+    }
+
+    fn parse_word(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(&Token::Word(_)) => (),
+            _ => return Err(self.error("Expected a name or number")),
+        }
+        match self.next() {
+            Some(Token::Word(word)) => Ok(word),
+            _ => unreachable!(),
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(&Token::Str(_)) => (),
+            _ => return Err(self.error("Expected a string")),
+        }
+        match self.next() {
+            Some(Token::Str(string)) => Ok(string),
+            _ => unreachable!(),
+        }
+    }
+
+    fn parse_number<T: FromStr>(&mut self) -> Result<T> {
+        let word = self.parse_word()?;
+        word.parse().map_err(|_| {
+            self.position -= 1;
+            self.error(&format!("Invalid number `{}`", word))
+        })
+    }
+
+    fn parse_hex_byte(&mut self) -> Result<u8> {
+        let word = self.parse_word()?;
+        let digits = word.strip_prefix("0x").unwrap_or(&word);
+        u8::from_str_radix(digits, 16).map_err(|_| {
+            self.position -= 1;
+            self.error(&format!("Invalid byte `{}`", word))
+        })
+    }
+
+    fn label(&mut self, name: &str) -> Label {
+        let next_label = self.labels.len() as Label;
+        *self.labels.entry(name.to_string()).or_insert(next_label)
+    }
+
+    fn define_label(&mut self, name: &str) -> Result<Label> {
+        let label = self.label(name);
+        if !self.defined_labels.insert(label) {
+            return Err(self.error(&format!("Label `{}` is defined more than once", name)));
+        }
+        Ok(label)
+    }
+}
+
+/// Returns the action without operands with the given lowercase name.
+fn simple_action(name: &str) -> Option<Action> {
+    macro_rules! simple_actions {
+        ($($action:ident),*) => {
+            $(
+                if name.eq_ignore_ascii_case(stringify!($action)) {
+                    return Some(Action::$action);
+                }
+            )*
+        };
+    }
+    simple_actions!(
+        Add,
+        Add2,
+        And,
+        AsciiToChar,
+        BitAnd,
+        BitLShift,
+        BitOr,
+        BitRShift,
+        BitURShift,
+        BitXor,
+        Call,
+        CallFunction,
+        CallMethod,
+        CastOp,
+        CharToAscii,
+        CloneSprite,
+        Decrement,
+        DefineLocal,
+        DefineLocal2,
+        Delete,
+        Delete2,
+        Divide,
+        End,
+        EndDrag,
+        Enumerate,
+        Enumerate2,
+        Equals,
+        Equals2,
+        Extends,
+        GetMember,
+        GetProperty,
+        GetTime,
+        GetVariable,
+        Greater,
+        ImplementsOp,
+        Increment,
+        InitArray,
+        InitObject,
+        InstanceOf,
+        Less,
+        Less2,
+        MBAsciiToChar,
+        MBCharToAscii,
+        MBStringExtract,
+        MBStringLength,
+        Modulo,
+        Multiply,
+        NewMethod,
+        NewObject,
+        NextFrame,
+        Not,
+        Or,
+        Play,
+        Pop,
+        PreviousFrame,
+        PushDuplicate,
+        RandomNumber,
+        RemoveSprite,
+        Return,
+        SetMember,
+        SetProperty,
+        SetTarget2,
+        SetVariable,
+        StackSwap,
+        StartDrag,
+        Stop,
+        StopSounds,
+        StrictEquals,
+        StringAdd,
+        StringEquals,
+        StringExtract,
+        StringGreater,
+        StringLength,
+        StringLess,
+        Subtract,
+        TargetPath,
+        Throw,
+        ToInteger,
+        ToNumber,
+        ToString,
+        ToggleQuality,
+        Trace,
+        TypeOf
+    );
+    None
+}
+
+/// Returns the number of registers that a function needs for its parameters, preloaded
+/// variables and the registers used by its actions.
+fn count_registers(function: &Function) -> u8 {
+    fn max_register(actions: &[Action]) -> u8 {
+        actions
+            .iter()
+            .map(|action| match *action {
+                Action::Push(ref values) => values
+                    .iter()
+                    .map(|value| match *value {
+                        Value::Register(register) => register,
+                        _ => 0,
+                    })
+                    .max()
+                    .unwrap_or(0),
+                Action::StoreRegister(register) => register,
+                Action::Try(ref try_block) => {
+                    let catch_register = match try_block.catch {
+                        Some((CatchVar::Register(register), _)) => register,
+                        _ => 0,
+                    };
+                    let catch = try_block
+                        .catch
+                        .as_ref()
+                        .map_or(0, |(_, catch)| max_register(catch));
+                    let finally = try_block
+                        .finally
+                        .as_ref()
+                        .map_or(0, |finally| max_register(finally));
+                    *[max_register(&try_block.try), catch_register, catch, finally]
+                        .iter()
+                        .max()
+                        .unwrap()
+                }
+                Action::With { ref actions } => max_register(actions),
+                _ => 0,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    let num_preloaded = [
+        function.preload_this,
+        function.preload_arguments,
+        function.preload_super,
+        function.preload_root,
+        function.preload_parent,
+        function.preload_global,
+    ]
+    .iter()
+    .filter(|&&is_preloaded| is_preloaded)
+    .count() as u8;
+    let max_param = function
+        .params
+        .iter()
+        .filter_map(|param| param.register_index)
+        .max()
+        .unwrap_or(0);
+    let max = *[num_preloaded, max_param, max_register(&function.actions)]
+        .iter()
+        .max()
+        .unwrap();
+    if max == 0 {
+        0
+    } else {
+        max.saturating_add(1)
+    }
+}
+
+/// Adds a constant pool with the pushed strings to code that neither has one nor refers to the
+/// pool of earlier code.
+///
+/// The strings that are pushed most often come first, so that they have one byte indices.
+fn build_constant_pool(actions: &mut ActionList) {
+    let mut has_constant_pool = false;
+    let mut counts: HashMap<String, (usize, usize)> = HashMap::new();
+    visit_bodies(actions, &mut |action| match *action {
+        Action::ConstantPool(_) => {
+            has_constant_pool = true;
+            false
+        }
+        Action::Push(ref values) => {
+            for value in values {
+                match *value {
+                    Value::Str(ref string) => {
+                        let first = counts.len();
+                        counts.entry(string.clone()).or_insert((0, first)).0 += 1;
+                    }
+                    Value::ConstantPool(_) => has_constant_pool = true,
+                    _ => (),
+                }
+            }
+            !has_constant_pool
+        }
+        _ => true,
+    });
+    if has_constant_pool || counts.is_empty() {
+        return;
+    }
+
+    let mut strings: Vec<_> = counts.into_iter().collect();
+    strings.sort_by_key(|&(_, (count, first))| (!count, first));
+    // The pool is limited by the length of its action record.
+    let mut length = 2;
+    let mut pool = vec![];
+    for (string, _) in strings {
+        length += string.len() + 1;
+        if length > usize::from(u16::MAX) {
+            break;
+        }
+        pool.push(string);
+    }
+    use_constant_pool(actions, pool);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avm1::disassemble::disassemble;
+    use avm1::read::Reader;
+
+    #[test]
+    fn assemble_code_with_branches() {
+        let text = "
+            ; Counts down from 3.
+            push \"i\", 3
+            setvariable
+        loop:
+            push \"i\", \"i\"
+            getvariable
+            decrement
+            setvariable
+            push \"i\"
+            getvariable
+            if loop
+        ";
+        assert_eq!(
+            assemble_code(text, 5).unwrap(),
+            vec![
+                0x88, 4, 0, 1, 0, 105, 0, // constantpool "i"
+                0x96, 7, 0, 8, 0, 7, 3, 0, 0, 0,    // push c:0, 3
+                0x1D, // setvariable
+                0x96, 4, 0, 8, 0, 8, 0, // push c:0, c:0
+                0x1C, 0x51, 0x1D, // getvariable, decrement, setvariable
+                0x96, 2, 0, 8, 0,    // push c:0
+                0x1C, // getvariable
+                0x9D, 2, 0, 235, 255, // if -21
+                0x00,
+            ]
+        );
+
+        // Code that refers to the pool of earlier code doesn't get its own.
+        assert_eq!(
+            assemble_code("push c:0\ntrace\npush \"i\"\ntrace\n", 5).unwrap(),
+            vec![0x96, 2, 0, 8, 0, 0x26, 0x96, 3, 0, 0, 105, 0, 0x26, 0x00]
+        );
+    }
+
+    #[test]
+    fn assemble_disassembly() {
+        let text = "
+            constantpool \"x\", \"a\\\"b\"
+            definefunction2 f(r:1='a', 'b') suppress_this preload_global {
+                push r:1, c:1 \"a\\\"b\", f:1.5, 2.0, -1, null, undefined, true
+                storeregister r:3
+                return
+            }
+            definefunction ('a') {
+                with {
+                    gotoframe2 play, 2
+                }
+            }
+        L0:
+            try {
+                throw
+            } catch r:2 {
+                jump L0
+            } finally {
+                geturl2 post, loadvars
+            }
+            unknown 0xff 01 02
+        ";
+        let actions = assemble(text).unwrap();
+        match actions[1] {
+            Action::DefineFunction2(ref function) => assert_eq!(function.num_registers, 4),
+            _ => panic!("Expected a function"),
+        }
+        assert_eq!(assemble(&disassemble(&actions)).unwrap(), actions);
+
+        let mut action_data = vec![];
+        Writer::new(&mut action_data, 8)
+            .write_action_list(&actions)
+            .unwrap();
+        let read_actions = Reader::new(&action_data[..], 8).read_action_list().unwrap();
+        assert_eq!(disassemble(&read_actions), disassemble(&actions));
+    }
+
+    #[test]
+    fn assemble_errors() {
+        let message = |text: &str| assemble(text).unwrap_err().to_string();
+        assert_eq!(message("jump end\n"), "Line 1: Undefined label `end`");
+        assert_eq!(
+            message("a:\na:\n"),
+            "Line 2: Label `a` is defined more than once"
+        );
+        assert_eq!(message("push 1,\n"), "Line 1: Expected a name or number");
+        assert_eq!(message("with {\nplay\n"), "Line 2: Expected `}`");
+        assert_eq!(message("foo\n"), "Line 1: Unknown action `foo`");
+        assert_eq!(
+            message(&"with {\n".repeat(5000)),
+            format!(
+                "Line {}: Actions are nested too deeply",
+                MAX_NESTING_DEPTH + 1
+            )
+        );
+    }
+}
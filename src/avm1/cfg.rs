@@ -0,0 +1,296 @@
+//! Control-flow graphs of AVM1 code.
+use avm1::types::*;
+use std::collections::HashMap;
+
+/// The basic blocks of a list of actions, and the branches between them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ControlFlowGraph<'a> {
+    /// The blocks, starting with the entry block.
+    pub blocks: Vec<BasicBlock<'a>>,
+}
+
+/// A run of actions that is only entered at its start, and only left at its end.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BasicBlock<'a> {
+    /// The labels at the start of the block.
+    pub labels: Vec<Label>,
+    /// The actions of the block, without its labels or its final `If` or `Jump` action.
+    pub actions: &'a [Action],
+    pub exit: Exit,
+}
+
+/// How a basic block is left.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Exit {
+    /// Continues with a block.
+    Goto(usize),
+    /// Pops a value, and continues with the first block if it is true, or else the second.
+    Branch { if_true: usize, if_false: usize },
+    /// Branches to a label that is not at an action of the list, such as a label in another
+    /// body or in the middle of an action. The block of the branch target has this exit, and
+    /// no actions.
+    Outside(Label),
+    /// Leaves the code, at the end of the list or by an `End`, `Return` or `Throw` action.
+    End,
+}
+
+impl Exit {
+    /// Returns the blocks that can follow a block with this exit.
+    pub fn successors(&self) -> Vec<usize> {
+        match *self {
+            Exit::Goto(block) => vec![block],
+            Exit::Branch { if_true, if_false } => vec![if_true, if_false],
+            Exit::Outside(_) | Exit::End => vec![],
+        }
+    }
+}
+
+impl<'a> ControlFlowGraph<'a> {
+    /// Splits a list of actions into basic blocks. The labels of branches between bodies are
+    /// outside the list of each body.
+    pub fn new(actions: &'a [Action]) -> ControlFlowGraph<'a> {
+        let mut starts = vec![0];
+        for (i, action) in actions.iter().enumerate() {
+            match *action {
+                Action::Label(_)
+                    if i > 0
+                        && !matches!(actions[i - 1], Action::Label(_))
+                        && starts.last() != Some(&i) =>
+                {
+                    starts.push(i)
+                }
+                Action::If { .. }
+                | Action::Jump { .. }
+                | Action::Return
+                | Action::Throw
+                | Action::End
+                    if i + 1 < actions.len() =>
+                {
+                    starts.push(i + 1)
+                }
+                _ => (),
+            }
+        }
+
+        let mut label_blocks = HashMap::new();
+        for (block, &start) in starts.iter().enumerate() {
+            for action in &actions[start..] {
+                match *action {
+                    Action::Label(label) => label_blocks.insert(label, block),
+                    _ => break,
+                };
+            }
+        }
+
+        let num_blocks = starts.len();
+        let mut cfg = ControlFlowGraph { blocks: vec![] };
+        let mut extra_blocks = vec![];
+        let mut outside_blocks = HashMap::new();
+        let mut end_block = None;
+        for (block, &start) in starts.iter().enumerate() {
+            let end = starts.get(block + 1).cloned().unwrap_or(actions.len());
+            let mut labels = vec![];
+            let mut body_start = start;
+            while let Some(&Action::Label(label)) = actions[body_start..end].first() {
+                labels.push(label);
+                body_start += 1;
+            }
+            let mut target_block = |label: Label| {
+                if let Some(&block) = label_blocks.get(&label) {
+                    return block;
+                }
+                *outside_blocks.entry(label).or_insert_with(|| {
+                    extra_blocks.push(Exit::Outside(label));
+                    num_blocks + extra_blocks.len() - 1
+                })
+            };
+            let (body_end, exit) = match actions[body_start..end].last() {
+                Some(&Action::If { target }) => {
+                    let if_true = target_block(target);
+                    let if_false = if block + 1 < num_blocks {
+                        block + 1
+                    } else {
+                        // Branches at the end of the list fall through to the end of the code.
+                        *end_block.get_or_insert_with(|| {
+                            extra_blocks.push(Exit::End);
+                            num_blocks + extra_blocks.len() - 1
+                        })
+                    };
+                    (end - 1, Exit::Branch { if_true, if_false })
+                }
+                Some(&Action::Jump { target }) => (end - 1, Exit::Goto(target_block(target))),
+                Some(&Action::Return) | Some(&Action::Throw) | Some(&Action::End) => {
+                    (end, Exit::End)
+                }
+                _ if block + 1 < num_blocks => (end, Exit::Goto(block + 1)),
+                _ => (end, Exit::End),
+            };
+            cfg.blocks.push(BasicBlock {
+                labels,
+                actions: &actions[body_start..body_end],
+                exit,
+            });
+        }
+        for exit in extra_blocks {
+            cfg.blocks.push(BasicBlock {
+                labels: vec![],
+                actions: &[],
+                exit,
+            });
+        }
+        cfg
+    }
+
+    /// Returns the blocks that branch to each block.
+    pub fn predecessors(&self) -> Vec<Vec<usize>> {
+        let mut predecessors = vec![vec![]; self.blocks.len()];
+        for (block, basic_block) in self.blocks.iter().enumerate() {
+            for successor in basic_block.exit.successors() {
+                if !predecessors[successor].contains(&block) {
+                    predecessors[successor].push(block);
+                }
+            }
+        }
+        predecessors
+    }
+
+    /// Returns the immediate post-dominator of each block: the first block after it that is on
+    /// every path from it to the end of the code. Blocks whose paths leave the code at
+    /// different places, and blocks that never leave it, have none.
+    pub fn immediate_post_dominators(&self) -> Vec<Option<usize>> {
+        // Post-dominators are the dominators of the reversed graph, from a node at the end.
+        let exit = self.blocks.len();
+        let mut successors = self.predecessors();
+        successors.push(
+            (0..exit)
+                .filter(|&block| self.blocks[block].exit.successors().is_empty())
+                .collect(),
+        );
+        immediate_dominators(&successors, exit)
+            .into_iter()
+            .take(exit)
+            .map(|dominator| dominator.filter(|&dominator| dominator != exit))
+            .collect()
+    }
+}
+
+/// Returns the immediate dominator of each node of a graph, or `None` for the entry node and
+/// nodes that cannot be reached from it.
+///
+/// This is the iterative algorithm of Cooper, Harvey and Kennedy.
+pub(crate) fn immediate_dominators(successors: &[Vec<usize>], entry: usize) -> Vec<Option<usize>> {
+    let num_nodes = successors.len();
+    let mut postorder = vec![];
+    let mut visited = vec![false; num_nodes];
+    let mut stack = vec![(entry, 0)];
+    visited[entry] = true;
+    while let Some(&mut (node, ref mut next)) = stack.last_mut() {
+        if let Some(&successor) = successors[node].get(*next) {
+            *next += 1;
+            if !visited[successor] {
+                visited[successor] = true;
+                stack.push((successor, 0));
+            }
+        } else {
+            postorder.push(node);
+            stack.pop();
+        }
+    }
+    let mut order = vec![usize::MAX; num_nodes];
+    for (i, &node) in postorder.iter().enumerate() {
+        order[node] = i;
+    }
+    let mut predecessors = vec![vec![]; num_nodes];
+    for (node, node_successors) in successors.iter().enumerate() {
+        for &successor in node_successors {
+            predecessors[successor].push(node);
+        }
+    }
+
+    let mut dominators: Vec<Option<usize>> = vec![None; num_nodes];
+    dominators[entry] = Some(entry);
+    let mut is_changed = true;
+    while is_changed {
+        is_changed = false;
+        for &node in postorder.iter().rev().skip(1) {
+            let mut new_dominator: Option<usize> = None;
+            for &predecessor in &predecessors[node] {
+                if dominators[predecessor].is_none() {
+                    continue;
+                }
+                new_dominator = Some(match new_dominator {
+                    None => predecessor,
+                    Some(mut a) => {
+                        let mut b = predecessor;
+                        while a != b {
+                            while order[a] < order[b] {
+                                a = dominators[a].unwrap();
+                            }
+                            while order[b] < order[a] {
+                                b = dominators[b].unwrap();
+                            }
+                        }
+                        a
+                    }
+                });
+            }
+            if new_dominator.is_some() && dominators[node] != new_dominator {
+                dominators[node] = new_dominator;
+                is_changed = true;
+            }
+        }
+    }
+    dominators[entry] = None;
+    dominators
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_control_flow_graph() {
+        let actions = vec![
+            Action::Push(vec![Value::Int(0)]),
+            Action::Label(0),
+            Action::PushDuplicate,
+            Action::Push(vec![Value::Int(10)]),
+            Action::Less2,
+            Action::Not,
+            Action::If { target: 1 },
+            Action::Increment,
+            Action::Jump { target: 0 },
+            Action::Label(1),
+            Action::Pop,
+            // The label of another body.
+            Action::Jump { target: 2 },
+        ];
+        let cfg = ControlFlowGraph::new(&actions);
+        let exits: Vec<_> = cfg
+            .blocks
+            .iter()
+            .map(|block| (block.labels.clone(), block.actions.len(), block.exit))
+            .collect();
+        assert_eq!(
+            exits,
+            vec![
+                (vec![], 1, Exit::Goto(1)),
+                (
+                    vec![0],
+                    4,
+                    Exit::Branch {
+                        if_true: 3,
+                        if_false: 2
+                    }
+                ),
+                (vec![], 1, Exit::Goto(1)),
+                (vec![1], 1, Exit::Goto(4)),
+                (vec![], 0, Exit::Outside(2)),
+            ]
+        );
+        assert_eq!(
+            cfg.immediate_post_dominators(),
+            vec![Some(1), Some(3), Some(1), Some(4), None]
+        );
+    }
+}
@@ -0,0 +1,344 @@
+//! Access to the blocks of AVM1 code in an SWF.
+//!
+//! AVM1 code is stored as bytes in `DoAction` and `DoInitAction` tags, in the clip actions of
+//! `PlaceObject` tags and in the actions of buttons. `Swf::avm1_code` finds all of them, with
+//! where they run, and `Swf::modify_avm1_code` decodes them to action lists and writes back
+//! the lists that are changed.
+use avm1::read::Reader;
+use avm1::types::ActionList;
+use avm1::write::Writer;
+use std::collections::HashSet;
+use std::io::Result;
+use types::*;
+
+/// Where a block of AVM1 code is, and when it runs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CodeContext {
+    /// The ID of the sprite whose timeline contains the code, or `None` for the main timeline.
+    pub sprite_id: Option<CharacterId>,
+    /// The frame of the timeline, starting from 0.
+    pub frame: usize,
+    pub source: CodeSource,
+}
+
+/// The tag or event that contains a block of AVM1 code.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CodeSource {
+    /// A `DoAction` tag, run when its frame is shown.
+    DoAction,
+    /// A `DoInitAction` tag, run once before the character is first placed.
+    DoInitAction { id: CharacterId },
+    /// A clip event handler of the character placed by a `PlaceObject` tag. The character ID is
+    /// `None` if the tag modifies a character placed by an earlier tag.
+    ClipAction {
+        depth: Depth,
+        character_id: Option<CharacterId>,
+        events: ClipEventFlags,
+        key_code: Option<u8>,
+    },
+    /// An action of a button, run when the button changes state.
+    ButtonAction {
+        id: CharacterId,
+        conditions: HashSet<ButtonActionCondition>,
+        key_code: Option<u8>,
+    },
+}
+
+/// A block of AVM1 code in an SWF.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Code<'a> {
+    pub context: CodeContext,
+    pub action_data: &'a [u8],
+    swf_version: u8,
+}
+
+impl<'a> Code<'a> {
+    /// Decodes the code with the version of its SWF.
+    pub fn read(&self) -> Result<ActionList> {
+        Reader::new(self.action_data, self.swf_version).read_action_list()
+    }
+}
+
+impl Swf {
+    /// Returns the blocks of AVM1 code in the SWF, including those in sprites and buttons, in
+    /// the order of their tags.
+    pub fn avm1_code(&self) -> Vec<Code<'_>> {
+        let mut code = vec![];
+        find_code(&self.tags, None, self.version, &mut code);
+        code
+    }
+
+    /// Decodes each block of AVM1 code in the SWF and passes it to `modify`, writing back the
+    /// action lists that it changes. Blocks that are not changed keep their original bytes.
+    ///
+    /// Every block is decoded, modified and encoded before any is written back, so the SWF is
+    /// left unchanged if this returns an error.
+    pub fn modify_avm1_code<F>(&mut self, mut modify: F) -> Result<()>
+    where
+        F: FnMut(&CodeContext, &mut ActionList) -> Result<()>,
+    {
+        let version = self.version;
+        let mut new_data = vec![];
+        for code in self.avm1_code() {
+            let original = code.read()?;
+            let mut actions = original.clone();
+            modify(&code.context, &mut actions)?;
+            if actions == original {
+                new_data.push(None);
+            } else {
+                let mut data = vec![];
+                Writer::new(&mut data, version).write_action_list(&actions)?;
+                new_data.push(Some(data));
+            }
+        }
+        let mut action_data = vec![];
+        find_action_data_mut(&mut self.tags, &mut action_data);
+        for (action_data, new_data) in action_data.into_iter().zip(new_data) {
+            if let Some(data) = new_data {
+                *action_data = data;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Tag {
+    /// Returns the blocks of AVM1 code in this tag, with their sources. Code in the tags of a
+    /// `DefineSprite` is not included.
+    pub fn avm1_code(&self) -> Vec<(CodeSource, &[u8])> {
+        match *self {
+            Tag::DoAction(ref action_data) => vec![(CodeSource::DoAction, &action_data[..])],
+            Tag::DoInitAction {
+                id,
+                ref action_data,
+            } => vec![(CodeSource::DoInitAction { id }, &action_data[..])],
+            Tag::PlaceObject(ref place_object) => {
+                let character_id = match place_object.action {
+                    PlaceObjectAction::Place(id) | PlaceObjectAction::Replace(id) => Some(id),
+                    PlaceObjectAction::Modify => None,
+                };
+                place_object
+                    .clip_actions
+                    .iter()
+                    .map(|clip_action| {
+                        let source = CodeSource::ClipAction {
+                            depth: place_object.depth,
+                            character_id,
+                            events: clip_action.events.clone(),
+                            key_code: clip_action.key_code,
+                        };
+                        (source, &clip_action.action_data[..])
+                    })
+                    .collect()
+            }
+            Tag::DefineButton(ref button) | Tag::DefineButton2(ref button) => button
+                .actions
+                .iter()
+                .map(|button_action| {
+                    let source = CodeSource::ButtonAction {
+                        id: button.id,
+                        conditions: button_action.conditions.clone(),
+                        key_code: button_action.key_code,
+                    };
+                    (source, &button_action.action_data[..])
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+}
+
+fn find_code<'a>(
+    tags: &'a [Tag],
+    sprite_id: Option<CharacterId>,
+    swf_version: u8,
+    code: &mut Vec<Code<'a>>,
+) {
+    let mut frame = 0;
+    for tag in tags {
+        match *tag {
+            Tag::ShowFrame => frame += 1,
+            Tag::DefineSprite(ref sprite) => {
+                find_code(&sprite.tags, Some(sprite.id), swf_version, code)
+            }
+            _ => {
+                for (source, action_data) in tag.avm1_code() {
+                    code.push(Code {
+                        context: CodeContext {
+                            sprite_id,
+                            frame,
+                            source,
+                        },
+                        action_data,
+                        swf_version,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Finds the action data of the code blocks in the same order as `find_code`.
+fn find_action_data_mut<'a>(tags: &'a mut [Tag], action_data: &mut Vec<&'a mut Vec<u8>>) {
+    for tag in tags {
+        match *tag {
+            Tag::DefineSprite(ref mut sprite) => {
+                find_action_data_mut(&mut sprite.tags, action_data)
+            }
+            Tag::DoAction(ref mut data)
+            | Tag::DoInitAction {
+                action_data: ref mut data,
+                ..
+            } => action_data.push(data),
+            Tag::PlaceObject(ref mut place_object) => action_data.extend(
+                place_object
+                    .clip_actions
+                    .iter_mut()
+                    .map(|clip_action| &mut clip_action.action_data),
+            ),
+            Tag::DefineButton(ref mut button) | Tag::DefineButton2(ref mut button) => action_data
+                .extend(
+                    button
+                        .actions
+                        .iter_mut()
+                        .map(|button_action| &mut button_action.action_data),
+                ),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avm1::types::Action;
+    use std::io::Error;
+    use test_data;
+
+    fn action_data(actions: &ActionList) -> Vec<u8> {
+        let mut data = vec![];
+        Writer::new(&mut data, 8)
+            .write_action_list(actions)
+            .unwrap();
+        data
+    }
+
+    fn place_with_clip_action(id: CharacterId, action_data: Vec<u8>) -> Tag {
+        let mut events = HashSet::new();
+        events.insert(ClipEvent::EnterFrame);
+        Tag::PlaceObject(Box::new(PlaceObject {
+            clip_actions: vec![ClipAction {
+                events,
+                key_code: None,
+                action_data,
+            }],
+            ..test_data::place_object(PlaceObjectAction::Place(id), 1)
+        }))
+    }
+
+    fn test_swf() -> Swf {
+        let mut conditions = HashSet::new();
+        conditions.insert(ButtonActionCondition::OverDownToOverUp);
+        test_data::swf(vec![
+            Tag::DefineButton2(Box::new(Button {
+                id: 1,
+                is_track_as_menu: false,
+                records: vec![],
+                actions: vec![ButtonAction {
+                    conditions,
+                    key_code: None,
+                    action_data: action_data(&vec![Action::Play]),
+                }],
+            })),
+            Tag::DefineSprite(Sprite {
+                id: 2,
+                num_frames: 2,
+                tags: vec![
+                    Tag::ShowFrame,
+                    Tag::DoAction(action_data(&vec![Action::Stop])),
+                    Tag::ShowFrame,
+                ],
+            }),
+            Tag::DoInitAction {
+                id: 2,
+                action_data: action_data(&vec![Action::Trace]),
+            },
+            Tag::ShowFrame,
+            place_with_clip_action(2, action_data(&vec![Action::NextFrame])),
+            // Code that is not written back the same way, as it has no `End` action.
+            Tag::DoAction(vec![0x07]),
+            Tag::ShowFrame,
+        ])
+    }
+
+    #[test]
+    fn find_avm1_code() {
+        let swf = test_swf();
+        let code: Vec<_> = swf
+            .avm1_code()
+            .iter()
+            .map(|code| {
+                let context = &code.context;
+                let source = match context.source {
+                    CodeSource::DoAction => "DoAction".to_string(),
+                    CodeSource::DoInitAction { id } => format!("DoInitAction {}", id),
+                    CodeSource::ClipAction {
+                        depth,
+                        character_id,
+                        ..
+                    } => format!("ClipAction {} {:?}", depth, character_id),
+                    CodeSource::ButtonAction { id, .. } => format!("ButtonAction {}", id),
+                };
+                (
+                    context.sprite_id,
+                    context.frame,
+                    source,
+                    code.read().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            code,
+            vec![
+                (None, 0, "ButtonAction 1".to_string(), vec![Action::Play]),
+                (Some(2), 1, "DoAction".to_string(), vec![Action::Stop]),
+                (None, 0, "DoInitAction 2".to_string(), vec![Action::Trace]),
+                (
+                    None,
+                    1,
+                    "ClipAction 1 Some(2)".to_string(),
+                    vec![Action::NextFrame]
+                ),
+                (None, 1, "DoAction".to_string(), vec![Action::Stop]),
+            ]
+        );
+    }
+
+    #[test]
+    fn modify_avm1_code() {
+        let mut swf = test_swf();
+        swf.modify_avm1_code(|context, actions| {
+            if context.sprite_id == Some(2) {
+                actions.insert(0, Action::Play);
+            }
+            Ok(())
+        })
+        .unwrap();
+        let mut expected = test_swf();
+        if let Tag::DefineSprite(ref mut sprite) = expected.tags[1] {
+            sprite.tags[1] = Tag::DoAction(action_data(&vec![Action::Play, Action::Stop]));
+        }
+        assert_eq!(swf, expected);
+
+        // No block is changed if one fails.
+        let result = swf.modify_avm1_code(|context, actions| {
+            actions.insert(0, Action::Play);
+            match context.source {
+                CodeSource::ClipAction { .. } => Err(Error::other("Modification failed")),
+                _ => Ok(()),
+            }
+        });
+        assert!(result.is_err());
+        assert_eq!(swf, expected);
+    }
+}
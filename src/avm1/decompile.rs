@@ -0,0 +1,1867 @@
+//! Decompilation of AVM1 code into ActionScript 2 source.
+//!
+//! The actions are split into basic blocks, from which `if`/`else`, `while`, `do`/`while`,
+//! `for`..`in` and `switch` statements are recovered. The values that actions push and pop are
+//! tracked as expressions, so that pushing the arguments of a method and calling it becomes a
+//! call expression. Branches that cannot be structured, such as branches into other bodies,
+//! are written as `goto` comments.
+use avm1::cfg::{ControlFlowGraph, Exit};
+use avm1::disassemble::quote;
+use avm1::types::*;
+use std::collections::{HashMap, HashSet};
+
+/// Decompiles a list of actions into ActionScript 2 source.
+pub fn decompile(actions: &[Action]) -> String {
+    let mut decompiler = Decompiler {
+        constant_pool: vec![],
+        registers: HashMap::new(),
+    };
+    let statements = decompiler.decompile_actions(actions);
+    let mut output = String::new();
+    write_statements(&mut output, &statements, 0);
+    output
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Undefined,
+    Null,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    Register(String),
+    /// A variable, with an expression for its name.
+    Variable(Box<Expr>),
+    Member(Box<Expr>, Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    New(Box<Expr>, Vec<Expr>),
+    Function(Box<FunctionDef>),
+    Array(Vec<Expr>),
+    Object(Vec<(Expr, Expr)>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    TypeOf(Box<Expr>),
+    Delete(Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// A call of a global function, such as `getTimer`.
+    Builtin(&'static str, Vec<Expr>),
+    /// A property of a movie clip, with the expressions of its target and index.
+    Property(Box<Expr>, Box<Expr>),
+    /// The names pushed by an `Enumerate` action, to be popped by a `for`..`in` loop.
+    Enumeration(Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BinaryOp {
+    Add,
+    StringAdd,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    BitAnd,
+    BitOr,
+    BitXor,
+    LeftShift,
+    RightShift,
+    UnsignedRightShift,
+    Equals,
+    NotEquals,
+    StrictEquals,
+    StrictNotEquals,
+    Less,
+    Greater,
+    StringEquals,
+    StringLess,
+    StringGreater,
+    InstanceOf,
+    And,
+    Or,
+    LogicalAnd,
+    LogicalOr,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Statement {
+    Expr(Expr),
+    Assign(Expr, Expr),
+    /// A local variable, with an expression for its name.
+    Var(Expr, Option<Expr>),
+    Return(Expr),
+    Throw(Expr),
+    If(Expr, Vec<Statement>, Vec<Statement>),
+    While(Expr, Vec<Statement>),
+    DoWhile(Vec<Statement>, Expr),
+    ForIn {
+        variable: Expr,
+        is_declared: bool,
+        object: Expr,
+        body: Vec<Statement>,
+    },
+    /// A switch, with the values of each case, where `None` is the default case.
+    Switch(Expr, Vec<(Vec<Option<Expr>>, Vec<Statement>)>),
+    Break,
+    Continue,
+    Try {
+        try: Vec<Statement>,
+        catch: Option<(Expr, Vec<Statement>)>,
+        finally: Option<Vec<Statement>>,
+    },
+    With(Expr, Vec<Statement>),
+    Function(FunctionDef),
+    Extends(Expr, Expr),
+    Implements(Expr, Vec<Expr>),
+    Comment(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct FunctionDef {
+    name: String,
+    params: Vec<String>,
+    body: Vec<Statement>,
+}
+
+struct Decompiler {
+    /// The strings of the last `ConstantPool` action.
+    constant_pool: Vec<String>,
+    /// The names of the registers of the function being decompiled.
+    registers: HashMap<u8, String>,
+}
+
+impl Decompiler {
+    fn decompile_actions(&mut self, actions: &[Action]) -> Vec<Statement> {
+        let cfg = ControlFlowGraph::new(actions);
+        let mut structurer = Structurer {
+            post_dominators: cfg.immediate_post_dominators(),
+            loops: find_loops(&cfg),
+            is_emitted: vec![false; cfg.blocks.len()],
+            entered_loops: HashSet::new(),
+            latch_condition: None,
+            cfg: &cfg,
+            decompiler: self,
+        };
+        let mut statements = vec![];
+        structurer.sequence(0, &Scope::default(), false, &mut vec![], &mut statements);
+        statements
+    }
+
+    fn function(
+        &mut self,
+        name: &str,
+        params: Vec<String>,
+        registers: HashMap<u8, String>,
+        actions: &[Action],
+    ) -> FunctionDef {
+        let outer_registers = ::std::mem::replace(&mut self.registers, registers);
+        let body = self.decompile_actions(actions);
+        self.registers = outer_registers;
+        FunctionDef {
+            name: name.to_string(),
+            params,
+            body,
+        }
+    }
+
+    fn register(&self, register: u8) -> Expr {
+        Expr::Register(
+            self.registers
+                .get(&register)
+                .cloned()
+                .unwrap_or_else(|| format!("_loc{}_", register)),
+        )
+    }
+
+    fn value(&self, value: &Value) -> Expr {
+        match *value {
+            Value::Undefined => Expr::Undefined,
+            Value::Null => Expr::Null,
+            Value::Bool(value) => Expr::Bool(value),
+            Value::Int(value) => Expr::Number(f64::from(value as i32)),
+            Value::Float(value) => Expr::Number(f64::from(value)),
+            Value::Double(value) => Expr::Number(value),
+            Value::Str(ref string) => Expr::Str(string.clone()),
+            Value::Register(register) => self.register(register),
+            // An index outside the pool pushes `undefined`.
+            Value::ConstantPool(index) => self
+                .constant_pool
+                .get(usize::from(index))
+                .map_or(Expr::Undefined, |string| Expr::Str(string.clone())),
+        }
+    }
+
+    /// Runs an action on a stack of expressions, adding the statements that it makes.
+    #[cfg_attr(feature = "clippy", allow(cyclomatic_complexity))]
+    fn simulate(&mut self, action: &Action, stack: &mut Vec<Expr>, out: &mut Vec<Statement>) {
+        let binary = |stack: &mut Vec<Expr>, op| {
+            let right = pop(stack);
+            let left = pop(stack);
+            stack.push(Expr::Binary(op, Box::new(left), Box::new(right)));
+        };
+        let unary = |stack: &mut Vec<Expr>, name| {
+            let value = pop(stack);
+            stack.push(Expr::Builtin(name, vec![value]));
+        };
+        let builtin = |out: &mut Vec<Statement>, name, args| {
+            out.push(Statement::Expr(Expr::Builtin(name, args)));
+        };
+        match *action {
+            Action::Add | Action::Add2 => binary(stack, BinaryOp::Add),
+            Action::And => binary(stack, BinaryOp::And),
+            Action::BitAnd => binary(stack, BinaryOp::BitAnd),
+            Action::BitLShift => binary(stack, BinaryOp::LeftShift),
+            Action::BitOr => binary(stack, BinaryOp::BitOr),
+            Action::BitRShift => binary(stack, BinaryOp::RightShift),
+            Action::BitURShift => binary(stack, BinaryOp::UnsignedRightShift),
+            Action::BitXor => binary(stack, BinaryOp::BitXor),
+            Action::Divide => binary(stack, BinaryOp::Divide),
+            Action::Equals | Action::Equals2 => binary(stack, BinaryOp::Equals),
+            Action::Greater => binary(stack, BinaryOp::Greater),
+            Action::InstanceOf => binary(stack, BinaryOp::InstanceOf),
+            Action::Less | Action::Less2 => binary(stack, BinaryOp::Less),
+            Action::Modulo => binary(stack, BinaryOp::Modulo),
+            Action::Multiply => binary(stack, BinaryOp::Multiply),
+            Action::Or => binary(stack, BinaryOp::Or),
+            Action::StrictEquals => binary(stack, BinaryOp::StrictEquals),
+            Action::StringAdd => binary(stack, BinaryOp::StringAdd),
+            Action::StringEquals => binary(stack, BinaryOp::StringEquals),
+            Action::StringGreater => binary(stack, BinaryOp::StringGreater),
+            Action::StringLess => binary(stack, BinaryOp::StringLess),
+            Action::Subtract => binary(stack, BinaryOp::Subtract),
+            Action::Increment | Action::Decrement => {
+                let value = pop(stack);
+                let op = if *action == Action::Increment {
+                    BinaryOp::Add
+                } else {
+                    BinaryOp::Subtract
+                };
+                stack.push(Expr::Binary(
+                    op,
+                    Box::new(value),
+                    Box::new(Expr::Number(1.0)),
+                ));
+            }
+            Action::Not => {
+                let value = pop(stack);
+                stack.push(negate(value));
+            }
+            Action::TypeOf => {
+                let value = pop(stack);
+                stack.push(Expr::TypeOf(Box::new(value)));
+            }
+            Action::AsciiToChar => unary(stack, "chr"),
+            Action::CharToAscii => unary(stack, "ord"),
+            Action::MBAsciiToChar => unary(stack, "mbchr"),
+            Action::MBCharToAscii => unary(stack, "mbord"),
+            Action::MBStringLength => unary(stack, "mblength"),
+            Action::RandomNumber => unary(stack, "random"),
+            Action::StringLength => unary(stack, "length"),
+            Action::TargetPath => unary(stack, "targetPath"),
+            Action::ToInteger => unary(stack, "int"),
+            Action::ToNumber => unary(stack, "Number"),
+            Action::ToString => unary(stack, "String"),
+            Action::StringExtract | Action::MBStringExtract => {
+                let count = pop(stack);
+                let index = pop(stack);
+                let string = pop(stack);
+                let name = if *action == Action::StringExtract {
+                    "substring"
+                } else {
+                    "mbsubstring"
+                };
+                stack.push(Expr::Builtin(name, vec![string, index, count]));
+            }
+            Action::GetTime => stack.push(Expr::Builtin("getTimer", vec![])),
+
+            Action::Push(ref values) => {
+                for value in values {
+                    let value = self.value(value);
+                    stack.push(value);
+                }
+            }
+            Action::Pop => {
+                let value = pop(stack);
+                if value.has_side_effects() {
+                    out.push(Statement::Expr(value));
+                }
+            }
+            Action::PushDuplicate => {
+                let value = pop(stack);
+                stack.push(value.clone());
+                stack.push(value);
+            }
+            Action::StackSwap => {
+                let a = pop(stack);
+                let b = pop(stack);
+                stack.push(a);
+                stack.push(b);
+            }
+            Action::StoreRegister(register) => {
+                let value = pop(stack);
+                let register = self.register(register);
+                out.push(Statement::Assign(register.clone(), value));
+                stack.push(register);
+            }
+            Action::ConstantPool(ref constant_pool) => self.constant_pool = constant_pool.clone(),
+
+            Action::GetVariable => {
+                let name = pop(stack);
+                stack.push(Expr::Variable(Box::new(name)));
+            }
+            Action::SetVariable => {
+                let value = pop(stack);
+                let name = pop(stack);
+                out.push(Statement::Assign(Expr::Variable(Box::new(name)), value));
+            }
+            Action::DefineLocal => {
+                let value = pop(stack);
+                let name = pop(stack);
+                out.push(Statement::Var(name, Some(value)));
+            }
+            Action::DefineLocal2 => {
+                let name = pop(stack);
+                out.push(Statement::Var(name, None));
+            }
+            Action::GetMember => {
+                let name = pop(stack);
+                let object = pop(stack);
+                stack.push(Expr::Member(Box::new(object), Box::new(name)));
+            }
+            Action::SetMember => {
+                let value = pop(stack);
+                let name = pop(stack);
+                let object = pop(stack);
+                out.push(Statement::Assign(
+                    Expr::Member(Box::new(object), Box::new(name)),
+                    value,
+                ));
+            }
+            Action::Delete => {
+                let name = pop(stack);
+                let object = pop(stack);
+                let member = Expr::Member(Box::new(object), Box::new(name));
+                stack.push(Expr::Delete(Box::new(member)));
+            }
+            Action::Delete2 => {
+                let name = pop(stack);
+                stack.push(Expr::Delete(Box::new(Expr::Variable(Box::new(name)))));
+            }
+            Action::GetProperty => {
+                let index = pop(stack);
+                let target = pop(stack);
+                stack.push(Expr::Property(Box::new(target), Box::new(index)));
+            }
+            Action::SetProperty => {
+                let value = pop(stack);
+                let index = pop(stack);
+                let target = pop(stack);
+                out.push(Statement::Assign(
+                    Expr::Property(Box::new(target), Box::new(index)),
+                    value,
+                ));
+            }
+
+            Action::CallFunction => {
+                let name = pop(stack);
+                let args = pop_args(stack);
+                stack.push(Expr::Call(Box::new(Expr::Variable(Box::new(name))), args));
+            }
+            Action::CallMethod => {
+                let name = pop(stack);
+                let object = pop(stack);
+                let args = pop_args(stack);
+                stack.push(Expr::Call(Box::new(method(object, name)), args));
+            }
+            Action::NewObject => {
+                let name = pop(stack);
+                let args = pop_args(stack);
+                stack.push(Expr::New(Box::new(Expr::Variable(Box::new(name))), args));
+            }
+            Action::NewMethod => {
+                let name = pop(stack);
+                let object = pop(stack);
+                let args = pop_args(stack);
+                stack.push(Expr::New(Box::new(method(object, name)), args));
+            }
+            Action::InitArray => {
+                let elements = pop_args(stack);
+                stack.push(Expr::Array(elements));
+            }
+            Action::InitObject => {
+                let mut properties = vec![];
+                for _ in 0..pop_count(stack) {
+                    let value = pop(stack);
+                    let name = pop(stack);
+                    properties.push((name, value));
+                }
+                properties.reverse();
+                stack.push(Expr::Object(properties));
+            }
+            Action::CastOp => {
+                let object = pop(stack);
+                let constructor = pop(stack);
+                stack.push(Expr::Call(Box::new(constructor), vec![object]));
+            }
+            Action::Extends => {
+                let superclass = pop(stack);
+                let subclass = pop(stack);
+                out.push(Statement::Extends(subclass, superclass));
+            }
+            Action::ImplementsOp => {
+                let constructor = pop(stack);
+                let interfaces = pop_args(stack);
+                out.push(Statement::Implements(constructor, interfaces));
+            }
+            Action::Enumerate => {
+                let name = pop(stack);
+                let object = Expr::Variable(Box::new(name));
+                stack.push(Expr::Enumeration(Box::new(object)));
+            }
+            Action::Enumerate2 => {
+                let object = pop(stack);
+                stack.push(Expr::Enumeration(Box::new(object)));
+            }
+
+            Action::DefineFunction {
+                ref name,
+                ref params,
+                ref actions,
+            } => {
+                let function = self.function(name, params.clone(), HashMap::new(), actions);
+                push_function(function, stack, out);
+            }
+            Action::DefineFunction2(ref function) => {
+                let mut registers = HashMap::new();
+                let preloaded = [
+                    (function.preload_this, "this"),
+                    (function.preload_arguments, "arguments"),
+                    (function.preload_super, "super"),
+                    (function.preload_root, "_root"),
+                    (function.preload_parent, "_parent"),
+                    (function.preload_global, "_global"),
+                ];
+                for &(_, name) in preloaded.iter().filter(|&&(is_preloaded, _)| is_preloaded) {
+                    let register = registers.len() as u8 + 1;
+                    registers.insert(register, name.to_string());
+                }
+                for param in &function.params {
+                    if let Some(register) = param.register_index {
+                        registers.insert(register, param.name.clone());
+                    }
+                }
+                let params = function
+                    .params
+                    .iter()
+                    .map(|param| param.name.clone())
+                    .collect();
+                let function = self.function(&function.name, params, registers, &function.actions);
+                push_function(function, stack, out);
+            }
+            Action::Return => {
+                let value = pop(stack);
+                out.push(Statement::Return(value));
+            }
+            Action::Throw => {
+                let value = pop(stack);
+                out.push(Statement::Throw(value));
+            }
+            Action::Try(ref try_block) => {
+                let catch = try_block.catch.as_ref().map(|(catch_var, actions)| {
+                    let catch_var = match *catch_var {
+                        CatchVar::Var(ref name) => {
+                            Expr::Variable(Box::new(Expr::Str(name.clone())))
+                        }
+                        CatchVar::Register(register) => self.register(register),
+                    };
+                    (catch_var, self.decompile_actions(actions))
+                });
+                out.push(Statement::Try {
+                    try: self.decompile_actions(&try_block.try),
+                    catch,
+                    finally: try_block
+                        .finally
+                        .as_ref()
+                        .map(|actions| self.decompile_actions(actions)),
+                });
+            }
+            Action::With { ref actions } => {
+                let object = pop(stack);
+                out.push(Statement::With(object, self.decompile_actions(actions)));
+            }
+
+            Action::Call => {
+                let frame = pop(stack);
+                builtin(out, "call", vec![frame]);
+            }
+            Action::CloneSprite => {
+                let depth = pop(stack);
+                let target = pop(stack);
+                let source = pop(stack);
+                builtin(out, "duplicateMovieClip", vec![source, target, depth]);
+            }
+            Action::EndDrag => builtin(out, "stopDrag", vec![]),
+            Action::GetUrl {
+                ref url,
+                ref target,
+            } => builtin(
+                out,
+                "getURL",
+                vec![Expr::Str(url.clone()), Expr::Str(target.clone())],
+            ),
+            Action::GetUrl2 {
+                send_vars_method,
+                is_target_sprite,
+                is_load_vars,
+            } => {
+                let target = pop(stack);
+                let url = pop(stack);
+                let name = if is_load_vars {
+                    "loadVariables"
+                } else if is_target_sprite {
+                    "loadMovie"
+                } else {
+                    "getURL"
+                };
+                let mut args = vec![url, target];
+                match send_vars_method {
+                    SendVarsMethod::None => (),
+                    SendVarsMethod::Get => args.push(Expr::Str("GET".to_string())),
+                    SendVarsMethod::Post => args.push(Expr::Str("POST".to_string())),
+                }
+                builtin(out, name, args);
+            }
+            Action::GotoFrame(frame) => builtin(
+                out,
+                "gotoAndStop",
+                vec![Expr::Number(f64::from(frame) + 1.0)],
+            ),
+            Action::GotoFrame2 {
+                set_playing,
+                scene_offset,
+            } => {
+                let mut args = vec![pop(stack)];
+                if scene_offset != 0 {
+                    args.push(Expr::Number(f64::from(scene_offset)));
+                }
+                let name = if set_playing {
+                    "gotoAndPlay"
+                } else {
+                    "gotoAndStop"
+                };
+                builtin(out, name, args);
+            }
+            Action::GotoLabel(ref label) => {
+                builtin(out, "gotoAndStop", vec![Expr::Str(label.clone())])
+            }
+            Action::NextFrame => builtin(out, "nextFrame", vec![]),
+            Action::Play => builtin(out, "play", vec![]),
+            Action::PreviousFrame => builtin(out, "prevFrame", vec![]),
+            Action::RemoveSprite => {
+                let target = pop(stack);
+                builtin(out, "removeMovieClip", vec![target]);
+            }
+            Action::SetTarget(ref target) => {
+                builtin(out, "setTarget", vec![Expr::Str(target.clone())])
+            }
+            Action::SetTarget2 => {
+                let target = pop(stack);
+                builtin(out, "setTarget", vec![target]);
+            }
+            Action::StartDrag => {
+                let target = pop(stack);
+                let lock_center = pop(stack);
+                let is_constrained = pop(stack);
+                let mut args = vec![target, lock_center];
+                if is_constrained != Expr::Bool(false) && is_constrained != Expr::Number(0.0) {
+                    let bottom = pop(stack);
+                    let right = pop(stack);
+                    let top = pop(stack);
+                    let left = pop(stack);
+                    args.extend(vec![left, top, right, bottom]);
+                }
+                builtin(out, "startDrag", args);
+            }
+            Action::Stop => builtin(out, "stop", vec![]),
+            Action::StopSounds => builtin(out, "stopAllSounds", vec![]),
+            Action::ToggleQuality => builtin(out, "toggleHighQuality", vec![]),
+            Action::Trace => {
+                let value = pop(stack);
+                builtin(out, "trace", vec![value]);
+            }
+            Action::WaitForFrame {
+                frame,
+                num_actions_to_skip,
+            } => out.push(Statement::Comment(format!(
+                "ifFrameLoaded({}) skips {} actions",
+                u32::from(frame) + 1,
+                num_actions_to_skip
+            ))),
+            Action::WaitForFrame2 {
+                num_actions_to_skip,
+            } => {
+                pop(stack);
+                out.push(Statement::Comment(format!(
+                    "ifFrameLoaded skips {} actions",
+                    num_actions_to_skip
+                )));
+            }
+            Action::Unknown { opcode, ref data } => out.push(Statement::Comment(format!(
+                "unknown action 0x{:02x} with {} bytes",
+                opcode,
+                data.len()
+            ))),
+            // Branches end basic blocks, and labels are not actions.
+            Action::End
+            | Action::If { .. }
+            | Action::Jump { .. }
+            | Action::Label(_)
+            | Action::OffsetLabel(_) => (),
+        }
+    }
+}
+
+/// A loop, with the blocks of its body and the blocks that branch back to its header.
+#[derive(Clone, Debug)]
+struct Loop {
+    body: HashSet<usize>,
+    latches: Vec<usize>,
+}
+
+/// The blocks at which a sequence of statements ends.
+#[derive(Clone, Debug, Default)]
+struct Scope {
+    /// The blocks after the sequence, such as the end of an `if` statement.
+    stops: Vec<usize>,
+    /// The header of the innermost loop, to which `continue` branches.
+    loop_header: Option<usize>,
+    /// The block to which `break` branches.
+    break_target: Option<usize>,
+    /// The block with the condition of a `do`..`while` loop.
+    latch: Option<usize>,
+}
+
+struct Structurer<'d, 'c, 'a: 'c> {
+    decompiler: &'d mut Decompiler,
+    cfg: &'c ControlFlowGraph<'a>,
+    post_dominators: Vec<Option<usize>>,
+    loops: HashMap<usize, Loop>,
+    is_emitted: Vec<bool>,
+    entered_loops: HashSet<usize>,
+    /// The condition of the latch of the `do`..`while` loop being structured.
+    latch_condition: Option<Expr>,
+}
+
+impl<'d, 'c, 'a> Structurer<'d, 'c, 'a> {
+    /// Structures the blocks from `block` until the end of the scope.
+    fn sequence(
+        &mut self,
+        mut block: usize,
+        scope: &Scope,
+        mut is_loop_start: bool,
+        stack: &mut Vec<Expr>,
+        out: &mut Vec<Statement>,
+    ) {
+        let cfg = self.cfg;
+        loop {
+            if scope.stops.contains(&block) {
+                return;
+            }
+            if self.is_break(block, scope) {
+                out.push(Statement::Break);
+                return;
+            }
+            if !is_loop_start && Some(block) == scope.loop_header {
+                out.push(Statement::Continue);
+                return;
+            }
+            is_loop_start = false;
+            if self.is_emitted[block] {
+                out.push(Statement::Comment(format!(
+                    "goto {}",
+                    self.block_name(block)
+                )));
+                return;
+            }
+            if let Some(next) = self.enumeration_cleanup(block) {
+                self.is_emitted[block] = true;
+                block = next;
+                continue;
+            }
+            if self.loops.contains_key(&block) && !self.entered_loops.contains(&block) {
+                match self.structure_loop(block, stack, out) {
+                    Some(next) => {
+                        block = next;
+                        continue;
+                    }
+                    None => return,
+                }
+            }
+
+            self.is_emitted[block] = true;
+            for action in cfg.blocks[block].actions {
+                self.decompiler.simulate(action, stack, out);
+            }
+            match cfg.blocks[block].exit {
+                Exit::Goto(next) => block = next,
+                Exit::Branch { .. } => {
+                    let condition = pop(stack);
+                    if Some(block) == scope.latch {
+                        self.latch_condition = Some(condition);
+                        return;
+                    }
+                    match self.structure_branch(block, condition, scope, stack, out) {
+                        Some(next) => block = next,
+                        None => return,
+                    }
+                }
+                Exit::Outside(label) => {
+                    out.push(Statement::Comment(format!("goto L{}", label)));
+                    return;
+                }
+                Exit::End => {
+                    for value in stack.drain(..) {
+                        if value.has_side_effects() {
+                            out.push(Statement::Expr(value));
+                        }
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Structures the branch at the end of a block as an `if` statement, a conditional
+    /// expression or a `switch` statement, and returns the block after it.
+    fn structure_branch(
+        &mut self,
+        block: usize,
+        condition: Expr,
+        scope: &Scope,
+        stack: &mut Vec<Expr>,
+        out: &mut Vec<Statement>,
+    ) -> Option<usize> {
+        let (if_true, if_false) = match self.cfg.blocks[block].exit {
+            Exit::Branch { if_true, if_false } => (if_true, if_false),
+            _ => return None,
+        };
+        if let Some(next) = self.structure_switch(block, &condition, if_true, if_false, scope, out)
+        {
+            return next;
+        }
+        for &(target, other, is_negated) in &[(if_true, if_false, false), (if_false, if_true, true)]
+        {
+            let jump = if self.is_break(target, scope) {
+                Statement::Break
+            } else if Some(target) == scope.loop_header {
+                Statement::Continue
+            } else {
+                continue;
+            };
+            let condition = if is_negated {
+                negate(condition)
+            } else {
+                condition
+            };
+            out.push(Statement::If(condition, vec![jump], vec![]));
+            return Some(other);
+        }
+
+        let merge = self.post_dominators[block];
+        let mut branch_scope = scope.clone();
+        branch_scope.stops.extend(merge);
+        let mut then_stack = stack.clone();
+        let mut then_out = vec![];
+        self.sequence(
+            if_false,
+            &branch_scope,
+            false,
+            &mut then_stack,
+            &mut then_out,
+        );
+        let mut else_stack = stack.clone();
+        let mut else_out = vec![];
+        self.sequence(
+            if_true,
+            &branch_scope,
+            false,
+            &mut else_stack,
+            &mut else_out,
+        );
+
+        // Branches that only push different values are conditional expressions.
+        let len = then_stack.len();
+        if then_out.is_empty()
+            && else_out.is_empty()
+            && len > 0
+            && else_stack.len() == len
+            && then_stack[..len - 1] == else_stack[..len - 1]
+            && then_stack[len - 1] != else_stack[len - 1]
+        {
+            let if_false_value = then_stack.pop().unwrap();
+            let if_true_value = else_stack.pop().unwrap();
+            then_stack.push(conditional(condition, if_true_value, if_false_value));
+        } else {
+            push_if(out, negate(condition), then_out, else_out);
+        }
+        *stack = then_stack;
+        merge
+    }
+
+    /// Structures a `switch` statement, which compares a register to the value of each case,
+    /// and returns the block after it.
+    fn structure_switch(
+        &mut self,
+        block: usize,
+        condition: &Expr,
+        if_true: usize,
+        if_false: usize,
+        scope: &Scope,
+        out: &mut Vec<Statement>,
+    ) -> Option<Option<usize>> {
+        let cfg = self.cfg;
+        let (register, value) = case_test(condition)?;
+        let discriminant = match out.last() {
+            Some(Statement::Assign(target, value)) if *target == register => value.clone(),
+            _ => return None,
+        };
+        let mut cases = vec![(Some(value), if_true)];
+        let mut tests = vec![];
+        let mut next = if_false;
+        while let Exit::Branch { if_true, if_false } = cfg.blocks[next].exit {
+            let mut test_stack = vec![];
+            let mut test_out = vec![];
+            for action in cfg.blocks[next].actions {
+                self.decompiler
+                    .simulate(action, &mut test_stack, &mut test_out);
+            }
+            match (test_out.is_empty(), &test_stack[..]) {
+                (true, [condition]) if !self.is_emitted[next] => match case_test(condition) {
+                    Some((test_register, value)) if test_register == register => {
+                        cases.push((Some(value), if_true));
+                        tests.push(next);
+                        next = if_false;
+                    }
+                    _ => break,
+                },
+                _ => break,
+            }
+        }
+        if tests.is_empty() {
+            return None;
+        }
+        // The last test is followed by a jump to the default case, or to the end.
+        let default = match cfg.blocks[next] {
+            ref basic_block if basic_block.actions.is_empty() && !self.is_emitted[next] => {
+                match basic_block.exit {
+                    Exit::Goto(target) => {
+                        tests.push(next);
+                        target
+                    }
+                    _ => next,
+                }
+            }
+            _ => next,
+        };
+        let follow = self.post_dominators[block];
+        if Some(default) != follow {
+            cases.push((None, default));
+        }
+        out.pop();
+        for test in tests {
+            self.is_emitted[test] = true;
+        }
+
+        let mut targets: Vec<usize> = cases.iter().map(|&(_, target)| target).collect();
+        targets.sort();
+        targets.dedup();
+        let mut switch_cases = vec![];
+        for (i, &target) in targets.iter().enumerate() {
+            let values = cases
+                .iter()
+                .filter(|&&(_, case_target)| case_target == target)
+                .map(|(value, _)| value.clone())
+                .collect();
+            let case_scope = Scope {
+                // Cases fall through to the next case, and jump to the end with `break`.
+                stops: targets.get(i + 1).cloned().into_iter().collect(),
+                break_target: follow,
+                ..scope.clone()
+            };
+            let mut body = vec![];
+            self.sequence(target, &case_scope, false, &mut vec![], &mut body);
+            if i + 1 == targets.len() && body.last() == Some(&Statement::Break) {
+                body.pop();
+            }
+            switch_cases.push((values, body));
+        }
+        out.push(Statement::Switch(discriminant, switch_cases));
+        Some(follow)
+    }
+
+    /// Structures the loop with a header, and returns the block after it.
+    fn structure_loop(
+        &mut self,
+        header: usize,
+        stack: &mut Vec<Expr>,
+        out: &mut Vec<Statement>,
+    ) -> Option<usize> {
+        let cfg = self.cfg;
+        let body_blocks = self.loops[&header].clone();
+        self.entered_loops.insert(header);
+        if let Some(follow) = self.structure_for_in(header, &body_blocks, stack, out) {
+            return follow;
+        }
+
+        // A `while` loop tests its condition in its header.
+        if let Exit::Branch { if_true, if_false } = cfg.blocks[header].exit {
+            let exit = match (
+                body_blocks.body.contains(&if_true),
+                body_blocks.body.contains(&if_false),
+            ) {
+                (false, true) => Some((if_false, if_true, true)),
+                (true, false) => Some((if_true, if_false, false)),
+                _ => None,
+            };
+            if let Some((inside, follow, is_exit_if_true)) = exit {
+                let mut header_stack = stack.clone();
+                let mut header_out = vec![];
+                for action in cfg.blocks[header].actions {
+                    self.decompiler
+                        .simulate(action, &mut header_stack, &mut header_out);
+                }
+                if header_out.is_empty()
+                    && header_stack.len() == stack.len() + 1
+                    && header_stack[..stack.len()] == stack[..]
+                {
+                    self.is_emitted[header] = true;
+                    let condition = header_stack.pop().unwrap();
+                    let condition = if is_exit_if_true {
+                        negate(condition)
+                    } else {
+                        condition
+                    };
+                    let body = self.loop_body(inside, header, Some(follow), None, stack);
+                    out.push(Statement::While(condition, body));
+                    return Some(follow);
+                }
+            }
+        }
+
+        // A `do`..`while` loop tests its condition at its end.
+        for &latch in &body_blocks.latches {
+            if let Exit::Branch { if_true, if_false } = cfg.blocks[latch].exit {
+                let exit = if if_true == header && !body_blocks.body.contains(&if_false) {
+                    Some((if_false, false))
+                } else if if_false == header && !body_blocks.body.contains(&if_true) {
+                    Some((if_true, true))
+                } else {
+                    None
+                };
+                if let Some((follow, is_negated)) = exit {
+                    let body = self.loop_body(header, header, Some(follow), Some(latch), stack);
+                    let condition = self.latch_condition.take().unwrap_or(Expr::Bool(true));
+                    let condition = if is_negated {
+                        negate(condition)
+                    } else {
+                        condition
+                    };
+                    out.push(Statement::DoWhile(body, condition));
+                    return Some(follow);
+                }
+            }
+        }
+
+        // Other loops are left by `break` statements.
+        let follow = body_blocks
+            .body
+            .iter()
+            .flat_map(|&block| cfg.blocks[block].exit.successors())
+            .filter(|block| !body_blocks.body.contains(block))
+            .min();
+        let body = self.loop_body(header, header, follow, None, stack);
+        out.push(Statement::While(Expr::Bool(true), body));
+        follow
+    }
+
+    /// Structures a `for`..`in` loop, which pops the names of an enumeration into a register
+    /// until it pops `null`.
+    fn structure_for_in(
+        &mut self,
+        header: usize,
+        body_blocks: &Loop,
+        stack: &mut Vec<Expr>,
+        out: &mut Vec<Statement>,
+    ) -> Option<Option<usize>> {
+        let cfg = self.cfg;
+        let register = match *cfg.blocks[header].actions {
+            [Action::StoreRegister(register), Action::Push(ref values), Action::Equals | Action::Equals2 | Action::StrictEquals]
+                if values[..] == [Value::Null] =>
+            {
+                register
+            }
+            _ => return None,
+        };
+        let (follow, inside) = match cfg.blocks[header].exit {
+            Exit::Branch { if_true, if_false } if !body_blocks.body.contains(&if_true) => {
+                (if_true, if_false)
+            }
+            _ => return None,
+        };
+        let object = match stack.last() {
+            Some(Expr::Enumeration(object)) => (**object).clone(),
+            _ => return None,
+        };
+        stack.pop();
+        self.is_emitted[header] = true;
+        let mut body = self.loop_body(inside, header, Some(follow), None, stack);
+
+        // The register is usually copied into the variable of the loop.
+        let register = self.decompiler.register(register);
+        let variable = match body.first() {
+            Some(Statement::Assign(variable, value)) if *value == register => {
+                Some((variable.clone(), false))
+            }
+            Some(Statement::Var(name, Some(value))) if *value == register => {
+                Some((Expr::Variable(Box::new(name.clone())), true))
+            }
+            _ => None,
+        };
+        let (variable, is_declared) = match variable {
+            Some(variable) => {
+                body.remove(0);
+                variable
+            }
+            None => (register, false),
+        };
+        out.push(Statement::ForIn {
+            variable,
+            is_declared,
+            object,
+            body,
+        });
+        Some(Some(follow))
+    }
+
+    fn loop_body(
+        &mut self,
+        start: usize,
+        header: usize,
+        follow: Option<usize>,
+        latch: Option<usize>,
+        stack: &[Expr],
+    ) -> Vec<Statement> {
+        let scope = Scope {
+            stops: vec![],
+            loop_header: Some(header),
+            break_target: follow,
+            latch,
+        };
+        let mut body = vec![];
+        self.sequence(
+            start,
+            &scope,
+            start == header,
+            &mut stack.to_vec(),
+            &mut body,
+        );
+        if body.last() == Some(&Statement::Continue) {
+            body.pop();
+        }
+        body
+    }
+
+    fn is_break(&self, block: usize, scope: &Scope) -> bool {
+        scope.break_target.is_some_and(|break_target| {
+            block == break_target || self.enumeration_cleanup(block) == Some(break_target)
+        })
+    }
+
+    /// Returns the block after a loop that pops the rest of an enumeration, which is run when
+    /// a `for`..`in` loop is left by `break`.
+    fn enumeration_cleanup(&self, block: usize) -> Option<usize> {
+        let basic_block = &self.cfg.blocks[block];
+        match (basic_block.actions, basic_block.exit) {
+            (
+                [Action::Push(ref values), Action::Equals | Action::Equals2 | Action::StrictEquals, Action::Not],
+                Exit::Branch { if_true, if_false },
+            ) if values[..] == [Value::Null] && if_true == block => Some(if_false),
+            _ => None,
+        }
+    }
+
+    fn block_name(&self, block: usize) -> String {
+        match self.cfg.blocks[block].labels.first() {
+            Some(label) => format!("L{}", label),
+            None => format!("block {}", block),
+        }
+    }
+}
+
+/// Finds the loops of a graph from its back edges, which branch to a block that is being
+/// visited in a depth-first search.
+fn find_loops(cfg: &ControlFlowGraph) -> HashMap<usize, Loop> {
+    let mut back_edges = vec![];
+    let mut is_visited = vec![false; cfg.blocks.len()];
+    let mut is_on_stack = vec![false; cfg.blocks.len()];
+    let mut stack = vec![(0, 0)];
+    is_visited[0] = true;
+    is_on_stack[0] = true;
+    while let Some(&mut (block, ref mut next)) = stack.last_mut() {
+        if let Some(&successor) = cfg.blocks[block].exit.successors().get(*next) {
+            *next += 1;
+            if is_on_stack[successor] {
+                back_edges.push((block, successor));
+            } else if !is_visited[successor] {
+                is_visited[successor] = true;
+                is_on_stack[successor] = true;
+                stack.push((successor, 0));
+            }
+        } else {
+            is_on_stack[block] = false;
+            stack.pop();
+        }
+    }
+
+    let predecessors = cfg.predecessors();
+    let mut loops = HashMap::new();
+    for (latch, header) in back_edges {
+        let body_blocks = loops.entry(header).or_insert_with(|| Loop {
+            body: vec![header].into_iter().collect(),
+            latches: vec![],
+        });
+        body_blocks.latches.push(latch);
+        let mut blocks = vec![latch];
+        while let Some(block) = blocks.pop() {
+            if body_blocks.body.insert(block) {
+                blocks.extend(&predecessors[block]);
+            }
+        }
+    }
+    loops
+}
+
+fn pop(stack: &mut Vec<Expr>) -> Expr {
+    // Popping an empty stack pushes `undefined`.
+    stack.pop().unwrap_or(Expr::Undefined)
+}
+
+/// Pops a count, such as the number of arguments of a function.
+fn pop_count(stack: &mut Vec<Expr>) -> usize {
+    match pop(stack) {
+        Expr::Number(count) if count >= 0.0 && count <= stack.len() as f64 => count as usize,
+        _ => 0,
+    }
+}
+
+/// Pops the arguments of a function, after their count.
+fn pop_args(stack: &mut Vec<Expr>) -> Vec<Expr> {
+    let count = pop_count(stack);
+    (0..count).map(|_| pop(stack)).collect()
+}
+
+fn method(object: Expr, name: Expr) -> Expr {
+    match name {
+        // A method without a name calls the object itself.
+        Expr::Undefined => object,
+        Expr::Str(ref name) if name.is_empty() => object,
+        _ => Expr::Member(Box::new(object), Box::new(name)),
+    }
+}
+
+fn push_function(function: FunctionDef, stack: &mut Vec<Expr>, out: &mut Vec<Statement>) {
+    if function.name.is_empty() {
+        stack.push(Expr::Function(Box::new(function)));
+    } else {
+        out.push(Statement::Function(function));
+    }
+}
+
+fn negate(expr: Expr) -> Expr {
+    match expr {
+        Expr::Not(value) => *value,
+        Expr::Binary(BinaryOp::Equals, left, right) => {
+            Expr::Binary(BinaryOp::NotEquals, left, right)
+        }
+        Expr::Binary(BinaryOp::NotEquals, left, right) => {
+            Expr::Binary(BinaryOp::Equals, left, right)
+        }
+        Expr::Binary(BinaryOp::StrictEquals, left, right) => {
+            Expr::Binary(BinaryOp::StrictNotEquals, left, right)
+        }
+        Expr::Binary(BinaryOp::StrictNotEquals, left, right) => {
+            Expr::Binary(BinaryOp::StrictEquals, left, right)
+        }
+        _ => Expr::Not(Box::new(expr)),
+    }
+}
+
+/// Returns a conditional expression, or `&&` and `||` expressions, which duplicate their left
+/// operand as the condition.
+fn conditional(condition: Expr, if_true: Expr, if_false: Expr) -> Expr {
+    if condition == if_true {
+        Expr::Binary(BinaryOp::LogicalOr, Box::new(if_true), Box::new(if_false))
+    } else if condition == negate(if_true.clone()) {
+        Expr::Binary(BinaryOp::LogicalAnd, Box::new(if_true), Box::new(if_false))
+    } else {
+        Expr::Conditional(Box::new(condition), Box::new(if_true), Box::new(if_false))
+    }
+}
+
+/// Returns the register and value of a comparison of a `switch` case.
+fn case_test(condition: &Expr) -> Option<(Expr, Expr)> {
+    match *condition {
+        Expr::Binary(BinaryOp::StrictEquals, ref left, ref right) => match (&**left, &**right) {
+            (&Expr::Register(_), value) | (value, &Expr::Register(_)) if value.is_constant() => {
+                let register = if let Expr::Register(_) = **left {
+                    left
+                } else {
+                    right
+                };
+                Some(((**register).clone(), value.clone()))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn push_if(
+    out: &mut Vec<Statement>,
+    condition: Expr,
+    then_statements: Vec<Statement>,
+    else_statements: Vec<Statement>,
+) {
+    let (condition, then_statements, else_statements) =
+        if then_statements.is_empty() && !else_statements.is_empty() {
+            (negate(condition), else_statements, then_statements)
+        } else {
+            (condition, then_statements, else_statements)
+        };
+    let is_jump = matches!(
+        then_statements.last(),
+        Some(&Statement::Return(_))
+            | Some(&Statement::Throw(_))
+            | Some(&Statement::Break)
+            | Some(&Statement::Continue)
+    );
+    if is_jump && !else_statements.is_empty() {
+        out.push(Statement::If(condition, then_statements, vec![]));
+        out.extend(else_statements);
+    } else {
+        out.push(Statement::If(condition, then_statements, else_statements));
+    }
+}
+
+impl Expr {
+    fn is_constant(&self) -> bool {
+        matches!(
+            *self,
+            Expr::Undefined | Expr::Null | Expr::Bool(_) | Expr::Number(_) | Expr::Str(_)
+        )
+    }
+
+    fn has_side_effects(&self) -> bool {
+        match *self {
+            Expr::Call(..) | Expr::New(..) | Expr::Delete(_) | Expr::Builtin(..) => true,
+            Expr::Variable(ref value)
+            | Expr::Not(ref value)
+            | Expr::TypeOf(ref value)
+            | Expr::Enumeration(ref value) => value.has_side_effects(),
+            Expr::Member(ref a, ref b)
+            | Expr::Binary(_, ref a, ref b)
+            | Expr::Property(ref a, ref b) => a.has_side_effects() || b.has_side_effects(),
+            Expr::Conditional(ref a, ref b, ref c) => {
+                a.has_side_effects() || b.has_side_effects() || c.has_side_effects()
+            }
+            Expr::Array(ref values) => values.iter().any(Expr::has_side_effects),
+            Expr::Object(ref properties) => properties
+                .iter()
+                .any(|(name, value)| name.has_side_effects() || value.has_side_effects()),
+            _ => false,
+        }
+    }
+
+    /// Returns the precedence of the expression, which binds tighter than lower precedences.
+    fn precedence(&self) -> u8 {
+        match *self {
+            Expr::Conditional(..) => 3,
+            Expr::Binary(op, ..) => op.precedence(),
+            Expr::Not(_) | Expr::TypeOf(_) | Expr::Delete(_) => 14,
+            Expr::New(..) => 16,
+            Expr::Number(value) if value < 0.0 => 14,
+            _ => 17,
+        }
+    }
+}
+
+impl BinaryOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::StringAdd => "add",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Modulo => "%",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^",
+            BinaryOp::LeftShift => "<<",
+            BinaryOp::RightShift => ">>",
+            BinaryOp::UnsignedRightShift => ">>>",
+            BinaryOp::Equals => "==",
+            BinaryOp::NotEquals => "!=",
+            BinaryOp::StrictEquals => "===",
+            BinaryOp::StrictNotEquals => "!==",
+            BinaryOp::Less => "<",
+            BinaryOp::Greater => ">",
+            BinaryOp::StringEquals => "eq",
+            BinaryOp::StringLess => "lt",
+            BinaryOp::StringGreater => "gt",
+            BinaryOp::InstanceOf => "instanceof",
+            BinaryOp::And => "and",
+            BinaryOp::Or => "or",
+            BinaryOp::LogicalAnd => "&&",
+            BinaryOp::LogicalOr => "||",
+        }
+    }
+
+    fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Or | BinaryOp::LogicalOr => 4,
+            BinaryOp::And | BinaryOp::LogicalAnd => 5,
+            BinaryOp::BitOr => 6,
+            BinaryOp::BitXor => 7,
+            BinaryOp::BitAnd => 8,
+            BinaryOp::Equals
+            | BinaryOp::NotEquals
+            | BinaryOp::StrictEquals
+            | BinaryOp::StrictNotEquals
+            | BinaryOp::StringEquals => 9,
+            BinaryOp::Less
+            | BinaryOp::Greater
+            | BinaryOp::StringLess
+            | BinaryOp::StringGreater
+            | BinaryOp::InstanceOf => 10,
+            BinaryOp::LeftShift | BinaryOp::RightShift | BinaryOp::UnsignedRightShift => 11,
+            BinaryOp::Add | BinaryOp::StringAdd | BinaryOp::Subtract => 12,
+            BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Modulo => 13,
+        }
+    }
+}
+
+/// The names of the properties of `GetProperty` and `SetProperty`, by index.
+const PROPERTY_NAMES: [&str; 22] = [
+    "_x",
+    "_y",
+    "_xscale",
+    "_yscale",
+    "_currentframe",
+    "_totalframes",
+    "_alpha",
+    "_visible",
+    "_width",
+    "_height",
+    "_rotation",
+    "_target",
+    "_framesloaded",
+    "_name",
+    "_droptarget",
+    "_url",
+    "_highquality",
+    "_focusrect",
+    "_soundbuftime",
+    "_quality",
+    "_xmouse",
+    "_ymouse",
+];
+
+fn write_statements(output: &mut String, statements: &[Statement], indent: usize) {
+    for statement in statements {
+        write_statement(output, statement, indent);
+    }
+}
+
+fn write_line(output: &mut String, indent: usize, line: &str) {
+    for _ in 0..indent {
+        output.push_str("    ");
+    }
+    output.push_str(line);
+    output.push('\n');
+}
+
+fn write_block(output: &mut String, indent: usize, header: &str, statements: &[Statement]) {
+    write_line(output, indent, &format!("{} {{", header));
+    write_statements(output, statements, indent + 1);
+}
+
+fn write_statement(output: &mut String, statement: &Statement, indent: usize) {
+    let expr = |value: &Expr| expression(value, 0, indent);
+    let line = match *statement {
+        Statement::Expr(ref value) => format!("{};", expr(value)),
+        Statement::Assign(ref target, ref value) => assignment(target, value, indent),
+        Statement::Var(ref name, Some(ref value)) => {
+            format!("var {} = {};", variable_name(name, indent), expr(value))
+        }
+        Statement::Var(ref name, None) => format!("var {};", variable_name(name, indent)),
+        Statement::Return(Expr::Undefined) => "return;".to_string(),
+        Statement::Return(ref value) => format!("return {};", expr(value)),
+        Statement::Throw(ref value) => format!("throw {};", expr(value)),
+        Statement::If(ref condition, ref then_statements, ref else_statements) => {
+            write_block(
+                output,
+                indent,
+                &format!("if ({})", expr(condition)),
+                then_statements,
+            );
+            let mut else_statements = else_statements;
+            while !else_statements.is_empty() {
+                match else_statements[..] {
+                    [Statement::If(ref condition, ref then_statements, ref next_else)] => {
+                        write_block(
+                            output,
+                            indent,
+                            &format!("}} else if ({})", expr(condition)),
+                            then_statements,
+                        );
+                        else_statements = next_else;
+                    }
+                    _ => {
+                        write_block(output, indent, "} else", else_statements);
+                        break;
+                    }
+                }
+            }
+            "}".to_string()
+        }
+        Statement::While(ref condition, ref body) => {
+            write_block(
+                output,
+                indent,
+                &format!("while ({})", expr(condition)),
+                body,
+            );
+            "}".to_string()
+        }
+        Statement::DoWhile(ref body, ref condition) => {
+            write_block(output, indent, "do", body);
+            format!("}} while ({});", expr(condition))
+        }
+        Statement::ForIn {
+            ref variable,
+            is_declared,
+            ref object,
+            ref body,
+        } => {
+            let header = format!(
+                "for ({}{} in {})",
+                if is_declared { "var " } else { "" },
+                expr(variable),
+                expr(object)
+            );
+            write_block(output, indent, &header, body);
+            "}".to_string()
+        }
+        Statement::Switch(ref discriminant, ref cases) => {
+            write_line(
+                output,
+                indent,
+                &format!("switch ({}) {{", expr(discriminant)),
+            );
+            for (values, body) in cases {
+                for value in values {
+                    let label = match *value {
+                        Some(ref value) => format!("case {}:", expr(value)),
+                        None => "default:".to_string(),
+                    };
+                    write_line(output, indent + 1, &label);
+                }
+                write_statements(output, body, indent + 2);
+            }
+            "}".to_string()
+        }
+        Statement::Break => "break;".to_string(),
+        Statement::Continue => "continue;".to_string(),
+        Statement::Try {
+            ref try,
+            ref catch,
+            ref finally,
+        } => {
+            write_block(output, indent, "try", try);
+            if let Some((ref catch_var, ref body)) = *catch {
+                write_block(
+                    output,
+                    indent,
+                    &format!("}} catch ({})", expr(catch_var)),
+                    body,
+                );
+            }
+            if let Some(ref body) = *finally {
+                write_block(output, indent, "} finally", body);
+            }
+            "}".to_string()
+        }
+        Statement::With(ref object, ref body) => {
+            write_block(output, indent, &format!("with ({})", expr(object)), body);
+            "}".to_string()
+        }
+        Statement::Function(ref function) => {
+            write_block(
+                output,
+                indent,
+                &format!("function {}({})", function.name, function.params.join(", ")),
+                &function.body,
+            );
+            "}".to_string()
+        }
+        Statement::Extends(ref subclass, ref superclass) => {
+            format!("{} extends {};", expr(subclass), expr(superclass))
+        }
+        Statement::Implements(ref constructor, ref interfaces) => format!(
+            "{} implements {};",
+            expr(constructor),
+            arguments(interfaces, indent)
+        ),
+        Statement::Comment(ref comment) => format!("// {}", comment),
+    };
+    write_line(output, indent, &line);
+}
+
+fn assignment(target: &Expr, value: &Expr, indent: usize) -> String {
+    match *target {
+        Expr::Property(ref target, ref index) if **target != Expr::Str(String::new()) => {
+            return format!(
+                "setProperty({}, {}, {});",
+                expression(target, 3, indent),
+                property_name(index, indent),
+                expression(value, 3, indent)
+            );
+        }
+        Expr::Variable(ref name) if !is_path(name) => {
+            return format!(
+                "set({}, {});",
+                expression(name, 3, indent),
+                expression(value, 3, indent)
+            );
+        }
+        _ => (),
+    }
+    let target_text = expression(target, 0, indent);
+    match *value {
+        Expr::Binary(op, ref left, ref right)
+            if **left == *target && **right == Expr::Number(1.0) =>
+        {
+            match op {
+                BinaryOp::Add => return format!("{}++;", target_text),
+                BinaryOp::Subtract => return format!("{}--;", target_text),
+                _ => (),
+            }
+        }
+        _ => (),
+    }
+    format!("{} = {};", target_text, expression(value, 0, indent))
+}
+
+/// Returns the text of an expression, in parentheses if it binds looser than `precedence`.
+fn expression(value: &Expr, precedence: u8, indent: usize) -> String {
+    let text = match *value {
+        Expr::Undefined => "undefined".to_string(),
+        Expr::Null => "null".to_string(),
+        Expr::Bool(value) => value.to_string(),
+        Expr::Number(value) => number(value),
+        Expr::Str(ref string) => quote(string, '"'),
+        Expr::Register(ref name) => name.clone(),
+        Expr::Variable(ref name) => {
+            if is_path(name) {
+                variable_name(name, indent)
+            } else {
+                format!("eval({})", expression(name, 3, indent))
+            }
+        }
+        Expr::Member(ref object, ref name) => match **name {
+            Expr::Str(ref name) if is_identifier(name) => {
+                format!("{}.{}", expression(object, 17, indent), name)
+            }
+            _ => format!(
+                "{}[{}]",
+                expression(object, 17, indent),
+                expression(name, 0, indent)
+            ),
+        },
+        Expr::Call(ref function, ref args) => format!(
+            "{}({})",
+            expression(function, 17, indent),
+            arguments(args, indent)
+        ),
+        Expr::New(ref constructor, ref args) => format!(
+            "new {}({})",
+            expression(constructor, 17, indent),
+            arguments(args, indent)
+        ),
+        Expr::Function(ref function) => {
+            let mut text = format!("function ({}) {{\n", function.params.join(", "));
+            write_statements(&mut text, &function.body, indent + 1);
+            for _ in 0..indent {
+                text.push_str("    ");
+            }
+            text.push('}');
+            text
+        }
+        Expr::Array(ref values) => format!("[{}]", arguments(values, indent)),
+        Expr::Object(ref properties) => {
+            let properties: Vec<_> = properties
+                .iter()
+                .map(|(name, value)| {
+                    let name = match *name {
+                        Expr::Str(ref name) if is_identifier(name) => name.clone(),
+                        _ => expression(name, 17, indent),
+                    };
+                    format!("{}: {}", name, expression(value, 3, indent))
+                })
+                .collect();
+            format!("{{{}}}", properties.join(", "))
+        }
+        Expr::Binary(op, ref left, ref right) => format!(
+            "{} {} {}",
+            expression(left, op.precedence(), indent),
+            op.symbol(),
+            expression(right, op.precedence() + 1, indent)
+        ),
+        Expr::Not(ref value) => format!("!{}", expression(value, 14, indent)),
+        Expr::TypeOf(ref value) => format!("typeof {}", expression(value, 14, indent)),
+        Expr::Delete(ref value) => format!("delete {}", expression(value, 14, indent)),
+        Expr::Conditional(ref condition, ref if_true, ref if_false) => format!(
+            "{} ? {} : {}",
+            expression(condition, 4, indent),
+            expression(if_true, 3, indent),
+            expression(if_false, 3, indent)
+        ),
+        Expr::Builtin(name, ref args) => format!("{}({})", name, arguments(args, indent)),
+        Expr::Property(ref target, ref index) => {
+            if **target == Expr::Str(String::new()) {
+                property_name(index, indent)
+            } else {
+                format!(
+                    "getProperty({}, {})",
+                    expression(target, 3, indent),
+                    property_name(index, indent)
+                )
+            }
+        }
+        Expr::Enumeration(ref object) => {
+            format!("enumerate({})", expression(object, 3, indent))
+        }
+    };
+    if value.precedence() < precedence {
+        format!("({})", text)
+    } else {
+        text
+    }
+}
+
+fn arguments(args: &[Expr], indent: usize) -> String {
+    let args: Vec<_> = args.iter().map(|arg| expression(arg, 3, indent)).collect();
+    args.join(", ")
+}
+
+fn property_name(index: &Expr, indent: usize) -> String {
+    match *index {
+        Expr::Number(index) if index >= 0.0 && (index as usize) < PROPERTY_NAMES.len() => {
+            PROPERTY_NAMES[index as usize].to_string()
+        }
+        _ => expression(index, 3, indent),
+    }
+}
+
+/// Returns the text of the name of a variable, which is written as is if it is a path.
+fn variable_name(name: &Expr, indent: usize) -> String {
+    match *name {
+        Expr::Str(ref name) if is_path(&Expr::Str(name.clone())) => name.clone(),
+        _ => expression(name, 3, indent),
+    }
+}
+
+/// Whether the name of a variable can be written as is, as an identifier or a path such as
+/// `_root.clip.x` or `/clip:x`.
+fn is_path(name: &Expr) -> bool {
+    match *name {
+        Expr::Str(ref name) => {
+            !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || "_$.:/".contains(c))
+        }
+        _ => false,
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+}
+
+fn number(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avm1::assemble::assemble;
+
+    fn decompile_text(text: &str) -> String {
+        decompile(&assemble(text).unwrap())
+    }
+
+    #[test]
+    fn decompile_if_and_while() {
+        let text = "
+            push \"i\", 0
+            definelocal
+        loop:
+            push \"i\"
+            getvariable
+            push 10
+            less2
+            not
+            if end
+            push \"i\"
+            getvariable
+            push 2
+            modulo
+            push 0
+            equals2
+            not
+            if odd
+            push \"i\"
+            getvariable
+            push 1
+            push \"_root\"
+            getvariable
+            push \"even\"
+            callmethod
+            pop
+            jump next
+        odd:
+            push \"i\"
+            getvariable
+            trace
+        next:
+            push \"i\", \"i\"
+            getvariable
+            increment
+            setvariable
+            jump loop
+        end:
+            push \"a\"
+            getvariable
+            pushduplicate
+            not
+            if and
+            pop
+            push \"b\"
+            getvariable
+        and:
+            push \"x\"
+            stackswap
+            setvariable
+        ";
+        assert_eq!(
+            decompile_text(text),
+            "var i = 0;
+while (i < 10) {
+    if (i % 2 == 0) {
+        _root.even(i);
+    } else {
+        trace(i);
+    }
+    i++;
+}
+x = a && b;
+"
+        );
+    }
+
+    #[test]
+    fn decompile_loops_and_switch() {
+        let text = "
+            definefunction2 f(r:1='o', r:2='n') registers:4 {
+                push r:1
+                enumerate2
+            loop:
+                storeregister r:0
+                push null
+                equals2
+                if done
+                push \"k\", r:0
+                definelocal
+                push \"k\"
+                getvariable
+                trace
+                jump loop
+            done:
+            repeat:
+                push r:2
+                decrement
+                storeregister r:2
+                pop
+                push r:2, 0
+                greater
+                if repeat
+                push r:2
+                storeregister r:3
+                push 1
+                strictequals
+                if one
+                push r:3, 2
+                strictequals
+                if two
+                jump default
+            one:
+                push \"one\"
+                trace
+                jump end
+            two:
+                push \"two\"
+                trace
+            default:
+                push \"other\"
+                trace
+            end:
+                push r:2
+                return
+            }
+        ";
+        assert_eq!(
+            decompile_text(text),
+            "function f(o, n) {
+    for (var k in o) {
+        trace(k);
+    }
+    do {
+        n--;
+    } while (n > 0);
+    switch (n) {
+        case 1:
+            trace(\"one\");
+            break;
+        case 2:
+            trace(\"two\");
+        default:
+            trace(\"other\");
+    }
+    return n;
+}
+"
+        );
+    }
+}
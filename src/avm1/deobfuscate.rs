@@ -0,0 +1,405 @@
+//! Passes that undo common obfuscations of AVM1 code.
+//!
+//! Obfuscators hide code behind branches whose conditions are pushed literals, and put junk
+//! such as `Unknown` actions where it is never run. `deobfuscate` runs each pass over a list of
+//! actions and its nested bodies until none changes it. The result can be written with
+//! `avm1::write::Writer` as usual.
+//!
+//! Lists with `WaitForFrame` actions are left as they are, as those skip a number of actions,
+//! and the action after an `OffsetLabel` is kept as it is, as it hides other actions in its
+//! encoding.
+use avm1::types::*;
+use std::collections::{HashMap, HashSet};
+
+/// Runs all of the passes until the code stops changing, and removes the labels that are no
+/// longer branched to. Returns whether the code was changed.
+pub fn deobfuscate(actions: &mut ActionList) -> bool {
+    let mut is_changed = false;
+    while fold_constant_branches(actions)
+        | collapse_jump_chains(actions)
+        | remove_unreachable_actions(actions)
+    {
+        is_changed = true;
+    }
+    remove_unused_labels(actions) || is_changed
+}
+
+/// Replaces `If` actions whose condition is a literal pushed by the previous action with a
+/// `Jump` if it is true, and removes them if it is false. Returns whether the code was changed.
+///
+/// Strings are not folded, as their truth depends on the SWF version.
+pub fn fold_constant_branches(actions: &mut ActionList) -> bool {
+    let mut is_changed = false;
+    for_each_list(actions, &mut |actions| {
+        if has_skips(actions) {
+            return;
+        }
+        let mut i = 1;
+        while i < actions.len() {
+            let is_hidden = i >= 2 && matches!(actions[i - 2], Action::OffsetLabel(_));
+            let condition = match (&actions[i - 1], &actions[i]) {
+                (Action::Push(values), &Action::If { target }) if !is_hidden => values
+                    .last()
+                    .and_then(literal_truth)
+                    .map(|value| (value, target)),
+                _ => None,
+            };
+            let (value, target) = match condition {
+                Some(condition) => condition,
+                None => {
+                    i += 1;
+                    continue;
+                }
+            };
+            if value {
+                actions[i] = Action::Jump { target };
+            } else {
+                actions.remove(i);
+            }
+            let is_push_empty = match actions[i - 1] {
+                Action::Push(ref mut values) => {
+                    values.pop();
+                    values.is_empty()
+                }
+                _ => false,
+            };
+            if is_push_empty {
+                actions.remove(i - 1);
+                i = usize::max(i - 1, 1);
+            }
+            is_changed = true;
+        }
+    });
+    is_changed
+}
+
+/// Retargets branches to a `Jump` to the final target of the jump, and removes branches to
+/// the next action. Returns whether the code was changed.
+pub fn collapse_jump_chains(actions: &mut ActionList) -> bool {
+    let mut is_changed = false;
+    for_each_list(actions, &mut |actions| {
+        let is_carrier = carriers(actions);
+        let mut jumps = HashMap::new();
+        for (i, action) in actions.iter().enumerate() {
+            if let Action::Label(label) = *action {
+                let next = actions[i + 1..]
+                    .iter()
+                    .find(|action| !matches!(action, Action::Label(_)));
+                if let Some(&Action::Jump { target }) = next {
+                    jumps.insert(label, target);
+                }
+            }
+        }
+        for (i, action) in actions.iter_mut().enumerate() {
+            if is_carrier[i] {
+                continue;
+            }
+            if let Action::If { ref mut target } | Action::Jump { ref mut target } = *action {
+                // Jumps may loop to themselves.
+                let mut final_target = *target;
+                let mut visited = HashSet::new();
+                while let Some(&next) = jumps.get(&final_target) {
+                    if !visited.insert(final_target) {
+                        break;
+                    }
+                    final_target = next;
+                }
+                if final_target != *target {
+                    *target = final_target;
+                    is_changed = true;
+                }
+            }
+        }
+
+        if has_skips(actions) {
+            return;
+        }
+        let mut i = 0;
+        while i < actions.len() {
+            let is_carrier = i > 0 && matches!(actions[i - 1], Action::OffsetLabel(_));
+            let is_to_next = match actions[i] {
+                _ if is_carrier => false,
+                Action::If { target } | Action::Jump { target } => actions[i + 1..]
+                    .iter()
+                    .take_while(|action| matches!(action, Action::Label(_)))
+                    .any(|action| *action == Action::Label(target)),
+                _ => false,
+            };
+            if !is_to_next {
+                i += 1;
+            } else if let Action::If { .. } = actions[i] {
+                // The condition is still popped.
+                actions[i] = Action::Pop;
+                is_changed = true;
+            } else {
+                actions.remove(i);
+                is_changed = true;
+            }
+        }
+    });
+    is_changed
+}
+
+/// Removes the actions that can not be run, such as actions after a `Jump` that is not
+/// branched to, including `Unknown` actions. Returns whether the code was changed.
+pub fn remove_unreachable_actions(actions: &mut ActionList) -> bool {
+    // Labels that are branched to from other lists, such as the labels that hidden actions
+    // rejoin, are always reachable.
+    let mut outside_targets = HashSet::new();
+    for_each_list(actions, &mut |actions| {
+        let labels: HashSet<Label> = actions.iter().filter_map(label).collect();
+        outside_targets.extend(
+            branch_targets(actions)
+                .into_iter()
+                .filter(|target| !labels.contains(target)),
+        );
+        // The labels that hidden actions rejoin are reachable, even in their own list.
+        for action in actions.iter() {
+            if let Action::OffsetLabel(ref offset_label) = *action {
+                outside_targets.extend(branch_targets(&offset_label.actions));
+            }
+        }
+    });
+
+    let mut is_changed = false;
+    for_each_list(actions, &mut |actions| {
+        if has_skips(actions) {
+            return;
+        }
+        let label_indices: HashMap<Label, usize> = actions
+            .iter()
+            .enumerate()
+            .filter_map(|(i, action)| label(action).map(|label| (label, i)))
+            .collect();
+        let mut is_reachable = vec![false; actions.len()];
+        let mut indices = vec![0];
+        indices.extend(
+            outside_targets
+                .iter()
+                .filter_map(|target| label_indices.get(target)),
+        );
+        while let Some(i) = indices.pop() {
+            if i >= actions.len() || is_reachable[i] {
+                continue;
+            }
+            is_reachable[i] = true;
+            match actions[i] {
+                Action::Jump { target } => indices.extend(label_indices.get(&target)),
+                Action::If { target } => {
+                    indices.extend(label_indices.get(&target));
+                    indices.push(i + 1);
+                }
+                Action::Return | Action::Throw | Action::End => (),
+                _ => indices.push(i + 1),
+            }
+        }
+        // The action with hidden actions is kept with its label.
+        for i in 1..actions.len() {
+            if is_reachable[i] && matches!(actions[i - 1], Action::OffsetLabel(_)) {
+                is_reachable[i - 1] = true;
+            }
+        }
+        if is_reachable.contains(&false) {
+            let mut reachable = is_reachable.into_iter();
+            actions.retain(|_| reachable.next().unwrap());
+            is_changed = true;
+        }
+    });
+    is_changed
+}
+
+/// Removes the labels that are not branched to. Returns whether the code was changed.
+pub fn remove_unused_labels(actions: &mut ActionList) -> bool {
+    let mut targets = HashSet::new();
+    for_each_list(actions, &mut |actions| {
+        targets.extend(branch_targets(actions))
+    });
+    let mut is_changed = false;
+    for_each_list(actions, &mut |actions| {
+        if has_skips(actions) {
+            return;
+        }
+        let len = actions.len();
+        actions.retain(|action| match *action {
+            Action::Label(label) => targets.contains(&label),
+            _ => true,
+        });
+        is_changed |= actions.len() != len;
+    });
+    is_changed
+}
+
+/// Calls `f` with a list of actions and each of its nested bodies.
+fn for_each_list(actions: &mut ActionList, f: &mut dyn FnMut(&mut ActionList)) {
+    f(actions);
+    for action in actions {
+        match *action {
+            Action::DefineFunction {
+                ref mut actions, ..
+            }
+            | Action::DefineFunction2(Function {
+                ref mut actions, ..
+            })
+            | Action::With { ref mut actions } => for_each_list(actions, f),
+            Action::Try(ref mut try_block) => {
+                for_each_list(&mut try_block.try, f);
+                if let Some((_, ref mut catch)) = try_block.catch {
+                    for_each_list(catch, f);
+                }
+                if let Some(ref mut finally) = try_block.finally {
+                    for_each_list(finally, f);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Returns the labels branched to by the actions of a list, and by the actions hidden in them.
+fn branch_targets(actions: &[Action]) -> Vec<Label> {
+    let mut targets = vec![];
+    for action in actions {
+        match *action {
+            Action::If { target } | Action::Jump { target } => targets.push(target),
+            Action::OffsetLabel(ref offset_label) => {
+                targets.extend(branch_targets(&offset_label.actions))
+            }
+            _ => (),
+        }
+    }
+    targets
+}
+
+/// Returns whether each action of a list follows an `OffsetLabel`, and so hides actions in its
+/// encoding. These actions must be written as they are.
+fn carriers(actions: &[Action]) -> Vec<bool> {
+    let mut is_carrier = vec![false; actions.len()];
+    for i in 1..actions.len() {
+        is_carrier[i] = matches!(actions[i - 1], Action::OffsetLabel(_));
+    }
+    is_carrier
+}
+
+/// Returns the label of a `Label` or `OffsetLabel` pseudo-action.
+fn label(action: &Action) -> Option<Label> {
+    match *action {
+        Action::Label(label) => Some(label),
+        Action::OffsetLabel(ref offset_label) => Some(offset_label.label),
+        _ => None,
+    }
+}
+
+fn has_skips(actions: &[Action]) -> bool {
+    actions.iter().any(|action| {
+        matches!(
+            *action,
+            Action::WaitForFrame { .. } | Action::WaitForFrame2 { .. }
+        )
+    })
+}
+
+/// Returns whether `If` branches on a value, if it is a literal whose truth does not depend on
+/// the SWF version.
+fn literal_truth(value: &Value) -> Option<bool> {
+    match *value {
+        Value::Undefined | Value::Null => Some(false),
+        Value::Bool(value) => Some(value),
+        Value::Int(value) => Some(value != 0),
+        Value::Float(value) => Some(value != 0.0 && !value.is_nan()),
+        Value::Double(value) => Some(value != 0.0 && !value.is_nan()),
+        Value::Str(_) | Value::Register(_) | Value::ConstantPool(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avm1::assemble::assemble;
+    use avm1::disassemble::disassemble;
+    use avm1::read::Reader;
+    use avm1::write::Writer;
+
+    #[test]
+    fn deobfuscate_junk_branches() {
+        let text = "
+            push true
+            if real
+            unknown 0xff 01 02
+            jump real
+        real:
+            jump step
+        trap:
+            push \"never\"
+            trace
+        step:
+            push \"x\", false
+            if trap
+            trace
+        ";
+        let mut actions = assemble(text).unwrap();
+        assert!(deobfuscate(&mut actions));
+        assert_eq!(
+            disassemble(&actions),
+            "    constantpool \"never\", \"x\"
+    push c:1 \"x\"
+    trace
+"
+        );
+        assert!(!deobfuscate(&mut actions));
+    }
+
+    #[test]
+    fn collapse_jumps() {
+        let text = "
+            push \"c\"
+            getvariable
+            if a
+            push 2
+            trace
+        a:
+            jump b
+            push 3
+        b:
+            push 4
+            trace
+        ";
+        let mut actions = assemble(text).unwrap();
+        assert!(deobfuscate(&mut actions));
+        assert_eq!(
+            disassemble(&actions),
+            "    constantpool \"c\"
+    push c:0 \"c\"
+    getvariable
+    if L1
+    push 2
+    trace
+L1:
+    push 4
+    trace
+"
+        );
+        Writer::new(&mut vec![], 8)
+            .write_action_list(&actions)
+            .unwrap();
+    }
+
+    #[test]
+    fn keep_hidden_actions() {
+        // `push true; if` branches into the data of the next `jump`, to a hidden `play` and
+        // `end`. The hidden actions rejoin the list after the six `play` actions.
+        let data = [
+            0x96, 2, 0, 5, 1, 0x9d, 2, 0, 3, 0, 0x99, 2, 0, 6, 0, 6, 6, 6, 6, 6, 6, 0x99, 2, 0, 0,
+            0, 7, 0,
+        ];
+        let mut actions = Reader::new(&data[..], 8).read_action_list().unwrap();
+        assert!(deobfuscate(&mut actions));
+        let mut written = vec![];
+        Writer::new(&mut written, 8)
+            .write_action_list(&actions)
+            .unwrap();
+        assert_eq!(
+            written,
+            vec![0x99, 2, 0, 3, 0, 0x99, 2, 0, 6, 0, 6, 6, 6, 6, 6, 6, 7, 0]
+        );
+    }
+}
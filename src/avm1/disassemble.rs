@@ -0,0 +1,457 @@
+//! Disassembly of AVM1 code into text.
+//!
+//! Each action is written on its own line, with the lowercase name of the action followed by
+//! its operands, separated by commas. Branch targets are written as labels such as `L0:`, and
+//! the bodies of functions, `With` and `Try` actions are indented inside braces:
+//!
+//! ```text
+//!     constantpool "x"
+//!     definefunction2 f(r:1='a') registers:2 suppress_this {
+//!         push r:1, c:0 "x"
+//!         add2
+//!         return
+//!     }
+//! L0:
+//!     push 1, f:1.5, 2.5, "s", true, null, undefined
+//!     if L0
+//! ```
+//!
+//! Strings are written in double quotes, `r:` prefixes a register, `c:` prefixes an index in the
+//! constant pool (followed by its string, if known), and `f:` prefixes a single-precision float.
+//! Comments start with `;`.
+use avm1::read::Reader;
+use avm1::types::*;
+use std::io::Result;
+use types::{ButtonAction, ClipAction, Tag};
+
+/// Disassembles a list of actions.
+pub fn disassemble(actions: &[Action]) -> String {
+    let mut disassembler = Disassembler {
+        output: String::new(),
+        constant_pool: None,
+    };
+    disassembler.write_actions(actions, 0);
+    disassembler.output
+}
+
+/// Reads and disassembles a block of AVM1 code.
+pub fn disassemble_code(action_data: &[u8], swf_version: u8) -> Result<String> {
+    let actions = Reader::new(action_data, swf_version).read_action_list()?;
+    Ok(disassemble(&actions))
+}
+
+/// Disassembles the code of a clip event handler, after a comment listing its events.
+pub fn disassemble_clip_action(clip_action: &ClipAction, swf_version: u8) -> Result<String> {
+    let mut events: Vec<_> = clip_action.events.iter().cloned().collect();
+    events.sort_by_key(|&event| event as u8);
+    let events: Vec<_> = events
+        .iter()
+        .map(|event| match (*event, clip_action.key_code) {
+            (::types::ClipEvent::KeyPress, Some(key_code)) => format!("keyPress {}", key_code),
+            _ => event_name(event),
+        })
+        .collect();
+    Ok(format!(
+        "; onClipEvent({})\n{}",
+        events.join(", "),
+        disassemble_code(&clip_action.action_data, swf_version)?
+    ))
+}
+
+/// Disassembles the code of a button action, after a comment listing its conditions.
+pub fn disassemble_button_action(button_action: &ButtonAction, swf_version: u8) -> Result<String> {
+    let mut conditions: Vec<_> = button_action.conditions.iter().cloned().collect();
+    conditions.sort_by_key(|&condition| condition as u8);
+    let conditions: Vec<_> = conditions
+        .iter()
+        .map(|condition| match (*condition, button_action.key_code) {
+            (::types::ButtonActionCondition::KeyPress, Some(key_code)) => {
+                format!("keyPress {}", key_code)
+            }
+            _ => event_name(condition),
+        })
+        .collect();
+    Ok(format!(
+        "; on({})\n{}",
+        conditions.join(", "),
+        disassemble_code(&button_action.action_data, swf_version)?
+    ))
+}
+
+/// Disassembles the AVM1 code of a `DoAction`, `DoInitAction`, `PlaceObject` or button tag.
+///
+/// Returns `None` for other tags. The code blocks of clip events and button actions are
+/// preceded by comments listing their events, and separated by blank lines.
+pub fn disassemble_tag(tag: &Tag, swf_version: u8) -> Result<Option<String>> {
+    let blocks = match *tag {
+        Tag::DoAction(ref action_data)
+        | Tag::DoInitAction {
+            ref action_data, ..
+        } => vec![disassemble_code(action_data, swf_version)?],
+        Tag::PlaceObject(ref place_object) => place_object
+            .clip_actions
+            .iter()
+            .map(|clip_action| disassemble_clip_action(clip_action, swf_version))
+            .collect::<Result<_>>()?,
+        Tag::DefineButton(ref button) | Tag::DefineButton2(ref button) => button
+            .actions
+            .iter()
+            .map(|button_action| disassemble_button_action(button_action, swf_version))
+            .collect::<Result<_>>()?,
+        _ => return Ok(None),
+    };
+    Ok(Some(blocks.join("\n")))
+}
+
+struct Disassembler<'a> {
+    output: String,
+    /// The strings of the nearest preceding `ConstantPool` action.
+    constant_pool: Option<&'a [String]>,
+}
+
+impl<'a> Disassembler<'a> {
+    /// Writes a list of actions, whose labels are indented by `depth` levels, and whose actions
+    /// are indented one level more.
+    fn write_actions(&mut self, actions: &'a [Action], depth: usize) {
+        for action in actions {
+            match *action {
+                Action::Label(label) => self.line(depth, &format!("L{}:", label)),
+                Action::OffsetLabel(ref label) if label.actions.is_empty() => {
+                    self.line(depth, &format!("L{} = {:+}", label.label, label.offset))
+                }
+                Action::OffsetLabel(ref label) => {
+                    self.line(depth, &format!("L{} = {:+} {{", label.label, label.offset));
+                    self.write_actions(&label.actions, depth + 1);
+                    self.line(depth, "}");
+                }
+                _ => self.write_action(action, depth + 1),
+            }
+        }
+    }
+
+    fn write_action(&mut self, action: &'a Action, depth: usize) {
+        let text = match *action {
+            Action::ConstantPool(ref strings) => {
+                self.constant_pool = Some(strings);
+                let strings: Vec<_> = strings.iter().map(|s| quote(s, '"')).collect();
+                format!("constantpool {}", strings.join(", "))
+            }
+            Action::DefineFunction {
+                ref name,
+                ref params,
+                ref actions,
+            } => {
+                let params: Vec<_> = params.iter().map(|param| quote(param, '\'')).collect();
+                let header = format!(
+                    "definefunction {}({})",
+                    function_name(name),
+                    params.join(", ")
+                );
+                return self.write_body(&header, actions, depth);
+            }
+            Action::DefineFunction2(ref function) => {
+                let params: Vec<_> = function
+                    .params
+                    .iter()
+                    .map(|param| match param.register_index {
+                        Some(register) => format!("r:{}={}", register, quote(&param.name, '\'')),
+                        None => quote(&param.name, '\''),
+                    })
+                    .collect();
+                let mut header = format!(
+                    "definefunction2 {}({}) registers:{}",
+                    function_name(&function.name),
+                    params.join(", "),
+                    function.num_registers
+                );
+                let flags = [
+                    (function.preload_this, "preload_this"),
+                    (function.suppress_this, "suppress_this"),
+                    (function.preload_arguments, "preload_arguments"),
+                    (function.suppress_arguments, "suppress_arguments"),
+                    (function.preload_super, "preload_super"),
+                    (function.suppress_super, "suppress_super"),
+                    (function.preload_root, "preload_root"),
+                    (function.preload_parent, "preload_parent"),
+                    (function.preload_global, "preload_global"),
+                ];
+                for &(_, flag) in flags.iter().filter(|&&(is_set, _)| is_set) {
+                    header.push(' ');
+                    header.push_str(flag);
+                }
+                return self.write_body(&header, &function.actions, depth);
+            }
+            Action::GetUrl {
+                ref url,
+                ref target,
+            } => {
+                format!("geturl {}, {}", quote(url, '"'), quote(target, '"'))
+            }
+            Action::GetUrl2 {
+                send_vars_method,
+                is_target_sprite,
+                is_load_vars,
+            } => {
+                let mut text = format!(
+                    "geturl2 {}",
+                    match send_vars_method {
+                        SendVarsMethod::None => "none",
+                        SendVarsMethod::Get => "get",
+                        SendVarsMethod::Post => "post",
+                    }
+                );
+                if is_target_sprite {
+                    text.push_str(", sprite");
+                }
+                if is_load_vars {
+                    text.push_str(", loadvars");
+                }
+                text
+            }
+            Action::GotoFrame(frame) => format!("gotoframe {}", frame),
+            Action::GotoFrame2 {
+                set_playing,
+                scene_offset,
+            } => {
+                let state = if set_playing { "play" } else { "stop" };
+                if scene_offset != 0 {
+                    format!("gotoframe2 {}, {}", state, scene_offset)
+                } else {
+                    format!("gotoframe2 {}", state)
+                }
+            }
+            Action::GotoLabel(ref label) => format!("gotolabel {}", quote(label, '"')),
+            Action::If { target } => format!("if L{}", target),
+            Action::Jump { target } => format!("jump L{}", target),
+            Action::Push(ref values) => {
+                let values: Vec<_> = values.iter().map(|value| self.value(value)).collect();
+                if values.is_empty() {
+                    "push".to_string()
+                } else {
+                    format!("push {}", values.join(", "))
+                }
+            }
+            Action::SetTarget(ref target) => format!("settarget {}", quote(target, '"')),
+            Action::StoreRegister(register) => format!("storeregister r:{}", register),
+            Action::Try(ref try_block) => {
+                self.line(depth, "try {");
+                self.write_actions(&try_block.try, depth);
+                if let Some((ref catch_var, ref actions)) = try_block.catch {
+                    let catch_var = match *catch_var {
+                        CatchVar::Var(ref name) => quote(name, '\''),
+                        CatchVar::Register(register) => format!("r:{}", register),
+                    };
+                    self.line(depth, &format!("}} catch {} {{", catch_var));
+                    self.write_actions(actions, depth);
+                }
+                if let Some(ref actions) = try_block.finally {
+                    self.line(depth, "} finally {");
+                    self.write_actions(actions, depth);
+                }
+                return self.line(depth, "}");
+            }
+            Action::WaitForFrame {
+                frame,
+                num_actions_to_skip,
+            } => format!("waitforframe {}, {}", frame, num_actions_to_skip),
+            Action::WaitForFrame2 {
+                num_actions_to_skip,
+            } => format!("waitforframe2 {}", num_actions_to_skip),
+            Action::With { ref actions } => return self.write_body("with", actions, depth),
+            Action::Unknown { opcode, ref data } => {
+                let mut text = format!("unknown 0x{:02x}", opcode);
+                for byte in data {
+                    text.push_str(&format!(" {:02x}", byte));
+                }
+                text
+            }
+            // The remaining actions have no operands, and are written as their lowercase names.
+            _ => format!("{:?}", action).to_lowercase(),
+        };
+        self.line(depth, &text);
+    }
+
+    /// Writes an action with a body, such as a function, as `header { actions }`.
+    fn write_body(&mut self, header: &str, actions: &'a [Action], depth: usize) {
+        self.line(depth, &format!("{} {{", header));
+        self.write_actions(actions, depth);
+        self.line(depth, "}");
+    }
+
+    fn value(&self, value: &Value) -> String {
+        match *value {
+            Value::Undefined => "undefined".to_string(),
+            Value::Null => "null".to_string(),
+            Value::Bool(value) => value.to_string(),
+            // Integers are signed when they are run.
+            Value::Int(value) => (value as i32).to_string(),
+            Value::Float(value) => format!("f:{:?}", value),
+            Value::Double(value) => format!("{:?}", value),
+            Value::Str(ref string) => quote(string, '"'),
+            Value::Register(register) => format!("r:{}", register),
+            Value::ConstantPool(index) => {
+                match self
+                    .constant_pool
+                    .and_then(|pool| pool.get(usize::from(index)))
+                {
+                    Some(string) => format!("c:{} {}", index, quote(string, '"')),
+                    None => format!("c:{}", index),
+                }
+            }
+        }
+    }
+
+    fn line(&mut self, depth: usize, text: &str) {
+        for _ in 0..depth {
+            self.output.push_str("    ");
+        }
+        self.output.push_str(text);
+        self.output.push('\n');
+    }
+}
+
+/// Returns the name of a function, which is quoted unless it is an identifier.
+fn function_name(name: &str) -> String {
+    let is_identifier = name.chars().enumerate().all(|(i, c)| {
+        c == '_' || c == '$' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit())
+    });
+    if is_identifier {
+        name.to_string()
+    } else {
+        quote(name, '"')
+    }
+}
+
+/// Returns the name of an event with a lowercase first letter, as in ActionScript.
+fn event_name<T: ::std::fmt::Debug>(event: &T) -> String {
+    let name = format!("{:?}", event);
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => name,
+    }
+}
+
+/// Quotes a string, escaping backslashes, quotes and control characters.
+pub(crate) fn quote(string: &str, quote: char) -> String {
+    let mut quoted = String::with_capacity(string.len() + 2);
+    quoted.push(quote);
+    for c in string.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            _ if c == quote => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            _ if c.is_control() => quoted.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push(quote);
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avm1::write::Writer;
+    use std::collections::HashSet;
+    use types::ClipEvent;
+
+    #[test]
+    fn disassemble_actions() {
+        let actions = vec![
+            Action::ConstantPool(vec!["x".to_string(), "a\"b".to_string()]),
+            Action::DefineFunction2(Function {
+                name: "f".to_string(),
+                params: vec![
+                    FunctionParam {
+                        name: "a".to_string(),
+                        register_index: Some(1),
+                    },
+                    FunctionParam {
+                        name: "b".to_string(),
+                        register_index: None,
+                    },
+                ],
+                num_registers: 2,
+                preload_parent: false,
+                preload_root: false,
+                suppress_super: false,
+                preload_super: false,
+                suppress_arguments: false,
+                preload_arguments: false,
+                suppress_this: true,
+                preload_this: false,
+                preload_global: false,
+                actions: vec![
+                    Action::Push(vec![Value::Register(1), Value::ConstantPool(0)]),
+                    Action::Add2,
+                    Action::Return,
+                ],
+            }),
+            Action::Label(0),
+            Action::Push(vec![
+                Value::Int(0xffff_ffff),
+                Value::Float(1.5),
+                Value::Double(2.0),
+                Value::Str("s".to_string()),
+                Value::ConstantPool(1),
+                Value::ConstantPool(2),
+            ]),
+            Action::Try(TryBlock {
+                try: vec![Action::Throw],
+                catch: Some((
+                    CatchVar::Var("e".to_string()),
+                    vec![Action::If { target: 0 }],
+                )),
+                finally: None,
+            }),
+            Action::GotoFrame2 {
+                set_playing: true,
+                scene_offset: 0,
+            },
+        ];
+        assert_eq!(
+            disassemble(&actions),
+            "    constantpool \"x\", \"a\\\"b\"
+    definefunction2 f(r:1='a', 'b') registers:2 suppress_this {
+        push r:1, c:0 \"x\"
+        add2
+        return
+    }
+L0:
+    push -1, f:1.5, 2.0, \"s\", c:1 \"a\\\"b\", c:2
+    try {
+        throw
+    } catch 'e' {
+        if L0
+    }
+    gotoframe2 play
+"
+        );
+    }
+
+    #[test]
+    fn disassemble_clip_actions() {
+        let mut action_data = vec![];
+        Writer::new(&mut action_data, 6)
+            .write_action_list(&vec![Action::Play])
+            .unwrap();
+        let mut events = HashSet::new();
+        events.insert(ClipEvent::KeyPress);
+        events.insert(ClipEvent::EnterFrame);
+        let clip_action = ClipAction {
+            events,
+            key_code: Some(13),
+            action_data,
+        };
+        assert_eq!(
+            disassemble_clip_action(&clip_action, 6).unwrap(),
+            "; onClipEvent(enterFrame, keyPress 13)\n    play\n"
+        );
+    }
+}
@@ -0,0 +1,319 @@
+//! Extraction of the URLs and strings used by AVM1 code, for reviews of what an SWF does.
+//!
+//! `extract_strings` lists the strings of an action list and its nested bodies, including the
+//! actions hidden in the encoding of others. The URLs and targets of `GetUrl2`, and the
+//! messages of `Trace`, are taken from the stack, so they are known only when the code pushes
+//! them as literals or concatenates literals right before the action. `Swf::avm1_strings`
+//! does the same for every block of code in an SWF, with where the code is.
+use avm1::code::CodeContext;
+use avm1::types::{Action, Function, SendVarsMethod, Value};
+use types::Swf;
+
+/// How a URL is used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UrlUse {
+    /// The URL is opened in a browser window, or runs a script.
+    GetUrl,
+    /// An SWF is loaded from the URL into a movie clip or a level.
+    LoadMovie,
+    /// Variables are loaded from the URL into a movie clip or a level.
+    LoadVariables,
+}
+
+/// A string used by AVM1 code.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CodeString {
+    /// A URL of a `GetUrl` or `GetUrl2` action. The URL or the target of `GetUrl2` is `None`
+    /// if it is not known statically.
+    Url {
+        url: Option<String>,
+        target: Option<String>,
+        method: SendVarsMethod,
+        url_use: UrlUse,
+    },
+    /// A string of a `ConstantPool` action.
+    ConstantPool(String),
+    /// A string pushed by a `Push` action.
+    Literal(String),
+    /// The message of a `Trace` action, or `None` if it is not known statically.
+    Trace(Option<String>),
+    /// The frame label of a `GotoLabel` action, or of a `GotoFrame2` action whose frame is a
+    /// string known statically.
+    GotoLabel(String),
+    /// The error message for a block of code that cannot be decoded, so its strings are not
+    /// known.
+    DecodeError(String),
+}
+
+impl CodeString {
+    /// Returns whether this is a URL that runs a script instead of opening a page, such as
+    /// `javascript:` and `fscommand:` URLs.
+    pub fn is_script_url(&self) -> bool {
+        match *self {
+            CodeString::Url {
+                url: Some(ref url), ..
+            } => {
+                let url = url.trim_start().to_lowercase();
+                url.starts_with("javascript:")
+                    || url.starts_with("fscommand:")
+                    || url.starts_with("vbscript:")
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A string used by a block of AVM1 code in an SWF.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FoundString {
+    pub context: CodeContext,
+    pub string: CodeString,
+}
+
+impl Swf {
+    /// Returns the strings used by each block of AVM1 code in the SWF, in the order of the
+    /// blocks and of their actions. A block that cannot be decoded is skipped, with a
+    /// `CodeString::DecodeError` in its place.
+    pub fn avm1_strings(&self) -> Vec<FoundString> {
+        let mut found = vec![];
+        for code in self.avm1_code() {
+            let strings = match code.read() {
+                Ok(actions) => extract_strings(&actions),
+                Err(error) => vec![CodeString::DecodeError(error.to_string())],
+            };
+            found.extend(strings.into_iter().map(|string| FoundString {
+                context: code.context.clone(),
+                string,
+            }));
+        }
+        found
+    }
+}
+
+/// Returns the uses of `javascript:`, `fscommand:` and other script URLs among found strings.
+pub fn script_urls(found: &[FoundString]) -> Vec<&FoundString> {
+    found
+        .iter()
+        .filter(|found| found.string.is_script_url())
+        .collect()
+}
+
+/// Returns the strings used by a list of actions and its nested bodies, in the order of the
+/// actions.
+pub fn extract_strings(actions: &[Action]) -> Vec<CodeString> {
+    let mut strings = vec![];
+    extract_from_list(actions, &mut vec![], &mut strings);
+    strings
+}
+
+/// Extracts the strings of a list of actions, with the constant pool that is used when it
+/// starts.
+fn extract_from_list(actions: &[Action], pool: &mut Vec<String>, strings: &mut Vec<CodeString>) {
+    // The strings on the stack, or `None` for values that are not known strings. Popping an
+    // empty stack gives `None`, as its values are not known.
+    let mut stack: Vec<Option<String>> = vec![];
+    for action in actions {
+        match *action {
+            Action::ConstantPool(ref constants) => {
+                strings.extend(constants.iter().cloned().map(CodeString::ConstantPool));
+                *pool = constants.clone();
+            }
+            Action::Push(ref values) => {
+                for value in values {
+                    let string = match *value {
+                        Value::Str(ref string) => {
+                            strings.push(CodeString::Literal(string.clone()));
+                            Some(string.clone())
+                        }
+                        Value::ConstantPool(index) => pool.get(usize::from(index)).cloned(),
+                        _ => None,
+                    };
+                    stack.push(string);
+                }
+            }
+            Action::Pop => {
+                stack.pop();
+            }
+            Action::PushDuplicate => {
+                let top = stack.last().cloned().unwrap_or(None);
+                stack.push(top);
+            }
+            Action::StackSwap => {
+                let a = stack.pop().unwrap_or(None);
+                let b = stack.pop().unwrap_or(None);
+                stack.push(a);
+                stack.push(b);
+            }
+            // Storing a register leaves its value on the stack.
+            Action::StoreRegister(_) => (),
+            Action::StringAdd | Action::Add2 => {
+                let b = stack.pop().unwrap_or(None);
+                let a = stack.pop().unwrap_or(None);
+                stack.push(match (a, b) {
+                    (Some(a), Some(b)) => Some(a + &b),
+                    _ => None,
+                });
+            }
+            Action::GetUrl {
+                ref url,
+                ref target,
+            } => strings.push(CodeString::Url {
+                url: Some(url.clone()),
+                target: Some(target.clone()),
+                method: SendVarsMethod::None,
+                url_use: UrlUse::GetUrl,
+            }),
+            Action::GetUrl2 {
+                send_vars_method,
+                is_target_sprite,
+                is_load_vars,
+            } => {
+                let target = stack.pop().unwrap_or(None);
+                let url = stack.pop().unwrap_or(None);
+                let url_use = if is_load_vars {
+                    UrlUse::LoadVariables
+                } else if is_target_sprite {
+                    UrlUse::LoadMovie
+                } else {
+                    UrlUse::GetUrl
+                };
+                strings.push(CodeString::Url {
+                    url,
+                    target,
+                    method: send_vars_method,
+                    url_use,
+                });
+            }
+            Action::Trace => strings.push(CodeString::Trace(stack.pop().unwrap_or(None))),
+            Action::GotoLabel(ref label) => strings.push(CodeString::GotoLabel(label.clone())),
+            Action::GotoFrame2 { .. } => {
+                if let Some(Some(frame)) = stack.pop() {
+                    // Strings that are numbers go to frame numbers.
+                    if frame.trim().parse::<f64>().is_err() {
+                        strings.push(CodeString::GotoLabel(frame));
+                    }
+                }
+            }
+            Action::DefineFunction {
+                actions: ref body, ..
+            }
+            | Action::DefineFunction2(Function {
+                actions: ref body, ..
+            }) => {
+                // Functions use the constant pool of the code that defines them, and do not
+                // change it.
+                extract_from_list(body, &mut pool.clone(), strings);
+                stack.clear();
+            }
+            Action::With { actions: ref body } => {
+                extract_from_list(body, pool, strings);
+                stack.clear();
+            }
+            Action::Try(ref try_block) => {
+                extract_from_list(&try_block.try, pool, strings);
+                if let Some((_, ref catch)) = try_block.catch {
+                    extract_from_list(catch, pool, strings);
+                }
+                if let Some(ref finally) = try_block.finally {
+                    extract_from_list(finally, pool, strings);
+                }
+                stack.clear();
+            }
+            Action::OffsetLabel(ref offset_label) => {
+                extract_from_list(&offset_label.actions, pool, strings);
+                stack.clear();
+            }
+            // The stack is not known after other actions, and where branches join.
+            _ => stack.clear(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avm1::assemble::assemble;
+    use avm1::code::CodeSource;
+    use avm1::write::Writer;
+    use test_data;
+    use types::*;
+
+    #[test]
+    fn extract_urls_and_traces() {
+        let text = "
+            constantpool 'java', 'script:'
+            geturl 'http://example.com/', '_blank'
+            push c:0, c:1
+            stringadd
+            push 'alert(1)'
+            add2
+            push '_self'
+            geturl2 get
+            push 'x'
+            getvariable
+            push ''
+            geturl2 none, sprite
+            push 'start'
+            gotoframe2 play
+            definefunction f() {
+                push c:1
+                trace
+            }
+        ";
+        let actions = assemble(text).unwrap();
+        let strings = extract_strings(&actions);
+        assert_eq!(
+            strings,
+            vec![
+                CodeString::ConstantPool("java".to_string()),
+                CodeString::ConstantPool("script:".to_string()),
+                CodeString::Url {
+                    url: Some("http://example.com/".to_string()),
+                    target: Some("_blank".to_string()),
+                    method: SendVarsMethod::None,
+                    url_use: UrlUse::GetUrl,
+                },
+                CodeString::Literal("alert(1)".to_string()),
+                CodeString::Literal("_self".to_string()),
+                CodeString::Url {
+                    url: Some("javascript:alert(1)".to_string()),
+                    target: Some("_self".to_string()),
+                    method: SendVarsMethod::Get,
+                    url_use: UrlUse::GetUrl,
+                },
+                CodeString::Literal("x".to_string()),
+                CodeString::Literal("".to_string()),
+                CodeString::Url {
+                    url: None,
+                    target: Some("".to_string()),
+                    method: SendVarsMethod::None,
+                    url_use: UrlUse::LoadMovie,
+                },
+                CodeString::Literal("start".to_string()),
+                CodeString::GotoLabel("start".to_string()),
+                CodeString::Trace(Some("script:".to_string())),
+            ]
+        );
+
+        let mut action_data = vec![];
+        Writer::new(&mut action_data, 8)
+            .write_action_list(&actions)
+            .unwrap();
+        let swf = test_data::swf(vec![
+            Tag::DoAction(action_data),
+            // Code with an action that extends past its end.
+            Tag::DoAction(vec![0x96, 5, 0, 1]),
+            Tag::ShowFrame,
+        ]);
+        let found = swf.avm1_strings();
+        assert_eq!(found.len(), strings.len() + 1);
+        match found[strings.len()].string {
+            CodeString::DecodeError(_) => (),
+            ref string => panic!("Expected a decoding error, found {:?}", string),
+        }
+        let script_urls = script_urls(&found);
+        assert_eq!(script_urls.len(), 1);
+        assert_eq!(script_urls[0].context.source, CodeSource::DoAction);
+        assert_eq!(script_urls[0].string, strings[5]);
+    }
+}
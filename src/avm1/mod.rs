@@ -1,4 +1,14 @@
+pub mod assemble;
+pub mod cfg;
+pub mod code;
+pub mod decompile;
+pub mod deobfuscate;
+pub mod disassemble;
+pub mod extract;
 mod opcode;
+pub mod optimize;
 pub mod read;
 pub mod types;
+pub mod validate;
+pub mod vm;
 pub mod write;
@@ -0,0 +1,382 @@
+//! Optimization of the constant pools of AVM1 code.
+//!
+//! A string pushed by a `Push` action takes its length plus two bytes, while a reference to a
+//! string in a constant pool takes two bytes, or three from index 256. `optimize_constant_pool`
+//! moves the strings that are worth it into one pool at the start of the code, which is also
+//! used by the bodies of the functions that the code defines.
+use avm1::types::*;
+use avm1::write::{push_value_length, Writer};
+use std::collections::HashMap;
+use std::io::Result;
+
+/// The result of an optimization of AVM1 code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OptimizeStats {
+    /// The number of strings in the constant pool of the optimized code.
+    pub num_constants: usize,
+    /// The length of the written code before the optimization, in bytes.
+    pub original_length: usize,
+    /// The length of the written code after the optimization, in bytes.
+    pub optimized_length: usize,
+}
+
+impl OptimizeStats {
+    /// Returns the number of bytes saved by the optimization.
+    pub fn savings(&self) -> usize {
+        self.original_length - self.optimized_length
+    }
+}
+
+/// Moves the strings pushed by the code and its nested bodies into a new constant pool at its
+/// start, and merges adjacent `Push` actions.
+///
+/// Strings are ranked by their number of uses times their length, and only those that make
+/// the code shorter are added to the pool. An existing pool is replaced only if it is the
+/// first action of the code and there are no others, so that the pool used by each reference
+/// is known. Code with other pools, code that refers to a pool without defining one, which is
+/// the pool of earlier code, and code for SWF versions before 5, which have no constant pools,
+/// only has its `Push` actions merged. Code with actions hidden in the encoding of
+/// others is left unchanged, as is code that would not be made shorter.
+pub fn optimize_constant_pool(actions: &mut ActionList, swf_version: u8) -> Result<OptimizeStats> {
+    let original_length = code_length(actions, swf_version)?;
+    let mut num_pools = 0;
+    let mut has_pool_references = false;
+    let mut has_hidden_actions = false;
+    visit_bodies(actions, &mut |action| {
+        match *action {
+            Action::ConstantPool(_) => num_pools += 1,
+            Action::Push(ref values) => {
+                has_pool_references |= values
+                    .iter()
+                    .any(|value| matches!(*value, Value::ConstantPool(_)))
+            }
+            Action::OffsetLabel(_) => has_hidden_actions = true,
+            _ => (),
+        }
+        true
+    });
+    let mut stats = OptimizeStats {
+        num_constants: match actions.first() {
+            Some(Action::ConstantPool(pool)) => pool.len(),
+            _ => 0,
+        },
+        original_length,
+        optimized_length: original_length,
+    };
+    if has_hidden_actions {
+        return Ok(stats);
+    }
+
+    let mut optimized = actions.clone();
+    let can_replace_pool = swf_version >= 5
+        && match optimized.first() {
+            Some(Action::ConstantPool(_)) => num_pools == 1,
+            _ => num_pools == 0 && !has_pool_references,
+        };
+    if can_replace_pool {
+        if let Some(Action::ConstantPool(_)) = optimized.first() {
+            if let Action::ConstantPool(pool) = optimized.remove(0) {
+                resolve_constants(&mut optimized, &pool);
+            }
+        }
+        let pool = rank_constants(&mut optimized);
+        use_constant_pool(&mut optimized, pool);
+    }
+    merge_pushes(&mut optimized);
+
+    let optimized_length = code_length(&optimized, swf_version)?;
+    if optimized_length < original_length {
+        stats.num_constants = match optimized.first() {
+            Some(Action::ConstantPool(pool)) => pool.len(),
+            _ => 0,
+        };
+        stats.optimized_length = optimized_length;
+        *actions = optimized;
+    }
+    Ok(stats)
+}
+
+/// Calls `visit` with each action of a list and of its nested bodies, until it returns false.
+/// Returns whether every action was visited.
+pub(crate) fn visit_bodies(
+    actions: &mut [Action],
+    visit: &mut dyn FnMut(&mut Action) -> bool,
+) -> bool {
+    for action in actions {
+        if !visit(action) {
+            return false;
+        }
+        let is_visited = match *action {
+            Action::DefineFunction {
+                ref mut actions, ..
+            }
+            | Action::DefineFunction2(Function {
+                ref mut actions, ..
+            })
+            | Action::With { ref mut actions } => visit_bodies(actions, visit),
+            Action::Try(ref mut try_block) => {
+                visit_bodies(&mut try_block.try, visit)
+                    && match try_block.catch {
+                        Some((_, ref mut catch)) => visit_bodies(catch, visit),
+                        None => true,
+                    }
+                    && match try_block.finally {
+                        Some(ref mut finally) => visit_bodies(finally, visit),
+                        None => true,
+                    }
+            }
+            _ => true,
+        };
+        if !is_visited {
+            return false;
+        }
+    }
+    true
+}
+
+/// Replaces the strings pushed by the code with references to a pool, and inserts the pool at
+/// its start. The strings not in the pool are left as they are.
+pub(crate) fn use_constant_pool(actions: &mut ActionList, pool: Vec<String>) {
+    if pool.is_empty() {
+        return;
+    }
+    let indices: HashMap<&str, u16> = pool
+        .iter()
+        .enumerate()
+        .map(|(i, string)| (&string[..], i as u16))
+        .collect();
+    visit_bodies(actions, &mut |action| {
+        if let Action::Push(ref mut values) = *action {
+            for value in values {
+                let index = match *value {
+                    Value::Str(ref string) => indices.get(&string[..]).cloned(),
+                    _ => None,
+                };
+                if let Some(index) = index {
+                    *value = Value::ConstantPool(index);
+                }
+            }
+        }
+        true
+    });
+    actions.insert(0, Action::ConstantPool(pool));
+}
+
+/// Replaces the references to a constant pool with the strings that they push.
+fn resolve_constants(actions: &mut ActionList, pool: &[String]) {
+    visit_bodies(actions, &mut |action| {
+        if let Action::Push(ref mut values) = *action {
+            for value in values {
+                if let Value::ConstantPool(index) = *value {
+                    // A reference outside the pool pushes `undefined`.
+                    *value = pool
+                        .get(usize::from(index))
+                        .map_or(Value::Undefined, |string| Value::Str(string.clone()));
+                }
+            }
+        }
+        true
+    });
+}
+
+/// Returns the strings that make the code shorter when moved into a constant pool, in the
+/// order of their uses times their length.
+fn rank_constants(actions: &mut ActionList) -> Vec<String> {
+    // Strings that are ranked equally keep the order of their first use.
+    let mut strings: Vec<(String, usize)> = vec![];
+    let mut indices = HashMap::new();
+    visit_bodies(actions, &mut |action| {
+        if let Action::Push(ref values) = *action {
+            for value in values {
+                if let Value::Str(ref string) = *value {
+                    let index = *indices.entry(string.clone()).or_insert_with(|| {
+                        strings.push((string.clone(), 0));
+                        strings.len() - 1
+                    });
+                    strings[index].1 += 1;
+                }
+            }
+        }
+        true
+    });
+    strings.sort_by_key(|(string, count)| usize::MAX - count * string.len());
+
+    let mut pool = vec![];
+    // The pool is limited by the length of its action record, and the count of its strings.
+    let mut record_length = 2;
+    let mut savings = 0;
+    for (string, count) in strings {
+        if pool.len() == usize::from(u16::MAX) {
+            break;
+        }
+        let reference_length = if pool.len() < 256 { 2 } else { 3 };
+        let entry_length = string.len() + 1;
+        let string_savings = count * (string.len() + 2);
+        let string_cost = count * reference_length + entry_length;
+        if string_savings <= string_cost || record_length + entry_length > usize::from(u16::MAX) {
+            continue;
+        }
+        record_length += entry_length;
+        savings += string_savings - string_cost;
+        pool.push(string);
+    }
+    // The action record of the pool has a header and a count.
+    if savings <= 5 {
+        pool.clear();
+    }
+    pool
+}
+
+/// Merges adjacent `Push` actions of the code and its nested bodies, while their action records
+/// are short enough. Lists with `WaitForFrame` actions are left as they are, as those skip a
+/// number of actions.
+fn merge_pushes(actions: &mut ActionList) {
+    let has_wait_for_frame = actions.iter().any(|action| {
+        matches!(
+            *action,
+            Action::WaitForFrame { .. } | Action::WaitForFrame2 { .. }
+        )
+    });
+    if !has_wait_for_frame {
+        let mut merged: ActionList = Vec::with_capacity(actions.len());
+        for action in actions.drain(..) {
+            if let Action::Push(values) = action {
+                if let Some(Action::Push(previous)) = merged.last_mut() {
+                    if push_length(previous) + push_length(&values) <= usize::from(u16::MAX) {
+                        previous.extend(values);
+                        continue;
+                    }
+                }
+                merged.push(Action::Push(values));
+            } else {
+                merged.push(action);
+            }
+        }
+        *actions = merged;
+    }
+
+    for action in actions {
+        match *action {
+            Action::DefineFunction {
+                ref mut actions, ..
+            }
+            | Action::DefineFunction2(Function {
+                ref mut actions, ..
+            })
+            | Action::With { ref mut actions } => merge_pushes(actions),
+            Action::Try(ref mut try_block) => {
+                merge_pushes(&mut try_block.try);
+                if let Some((_, ref mut catch)) = try_block.catch {
+                    merge_pushes(catch);
+                }
+                if let Some(ref mut finally) = try_block.finally {
+                    merge_pushes(finally);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn push_length(values: &[Value]) -> usize {
+    values.iter().map(push_value_length).sum()
+}
+
+fn code_length(actions: &ActionList, swf_version: u8) -> Result<usize> {
+    let mut data = vec![];
+    Writer::new(&mut data, swf_version).write_action_list(actions)?;
+    Ok(data.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avm1::assemble::assemble;
+    use avm1::disassemble::disassemble;
+
+    #[test]
+    fn optimize_constants() {
+        let text = "
+            constantpool \"unused\", \"_root\"
+            push c:1
+            getvariable
+            push \"gotoAndPlay\"
+            push \"frame\", 1
+            push c:1
+            getvariable
+            push \"gotoAndPlay\"
+            callmethod
+            definefunction2 f() {
+                push \"_root\", \"frame\", c:1
+                getvariable
+            }
+            push \"x\", \"x\"
+            setvariable
+        ";
+        let mut actions = assemble(text).unwrap();
+        let stats = optimize_constant_pool(&mut actions, 8).unwrap();
+        assert_eq!(
+            disassemble(&actions),
+            "    constantpool \"gotoAndPlay\", \"_root\", \"frame\"
+    push c:1 \"_root\"
+    getvariable
+    push c:0 \"gotoAndPlay\", c:2 \"frame\", 1, c:1 \"_root\"
+    getvariable
+    push c:0 \"gotoAndPlay\"
+    callmethod
+    definefunction2 f() registers:0 {
+        push c:1 \"_root\", c:2 \"frame\", c:1 \"_root\"
+        getvariable
+    }
+    push \"x\", \"x\"
+    setvariable
+"
+        );
+        assert_eq!(stats.num_constants, 3);
+        assert_eq!(stats.savings(), 32);
+
+        // Only the pushes are merged in code with more than one pool.
+        let text = "
+            push \"a\"
+            push \"b\"
+            constantpool \"b\"
+            definefunction f() {
+                constantpool \"c\"
+                push c:0
+                push c:0
+            }
+        ";
+        let mut actions = assemble(text).unwrap();
+        let stats = optimize_constant_pool(&mut actions, 8).unwrap();
+        assert_eq!(
+            disassemble(&actions),
+            "    push \"a\", \"b\"
+    constantpool \"b\"
+    definefunction f() {
+        constantpool \"c\"
+        push c:0 \"c\", c:0 \"c\"
+    }
+"
+        );
+        assert_eq!(stats.num_constants, 0);
+        assert_eq!(stats.savings(), 6);
+
+        // References to the pool of earlier code keep it, so no pool is added.
+        let push_string = Action::Push(vec![Value::Str("abcdefghij".to_string())]);
+        let mut actions = vec![
+            Action::Push(vec![Value::ConstantPool(0)]),
+            Action::Trace,
+            push_string.clone(),
+            Action::Trace,
+            push_string.clone(),
+            Action::Trace,
+            push_string,
+            Action::Trace,
+        ];
+        let original = actions.clone();
+        let stats = optimize_constant_pool(&mut actions, 8).unwrap();
+        assert_eq!(actions, original);
+        assert_eq!(stats.num_constants, 0);
+        assert_eq!(stats.savings(), 0);
+    }
+}
@@ -1,9 +1,14 @@
 use avm1::opcode::OpCode;
 use avm1::types::*;
+use avm1::write::Writer;
 use read::SwfRead;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::io::{Error, ErrorKind, Read, Result};
 
+/// The maximum number of bodies that actions can be nested in. Deeper bodies are read as part
+/// of the code that contains them, so that hostile code can't overflow the stack.
+pub(crate) const MAX_NESTING_DEPTH: usize = 64;
+
 pub struct Reader<R: Read> {
     inner: R,
     version: u8,
@@ -27,296 +32,238 @@ impl<R: Read> Reader<R> {
         }
     }
 
+    /// Reads the actions of a code block, such as the data of a `DoAction` tag, up to the end
+    /// of the input.
+    ///
+    /// Branch targets are marked by `Action::Label` pseudo-actions, which may be in the bodies
+    /// of other functions, `With` or `Try` actions than the branch. Targets in the middle of an
+    /// action or outside the code block are marked by `Action::OffsetLabel`, along with the
+    /// actions hidden in the encoding of the action they are in. Actions after an `End` action
+    /// are read, as branches may reach them; only the `End` action at the end of the input is
+    /// left out of the list.
+    ///
+    /// Actions that cannot be decoded, or that would not be written back the same way, are
+    /// read as `Action::Unknown`, so `Writer::write_action_list` writes the same bytes for the
+    /// unmodified list. So are the actions that would nest bodies more than 64 deep, whose
+    /// bodies are read as the actions that follow them. Returns an error if an action extends
+    /// past the end of the input.
     pub fn read_action_list(&mut self) -> Result<ActionList> {
-        let mut actions = Vec::new();
-        let mut positions = vec![0];
-        let mut position = 0;
-        while let Some(action_with_size) = try!(self.read_action()) {
-            actions.push(action_with_size.0);
-            position += action_with_size.1;
-            positions.push(position);
-        }
-        let position_to_idx: HashMap<usize, usize> = positions
-            .iter()
-            .enumerate()
-            .map(|(idx, pos)| (*pos, idx))
-            .collect();
-        trace!("potions {:?}", positions);
-        trace!("position_to_idx {:?}", position_to_idx);
-
-        for (idx, action) in actions.iter_mut().enumerate() {
-            match action {
-                Action::If { offset, jump_to } | Action::Jump { offset, jump_to } => {
-                    trace!("offset {}", offset);
-                    trace!("current_idx {}", idx);
-                    let current_pos = positions[idx + 1] as i16;
-                    trace!("current_pos {}", current_pos);
-                    let next_pos = (current_pos + *offset) as usize;
-                    trace!("next_pos {}", next_pos);
-                    *jump_to = position_to_idx[&next_pos] as i16;
-                    trace!("next_idx {}", position_to_idx[&next_pos]);
-                }
-                _ => {}
-            }
-        }
-
-        Ok(actions)
+        let mut data = vec![];
+        self.inner.read_to_end(&mut data)?;
+        Decoder::new(&data, self.version).decode()
     }
 
+    /// Reads the next action, along with the number of bytes read, including the bodies of
+    /// `DefineFunction` and `With` actions. Returns `None` at an `End` action or at the end of
+    /// the input.
+    ///
+    /// The target of an `If` or `Jump` action is only known in its code block, so it is read
+    /// as label 0, which marks no action.
+    #[deprecated(note = "use `read_action_list`, which resolves the targets of branches")]
     pub fn read_action(&mut self) -> Result<Option<(Action, usize)>> {
-        let result = self.read_opcode_and_length();
-        if let Err(err) = result {
-            use std::io::ErrorKind::UnexpectedEof;
-            if err.kind() == UnexpectedEof {
-                return Ok(None);
-            } else {
-                return Err(err);
-            }
-        }
-        let (opcode, length) = result.unwrap();
-        trace!("opcode {} length {}", opcode, length);
-
-        let mut action;
-        let mut code_length = 0; // for DefineFunction / DefineFunction2
-        {
-            let mut action_reader =
-                Reader::new(self.inner.by_ref().take(length as u64), self.version);
-
-            use num::FromPrimitive;
-            action = if let Some(op) = OpCode::from_u8(opcode) {
-                match op {
-                    OpCode::End => return Ok(None),
-
-                    OpCode::Add => Action::Add,
-                    OpCode::Add2 => Action::Add2,
-                    OpCode::And => Action::And,
-                    OpCode::AsciiToChar => Action::AsciiToChar,
-                    OpCode::BitAnd => Action::BitAnd,
-                    OpCode::BitLShift => Action::BitLShift,
-                    OpCode::BitOr => Action::BitOr,
-                    OpCode::BitRShift => Action::BitRShift,
-                    OpCode::BitURShift => Action::BitURShift,
-                    OpCode::BitXor => Action::BitXor,
-                    OpCode::Call => Action::Call,
-                    OpCode::CallFunction => Action::CallFunction,
-                    OpCode::CallMethod => Action::CallMethod,
-                    OpCode::CastOp => Action::CastOp,
-                    OpCode::CharToAscii => Action::CharToAscii,
-                    OpCode::CloneSprite => Action::CloneSprite,
-                    OpCode::ConstantPool => {
-                        let mut constants = vec![];
-                        for _ in 0..action_reader.read_u16()? {
-                            constants.push(action_reader.read_c_string()?);
-                        }
-                        Action::ConstantPool(constants)
-                    }
-                    OpCode::Decrement => Action::Decrement,
-                    OpCode::DefineFunction => {
-                        let action = action_reader.read_define_function()?;
-                        code_length = action_reader.read_u16()?;
-                        action
-                    }
-                    OpCode::DefineFunction2 => {
-                        let action = action_reader.read_define_function_2()?;
-                        code_length = action_reader.read_u16()?;
-                        action
-                    }
-                    OpCode::DefineLocal => Action::DefineLocal,
-                    OpCode::DefineLocal2 => Action::DefineLocal2,
-                    OpCode::Delete => Action::Delete,
-                    OpCode::Delete2 => Action::Delete2,
-                    OpCode::Divide => Action::Divide,
-                    OpCode::EndDrag => Action::EndDrag,
-                    OpCode::Enumerate => Action::Enumerate,
-                    OpCode::Enumerate2 => Action::Enumerate2,
-                    OpCode::Equals => Action::Equals,
-                    OpCode::Equals2 => Action::Equals2,
-                    OpCode::Extends => Action::Extends,
-                    OpCode::GetMember => Action::GetMember,
-                    OpCode::GetProperty => Action::GetProperty,
-                    OpCode::GetTime => Action::GetTime,
-                    OpCode::GetUrl => Action::GetUrl {
-                        url: try!(action_reader.read_c_string()),
-                        target: try!(action_reader.read_c_string()),
-                    },
-                    OpCode::GetUrl2 => {
-                        let flags = try!(action_reader.read_u8());
-                        Action::GetUrl2 {
-                            is_target_sprite: flags & 0b10 != 0,
-                            is_load_vars: flags & 0b1 != 0,
-                            send_vars_method: match flags >> 6 {
-                                0 => SendVarsMethod::None,
-                                1 => SendVarsMethod::Get,
-                                2 => SendVarsMethod::Post,
-                                _ => {
-                                    return Err(Error::new(
-                                        ErrorKind::InvalidData,
-                                        "Invalid HTTP method in ActionGetUrl2",
-                                    ))
-                                }
-                            },
-                        }
-                    }
-                    OpCode::GetVariable => Action::GetVariable,
-                    OpCode::GotoFrame => {
-                        let frame = try!(action_reader.read_u16());
-                        Action::GotoFrame(frame)
-                    }
-                    OpCode::GotoFrame2 => {
-                        let flags = try!(action_reader.read_u8());
-                        Action::GotoFrame2 {
-                            set_playing: flags & 0b1 != 0,
-                            scene_offset: if flags & 0b10 != 0 {
-                                try!(action_reader.read_u16())
-                            } else {
-                                0
-                            },
-                        }
-                    }
-                    OpCode::GotoLabel => Action::GotoLabel(try!(action_reader.read_c_string())),
-                    OpCode::Greater => Action::Greater,
-                    OpCode::If => Action::If {
-                        offset: try!(action_reader.read_i16()),
-                        jump_to: 0, // update later
-                    },
-                    OpCode::ImplementsOp => Action::ImplementsOp,
-                    OpCode::Increment => Action::Increment,
-                    OpCode::InitArray => Action::InitArray,
-                    OpCode::InitObject => Action::InitObject,
-                    OpCode::InstanceOf => Action::InstanceOf,
-                    OpCode::Jump => Action::Jump {
-                        offset: try!(action_reader.read_i16()),
-                        jump_to: 0, // update later
-                    },
-                    OpCode::Less => Action::Less,
-                    OpCode::Less2 => Action::Less2,
-                    OpCode::MBAsciiToChar => Action::MBAsciiToChar,
-                    OpCode::MBCharToAscii => Action::MBCharToAscii,
-                    OpCode::MBStringExtract => Action::MBStringExtract,
-                    OpCode::MBStringLength => Action::MBStringLength,
-                    OpCode::Modulo => Action::Modulo,
-                    OpCode::Multiply => Action::Multiply,
-                    OpCode::NewMethod => Action::NewMethod,
-                    OpCode::NewObject => Action::NewObject,
-                    OpCode::NextFrame => Action::NextFrame,
-                    OpCode::Not => Action::Not,
-                    OpCode::Or => Action::Or,
-                    OpCode::Play => Action::Play,
-                    OpCode::Pop => Action::Pop,
-                    OpCode::PreviousFrame => Action::PreviousFrame,
-                    // TODO: Verify correct version for complex types.
-                    OpCode::Push => {
-                        let mut values = vec![];
-                        while let Ok(value) = action_reader.read_push_value() {
-                            values.push(value);
-                        }
-                        Action::Push(values)
-                    }
-                    OpCode::PushDuplicate => Action::PushDuplicate,
-                    OpCode::RandomNumber => Action::RandomNumber,
-                    OpCode::RemoveSprite => Action::RemoveSprite,
-                    OpCode::Return => Action::Return,
-                    OpCode::SetMember => Action::SetMember,
-                    OpCode::SetProperty => Action::SetProperty,
-                    OpCode::SetTarget => Action::SetTarget(action_reader.read_c_string()?),
-                    OpCode::SetTarget2 => Action::SetTarget2,
-                    OpCode::SetVariable => Action::SetVariable,
-                    OpCode::StackSwap => Action::StackSwap,
-                    OpCode::StartDrag => Action::StartDrag,
-                    OpCode::Stop => Action::Stop,
-                    OpCode::StopSounds => Action::StopSounds,
-                    OpCode::StoreRegister => Action::StoreRegister(action_reader.read_u8()?),
-                    OpCode::StrictEquals => Action::StrictEquals,
-                    OpCode::StringAdd => Action::StringAdd,
-                    OpCode::StringEquals => Action::StringEquals,
-                    OpCode::StringExtract => Action::StringExtract,
-                    OpCode::StringGreater => Action::StringGreater,
-                    OpCode::StringLength => Action::StringLength,
-                    OpCode::StringLess => Action::StringLess,
-                    OpCode::Subtract => Action::Subtract,
-                    OpCode::TargetPath => Action::TargetPath,
-                    OpCode::Throw => Action::Throw,
-                    OpCode::ToggleQuality => Action::ToggleQuality,
-                    OpCode::ToInteger => Action::ToInteger,
-                    OpCode::ToNumber => Action::ToNumber,
-                    OpCode::ToString => Action::ToString,
-                    OpCode::Trace => Action::Trace,
-                    OpCode::Try => action_reader.read_try()?,
-                    OpCode::TypeOf => Action::TypeOf,
-                    OpCode::WaitForFrame => Action::WaitForFrame {
-                        frame: try!(action_reader.read_u16()),
-                        num_actions_to_skip: try!(action_reader.read_u8()),
-                    },
-                    OpCode::With => {
-                        let code_length = action_reader.read_u16()?;
-                        let mut with_reader = Reader::new(
-                            (&mut action_reader.inner as &mut Read).take(code_length as u64),
-                            self.version,
-                        );
-                        Action::With {
-                            actions: with_reader.read_action_list()?,
-                        }
-                    }
-                    OpCode::WaitForFrame2 => Action::WaitForFrame2 {
-                        num_actions_to_skip: try!(action_reader.read_u8()),
-                    },
-                }
-            } else {
-                action_reader.read_unknown_action(opcode, length)?
-            };
+        let (opcode, length) = match self.read_opcode_and_length() {
+            Ok((0, _)) => return Ok(None),
+            Ok(header) => header,
+            Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
         };
-
-        action = match action {
-            Action::DefineFunction {
-                name,
-                params,
-                actions: _,
-            } => {
-                let mut fn_reader = Reader::new(
-                    (&mut self.inner as &mut Read).take(code_length as u64),
-                    self.version,
-                );
-                let actions = fn_reader.read_action_list()?;
-
-                Action::DefineFunction {
-                    name: name,
-                    params: params,
-                    actions: actions,
-                }
-            }
-            Action::DefineFunction2(mut function) => {
-                let mut fn_reader = Reader::new(
-                    (&mut self.inner as &mut Read).take(code_length as u64),
-                    self.version,
-                );
-                let actions = fn_reader.read_action_list()?;
-                function.actions = actions;
-                Action::DefineFunction2(function)
+        let mut data = vec![opcode];
+        if opcode >= 0x80 {
+            data.extend_from_slice(&(length as u16).to_le_bytes());
+        }
+        let header_size = data.len();
+        (&mut self.inner)
+            .take(length as u64)
+            .read_to_end(&mut data)?;
+        if data.len() < header_size + length {
+            return Err(truncated_action());
+        }
+        // The bodies of these actions follow their records, with the code length at the end.
+        let has_body = opcode == OpCode::DefineFunction as u8
+            || opcode == OpCode::DefineFunction2 as u8
+            || opcode == OpCode::With as u8;
+        if has_body && length >= 2 {
+            let code_length =
+                u16::from(data[data.len() - 2]) | (u16::from(data[data.len() - 1]) << 8);
+            let record_size = data.len();
+            (&mut self.inner)
+                .take(code_length.into())
+                .read_to_end(&mut data)?;
+            if data.len() < record_size + usize::from(code_length) {
+                return Err(truncated_action());
             }
-            _ => action,
-        };
-
-        let size = if length == 0 { 1 } else { 1 + 2 + length };
+        }
+        let size = data.len();
+        let action = Decoder::new(&data, self.version)
+            .decode()?
+            .into_iter()
+            .find(|action| !matches!(*action, Action::Label(_) | Action::OffsetLabel(_)))
+            .unwrap_or(Action::End);
         Ok(Some((action, size)))
     }
 
     pub fn read_opcode_and_length(&mut self) -> Result<(u8, usize)> {
-        let opcode = try!(self.read_u8());
+        let opcode = self.read_u8()?;
         let length = if opcode >= 0x80 {
-            try!(self.read_u16()) as usize
+            self.read_u16()? as usize
         } else {
             0
         };
         Ok((opcode, length))
     }
 
-    fn read_unknown_action(&mut self, opcode: u8, length: usize) -> Result<Action> {
-        let mut data = vec![0u8; length];
-        self.inner.read_exact(&mut data)?;
-        Ok(Action::Unknown {
-            opcode: opcode,
-            data: data,
-        })
+    /// Reads the data of an action that does not contain other actions.
+    fn read_action_data(&mut self, opcode: OpCode) -> Result<Action> {
+        let action = match opcode {
+            OpCode::End => Action::End,
+
+            OpCode::Add => Action::Add,
+            OpCode::Add2 => Action::Add2,
+            OpCode::And => Action::And,
+            OpCode::AsciiToChar => Action::AsciiToChar,
+            OpCode::BitAnd => Action::BitAnd,
+            OpCode::BitLShift => Action::BitLShift,
+            OpCode::BitOr => Action::BitOr,
+            OpCode::BitRShift => Action::BitRShift,
+            OpCode::BitURShift => Action::BitURShift,
+            OpCode::BitXor => Action::BitXor,
+            OpCode::Call => Action::Call,
+            OpCode::CallFunction => Action::CallFunction,
+            OpCode::CallMethod => Action::CallMethod,
+            OpCode::CastOp => Action::CastOp,
+            OpCode::CharToAscii => Action::CharToAscii,
+            OpCode::CloneSprite => Action::CloneSprite,
+            OpCode::ConstantPool => {
+                let mut constants = vec![];
+                for _ in 0..self.read_u16()? {
+                    constants.push(self.read_c_string()?);
+                }
+                Action::ConstantPool(constants)
+            }
+            OpCode::Decrement => Action::Decrement,
+            OpCode::DefineLocal => Action::DefineLocal,
+            OpCode::DefineLocal2 => Action::DefineLocal2,
+            OpCode::Delete => Action::Delete,
+            OpCode::Delete2 => Action::Delete2,
+            OpCode::Divide => Action::Divide,
+            OpCode::EndDrag => Action::EndDrag,
+            OpCode::Enumerate => Action::Enumerate,
+            OpCode::Enumerate2 => Action::Enumerate2,
+            OpCode::Equals => Action::Equals,
+            OpCode::Equals2 => Action::Equals2,
+            OpCode::Extends => Action::Extends,
+            OpCode::GetMember => Action::GetMember,
+            OpCode::GetProperty => Action::GetProperty,
+            OpCode::GetTime => Action::GetTime,
+            OpCode::GetUrl => Action::GetUrl {
+                url: self.read_c_string()?,
+                target: self.read_c_string()?,
+            },
+            OpCode::GetUrl2 => {
+                let flags = self.read_u8()?;
+                Action::GetUrl2 {
+                    is_target_sprite: flags & 0b10 != 0,
+                    is_load_vars: flags & 0b1 != 0,
+                    send_vars_method: match flags >> 6 {
+                        0 => SendVarsMethod::None,
+                        1 => SendVarsMethod::Get,
+                        2 => SendVarsMethod::Post,
+                        _ => {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                "Invalid HTTP method in ActionGetUrl2",
+                            ))
+                        }
+                    },
+                }
+            }
+            OpCode::GetVariable => Action::GetVariable,
+            OpCode::GotoFrame => Action::GotoFrame(self.read_u16()?),
+            OpCode::GotoFrame2 => {
+                let flags = self.read_u8()?;
+                Action::GotoFrame2 {
+                    set_playing: flags & 0b1 != 0,
+                    scene_offset: if flags & 0b10 != 0 {
+                        self.read_u16()?
+                    } else {
+                        0
+                    },
+                }
+            }
+            OpCode::GotoLabel => Action::GotoLabel(self.read_c_string()?),
+            OpCode::Greater => Action::Greater,
+            OpCode::ImplementsOp => Action::ImplementsOp,
+            OpCode::Increment => Action::Increment,
+            OpCode::InitArray => Action::InitArray,
+            OpCode::InitObject => Action::InitObject,
+            OpCode::InstanceOf => Action::InstanceOf,
+            OpCode::Less => Action::Less,
+            OpCode::Less2 => Action::Less2,
+            OpCode::MBAsciiToChar => Action::MBAsciiToChar,
+            OpCode::MBCharToAscii => Action::MBCharToAscii,
+            OpCode::MBStringExtract => Action::MBStringExtract,
+            OpCode::MBStringLength => Action::MBStringLength,
+            OpCode::Modulo => Action::Modulo,
+            OpCode::Multiply => Action::Multiply,
+            OpCode::NewMethod => Action::NewMethod,
+            OpCode::NewObject => Action::NewObject,
+            OpCode::NextFrame => Action::NextFrame,
+            OpCode::Not => Action::Not,
+            OpCode::Or => Action::Or,
+            OpCode::Play => Action::Play,
+            OpCode::Pop => Action::Pop,
+            OpCode::PreviousFrame => Action::PreviousFrame,
+            OpCode::PushDuplicate => Action::PushDuplicate,
+            OpCode::RandomNumber => Action::RandomNumber,
+            OpCode::RemoveSprite => Action::RemoveSprite,
+            OpCode::Return => Action::Return,
+            OpCode::SetMember => Action::SetMember,
+            OpCode::SetProperty => Action::SetProperty,
+            OpCode::SetTarget => Action::SetTarget(self.read_c_string()?),
+            OpCode::SetTarget2 => Action::SetTarget2,
+            OpCode::SetVariable => Action::SetVariable,
+            OpCode::StackSwap => Action::StackSwap,
+            OpCode::StartDrag => Action::StartDrag,
+            OpCode::Stop => Action::Stop,
+            OpCode::StopSounds => Action::StopSounds,
+            OpCode::StoreRegister => Action::StoreRegister(self.read_u8()?),
+            OpCode::StrictEquals => Action::StrictEquals,
+            OpCode::StringAdd => Action::StringAdd,
+            OpCode::StringEquals => Action::StringEquals,
+            OpCode::StringExtract => Action::StringExtract,
+            OpCode::StringGreater => Action::StringGreater,
+            OpCode::StringLength => Action::StringLength,
+            OpCode::StringLess => Action::StringLess,
+            OpCode::Subtract => Action::Subtract,
+            OpCode::TargetPath => Action::TargetPath,
+            OpCode::Throw => Action::Throw,
+            OpCode::ToggleQuality => Action::ToggleQuality,
+            OpCode::ToInteger => Action::ToInteger,
+            OpCode::ToNumber => Action::ToNumber,
+            OpCode::ToString => Action::ToString,
+            OpCode::Trace => Action::Trace,
+            OpCode::TypeOf => Action::TypeOf,
+            OpCode::WaitForFrame => Action::WaitForFrame {
+                frame: self.read_u16()?,
+                num_actions_to_skip: self.read_u8()?,
+            },
+            OpCode::WaitForFrame2 => Action::WaitForFrame2 {
+                num_actions_to_skip: self.read_u8()?,
+            },
+            OpCode::DefineFunction
+            | OpCode::DefineFunction2
+            | OpCode::If
+            | OpCode::Jump
+            | OpCode::Push
+            | OpCode::Try
+            | OpCode::With => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "Action contains other data",
+                ))
+            }
+        };
+        Ok(action)
     }
 
     fn read_push_value(&mut self) -> Result<Value> {
@@ -385,70 +332,848 @@ impl<R: Read> Reader<R> {
         }))
     }
 
-    fn read_try(&mut self) -> Result<Action> {
+    /// Reads the fields of an `ActionTry` before its bodies, returning the block with empty
+    /// bodies and the sizes of the try, catch and finally bodies.
+    fn read_try_header(&mut self) -> Result<(TryBlock, [usize; 3])> {
         let flags = self.read_u8()?;
         let try_length = self.read_u16()?;
         let catch_length = self.read_u16()?;
         let finally_length = self.read_u16()?;
         let catch_var = if flags & 0b100 != 0 {
-            CatchVar::Var(self.read_c_string()?)
-        } else {
             CatchVar::Register(self.read_u8()?)
+        } else {
+            CatchVar::Var(self.read_c_string()?)
         };
-        let try_actions = {
-            let mut fn_reader = Reader::new(
-                (&mut self.inner as &mut Read).take(try_length as u64),
-                self.version,
-            );
-            fn_reader.read_action_list()?
-        };
-        let catch_actions = {
-            let mut fn_reader = Reader::new(
-                (&mut self.inner as &mut Read).take(catch_length as u64),
-                self.version,
-            );
-            fn_reader.read_action_list()?
-        };
-        let finally_actions = {
-            let mut fn_reader = Reader::new(
-                (&mut self.inner as &mut Read).take(finally_length as u64),
-                self.version,
-            );
-            fn_reader.read_action_list()?
-        };
-        Ok(Action::Try(TryBlock {
-            try: try_actions,
+        let try_block = TryBlock {
+            try: vec![],
             catch: if flags & 0b1 != 0 {
-                Some((catch_var, catch_actions))
+                Some((catch_var, vec![]))
             } else {
                 None
             },
             finally: if flags & 0b10 != 0 {
-                Some(finally_actions)
+                Some(vec![])
             } else {
                 None
             },
-        }))
+        };
+        let lengths = [
+            try_length.into(),
+            catch_length.into(),
+            finally_length.into(),
+        ];
+        Ok((try_block, lengths))
+    }
+}
+
+/// The decoded actions of a range of the code.
+struct Block {
+    start: usize,
+    end: usize,
+    items: Vec<Item>,
+    depth: usize,
+    /// For actions hidden in the encoding of another action, and the bodies they contain, the
+    /// block that contains that action.
+    host: Option<usize>,
+}
+
+/// An action with its position in the code, before its bodies and branch targets are resolved.
+struct Item {
+    position: usize,
+    /// The size of the action record, including the bodies of a `Try` action.
+    record_size: usize,
+    /// The size of the record and the bodies that follow it.
+    size: usize,
+    action: Action,
+    /// The blocks of the action's bodies, in order.
+    bodies: Vec<usize>,
+    /// The position targeted by a branch.
+    target: Option<i64>,
+}
+
+/// The position of a branch target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Location {
+    /// Before the start of the main code block, in bytes from its start.
+    Before(i64),
+    /// At the start of an action in a block, or at the end of the block.
+    Boundary { block: usize, index: usize },
+    /// Inside the encoding of an action.
+    Inside {
+        block: usize,
+        index: usize,
+        offset: usize,
+    },
+    /// After the end of the main code block, in bytes from its end.
+    After(i64),
+}
+
+/// The labels of the branch targets, by the position of their pseudo-action and by location.
+struct Labels {
+    by_index: BTreeMap<(usize, usize), Vec<(Location, Label)>>,
+    by_location: HashMap<Location, Label>,
+}
+
+/// Decodes the actions of a code block and resolves its branch targets to labels.
+struct Decoder<'a> {
+    data: &'a [u8],
+    version: u8,
+    blocks: Vec<Block>,
+    /// The location of each branch target, by position and the block of the branch.
+    locations: HashMap<(i64, usize), Location>,
+    /// The blocks of hidden actions, and the positions at which they rejoin their host block.
+    hidden: HashMap<usize, (usize, Option<usize>)>,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(data: &'a [u8], version: u8) -> Decoder<'a> {
+        Decoder {
+            data,
+            version,
+            blocks: vec![],
+            locations: HashMap::new(),
+            hidden: HashMap::new(),
+        }
+    }
+
+    fn decode(mut self) -> Result<ActionList> {
+        let main = self.decode_block(0, self.data.len(), 0, None)?;
+        {
+            // Leave out the End action that terminates the code.
+            let block = &mut self.blocks[main];
+            let is_end = match block.items.last() {
+                Some(item) => item.action == Action::End && item.position + 1 == block.end,
+                None => false,
+            };
+            if is_end {
+                block.items.pop();
+                block.end -= 1;
+            }
+        }
+
+        // Hidden actions that are found add more blocks and branches.
+        let mut block = 0;
+        while block < self.blocks.len() {
+            let targets: Vec<i64> = self.blocks[block]
+                .items
+                .iter()
+                .filter_map(|item| item.target)
+                .collect();
+            for target in targets {
+                self.resolve(target, block);
+            }
+            block += 1;
+        }
+
+        let mut locations: Vec<Location> = self.locations.values().cloned().collect();
+        locations.sort_by_key(|&location| (self.location_position(location), location));
+        locations.dedup();
+        let mut labels = Labels {
+            by_index: BTreeMap::new(),
+            by_location: HashMap::new(),
+        };
+        for (label, &location) in locations.iter().enumerate() {
+            let key = match location {
+                Location::Before(_) => (main, 0),
+                Location::Boundary { block, index } | Location::Inside { block, index, .. } => {
+                    (block, index)
+                }
+                Location::After(_) => (main, self.blocks[main].items.len()),
+            };
+            labels
+                .by_index
+                .entry(key)
+                .or_default()
+                .push((location, label as Label));
+            labels.by_location.insert(location, label as Label);
+        }
+        Ok(self.build(main, &labels))
+    }
+
+    fn decode_block(
+        &mut self,
+        start: usize,
+        end: usize,
+        depth: usize,
+        host: Option<usize>,
+    ) -> Result<usize> {
+        let id = self.blocks.len();
+        self.blocks.push(Block {
+            start,
+            end,
+            items: vec![],
+            depth,
+            host,
+        });
+        let mut items = vec![];
+        let mut position = start;
+        while position < end {
+            let item = self.decode_item(position, end, depth, host)?;
+            position += item.size;
+            items.push(item);
+        }
+        self.blocks[id].items = items;
+        Ok(id)
+    }
+
+    /// Decodes the action at `position`, which must be before `end`.
+    ///
+    /// Actions that cannot be decoded, or that are not written back the same way, are decoded
+    /// as `Action::Unknown`.
+    fn decode_item(
+        &mut self,
+        position: usize,
+        end: usize,
+        depth: usize,
+        host: Option<usize>,
+    ) -> Result<Item> {
+        let opcode = self.data[position];
+        let (header_size, length) = if opcode >= 0x80 {
+            if position + 3 > end {
+                return Err(truncated_action());
+            }
+            let length =
+                u16::from(self.data[position + 1]) | (u16::from(self.data[position + 2]) << 8);
+            (3, usize::from(length))
+        } else {
+            (1, 0)
+        };
+        let record_end = position + header_size + length;
+        if record_end > end {
+            return Err(truncated_action());
+        }
+
+        let num_blocks = self.blocks.len();
+        match self.decode_action(position, record_end, end, depth, host) {
+            Ok(Some(item)) => return Ok(item),
+            Ok(None) | Err(_) => self.blocks.truncate(num_blocks),
+        }
+        Ok(Item {
+            position,
+            record_size: record_end - position,
+            size: record_end - position,
+            action: Action::Unknown {
+                opcode,
+                data: self.data[position + header_size..record_end].to_vec(),
+            },
+            bodies: vec![],
+            target: None,
+        })
+    }
+
+    /// Decodes an action record, returning `None` if it would not be written back the same way
+    /// or if its bodies would be nested too deeply.
+    fn decode_action(
+        &mut self,
+        position: usize,
+        record_end: usize,
+        end: usize,
+        depth: usize,
+        host: Option<usize>,
+    ) -> Result<Option<Item>> {
+        use num::FromPrimitive;
+        let data = self.data;
+        let opcode = data[position];
+        let header_size = if opcode >= 0x80 { 3 } else { 1 };
+        let record = &data[position..record_end];
+        let mut reader = Reader::new(&data[position + header_size..record_end], self.version);
+        let mut item = Item {
+            position,
+            record_size: record.len(),
+            size: record.len(),
+            action: Action::End,
+            bodies: vec![],
+            target: None,
+        };
+        let op = match OpCode::from_u8(opcode) {
+            Some(op) => op,
+            None => return Ok(None),
+        };
+        let mut header = vec![];
+        let body_lengths: Vec<usize> = match op {
+            OpCode::DefineFunction | OpCode::DefineFunction2 | OpCode::With => {
+                item.action = match op {
+                    OpCode::DefineFunction => reader.read_define_function()?,
+                    OpCode::DefineFunction2 => reader.read_define_function_2()?,
+                    _ => Action::With { actions: vec![] },
+                };
+                let code_length = usize::from(reader.read_u16()?);
+                Writer::new(&mut header, self.version)
+                    .write_action_header_for_body(&item.action, code_length)?;
+                item.size += code_length;
+                vec![code_length]
+            }
+            OpCode::If | OpCode::Jump => {
+                let offset = reader.read_i16()?;
+                item.target = Some(record_end as i64 + i64::from(offset));
+                item.action = if op == OpCode::If {
+                    Action::If { target: 0 }
+                } else {
+                    Action::Jump { target: 0 }
+                };
+                header.extend_from_slice(record);
+                vec![]
+            }
+            OpCode::Push => {
+                let mut values = vec![];
+                while !reader.inner.is_empty() {
+                    values.push(reader.read_push_value()?);
+                }
+                item.action = Action::Push(values);
+                Writer::new(&mut header, self.version).write_action(&item.action)?;
+                vec![]
+            }
+            OpCode::Try => {
+                let (try_block, lengths) = reader.read_try_header()?;
+                if lengths.iter().sum::<usize>() != reader.inner.len() {
+                    return Ok(None);
+                }
+                Writer::new(&mut header, self.version).write_try_header(&try_block, lengths)?;
+                item.action = Action::Try(try_block);
+                lengths.to_vec()
+            }
+            _ => {
+                item.action = reader.read_action_data(op)?;
+                Writer::new(&mut header, self.version).write_action(&item.action)?;
+                vec![]
+            }
+        };
+        // The header must be the whole record, apart from the bodies of a `Try` action.
+        let header_end = record.len() - reader.inner.len();
+        if op != OpCode::Try && !reader.inner.is_empty() || header[..] != record[..header_end] {
+            return Ok(None);
+        }
+
+        // The bodies follow the header of a `Try` action, and the records of the others.
+        let mut body_start = position + header.len();
+        if body_start + body_lengths.iter().sum::<usize>() > end
+            || !body_lengths.is_empty() && depth >= MAX_NESTING_DEPTH
+        {
+            return Ok(None);
+        }
+        for length in body_lengths {
+            let block = self.decode_block(body_start, body_start + length, depth + 1, host)?;
+            item.bodies.push(block);
+            body_start += length;
+        }
+        Ok(Some(item))
+    }
+
+    /// Finds the location of a branch target, preferring the block of the branch, and decodes
+    /// the hidden actions at targets inside other actions.
+    fn resolve(&mut self, target: i64, block: usize) {
+        let block = self.blocks[block].host.unwrap_or(block);
+        if self.locations.contains_key(&(target, block)) {
+            return;
+        }
+        let location = self.locate(target, block);
+        self.locations.insert((target, block), location);
+        if let Location::Inside { block, .. } = location {
+            let position = target as usize;
+            if !self.hidden.contains_key(&position) {
+                let (hidden, rejoin) = self.decode_hidden(position, block);
+                self.hidden.insert(position, (hidden, rejoin));
+                if let Some(rejoin) = rejoin {
+                    self.resolve(rejoin as i64, block);
+                }
+            }
+        }
+    }
+
+    fn locate(&self, target: i64, block: usize) -> Location {
+        let main = &self.blocks[0];
+        if target < main.start as i64 {
+            return Location::Before(target - main.start as i64);
+        }
+        if target > main.end as i64 {
+            return Location::After(target - main.end as i64);
+        }
+        let position = target as usize;
+        if let Some(index) = self.boundary_index(block, position) {
+            return Location::Boundary { block, index };
+        }
+
+        // Prefer the start of an action to the end of a body, and then the deepest block.
+        let mut best_boundary: Option<((bool, usize), Location)> = None;
+        for (id, block) in self.blocks.iter().enumerate() {
+            if block.host.is_some() {
+                continue;
+            }
+            if let Some(index) = self.boundary_index(id, position) {
+                let rank = (index < block.items.len(), block.depth);
+                let is_better = match best_boundary {
+                    Some((best_rank, _)) => rank > best_rank,
+                    None => true,
+                };
+                if is_better {
+                    best_boundary = Some((rank, Location::Boundary { block: id, index }));
+                }
+            }
+        }
+        if let Some((_, location)) = best_boundary {
+            return location;
+        }
+
+        let mut best: Option<(usize, Location)> = None;
+        for (id, block) in self.blocks.iter().enumerate() {
+            if block.host.is_some() || best.is_some_and(|(depth, _)| depth >= block.depth) {
+                continue;
+            }
+            let index = block.items.iter().position(|item| {
+                item.position < position && position < item.position + item.record_size
+            });
+            if let Some(index) = index {
+                let offset = position - block.items[index].position;
+                best = Some((
+                    block.depth,
+                    Location::Inside {
+                        block: id,
+                        index,
+                        offset,
+                    },
+                ));
+            }
+        }
+        match best {
+            Some((_, location)) => location,
+            // Every position in the main block is in an action or a body.
+            None => Location::After(target - main.end as i64),
+        }
+    }
+
+    /// Returns the index of the action at `position` in a block, or the number of actions if it
+    /// is the end of the block.
+    fn boundary_index(&self, block: usize, position: usize) -> Option<usize> {
+        let block = &self.blocks[block];
+        if position == block.end {
+            return Some(block.items.len());
+        }
+        block
+            .items
+            .binary_search_by_key(&position, |item| item.position)
+            .ok()
+    }
+
+    /// Decodes the actions hidden in the encoding of an action of `host`, from `position` up to
+    /// the position at which they rejoin the actions of `host`. Decoding stops early at an
+    /// action that cannot be decoded.
+    fn decode_hidden(&mut self, position: usize, host: usize) -> (usize, Option<usize>) {
+        let (end, depth) = (self.blocks[host].end, self.blocks[host].depth + 1);
+        let id = self.blocks.len();
+        self.blocks.push(Block {
+            start: position,
+            end,
+            items: vec![],
+            depth,
+            host: Some(host),
+        });
+        let mut items = vec![];
+        let mut current = position;
+        let mut rejoin = None;
+        while current < end {
+            if current > position && self.boundary_index(host, current).is_some() {
+                rejoin = Some(current);
+                break;
+            }
+            match self.decode_item(current, end, depth, Some(host)) {
+                Ok(item) => {
+                    current += item.size;
+                    items.push(item);
+                }
+                Err(_) => break,
+            }
+        }
+        if current == end {
+            rejoin = Some(end);
+        }
+        let block = &mut self.blocks[id];
+        block.items = items;
+        block.end = current;
+        (id, rejoin)
+    }
+
+    fn location_position(&self, location: Location) -> i64 {
+        let main = &self.blocks[0];
+        match location {
+            Location::Before(offset) => main.start as i64 + offset,
+            Location::Boundary { block, index } => {
+                let block = &self.blocks[block];
+                block
+                    .items
+                    .get(index)
+                    .map_or(block.end, |item| item.position) as i64
+            }
+            Location::Inside {
+                block,
+                index,
+                offset,
+            } => (self.blocks[block].items[index].position + offset) as i64,
+            Location::After(offset) => main.end as i64 + offset,
+        }
+    }
+
+    fn label(&self, labels: &Labels, target: i64, block: usize) -> Label {
+        let block = self.blocks[block].host.unwrap_or(block);
+        self.locations
+            .get(&(target, block))
+            .and_then(|location| labels.by_location.get(location))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Builds the actions of a block. The recursion is bounded, as blocks are nested at most
+    /// `MAX_NESTING_DEPTH` deep, plus one level for the blocks of hidden actions.
+    fn build(&self, id: usize, labels: &Labels) -> ActionList {
+        let block = &self.blocks[id];
+        let mut actions = Vec::with_capacity(block.items.len());
+        for index in 0..=block.items.len() {
+            for &(location, label) in labels.by_index.get(&(id, index)).into_iter().flatten() {
+                let action = match location {
+                    Location::Boundary { .. } => Action::Label(label),
+                    Location::Before(offset) | Location::After(offset) => {
+                        Action::OffsetLabel(OffsetLabel {
+                            label,
+                            offset: offset as i32,
+                            actions: vec![],
+                        })
+                    }
+                    Location::Inside { offset, .. } => {
+                        let position = block.items[index].position + offset;
+                        let (hidden, rejoin) = self.hidden[&position];
+                        let mut hidden_actions = self.build(hidden, labels);
+                        if let Some(rejoin) = rejoin {
+                            hidden_actions.push(Action::Jump {
+                                target: self.label(labels, rejoin as i64, id),
+                            });
+                        }
+                        Action::OffsetLabel(OffsetLabel {
+                            label,
+                            offset: offset as i32,
+                            actions: hidden_actions,
+                        })
+                    }
+                };
+                actions.push(action);
+            }
+            if let Some(item) = block.items.get(index) {
+                actions.push(self.build_action(item, id, labels));
+            }
+        }
+        actions
+    }
+
+    fn build_action(&self, item: &Item, block: usize, labels: &Labels) -> Action {
+        let mut bodies = item.bodies.iter().map(|&body| self.build(body, labels));
+        let mut action = item.action.clone();
+        match action {
+            Action::DefineFunction {
+                ref mut actions, ..
+            }
+            | Action::DefineFunction2(Function {
+                ref mut actions, ..
+            })
+            | Action::With { ref mut actions } => *actions = bodies.next().unwrap_or_default(),
+            Action::If { ref mut target } | Action::Jump { ref mut target } => {
+                *target = self.label(labels, item.target.unwrap_or(0), block);
+            }
+            Action::Try(ref mut try_block) => {
+                try_block.try = bodies.next().unwrap_or_default();
+                let catch = bodies.next().unwrap_or_default();
+                if let Some((_, ref mut actions)) = try_block.catch {
+                    *actions = catch;
+                }
+                let finally = bodies.next().unwrap_or_default();
+                if let Some(ref mut actions) = try_block.finally {
+                    *actions = finally;
+                }
+            }
+            _ => (),
+        }
+        action
     }
 }
 
+fn truncated_action() -> Error {
+    Error::new(
+        ErrorKind::UnexpectedEof,
+        "Action extends past the end of the code",
+    )
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use avm1::write::Writer;
     use test_data;
 
+    fn read(bytes: &[u8]) -> ActionList {
+        Reader::new(bytes, 8).read_action_list().unwrap()
+    }
+
+    fn write(actions: &ActionList) -> Vec<u8> {
+        let mut bytes = vec![];
+        Writer::new(&mut bytes, 8)
+            .write_action_list(actions)
+            .unwrap();
+        bytes
+    }
+
     #[test]
     fn read_action() {
-        for (swf_version, expected_action, action_bytes) in test_data::avm1_tests() {
+        for (swf_version, expected_action, mut action_bytes) in test_data::avm1_tests() {
+            action_bytes.push(0);
             let mut reader = Reader::new(&action_bytes[..], swf_version);
-            let parsed_action = reader.read_action().unwrap().unwrap();
-            if parsed_action.0 != expected_action {
+            let parsed_actions = reader.read_action_list().unwrap();
+            if parsed_actions != vec![expected_action.clone()] {
                 // Failed, result doesn't match.
                 panic!(
                     "Incorrectly parsed action.\nRead:\n{:?}\n\nExpected:\n{:?}",
-                    parsed_action, expected_action
+                    parsed_actions, expected_action
                 );
             }
         }
     }
+
+    #[test]
+    fn read_action_list() {
+        for (swf_version, expected_actions, action_bytes) in test_data::avm1_list_tests() {
+            let mut reader = Reader::new(&action_bytes[..], swf_version);
+            let parsed_actions = reader.read_action_list().unwrap();
+            if parsed_actions != expected_actions {
+                panic!(
+                    "Incorrectly parsed actions.\nRead:\n{:?}\n\nExpected:\n{:?}",
+                    parsed_actions, expected_actions
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn read_single_actions() {
+        // if; stop; function f() { play }; end
+        let bytes = [
+            0x9D, 2, 0, 1, 0, 0x07, 0x9B, 6, 0, b'f', 0, 0, 0, 1, 0, 0x06, 0,
+        ];
+        let mut reader = Reader::new(&bytes[..], 8);
+        assert_eq!(
+            reader.read_action().unwrap(),
+            Some((Action::If { target: 0 }, 5))
+        );
+        assert_eq!(reader.read_action().unwrap(), Some((Action::Stop, 1)));
+        assert_eq!(
+            reader.read_action().unwrap(),
+            Some((
+                Action::DefineFunction {
+                    name: "f".to_string(),
+                    params: vec![],
+                    actions: vec![Action::Play],
+                },
+                10
+            ))
+        );
+        assert_eq!(reader.read_action().unwrap(), None);
+    }
+
+    #[test]
+    fn read_branches() {
+        // push true; if L0; stop; L0: jump L0; end
+        let bytes = [
+            0x96, 2, 0, 5, 1, 0x9D, 2, 0, 1, 0, 0x07, 0x99, 2, 0, 0xFB, 0xFF, 0,
+        ];
+        let actions = read(&bytes);
+        assert_eq!(
+            actions,
+            vec![
+                Action::Push(vec![Value::Bool(true)]),
+                Action::If { target: 0 },
+                Action::Stop,
+                Action::Label(0),
+                Action::Jump { target: 0 },
+            ]
+        );
+        assert_eq!(write(&actions), bytes);
+    }
+
+    #[test]
+    fn read_branch_into_action() {
+        // jump into the data of a push, which hides a stop action.
+        let bytes = [0x99, 2, 0, 4, 0, 0x96, 3, 0, 0, 0x07, 0, 0x06, 0];
+        let actions = read(&bytes);
+        assert_eq!(
+            actions,
+            vec![
+                Action::Jump { target: 0 },
+                Action::OffsetLabel(OffsetLabel {
+                    label: 0,
+                    offset: 4,
+                    actions: vec![Action::Stop, Action::End, Action::Jump { target: 1 }],
+                }),
+                Action::Push(vec![Value::Str("\u{7}".to_string())]),
+                Action::Label(1),
+                Action::Play,
+            ]
+        );
+        assert_eq!(write(&actions), bytes);
+    }
+
+    #[test]
+    fn read_branch_out_of_range() {
+        let bytes = [0x99, 2, 0, 0xF0, 0xFF, 0x9D, 2, 0, 0x10, 0, 0];
+        let actions = read(&bytes);
+        assert_eq!(
+            actions,
+            vec![
+                Action::OffsetLabel(OffsetLabel {
+                    label: 0,
+                    offset: -11,
+                    actions: vec![],
+                }),
+                Action::Jump { target: 0 },
+                Action::If { target: 1 },
+                Action::OffsetLabel(OffsetLabel {
+                    label: 1,
+                    offset: 16,
+                    actions: vec![],
+                }),
+            ]
+        );
+        assert_eq!(write(&actions), bytes);
+    }
+
+    #[test]
+    fn read_branch_into_function() {
+        // jump to the second action of a function's body.
+        let bytes = [
+            0x99, 2, 0, 10, 0, 0x9B, 6, 0, b'f', 0, 0, 0, 2, 0, 0x07, 0x06, 0,
+        ];
+        let actions = read(&bytes);
+        assert_eq!(
+            actions,
+            vec![
+                Action::Jump { target: 0 },
+                Action::DefineFunction {
+                    name: "f".to_string(),
+                    params: vec![],
+                    actions: vec![Action::Stop, Action::Label(0), Action::Play],
+                },
+            ]
+        );
+        assert_eq!(write(&actions), bytes);
+    }
+
+    #[test]
+    fn read_branch_to_end_of_body() {
+        // A branch in one function to the end of the next function's body, which is also the
+        // start of the next action of the code block.
+        let bytes = [
+            0x9B, 6, 0, b'g', 0, 0, 0, 5, 0, 0x99, 2, 0, 10, 0, 0x9B, 6, 0, b'f', 0, 0, 0, 1, 0,
+            0x07, 0x06, 0,
+        ];
+        let actions = read(&bytes);
+        assert_eq!(
+            actions,
+            vec![
+                Action::DefineFunction {
+                    name: "g".to_string(),
+                    params: vec![],
+                    actions: vec![Action::Jump { target: 0 }],
+                },
+                Action::DefineFunction {
+                    name: "f".to_string(),
+                    params: vec![],
+                    actions: vec![Action::Stop],
+                },
+                Action::Label(0),
+                Action::Play,
+            ]
+        );
+        assert_eq!(write(&actions), bytes);
+    }
+
+    #[test]
+    fn read_malformed_actions() {
+        // A push with an invalid value type, and code after the end.
+        let bytes = [0x96, 2, 0, 0x20, 1, 0, 0x07, 0];
+        let actions = read(&bytes);
+        assert_eq!(
+            actions,
+            vec![
+                Action::Unknown {
+                    opcode: 0x96,
+                    data: vec![0x20, 1],
+                },
+                Action::End,
+                Action::Stop,
+            ]
+        );
+        assert_eq!(write(&actions), bytes);
+
+        let mut reader = Reader::new(&[0x96, 5, 0, 1][..], 8);
+        assert!(reader.read_action_list().is_err());
+    }
+
+    #[test]
+    fn read_deeply_nested_actions() {
+        // Thousands of nested `With` actions, around a `Stop` action.
+        let num_withs = 5000;
+        let mut bytes = vec![];
+        for i in 0..num_withs {
+            let length = 5 * (num_withs - 1 - i) as u16 + 1;
+            bytes.extend_from_slice(&[0x94, 2, 0, length as u8, (length >> 8) as u8]);
+        }
+        bytes.extend_from_slice(&[0x07, 0]);
+
+        let actions = read(&bytes);
+        let mut body = &actions;
+        for _ in 0..MAX_NESTING_DEPTH {
+            body = match body[..] {
+                [Action::With { ref actions }] => actions,
+                _ => panic!("Expected a With action: {:?}", body),
+            };
+        }
+        assert_eq!(body.len(), num_withs - MAX_NESTING_DEPTH + 1);
+        assert_eq!(
+            body[0],
+            Action::Unknown {
+                opcode: 0x94,
+                data: bytes[5 * MAX_NESTING_DEPTH + 3..5 * MAX_NESTING_DEPTH + 5].to_vec(),
+            }
+        );
+        assert_eq!(body.last(), Some(&Action::Stop));
+        assert_eq!(write(&actions), bytes);
+    }
+
+    #[test]
+    fn read_random_code() {
+        // Reading arbitrary code never panics, and writes the same code back.
+        let mut seed: u32 = 1;
+        let opcodes = [
+            0x00, 0x07, 0x17, 0x8E, 0x94, 0x96, 0x99, 0x9B, 0x9D, 0x8F, 0x88, 0x20,
+        ];
+        for _ in 0..2000 {
+            let mut bytes = vec![];
+            for _ in 0..(seed % 40) {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let byte = (seed >> 16) as u8;
+                bytes.push(if byte < 0x80 {
+                    opcodes[usize::from(byte) % opcodes.len()]
+                } else {
+                    byte % 24
+                });
+            }
+            bytes.push(0);
+            if let Ok(actions) = Reader::new(&bytes[..], 8).read_action_list() {
+                let mut written = vec![];
+                Writer::new(&mut written, 8)
+                    .write_action_list(&actions)
+                    .unwrap();
+                // Code that does not end with an `End` action gains one.
+                if written.len() == bytes.len() + 1 {
+                    written.pop();
+                }
+                assert_eq!(written, bytes);
+            }
+        }
+    }
 }
@@ -29,6 +29,10 @@ pub enum Action {
     Delete,
     Delete2,
     Divide,
+    /// Ends the execution of a code block. This is not needed at the end of an `ActionList`, as
+    /// `Writer::write_action_list` ends the list with it; it only appears in lists read from
+    /// code that has more actions after an end.
+    End,
     EndDrag,
     Enumerate,
     Enumerate2,
@@ -56,8 +60,7 @@ pub enum Action {
     GotoLabel(String),
     Greater,
     If {
-        offset: i16,
-        jump_to: i16,
+        target: Label,
     },
     ImplementsOp,
     Increment,
@@ -65,9 +68,11 @@ pub enum Action {
     InitObject,
     InstanceOf,
     Jump {
-        offset: i16,
-        jump_to: i16,
+        target: Label,
     },
+    /// Marks the position of a branch target. Labels are pseudo-actions: they are not encoded,
+    /// but set the offsets of the branches that target them when the list is written.
+    Label(Label),
     Less,
     Less2,
     MBAsciiToChar,
@@ -80,6 +85,9 @@ pub enum Action {
     NewObject,
     NextFrame,
     Not,
+    /// Marks a branch target that is not at the start of an action. This is a pseudo-action,
+    /// like `Action::Label`.
+    OffsetLabel(OffsetLabel),
     Or,
     Play,
     Pop,
@@ -134,6 +142,28 @@ pub enum Action {
 
 pub type ActionList = Vec<Action>;
 
+/// Identifies the target of a branch.
+///
+/// Labels are unique within a code block, including the bodies of the `DefineFunction`, `With`
+/// and `Try` actions it contains, so a branch may target a label in another body.
+pub type Label = u32;
+
+/// A branch target in the middle of an action, or outside the code block.
+///
+/// Obfuscated code branches into the data of an action, such as the values of an
+/// `Action::Push`, to run actions that are hidden in its encoding.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OffsetLabel {
+    pub label: Label,
+    /// The position of the label, in bytes from the position of this pseudo-action.
+    pub offset: i32,
+    /// The actions hidden in the encoding of the following action that are run from the
+    /// label, ending with an `Action::Jump` to the action at which they rejoin the list. These
+    /// actions are not written, as they are part of the following action's encoding; they are
+    /// empty for labels outside the code block.
+    pub actions: ActionList,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Value {
     Undefined,
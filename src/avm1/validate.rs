@@ -0,0 +1,812 @@
+//! Validation of AVM1 code.
+//!
+//! `validate` follows the control-flow graph of a list of actions and of its nested bodies,
+//! tracking the depth of the stack. It reports the actions that pop more values than the stack
+//! holds, the labels that are reached with different depths, branches that do not land on an
+//! action of their list, registers that a function does not have, and actions that are newer
+//! than the SWF version.
+//!
+//! The depth is not known after the actions that push or pop a number of values that is not
+//! known statically: `Enumerate`, `WaitForFrame`, unknown actions, and actions such as
+//! `CallFunction` whose count of arguments is not a pushed literal. Code after them is not
+//! checked until the depth is known again.
+use avm1::cfg::{ControlFlowGraph, Exit};
+use avm1::types::*;
+use std::collections::HashSet;
+use std::mem;
+
+/// A problem found in AVM1 code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// The action pops more values than are on the stack.
+    StackUnderflow,
+    /// The paths that reach the label have stacks of different depths.
+    InconsistentDepth {
+        label: Label,
+        depths: (usize, usize),
+    },
+    /// The action branches into the middle of an action, to run actions hidden in its
+    /// encoding.
+    BranchIntoAction { label: Label },
+    /// The action branches to a label that is not in its list, such as a label in another
+    /// body.
+    BranchOutOfBody { label: Label },
+    /// The action uses a register that its function does not have.
+    InvalidRegister { register: u8, num_registers: u8 },
+    /// The action is not supported by the version of the SWF.
+    ActionTooNew { min_version: u8 },
+}
+
+/// A problem, and the action where it is found.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Issue {
+    /// The index of the action in its list, after the indices of the actions whose bodies
+    /// contain the list. The bodies of a `Try` action are numbered 0 for `try`, 1 for `catch`
+    /// and 2 for `finally`, before the index in the body.
+    pub path: Vec<usize>,
+    pub problem: Problem,
+}
+
+/// Returns the problems found in a list of actions and its nested bodies, in the order of
+/// their actions.
+pub fn validate(actions: &[Action], swf_version: u8) -> Vec<Issue> {
+    let mut validator = Validator {
+        swf_version,
+        path: vec![],
+        issues: vec![],
+    };
+    // Code outside functions has 4 registers.
+    validator.check_actions(actions, 4);
+    validator.track_stack(actions, Some(vec![]));
+    let mut issues = validator.issues;
+    issues.sort_by(|a, b| a.path.cmp(&b.path));
+    issues
+}
+
+/// Returns the number of values that an action pops from the stack and the number that it
+/// pushes, without the actions of its nested bodies. Returns `None` if the numbers depend on
+/// the values on the stack, as for `CallFunction`, or if the action is unknown.
+pub fn stack_effect(action: &Action) -> Option<(usize, usize)> {
+    let effect = match *action {
+        Action::Push(ref values) => (0, values.len()),
+        Action::DefineFunction { ref name, .. }
+        | Action::DefineFunction2(Function { ref name, .. }) => {
+            // Functions without names are pushed.
+            (0, if name.is_empty() { 1 } else { 0 })
+        }
+        Action::ConstantPool(_)
+        | Action::End
+        | Action::EndDrag
+        | Action::GetUrl { .. }
+        | Action::GotoFrame(_)
+        | Action::GotoLabel(_)
+        | Action::Jump { .. }
+        | Action::Label(_)
+        | Action::NextFrame
+        | Action::OffsetLabel(_)
+        | Action::Play
+        | Action::PreviousFrame
+        | Action::SetTarget(_)
+        | Action::Stop
+        | Action::StopSounds
+        | Action::ToggleQuality
+        | Action::Try(_)
+        | Action::WaitForFrame { .. } => (0, 0),
+        Action::GetTime => (0, 1),
+        Action::Call
+        | Action::DefineLocal2
+        | Action::GotoFrame2 { .. }
+        | Action::If { .. }
+        | Action::Pop
+        | Action::RemoveSprite
+        | Action::Return
+        | Action::SetTarget2
+        | Action::Throw
+        | Action::Trace
+        | Action::WaitForFrame2 { .. }
+        | Action::With { .. } => (1, 0),
+        Action::AsciiToChar
+        | Action::CharToAscii
+        | Action::Decrement
+        | Action::Delete2
+        | Action::GetVariable
+        | Action::Increment
+        | Action::MBAsciiToChar
+        | Action::MBCharToAscii
+        | Action::MBStringLength
+        | Action::Not
+        | Action::RandomNumber
+        | Action::StoreRegister(_)
+        | Action::StringLength
+        | Action::TargetPath
+        | Action::ToInteger
+        | Action::ToNumber
+        | Action::ToString
+        | Action::TypeOf => (1, 1),
+        Action::PushDuplicate => (1, 2),
+        Action::DefineLocal | Action::Extends | Action::GetUrl2 { .. } | Action::SetVariable => {
+            (2, 0)
+        }
+        Action::Add
+        | Action::Add2
+        | Action::And
+        | Action::BitAnd
+        | Action::BitLShift
+        | Action::BitOr
+        | Action::BitRShift
+        | Action::BitURShift
+        | Action::BitXor
+        | Action::CastOp
+        | Action::Delete
+        | Action::Divide
+        | Action::Equals
+        | Action::Equals2
+        | Action::GetMember
+        | Action::GetProperty
+        | Action::Greater
+        | Action::InstanceOf
+        | Action::Less
+        | Action::Less2
+        | Action::Modulo
+        | Action::Multiply
+        | Action::Or
+        | Action::StrictEquals
+        | Action::StringAdd
+        | Action::StringEquals
+        | Action::StringGreater
+        | Action::StringLess
+        | Action::Subtract => (2, 1),
+        Action::StackSwap => (2, 2),
+        Action::CloneSprite | Action::SetMember | Action::SetProperty => (3, 0),
+        Action::MBStringExtract | Action::StringExtract => (3, 1),
+        Action::CallFunction
+        | Action::CallMethod
+        | Action::Enumerate
+        | Action::Enumerate2
+        | Action::ImplementsOp
+        | Action::InitArray
+        | Action::InitObject
+        | Action::NewMethod
+        | Action::NewObject
+        | Action::StartDrag
+        | Action::Unknown { .. } => return None,
+    };
+    Some(effect)
+}
+
+/// Returns the first SWF version that supports an action.
+pub fn min_version(action: &Action) -> u8 {
+    match *action {
+        Action::End | Action::Label(_) | Action::OffsetLabel(_) | Action::Unknown { .. } => 1,
+        Action::GetUrl { .. }
+        | Action::GotoFrame(_)
+        | Action::GotoLabel(_)
+        | Action::NextFrame
+        | Action::Play
+        | Action::PreviousFrame
+        | Action::SetTarget(_)
+        | Action::Stop
+        | Action::StopSounds
+        | Action::ToggleQuality
+        | Action::WaitForFrame { .. } => 3,
+        // SWF 4 only pushes strings and floats.
+        Action::Push(ref values) => {
+            let is_swf_4 = values
+                .iter()
+                .all(|value| matches!(*value, Value::Str(_) | Value::Float(_)));
+            if is_swf_4 {
+                4
+            } else {
+                5
+            }
+        }
+        Action::Add
+        | Action::And
+        | Action::AsciiToChar
+        | Action::Call
+        | Action::CharToAscii
+        | Action::CloneSprite
+        | Action::Divide
+        | Action::EndDrag
+        | Action::Equals
+        | Action::GetProperty
+        | Action::GetTime
+        | Action::GetUrl2 { .. }
+        | Action::GetVariable
+        | Action::GotoFrame2 { .. }
+        | Action::If { .. }
+        | Action::Jump { .. }
+        | Action::Less
+        | Action::MBAsciiToChar
+        | Action::MBCharToAscii
+        | Action::MBStringExtract
+        | Action::MBStringLength
+        | Action::Multiply
+        | Action::Not
+        | Action::Or
+        | Action::Pop
+        | Action::RandomNumber
+        | Action::RemoveSprite
+        | Action::SetProperty
+        | Action::SetTarget2
+        | Action::SetVariable
+        | Action::StartDrag
+        | Action::StringAdd
+        | Action::StringEquals
+        | Action::StringExtract
+        | Action::StringLength
+        | Action::StringLess
+        | Action::Subtract
+        | Action::ToInteger
+        | Action::Trace
+        | Action::WaitForFrame2 { .. } => 4,
+        Action::Add2
+        | Action::BitAnd
+        | Action::BitLShift
+        | Action::BitOr
+        | Action::BitRShift
+        | Action::BitURShift
+        | Action::BitXor
+        | Action::CallFunction
+        | Action::CallMethod
+        | Action::ConstantPool(_)
+        | Action::Decrement
+        | Action::DefineFunction { .. }
+        | Action::DefineLocal
+        | Action::DefineLocal2
+        | Action::Delete
+        | Action::Delete2
+        | Action::Enumerate
+        | Action::Equals2
+        | Action::GetMember
+        | Action::Increment
+        | Action::InitArray
+        | Action::InitObject
+        | Action::Less2
+        | Action::Modulo
+        | Action::NewMethod
+        | Action::NewObject
+        | Action::PushDuplicate
+        | Action::Return
+        | Action::SetMember
+        | Action::StackSwap
+        | Action::StoreRegister(_)
+        | Action::TargetPath
+        | Action::ToNumber
+        | Action::ToString
+        | Action::TypeOf
+        | Action::With { .. } => 5,
+        Action::Enumerate2
+        | Action::Greater
+        | Action::InstanceOf
+        | Action::StrictEquals
+        | Action::StringGreater => 6,
+        Action::CastOp
+        | Action::DefineFunction2(_)
+        | Action::Extends
+        | Action::ImplementsOp
+        | Action::Throw
+        | Action::Try(_) => 7,
+    }
+}
+
+/// The values on the stack, or `None` if its depth is not known. Values are `Some` if they are
+/// numbers known statically, such as the counts of arguments.
+type Stack = Option<Vec<Option<f64>>>;
+
+struct Validator {
+    swf_version: u8,
+    /// The indices of the actions whose bodies are being validated.
+    path: Vec<usize>,
+    issues: Vec<Issue>,
+}
+
+impl Validator {
+    fn report(&mut self, index: usize, problem: Problem) {
+        let mut path = self.path.clone();
+        path.push(index);
+        let issue = Issue { path, problem };
+        // Blocks are tracked again when the stack at their start changes.
+        if !self.issues.contains(&issue) {
+            self.issues.push(issue);
+        }
+    }
+
+    /// Checks the versions, registers and branches of a list of actions and its nested bodies,
+    /// and tracks the stacks of the bodies of its functions.
+    fn check_actions(&mut self, actions: &[Action], num_registers: u8) {
+        let labels: HashSet<Label> = actions
+            .iter()
+            .filter_map(|action| match *action {
+                Action::Label(label) => Some(label),
+                _ => None,
+            })
+            .collect();
+        let offset_labels: HashSet<Label> = actions
+            .iter()
+            .filter_map(|action| match *action {
+                Action::OffsetLabel(ref offset_label) => Some(offset_label.label),
+                _ => None,
+            })
+            .collect();
+        for (i, action) in actions.iter().enumerate() {
+            let min_version = min_version(action);
+            if min_version > self.swf_version {
+                self.report(i, Problem::ActionTooNew { min_version });
+            }
+            match *action {
+                Action::If { target } | Action::Jump { target } if !labels.contains(&target) => {
+                    let problem = if offset_labels.contains(&target) {
+                        Problem::BranchIntoAction { label: target }
+                    } else {
+                        Problem::BranchOutOfBody { label: target }
+                    };
+                    self.report(i, problem);
+                }
+                Action::StoreRegister(register) => self.check_register(i, register, num_registers),
+                Action::Push(ref values) => {
+                    for value in values {
+                        if let Value::Register(register) = *value {
+                            self.check_register(i, register, num_registers);
+                        }
+                    }
+                }
+                Action::DefineFunction { ref actions, .. } => {
+                    self.path.push(i);
+                    self.check_actions(actions, 4);
+                    self.track_stack(actions, Some(vec![]));
+                    self.path.pop();
+                }
+                Action::DefineFunction2(ref function) => {
+                    // Preloaded values are stored in registers from 1.
+                    let num_preloaded = [
+                        function.preload_this,
+                        function.preload_arguments,
+                        function.preload_super,
+                        function.preload_root,
+                        function.preload_parent,
+                        function.preload_global,
+                    ]
+                    .iter()
+                    .filter(|&&is_preloaded| is_preloaded)
+                    .count();
+                    if num_preloaded > 0 {
+                        self.check_register(i, num_preloaded as u8, function.num_registers);
+                    }
+                    for param in &function.params {
+                        if let Some(register) = param.register_index {
+                            self.check_register(i, register, function.num_registers);
+                        }
+                    }
+                    self.path.push(i);
+                    self.check_actions(&function.actions, function.num_registers);
+                    self.track_stack(&function.actions, Some(vec![]));
+                    self.path.pop();
+                }
+                Action::With { ref actions } => {
+                    self.path.push(i);
+                    self.check_actions(actions, num_registers);
+                    self.path.pop();
+                }
+                Action::Try(ref try_block) => {
+                    if let Some((CatchVar::Register(register), _)) = try_block.catch {
+                        self.check_register(i, register, num_registers);
+                    }
+                    for (body, actions) in try_bodies(try_block) {
+                        self.path.push(i);
+                        self.path.push(body);
+                        self.check_actions(actions, num_registers);
+                        self.path.pop();
+                        self.path.pop();
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn check_register(&mut self, index: usize, register: u8, num_registers: u8) {
+        if register >= num_registers {
+            self.report(
+                index,
+                Problem::InvalidRegister {
+                    register,
+                    num_registers,
+                },
+            );
+        }
+    }
+
+    /// Tracks the stack through the control-flow graph of a list of actions, and returns the
+    /// stack after its last action, if it is known.
+    fn track_stack(&mut self, actions: &[Action], stack: Stack) -> Stack {
+        let cfg = ControlFlowGraph::new(actions);
+        let mut entries: Vec<Option<Stack>> = vec![None; cfg.blocks.len()];
+        entries[0] = Some(stack);
+        let mut queue = vec![0];
+        let mut inconsistent_blocks = HashSet::new();
+        let mut end_stack: Option<Stack> = None;
+        while let Some(block) = queue.pop() {
+            let basic_block = &cfg.blocks[block];
+            let start = index_in(actions, basic_block.actions);
+            let mut stack = entries[block].clone().unwrap_or(None);
+            for (i, action) in basic_block.actions.iter().enumerate() {
+                self.simulate(start + i, action, &mut stack);
+            }
+            match basic_block.exit {
+                Exit::Branch { .. } => {
+                    self.pop(start + basic_block.actions.len(), &mut stack, 1);
+                }
+                Exit::End => {
+                    let is_left = matches!(
+                        basic_block.actions.last(),
+                        Some(Action::Return) | Some(Action::Throw) | Some(Action::End)
+                    );
+                    if !is_left {
+                        match end_stack {
+                            Some(ref mut end_stack) => {
+                                let _ = merge(end_stack, &stack);
+                            }
+                            None => end_stack = Some(stack.clone()),
+                        }
+                    }
+                }
+                Exit::Goto(_) | Exit::Outside(_) => (),
+            }
+
+            for successor in basic_block.exit.successors() {
+                let is_changed = match entries[successor] {
+                    Some(ref mut entry) => match merge(entry, &stack) {
+                        Ok(is_changed) => is_changed,
+                        Err(depths) => {
+                            let labels = &cfg.blocks[successor].labels;
+                            // Blocks without labels are only joined at the end of the code,
+                            // where the depth does not matter.
+                            if !labels.is_empty() && inconsistent_blocks.insert(successor) {
+                                let index =
+                                    index_in(actions, cfg.blocks[successor].actions) - labels.len();
+                                let label = labels[0];
+                                self.report(index, Problem::InconsistentDepth { label, depths });
+                            }
+                            false
+                        }
+                    },
+                    None => {
+                        entries[successor] = Some(stack.clone());
+                        true
+                    }
+                };
+                if is_changed && !queue.contains(&successor) {
+                    queue.push(successor);
+                }
+            }
+        }
+        end_stack.unwrap_or(None)
+    }
+
+    /// Applies the stack effect of an action, and tracks the stacks of its `With` or `Try`
+    /// bodies, which share the stack of the list.
+    fn simulate(&mut self, index: usize, action: &Action, stack: &mut Stack) {
+        match *action {
+            Action::Push(ref values) => {
+                if let Some(ref mut stack) = *stack {
+                    stack.extend(values.iter().map(|value| match *value {
+                        Value::Bool(value) => Some(if value { 1.0 } else { 0.0 }),
+                        Value::Int(value) => Some(f64::from(value as i32)),
+                        Value::Float(value) => Some(f64::from(value)),
+                        Value::Double(value) => Some(value),
+                        _ => None,
+                    }));
+                }
+            }
+            Action::PushDuplicate => {
+                let value = self.pop(index, stack, 1)[0];
+                push(stack, &[value, value]);
+            }
+            Action::StackSwap => {
+                let values = self.pop(index, stack, 2);
+                push(stack, &values);
+            }
+            // Storing a register leaves the value on the stack.
+            Action::StoreRegister(_) => {
+                let values = self.pop(index, stack, 1);
+                push(stack, &values);
+            }
+            Action::CallFunction | Action::NewObject => {
+                self.pop(index, stack, 1);
+                self.pop_counted(index, stack, 1);
+                push(stack, &[None]);
+            }
+            Action::CallMethod | Action::NewMethod => {
+                self.pop(index, stack, 2);
+                self.pop_counted(index, stack, 1);
+                push(stack, &[None]);
+            }
+            Action::InitArray => {
+                self.pop_counted(index, stack, 1);
+                push(stack, &[None]);
+            }
+            Action::InitObject => {
+                self.pop_counted(index, stack, 2);
+                push(stack, &[None]);
+            }
+            Action::ImplementsOp => {
+                self.pop(index, stack, 1);
+                self.pop_counted(index, stack, 1);
+            }
+            Action::StartDrag => {
+                let values = self.pop(index, stack, 3);
+                // A constrained drag also pops its rectangle.
+                match values[2] {
+                    Some(value) if value != 0.0 => {
+                        self.pop(index, stack, 4);
+                    }
+                    Some(_) => (),
+                    None => *stack = None,
+                }
+            }
+            Action::With { ref actions } => {
+                self.pop(index, stack, 1);
+                self.path.push(index);
+                *stack = self.track_stack(actions, stack.take());
+                self.path.pop();
+            }
+            Action::Try(ref try_block) => {
+                self.path.push(index);
+                for (body, actions) in try_bodies(try_block) {
+                    self.path.push(body);
+                    // The stack is not known after a value is thrown.
+                    let body_stack = if body == 0 { stack.clone() } else { None };
+                    let end_stack = self.track_stack(actions, body_stack);
+                    if body == 0 {
+                        *stack = end_stack;
+                    }
+                    self.path.pop();
+                }
+                self.path.pop();
+                if try_block.catch.is_some() || try_block.finally.is_some() {
+                    *stack = None;
+                }
+            }
+            // The actions to skip are not known to be run.
+            Action::WaitForFrame { .. } | Action::WaitForFrame2 { .. } => {
+                if let Some((pops, _)) = stack_effect(action) {
+                    self.pop(index, stack, pops);
+                }
+                *stack = None;
+            }
+            _ => match stack_effect(action) {
+                Some((pops, pushes)) => {
+                    self.discard(index, stack, pops);
+                    push(stack, &vec![None; pushes]);
+                }
+                None => *stack = None,
+            },
+        }
+    }
+
+    /// Pops values from the stack, from the top. The stack is no longer known if it has too
+    /// few values.
+    fn pop(&mut self, index: usize, stack: &mut Stack, count: usize) -> Vec<Option<f64>> {
+        let values = match *stack {
+            Some(ref mut values) if values.len() >= count => values.split_off(values.len() - count),
+            _ => {
+                self.discard(index, stack, count);
+                return vec![None; count];
+            }
+        };
+        values.into_iter().rev().collect()
+    }
+
+    /// Pops values from the stack without returning them, for counts taken from the code.
+    fn discard(&mut self, index: usize, stack: &mut Stack, count: usize) {
+        let is_underflow = match *stack {
+            Some(ref mut values) if values.len() >= count => {
+                let len = values.len() - count;
+                values.truncate(len);
+                false
+            }
+            Some(_) => true,
+            None => false,
+        };
+        if is_underflow {
+            self.report(index, Problem::StackUnderflow);
+            *stack = None;
+        }
+    }
+
+    /// Pops a count, and that many groups of values.
+    fn pop_counted(&mut self, index: usize, stack: &mut Stack, group_size: usize) {
+        match self.pop(index, stack, 1)[0] {
+            Some(count) if count >= 0.0 && count.is_finite() => {
+                // Counts too large for the stack are underflows.
+                let count = (count.min(u32::MAX.into()) as usize).saturating_mul(group_size);
+                self.discard(index, stack, count);
+            }
+            _ => *stack = None,
+        }
+    }
+}
+
+/// Pushes values to the stack, from the bottom.
+fn push(stack: &mut Stack, values: &[Option<f64>]) {
+    if let Some(ref mut stack) = *stack {
+        stack.extend(values.iter().rev());
+    }
+}
+
+/// Merges a stack into the stack at the start of a block, forgetting the values that differ.
+/// Returns whether the stack at the start changed, or the two depths if they differ.
+fn merge(entry: &mut Stack, stack: &Stack) -> Result<bool, (usize, usize)> {
+    let entry_values = match *entry {
+        Some(ref mut entry_values) => entry_values,
+        None => return Ok(false),
+    };
+    let values = match *stack {
+        Some(ref values) => values,
+        None => {
+            *entry = None;
+            return Ok(true);
+        }
+    };
+    if entry_values.len() != values.len() {
+        return Err((entry_values.len(), values.len()));
+    }
+    let mut is_changed = false;
+    for (entry_value, value) in entry_values.iter_mut().zip(values) {
+        if entry_value.is_some() && entry_value != value {
+            *entry_value = None;
+            is_changed = true;
+        }
+    }
+    Ok(is_changed)
+}
+
+/// Returns the index of the first action of a slice of a list of actions. The index is not
+/// meaningful for the blocks that a control-flow graph adds without actions or labels.
+fn index_in(actions: &[Action], slice: &[Action]) -> usize {
+    (slice.as_ptr() as usize).wrapping_sub(actions.as_ptr() as usize) / mem::size_of::<Action>()
+}
+
+/// Returns the bodies of a `Try` action, numbered as in `Issue::path`.
+fn try_bodies(try_block: &TryBlock) -> Vec<(usize, &ActionList)> {
+    let mut bodies = vec![(0, &try_block.try)];
+    if let Some((_, ref catch)) = try_block.catch {
+        bodies.push((1, catch));
+    }
+    if let Some(ref finally) = try_block.finally {
+        bodies.push((2, finally));
+    }
+    bodies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avm1::assemble::assemble;
+
+    #[test]
+    fn validate_stack() {
+        let text = "
+            push 1, 2
+            if a
+            push 3
+        a:
+            pop
+            pop
+            pop
+            push 'x', 2, 1, 'f'
+            callfunction
+            pop
+            push 0, 'Object'
+            newobject
+            storeregister r:0
+            return
+        ";
+        let actions = assemble(text).unwrap();
+        let label = match actions[4] {
+            Action::Label(label) => label,
+            _ => panic!("Expected a label"),
+        };
+        assert_eq!(
+            validate(&actions, 8),
+            vec![
+                Issue {
+                    path: vec![4],
+                    problem: Problem::InconsistentDepth {
+                        label,
+                        depths: (1, 2),
+                    },
+                },
+                Issue {
+                    path: vec![6],
+                    problem: Problem::StackUnderflow,
+                },
+            ]
+        );
+        // Calls pop the number of arguments that is pushed before them.
+        let actions = assemble("push 'a', 1, 'f'\ncallfunction\npop\npop").unwrap();
+        assert_eq!(
+            validate(&actions, 8),
+            vec![Issue {
+                path: vec![4],
+                problem: Problem::StackUnderflow,
+            }]
+        );
+        let text = "push 'n'\ngetvariable\npush 'f'\ncallfunction\npop\npop";
+        let actions = assemble(text).unwrap();
+        assert_eq!(validate(&actions, 8), vec![]);
+
+        // Huge counts are underflows.
+        let underflow = vec![Issue {
+            path: vec![1],
+            problem: Problem::StackUnderflow,
+        }];
+        let actions = vec![
+            Action::Push(vec![Value::Int(0x7fff_ffff)]),
+            Action::InitArray,
+        ];
+        assert_eq!(validate(&actions, 8), underflow);
+        let actions = vec![Action::Push(vec![Value::Double(1e300)]), Action::InitObject];
+        assert_eq!(validate(&actions, 8), underflow);
+    }
+
+    #[test]
+    fn validate_registers_and_versions() {
+        let text = "
+            definefunction2 f(r:3='a') registers:2 preload_this {
+                push r:1
+                storeregister r:2
+                return
+            }
+            push r:5
+            jump hidden
+        hidden = 1
+            push 2.5
+            try {
+                push 'x'
+                throw
+            } catch r:1 {
+            }
+        ";
+        let actions = assemble(text).unwrap();
+        let issues: Vec<_> = validate(&actions, 6)
+            .into_iter()
+            .map(|issue| (issue.path, issue.problem))
+            .collect();
+        let label = match actions[4] {
+            Action::OffsetLabel(ref offset_label) => offset_label.label,
+            _ => panic!("Expected an offset label"),
+        };
+        assert_eq!(
+            issues,
+            vec![
+                (vec![1], Problem::ActionTooNew { min_version: 7 }),
+                (
+                    vec![1],
+                    Problem::InvalidRegister {
+                        register: 3,
+                        num_registers: 2,
+                    }
+                ),
+                (
+                    vec![1, 1],
+                    Problem::InvalidRegister {
+                        register: 2,
+                        num_registers: 2,
+                    }
+                ),
+                (
+                    vec![2],
+                    Problem::InvalidRegister {
+                        register: 5,
+                        num_registers: 4,
+                    }
+                ),
+                (vec![3], Problem::BranchIntoAction { label }),
+                (vec![6], Problem::ActionTooNew { min_version: 7 }),
+                (vec![6, 0, 1], Problem::ActionTooNew { min_version: 7 }),
+            ]
+        );
+    }
+}
@@ -0,0 +1,2350 @@
+//! A headless AVM1 virtual machine, for running snippets of code.
+//!
+//! `Vm` runs action lists with a value stack, registers, a scope chain, objects and arrays with
+//! prototypes, and functions. Values are coerced as Flash Player does for the SWF version of
+//! the code. The machine has no movie clips: the root timeline is a plain object, and the
+//! actions that control movie clips call a `Host`.
+//!
+//! ```
+//! use swf::avm1::assemble::assemble;
+//! use swf::avm1::vm::{NullHost, Value, Vm};
+//!
+//! let actions = assemble("push 'a', 'b'\nadd2\nreturn").unwrap();
+//! let mut host = NullHost;
+//! let mut vm = Vm::new(8, &mut host);
+//! assert_eq!(vm.run(&actions).unwrap(), Value::Str("ab".to_string()));
+//! ```
+use avm1::types::{self, Action, ActionList, CatchVar, Label, SendVarsMethod, TryBlock};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+use std::rc::Rc;
+
+/// A value of the virtual machine.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Undefined,
+    Null,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    Object(ObjectRef),
+}
+
+/// A reference to an object. References are equal if they are to the same object.
+#[derive(Clone)]
+pub struct ObjectRef(Rc<RefCell<Object>>);
+
+impl PartialEq for ObjectRef {
+    fn eq(&self, other: &ObjectRef) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for ObjectRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Objects usually refer to themselves through their prototypes.
+        write!(f, "ObjectRef({:p})", self.0)
+    }
+}
+
+/// An object, with its own properties and its prototype.
+pub struct Object {
+    properties: Vec<Property>,
+    prototype: Option<ObjectRef>,
+    kind: ObjectKind,
+}
+
+struct Property {
+    name: String,
+    value: Value,
+    /// Whether the property is listed by `for`..`in` loops.
+    is_enumerable: bool,
+}
+
+enum ObjectKind {
+    Object,
+    Array(Vec<Value>),
+    Function(Callable),
+    /// The `super` object of a method, which looks up methods from a prototype and calls them
+    /// with the `this` of the method.
+    Super {
+        this: Value,
+        prototype: Option<ObjectRef>,
+        constructor: Value,
+    },
+}
+
+#[derive(Clone)]
+enum Callable {
+    Script(Rc<ScriptFunction>),
+    Native(NativeFunction),
+}
+
+type NativeFunction = fn(&mut Vm, Value, Vec<Value>) -> Run<Value>;
+
+struct ScriptFunction {
+    params: Vec<(String, Option<u8>)>,
+    /// The flags of a function defined by `DefineFunction2`.
+    function2: Option<types::Function>,
+    actions: ActionList,
+    scope: Rc<Scope>,
+    constant_pool: Rc<Vec<String>>,
+}
+
+/// The objects whose properties are variables, from the innermost.
+struct Scope {
+    object: ObjectRef,
+    parent: Option<Rc<Scope>>,
+}
+
+/// The state of a running function, or of the code that is not in a function.
+struct Frame {
+    scope: Rc<Scope>,
+    /// The object of the local variables.
+    locals: ObjectRef,
+    this: Value,
+    stack: Vec<Value>,
+    registers: Vec<Value>,
+    constant_pool: Rc<Vec<String>>,
+}
+
+/// Why the actions of a list stopped running before their end.
+enum Abort {
+    /// A value thrown by a `Throw` action, which can be caught by a `Try` action.
+    Throw(Value),
+    Error(Error),
+}
+
+impl From<Error> for Abort {
+    fn from(error: Error) -> Abort {
+        Abort::Error(error)
+    }
+}
+
+type Run<T> = ::std::result::Result<T, Abort>;
+
+/// The player that runs the virtual machine, which controls movie clips and provides the
+/// time. Each method does nothing by default.
+pub trait Host {
+    /// Writes a message from a `trace` action.
+    fn trace(&mut self, _message: &str) {}
+
+    /// Opens a URL in a browser window.
+    fn get_url(&mut self, _url: &str, _target: &str, _method: SendVarsMethod) {}
+
+    /// Loads an SWF into a movie clip or a level.
+    fn load_movie(&mut self, _url: &str, _target: &str, _method: SendVarsMethod) {}
+
+    /// Loads variables from a URL into a movie clip or a level.
+    fn load_variables(&mut self, _url: &str, _target: &str, _method: SendVarsMethod) {}
+
+    /// Goes to a frame of the current timeline, starting from 0.
+    fn goto_frame(&mut self, _frame: u32) {}
+
+    /// Goes to the frame with a label in the current timeline.
+    fn goto_label(&mut self, _label: &str) {}
+
+    fn play(&mut self) {}
+
+    fn stop(&mut self) {}
+
+    fn next_frame(&mut self) {}
+
+    fn previous_frame(&mut self) {}
+
+    /// Returns whether a frame of the current timeline, starting from 0, has been loaded.
+    fn is_frame_loaded(&mut self, _frame: u32) -> bool {
+        true
+    }
+
+    /// Runs the actions of a frame of a timeline, given as a path such as `/clip:3`.
+    fn call_frame(&mut self, _frame: &str) {}
+
+    /// Sets the timeline that the actions control, given as a path, or the original one if
+    /// the path is empty.
+    fn set_target(&mut self, _target: &str) {}
+
+    /// Returns a property of a movie clip by its index, such as 0 for `_x`.
+    fn get_property(&mut self, _target: &str, _property: u8) -> Value {
+        Value::Undefined
+    }
+
+    /// Sets a property of a movie clip by its index.
+    fn set_property(&mut self, _target: &str, _property: u8, _value: Value) {}
+
+    /// Duplicates a movie clip with a new name, at a depth.
+    fn clone_sprite(&mut self, _source: &str, _target: &str, _depth: i32) {}
+
+    fn remove_sprite(&mut self, _target: &str) {}
+
+    /// Starts dragging a movie clip, within a rectangle from its left, top, right and bottom
+    /// coordinates.
+    fn start_drag(&mut self, _target: &str, _lock_center: bool, _bounds: Option<[f64; 4]>) {}
+
+    fn end_drag(&mut self) {}
+
+    fn stop_sounds(&mut self) {}
+
+    fn toggle_quality(&mut self) {}
+
+    /// Returns the time since the SWF started playing, in milliseconds.
+    fn get_time(&mut self) -> f64 {
+        0.0
+    }
+
+    /// Returns a random integer that is at least 0 and less than `max`.
+    fn random(&mut self, _max: u32) -> u32 {
+        0
+    }
+}
+
+/// A host that ignores all movie clip actions.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NullHost;
+
+impl Host for NullHost {}
+
+/// The maximum depth of nested function calls. Each call takes about 15 KB of the thread's
+/// stack in debug builds, so this stays well within the 2 MB of a spawned thread.
+const MAX_CALL_DEPTH: usize = 64;
+
+/// A virtual machine that runs AVM1 code.
+pub struct Vm<'a> {
+    host: &'a mut dyn Host,
+    swf_version: u8,
+    global: ObjectRef,
+    root: ObjectRef,
+    object_prototype: ObjectRef,
+    function_prototype: ObjectRef,
+    array_prototype: ObjectRef,
+    string_prototype: ObjectRef,
+    number_prototype: ObjectRef,
+    boolean_prototype: ObjectRef,
+    max_actions: usize,
+    num_actions: usize,
+    call_depth: usize,
+}
+
+impl<'a> Vm<'a> {
+    /// Creates a virtual machine for the code of an SWF version, with the global objects and
+    /// functions and an empty root timeline.
+    pub fn new(swf_version: u8, host: &'a mut dyn Host) -> Vm<'a> {
+        let object_prototype = new_object(None, ObjectKind::Object);
+        let prototype = || new_object(Some(object_prototype.clone()), ObjectKind::Object);
+        let mut vm = Vm {
+            host,
+            swf_version,
+            global: prototype(),
+            root: prototype(),
+            function_prototype: prototype(),
+            array_prototype: prototype(),
+            string_prototype: prototype(),
+            number_prototype: prototype(),
+            boolean_prototype: prototype(),
+            object_prototype: object_prototype.clone(),
+            max_actions: 1_000_000,
+            num_actions: 0,
+            call_depth: 0,
+        };
+        vm.define_globals();
+        vm
+    }
+
+    /// Sets the maximum number of actions that `run` and `call` run before failing, to stop
+    /// code that loops forever. The default is 1,000,000.
+    pub fn set_max_actions(&mut self, max_actions: usize) {
+        self.max_actions = max_actions;
+    }
+
+    /// Returns the `_global` object.
+    pub fn global(&self) -> ObjectRef {
+        self.global.clone()
+    }
+
+    /// Returns the object of the root timeline, which holds the variables of the code.
+    pub fn root(&self) -> ObjectRef {
+        self.root.clone()
+    }
+
+    /// Runs a list of actions on the root timeline, and returns the value that it returns, or
+    /// `undefined` if it does not return one.
+    ///
+    /// Values that are thrown and not caught are returned as errors.
+    pub fn run(&mut self, actions: &[Action]) -> Result<Value> {
+        self.num_actions = 0;
+        let mut frame = Frame {
+            scope: Rc::new(Scope {
+                object: self.root.clone(),
+                parent: None,
+            }),
+            locals: self.root.clone(),
+            this: Value::Object(self.root.clone()),
+            stack: vec![],
+            // Code outside functions has 4 registers.
+            registers: vec![Value::Undefined; 4],
+            constant_pool: Rc::new(vec![]),
+        };
+        let result = self.run_actions(actions, &mut frame);
+        self.result(result.map(|value| value.unwrap_or(Value::Undefined)))
+    }
+
+    /// Calls a function with a `this` value and arguments, and returns its result.
+    pub fn call(&mut self, function: &Value, this: &Value, args: &[Value]) -> Result<Value> {
+        self.num_actions = 0;
+        let result = self.call_function(function, this.clone(), args.to_vec());
+        self.result(result)
+    }
+
+    /// Returns a variable of the root timeline, or a global variable.
+    pub fn get_variable(&mut self, name: &str) -> Value {
+        let scope = Scope {
+            object: self.root.clone(),
+            parent: None,
+        };
+        self.find_variable(&scope, &Value::Object(self.root.clone()), name)
+    }
+
+    /// Sets a variable of the root timeline.
+    pub fn set_variable(&mut self, name: &str, value: Value) {
+        let root = self.root.clone();
+        self.set_property(&root, name, value);
+    }
+
+    fn result(&self, result: Run<Value>) -> Result<Value> {
+        match result {
+            Ok(value) => Ok(value),
+            Err(Abort::Throw(value)) => Err(Error::other(format!(
+                "Uncaught exception: {}",
+                self.to_string(&value)
+            ))),
+            Err(Abort::Error(error)) => Err(error),
+        }
+    }
+
+    /// Runs a list of actions, and returns the value returned by a `Return` action.
+    fn run_actions(&mut self, actions: &[Action], frame: &mut Frame) -> Run<Option<Value>> {
+        let labels: HashMap<Label, usize> = actions
+            .iter()
+            .enumerate()
+            .filter_map(|(i, action)| match *action {
+                Action::Label(label) => Some((label, i)),
+                _ => None,
+            })
+            .collect();
+        let branch = |target: Label| {
+            labels.get(&target).cloned().ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    "Branches to labels in other bodies or actions are not supported",
+                )
+            })
+        };
+        let mut i = 0;
+        while i < actions.len() {
+            self.num_actions += 1;
+            if self.num_actions > self.max_actions {
+                return Err(Error::other("Too many actions were run").into());
+            }
+            let action = &actions[i];
+            i += 1;
+            match *action {
+                Action::If { target } => {
+                    let condition = pop(frame);
+                    if self.to_boolean(&condition) {
+                        i = branch(target)?;
+                    }
+                }
+                Action::Jump { target } => i = branch(target)?,
+                Action::Return => return Ok(Some(pop(frame))),
+                Action::End => return Ok(None),
+                Action::WaitForFrame {
+                    frame: frame_number,
+                    num_actions_to_skip,
+                } => {
+                    if !self.host.is_frame_loaded(u32::from(frame_number)) {
+                        i = skip_actions(actions, i, num_actions_to_skip);
+                    }
+                }
+                Action::WaitForFrame2 {
+                    num_actions_to_skip,
+                } => {
+                    let frame_number = pop(frame);
+                    let frame_number = self.to_number(&frame_number) - 1.0;
+                    let is_loaded =
+                        frame_number < 0.0 || self.host.is_frame_loaded(frame_number as u32);
+                    if !is_loaded {
+                        i = skip_actions(actions, i, num_actions_to_skip);
+                    }
+                }
+                Action::With { ref actions } => {
+                    let object = pop(frame);
+                    if let Value::Object(object) = object {
+                        let scope = frame.scope.clone();
+                        frame.scope = Rc::new(Scope {
+                            object,
+                            parent: Some(scope.clone()),
+                        });
+                        let result = self.run_actions(actions, frame);
+                        frame.scope = scope;
+                        if let Some(value) = result? {
+                            return Ok(Some(value));
+                        }
+                    }
+                }
+                Action::Try(ref try_block) => {
+                    if let Some(value) = self.run_try(try_block, frame)? {
+                        return Ok(Some(value));
+                    }
+                }
+                _ => self.run_action(action, frame)?,
+            }
+        }
+        Ok(None)
+    }
+
+    fn run_try(&mut self, try_block: &TryBlock, frame: &mut Frame) -> Run<Option<Value>> {
+        let mut result = self.run_actions(&try_block.try, frame);
+        if let Err(Abort::Throw(ref value)) = result {
+            if let Some((ref catch_var, ref actions)) = try_block.catch {
+                match *catch_var {
+                    CatchVar::Var(ref name) => {
+                        self.set_variable_in_scope(frame, name, value.clone())
+                    }
+                    CatchVar::Register(register) => set_register(frame, register, value.clone()),
+                }
+                result = self.run_actions(actions, frame);
+            }
+        }
+        if let Some(ref actions) = try_block.finally {
+            // A return from the `finally` block replaces the result of the others.
+            if let Some(value) = self.run_actions(actions, frame)? {
+                return Ok(Some(value));
+            }
+        }
+        result
+    }
+
+    fn run_action(&mut self, action: &Action, frame: &mut Frame) -> Run<()> {
+        let version = self.swf_version;
+        match *action {
+            Action::Push(ref values) => {
+                for value in values {
+                    let value = match *value {
+                        types::Value::Undefined => Value::Undefined,
+                        types::Value::Null => Value::Null,
+                        types::Value::Bool(value) => Value::Bool(value),
+                        types::Value::Int(value) => Value::Number(f64::from(value as i32)),
+                        types::Value::Float(value) => Value::Number(f64::from(value)),
+                        types::Value::Double(value) => Value::Number(value),
+                        types::Value::Str(ref string) => Value::Str(string.clone()),
+                        types::Value::Register(register) => frame
+                            .registers
+                            .get(usize::from(register))
+                            .cloned()
+                            .unwrap_or(Value::Undefined),
+                        types::Value::ConstantPool(index) => frame
+                            .constant_pool
+                            .get(usize::from(index))
+                            .map_or(Value::Undefined, |string| Value::Str(string.clone())),
+                    };
+                    frame.stack.push(value);
+                }
+            }
+            Action::Pop => {
+                pop(frame);
+            }
+            Action::PushDuplicate => {
+                let value = frame.stack.last().cloned().unwrap_or(Value::Undefined);
+                frame.stack.push(value);
+            }
+            Action::StackSwap => {
+                let a = pop(frame);
+                let b = pop(frame);
+                frame.stack.push(a);
+                frame.stack.push(b);
+            }
+            Action::StoreRegister(register) => {
+                let value = frame.stack.last().cloned().unwrap_or(Value::Undefined);
+                set_register(frame, register, value);
+            }
+            Action::ConstantPool(ref constant_pool) => {
+                frame.constant_pool = Rc::new(constant_pool.clone())
+            }
+
+            Action::Add => {
+                let b = self.pop_number(frame);
+                let a = self.pop_number(frame);
+                frame.stack.push(Value::Number(a + b));
+            }
+            Action::Add2 => {
+                let b = pop(frame);
+                let a = pop(frame);
+                let (a, b) = (self.to_primitive(&a), self.to_primitive(&b));
+                let value = match (&a, &b) {
+                    (Value::Str(_), _) | (_, Value::Str(_)) => {
+                        Value::Str(self.to_string(&a) + &self.to_string(&b))
+                    }
+                    _ => Value::Number(self.to_number(&a) + self.to_number(&b)),
+                };
+                frame.stack.push(value);
+            }
+            Action::Subtract => self.binary_number(frame, |a, b| a - b),
+            Action::Multiply => self.binary_number(frame, |a, b| a * b),
+            Action::Divide => {
+                let b = self.pop_number(frame);
+                let a = self.pop_number(frame);
+                // SWF 4 has no infinity.
+                let value = if b == 0.0 && version < 5 {
+                    Value::Str("#ERROR#".to_string())
+                } else {
+                    Value::Number(a / b)
+                };
+                frame.stack.push(value);
+            }
+            Action::Modulo => self.binary_number(frame, |a, b| a % b),
+            Action::Increment => self.unary_number(frame, |a| a + 1.0),
+            Action::Decrement => self.unary_number(frame, |a| a - 1.0),
+            Action::BitAnd => self.binary_number(frame, |a, b| f64::from(to_i32(a) & to_i32(b))),
+            Action::BitOr => self.binary_number(frame, |a, b| f64::from(to_i32(a) | to_i32(b))),
+            Action::BitXor => self.binary_number(frame, |a, b| f64::from(to_i32(a) ^ to_i32(b))),
+            Action::BitLShift => self.binary_number(frame, |a, b| {
+                f64::from(to_i32(a).wrapping_shl(to_i32(b) as u32 & 31))
+            }),
+            Action::BitRShift => self.binary_number(frame, |a, b| {
+                f64::from(to_i32(a).wrapping_shr(to_i32(b) as u32 & 31))
+            }),
+            Action::BitURShift => self.binary_number(frame, |a, b| {
+                f64::from((to_i32(a) as u32).wrapping_shr(to_i32(b) as u32 & 31))
+            }),
+            Action::ToInteger => {
+                self.unary_number(frame, |a| if a.is_nan() { 0.0 } else { a.trunc() })
+            }
+            Action::ToNumber => self.unary_number(frame, |a| a),
+            Action::ToString => {
+                let value = pop(frame);
+                let value = self.to_string(&value);
+                frame.stack.push(Value::Str(value));
+            }
+
+            Action::Equals => {
+                let b = self.pop_number(frame);
+                let a = self.pop_number(frame);
+                let value = self.boolean(a == b);
+                frame.stack.push(value);
+            }
+            Action::Less => {
+                let b = self.pop_number(frame);
+                let a = self.pop_number(frame);
+                let value = self.boolean(a < b);
+                frame.stack.push(value);
+            }
+            Action::Equals2 => {
+                let b = pop(frame);
+                let a = pop(frame);
+                let value = self.equals(&a, &b);
+                frame.stack.push(Value::Bool(value));
+            }
+            Action::StrictEquals => {
+                let b = pop(frame);
+                let a = pop(frame);
+                frame.stack.push(Value::Bool(strict_equals(&a, &b)));
+            }
+            Action::Less2 | Action::Greater => {
+                let b = pop(frame);
+                let a = pop(frame);
+                let value = if *action == Action::Less2 {
+                    self.less(&a, &b)
+                } else {
+                    self.less(&b, &a)
+                };
+                frame.stack.push(value);
+            }
+            Action::And | Action::Or => {
+                let b = pop(frame);
+                let a = pop(frame);
+                let (a, b) = (self.to_boolean(&a), self.to_boolean(&b));
+                let value = if *action == Action::And {
+                    a && b
+                } else {
+                    a || b
+                };
+                let value = self.boolean(value);
+                frame.stack.push(value);
+            }
+            Action::Not => {
+                let value = pop(frame);
+                let value = !self.to_boolean(&value);
+                let value = self.boolean(value);
+                frame.stack.push(value);
+            }
+
+            Action::StringAdd => {
+                let b = self.pop_string(frame);
+                let a = self.pop_string(frame);
+                frame.stack.push(Value::Str(a + &b));
+            }
+            Action::StringEquals | Action::StringLess | Action::StringGreater => {
+                let b = self.pop_string(frame);
+                let a = self.pop_string(frame);
+                let value = match *action {
+                    Action::StringEquals => a == b,
+                    Action::StringLess => a < b,
+                    _ => a > b,
+                };
+                let value = self.boolean(value);
+                frame.stack.push(value);
+            }
+            Action::StringLength | Action::MBStringLength => {
+                let string = self.pop_string(frame);
+                frame
+                    .stack
+                    .push(Value::Number(string.chars().count() as f64));
+            }
+            Action::StringExtract | Action::MBStringExtract => {
+                let count = self.pop_number(frame);
+                let index = self.pop_number(frame);
+                let string = self.pop_string(frame);
+                // The index starts from 1, and a negative count extracts the rest.
+                let start = if index.is_nan() || index < 1.0 {
+                    0
+                } else {
+                    index as usize - 1
+                };
+                let count = if count.is_nan() || count < 0.0 {
+                    usize::MAX
+                } else {
+                    count as usize
+                };
+                let value = string.chars().skip(start).take(count).collect();
+                frame.stack.push(Value::Str(value));
+            }
+            Action::CharToAscii | Action::MBCharToAscii => {
+                let string = self.pop_string(frame);
+                let code = string.chars().next().map_or(0, u32::from);
+                frame.stack.push(Value::Number(f64::from(code)));
+            }
+            Action::AsciiToChar | Action::MBAsciiToChar => {
+                let code = self.pop_number(frame);
+                let value = ::std::char::from_u32(to_i32(code) as u32)
+                    .filter(|&c| c != '\0')
+                    .map(String::from)
+                    .unwrap_or_default();
+                frame.stack.push(Value::Str(value));
+            }
+
+            Action::GetVariable => {
+                let name = self.pop_string(frame);
+                let value = self.find_variable(&frame.scope, &frame.this, &name);
+                frame.stack.push(value);
+            }
+            Action::SetVariable => {
+                let value = pop(frame);
+                let name = self.pop_string(frame);
+                self.set_variable_in_scope(frame, &name, value);
+            }
+            Action::DefineLocal => {
+                let value = pop(frame);
+                let name = self.pop_string(frame);
+                let locals = frame.locals.clone();
+                self.set_property(&locals, &name, value);
+            }
+            Action::DefineLocal2 => {
+                let name = self.pop_string(frame);
+                let locals = frame.locals.clone();
+                if self.own_property(&locals, &name).is_none() {
+                    self.set_property(&locals, &name, Value::Undefined);
+                }
+            }
+            Action::Delete => {
+                let name = self.pop_string(frame);
+                let object = pop(frame);
+                let is_deleted = match object {
+                    Value::Object(ref object) => self.delete_property(object, &name),
+                    _ => false,
+                };
+                frame.stack.push(Value::Bool(is_deleted));
+            }
+            Action::Delete2 => {
+                let name = self.pop_string(frame);
+                let mut scope = Some(frame.scope.clone());
+                let mut is_deleted = false;
+                while let Some(current) = scope {
+                    if self.own_property(&current.object, &name).is_some() {
+                        is_deleted = self.delete_property(&current.object, &name);
+                        break;
+                    }
+                    scope = current.parent.clone();
+                }
+                frame.stack.push(Value::Bool(is_deleted));
+            }
+            Action::GetMember => {
+                let name = self.pop_string(frame);
+                let object = pop(frame);
+                let value = self.get_member(&object, &name);
+                frame.stack.push(value);
+            }
+            Action::SetMember => {
+                let value = pop(frame);
+                let name = self.pop_string(frame);
+                let object = pop(frame);
+                if let Value::Object(ref object) = object {
+                    self.set_property(object, &name, value);
+                }
+            }
+
+            Action::CallFunction => {
+                let name = self.pop_string(frame);
+                let args = self.pop_args(frame);
+                let function = self.find_variable(&frame.scope, &frame.this, &name);
+                let this = Value::Object(self.root.clone());
+                let value = self.call_function(&function, this, args)?;
+                frame.stack.push(value);
+            }
+            Action::CallMethod => {
+                let name = pop(frame);
+                let object = pop(frame);
+                let args = self.pop_args(frame);
+                let value = match name {
+                    Value::Undefined => self.call_function(&object, Value::Undefined, args)?,
+                    Value::Str(ref name) if name.is_empty() => {
+                        self.call_function(&object, Value::Undefined, args)?
+                    }
+                    _ => {
+                        let name = self.to_string(&name);
+                        let function = self.get_member(&object, &name);
+                        self.call_function(&function, object, args)?
+                    }
+                };
+                frame.stack.push(value);
+            }
+            Action::NewObject => {
+                let name = self.pop_string(frame);
+                let args = self.pop_args(frame);
+                let constructor = self.find_variable(&frame.scope, &frame.this, &name);
+                let value = self.construct(&constructor, args)?;
+                frame.stack.push(value);
+            }
+            Action::NewMethod => {
+                let name = pop(frame);
+                let object = pop(frame);
+                let args = self.pop_args(frame);
+                let constructor = match name {
+                    Value::Undefined => object,
+                    Value::Str(ref name) if name.is_empty() => object,
+                    _ => {
+                        let name = self.to_string(&name);
+                        self.get_member(&object, &name)
+                    }
+                };
+                let value = self.construct(&constructor, args)?;
+                frame.stack.push(value);
+            }
+            Action::InitArray => {
+                let elements = self.pop_args(frame);
+                let array = self.new_array(elements);
+                frame.stack.push(Value::Object(array));
+            }
+            Action::InitObject => {
+                let count = self.pop_number(frame);
+                let count = if count.is_nan() || count < 0.0 {
+                    0
+                } else {
+                    count as usize
+                };
+                let object = self.new_object();
+                let mut properties = vec![];
+                for _ in 0..count.min(frame.stack.len() / 2) {
+                    let value = pop(frame);
+                    let name = self.pop_string(frame);
+                    properties.push((name, value));
+                }
+                for (name, value) in properties.into_iter().rev() {
+                    self.set_property(&object, &name, value);
+                }
+                frame.stack.push(Value::Object(object));
+            }
+            Action::DefineFunction {
+                ref name,
+                ref params,
+                ref actions,
+            } => {
+                let params = params.iter().map(|name| (name.clone(), None)).collect();
+                self.define_function(frame, name, params, None, actions);
+            }
+            Action::DefineFunction2(ref function) => {
+                let params = function
+                    .params
+                    .iter()
+                    .map(|param| (param.name.clone(), param.register_index))
+                    .collect();
+                let flags = types::Function {
+                    actions: vec![],
+                    ..function.clone()
+                };
+                self.define_function(
+                    frame,
+                    &function.name,
+                    params,
+                    Some(flags),
+                    &function.actions,
+                );
+            }
+            Action::Enumerate | Action::Enumerate2 => {
+                let object = if *action == Action::Enumerate {
+                    let name = self.pop_string(frame);
+                    self.find_variable(&frame.scope, &frame.this, &name)
+                } else {
+                    pop(frame)
+                };
+                frame.stack.push(Value::Null);
+                if let Value::Object(ref object) = object {
+                    for name in self.enumerate(object) {
+                        frame.stack.push(Value::Str(name));
+                    }
+                }
+            }
+            Action::InstanceOf => {
+                let constructor = pop(frame);
+                let object = pop(frame);
+                let value = self.instance_of(&object, &constructor);
+                frame.stack.push(Value::Bool(value));
+            }
+            Action::CastOp => {
+                let object = pop(frame);
+                let constructor = pop(frame);
+                let value = if self.instance_of(&object, &constructor) {
+                    object
+                } else {
+                    Value::Null
+                };
+                frame.stack.push(value);
+            }
+            Action::Extends => {
+                let superclass = pop(frame);
+                let subclass = pop(frame);
+                if let Value::Object(ref subclass) = subclass {
+                    let prototype = match self.get_member(&superclass, "prototype") {
+                        Value::Object(prototype) => Some(prototype),
+                        _ => None,
+                    };
+                    let object = new_object(prototype, ObjectKind::Object);
+                    set_hidden(&object, "__constructor__", superclass);
+                    set_hidden(subclass, "prototype", Value::Object(object));
+                }
+            }
+            Action::ImplementsOp => {
+                pop(frame);
+                self.pop_args(frame);
+            }
+            Action::TypeOf => {
+                let value = pop(frame);
+                let type_name = match value {
+                    Value::Undefined => "undefined",
+                    Value::Null => "null",
+                    Value::Bool(_) => "boolean",
+                    Value::Number(_) => "number",
+                    Value::Str(_) => "string",
+                    Value::Object(ref object) => match object.0.borrow().kind {
+                        ObjectKind::Function(_) => "function",
+                        _ => "object",
+                    },
+                };
+                frame.stack.push(Value::Str(type_name.to_string()));
+            }
+            Action::Throw => return Err(Abort::Throw(pop(frame))),
+
+            Action::Trace => {
+                let value = pop(frame);
+                // `undefined` is traced by name in all versions.
+                let message = match value {
+                    Value::Undefined => "undefined".to_string(),
+                    _ => self.to_string(&value),
+                };
+                self.host.trace(&message);
+            }
+            Action::GetTime => {
+                let time = self.host.get_time();
+                frame.stack.push(Value::Number(time));
+            }
+            Action::RandomNumber => {
+                let max = self.pop_number(frame);
+                let max = if max.is_nan() || max < 1.0 {
+                    1
+                } else {
+                    max as u32
+                };
+                let value = self.host.random(max);
+                frame.stack.push(Value::Number(f64::from(value)));
+            }
+            Action::GetProperty => {
+                let index = self.pop_number(frame);
+                let target = self.pop_string(frame);
+                let value = self.host.get_property(&target, index as u8);
+                frame.stack.push(value);
+            }
+            Action::SetProperty => {
+                let value = pop(frame);
+                let index = self.pop_number(frame);
+                let target = self.pop_string(frame);
+                self.host.set_property(&target, index as u8, value);
+            }
+            Action::TargetPath => {
+                pop(frame);
+                frame.stack.push(Value::Undefined);
+            }
+            Action::GetUrl {
+                ref url,
+                ref target,
+            } => self.host.get_url(url, target, SendVarsMethod::None),
+            Action::GetUrl2 {
+                send_vars_method,
+                is_target_sprite,
+                is_load_vars,
+            } => {
+                let target = self.pop_string(frame);
+                let url = self.pop_string(frame);
+                if is_load_vars {
+                    self.host.load_variables(&url, &target, send_vars_method);
+                } else if is_target_sprite {
+                    self.host.load_movie(&url, &target, send_vars_method);
+                } else {
+                    self.host.get_url(&url, &target, send_vars_method);
+                }
+            }
+            Action::GotoFrame(frame_number) => self.host.goto_frame(u32::from(frame_number)),
+            Action::GotoFrame2 {
+                set_playing,
+                scene_offset,
+            } => {
+                let frame_value = pop(frame);
+                let frame_number = match frame_value {
+                    Value::Str(ref label) => {
+                        let frame_number = self.to_number(&frame_value);
+                        if frame_number.is_nan() {
+                            self.host.goto_label(label);
+                            None
+                        } else {
+                            Some(frame_number)
+                        }
+                    }
+                    _ => Some(self.to_number(&frame_value)),
+                };
+                if let Some(frame_number) = frame_number {
+                    let frame_number = frame_number.trunc() - 1.0 + f64::from(scene_offset);
+                    if frame_number >= 0.0 {
+                        self.host.goto_frame(frame_number as u32);
+                    }
+                }
+                if set_playing {
+                    self.host.play();
+                } else {
+                    self.host.stop();
+                }
+            }
+            Action::GotoLabel(ref label) => self.host.goto_label(label),
+            Action::NextFrame => self.host.next_frame(),
+            Action::PreviousFrame => self.host.previous_frame(),
+            Action::Play => self.host.play(),
+            Action::Stop => self.host.stop(),
+            Action::StopSounds => self.host.stop_sounds(),
+            Action::ToggleQuality => self.host.toggle_quality(),
+            Action::Call => {
+                let frame_path = self.pop_string(frame);
+                self.host.call_frame(&frame_path);
+            }
+            Action::SetTarget(ref target) => self.host.set_target(target),
+            Action::SetTarget2 => {
+                let target = self.pop_string(frame);
+                self.host.set_target(&target);
+            }
+            Action::CloneSprite => {
+                let depth = self.pop_number(frame);
+                let target = self.pop_string(frame);
+                let source = self.pop_string(frame);
+                self.host.clone_sprite(&source, &target, to_i32(depth));
+            }
+            Action::RemoveSprite => {
+                let target = self.pop_string(frame);
+                self.host.remove_sprite(&target);
+            }
+            Action::StartDrag => {
+                let target = self.pop_string(frame);
+                let lock_center = pop(frame);
+                let lock_center = self.to_boolean(&lock_center);
+                let is_constrained = pop(frame);
+                let bounds = if self.to_boolean(&is_constrained) {
+                    let bottom = self.pop_number(frame);
+                    let right = self.pop_number(frame);
+                    let top = self.pop_number(frame);
+                    let left = self.pop_number(frame);
+                    Some([left, top, right, bottom])
+                } else {
+                    None
+                };
+                self.host.start_drag(&target, lock_center, bounds);
+            }
+            Action::EndDrag => self.host.end_drag(),
+
+            // Flash Player ignores actions that it does not know.
+            Action::Unknown { .. } | Action::Label(_) | Action::OffsetLabel(_) => (),
+            Action::If { .. }
+            | Action::Jump { .. }
+            | Action::Return
+            | Action::End
+            | Action::WaitForFrame { .. }
+            | Action::WaitForFrame2 { .. }
+            | Action::With { .. }
+            | Action::Try(_) => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn define_function(
+        &mut self,
+        frame: &mut Frame,
+        name: &str,
+        params: Vec<(String, Option<u8>)>,
+        function2: Option<types::Function>,
+        actions: &[Action],
+    ) {
+        let function = ScriptFunction {
+            params,
+            function2,
+            actions: actions.to_vec(),
+            scope: frame.scope.clone(),
+            constant_pool: frame.constant_pool.clone(),
+        };
+        let function = self.new_function(Callable::Script(Rc::new(function)));
+        if name.is_empty() {
+            frame.stack.push(Value::Object(function));
+        } else {
+            let locals = frame.locals.clone();
+            self.set_property(&locals, name, Value::Object(function));
+        }
+    }
+
+    fn call_function(&mut self, function: &Value, this: Value, args: Vec<Value>) -> Run<Value> {
+        let object = match *function {
+            Value::Object(ref object) => object.clone(),
+            _ => return Ok(Value::Undefined),
+        };
+        let (callable, this) = match object.0.borrow().kind {
+            ObjectKind::Function(ref callable) => (callable.clone(), this),
+            // Calling `super` calls the constructor of the superclass.
+            ObjectKind::Super {
+                ref this,
+                constructor: Value::Object(ref constructor),
+                ..
+            } => match constructor.0.borrow().kind {
+                ObjectKind::Function(ref callable) => (callable.clone(), this.clone()),
+                _ => return Ok(Value::Undefined),
+            },
+            _ => return Ok(Value::Undefined),
+        };
+        let function = match callable {
+            Callable::Native(native) => return native(self, this, args),
+            Callable::Script(function) => function,
+        };
+        if self.call_depth >= MAX_CALL_DEPTH {
+            return Err(Error::other("Too many nested function calls").into());
+        }
+
+        let locals = new_object(None, ObjectKind::Object);
+        let num_registers = match function.function2 {
+            Some(ref flags) => usize::from(flags.num_registers),
+            None => 4,
+        };
+        let mut frame = Frame {
+            scope: Rc::new(Scope {
+                object: locals.clone(),
+                parent: Some(function.scope.clone()),
+            }),
+            locals: locals.clone(),
+            this: this.clone(),
+            stack: vec![],
+            registers: vec![Value::Undefined; num_registers.max(1)],
+            constant_pool: function.constant_pool.clone(),
+        };
+        let arguments = Value::Object(self.new_array(args.clone()));
+        let super_object = match this {
+            Value::Object(ref object) => {
+                let prototype = object.0.borrow().prototype.clone();
+                let (prototype, constructor) = match prototype {
+                    Some(prototype) => (
+                        prototype.0.borrow().prototype.clone(),
+                        self.own_property(&prototype, "__constructor__")
+                            .unwrap_or(Value::Undefined),
+                    ),
+                    None => (None, Value::Undefined),
+                };
+                let kind = ObjectKind::Super {
+                    this: this.clone(),
+                    prototype,
+                    constructor,
+                };
+                Value::Object(new_object(None, kind))
+            }
+            _ => Value::Undefined,
+        };
+
+        match function.function2 {
+            Some(ref flags) => {
+                // Preloaded values are stored in registers from 1, in this order.
+                let preloads = [
+                    (flags.preload_this, this.clone()),
+                    (flags.preload_arguments, arguments.clone()),
+                    (flags.preload_super, super_object.clone()),
+                    (flags.preload_root, Value::Object(self.root.clone())),
+                    (flags.preload_parent, Value::Undefined),
+                    (flags.preload_global, Value::Object(self.global.clone())),
+                ];
+                let mut register = 1;
+                for (is_preloaded, value) in preloads.iter().cloned() {
+                    if is_preloaded {
+                        set_register(&mut frame, register, value);
+                        register += 1;
+                    }
+                }
+                if !flags.suppress_arguments && !flags.preload_arguments {
+                    set_hidden(&locals, "arguments", arguments);
+                }
+                if !flags.suppress_super && !flags.preload_super {
+                    set_hidden(&locals, "super", super_object);
+                }
+            }
+            None => {
+                set_hidden(&locals, "arguments", arguments);
+                set_hidden(&locals, "super", super_object);
+            }
+        }
+        for (i, (name, register)) in function.params.iter().enumerate() {
+            let value = args.get(i).cloned().unwrap_or(Value::Undefined);
+            match *register {
+                Some(register) => set_register(&mut frame, register, value),
+                None => self.set_property(&locals, name, value),
+            }
+        }
+
+        self.call_depth += 1;
+        let result = self.run_actions(&function.actions, &mut frame);
+        self.call_depth -= 1;
+        Ok(result?.unwrap_or(Value::Undefined))
+    }
+
+    fn construct(&mut self, constructor: &Value, args: Vec<Value>) -> Run<Value> {
+        let is_function = match *constructor {
+            Value::Object(ref object) => matches!(object.0.borrow().kind, ObjectKind::Function(_)),
+            _ => false,
+        };
+        if !is_function {
+            return Ok(Value::Undefined);
+        }
+        let prototype = match self.get_member(constructor, "prototype") {
+            Value::Object(prototype) => prototype,
+            _ => self.object_prototype.clone(),
+        };
+        let object = new_object(Some(prototype), ObjectKind::Object);
+        set_hidden(&object, "__constructor__", constructor.clone());
+        if self.swf_version < 7 {
+            set_hidden(&object, "constructor", constructor.clone());
+        }
+        let value = self.call_function(constructor, Value::Object(object.clone()), args)?;
+        // A constructor may return another object, such as a new array.
+        Ok(match value {
+            Value::Object(_) => value,
+            _ => Value::Object(object),
+        })
+    }
+
+    /// Returns the value of a variable from a scope chain, or from `_global`. Paths separated
+    /// by dots are looked up as members.
+    fn find_variable(&mut self, scope: &Scope, this: &Value, name: &str) -> Value {
+        if let Some(dot) = name.find('.') {
+            let object = self.find_variable(scope, this, &name[..dot]);
+            return name[dot + 1..]
+                .split('.')
+                .fold(object, |object, name| self.get_member(&object, name));
+        }
+        match name {
+            "this" => return this.clone(),
+            "_global" => return Value::Object(self.global.clone()),
+            "_root" | "_level0" => return Value::Object(self.root.clone()),
+            _ => (),
+        }
+        let mut scope = Some(scope);
+        while let Some(current) = scope {
+            if self.has_property(&current.object, name) {
+                return self.get_property(&current.object, name);
+            }
+            scope = current.parent.as_deref();
+        }
+        let global = self.global.clone();
+        self.get_property(&global, name)
+    }
+
+    /// Sets a variable in the first object of the scope chain that has it, or as a local
+    /// variable of the timeline.
+    fn set_variable_in_scope(&mut self, frame: &Frame, name: &str, value: Value) {
+        if let Some(dot) = name.rfind('.') {
+            let object = self.find_variable(&frame.scope, &frame.this, &name[..dot]);
+            if let Value::Object(ref object) = object {
+                self.set_property(object, &name[dot + 1..], value);
+            }
+            return;
+        }
+        let mut scope = Some(&*frame.scope);
+        while let Some(current) = scope {
+            if self.has_property(&current.object, name) {
+                let object = current.object.clone();
+                self.set_property(&object, name, value);
+                return;
+            }
+            scope = current.parent.as_deref();
+        }
+        let root = self.root.clone();
+        self.set_property(&root, name, value);
+    }
+
+    /// Returns a member of a value. Strings, numbers and booleans have the members of their
+    /// prototypes.
+    fn get_member(&mut self, object: &Value, name: &str) -> Value {
+        let prototype = match *object {
+            Value::Object(ref object) => {
+                let super_prototype = match object.0.borrow().kind {
+                    ObjectKind::Super { ref prototype, .. } => Some(prototype.clone()),
+                    _ => None,
+                };
+                return match super_prototype {
+                    Some(Some(prototype)) => self.get_property(&prototype, name),
+                    Some(None) => Value::Undefined,
+                    None => self.get_property(object, name),
+                };
+            }
+            Value::Str(ref string) if name == "length" => {
+                return Value::Number(string.chars().count() as f64)
+            }
+            Value::Str(_) => self.string_prototype.clone(),
+            Value::Number(_) => self.number_prototype.clone(),
+            Value::Bool(_) => self.boolean_prototype.clone(),
+            Value::Undefined | Value::Null => return Value::Undefined,
+        };
+        self.get_property(&prototype, name)
+    }
+
+    fn is_case_sensitive(&self) -> bool {
+        self.swf_version >= 7
+    }
+
+    fn own_property(&self, object: &ObjectRef, name: &str) -> Option<Value> {
+        let is_case_sensitive = self.is_case_sensitive();
+        let object = object.0.borrow();
+        if let ObjectKind::Array(ref elements) = object.kind {
+            if name == "length" {
+                return Some(Value::Number(elements.len() as f64));
+            }
+            if let Some(index) = array_index(name) {
+                return elements.get(index).cloned();
+            }
+        }
+        if name == "__proto__" {
+            return object.prototype.clone().map(Value::Object);
+        }
+        object
+            .properties
+            .iter()
+            .find(|property| names_equal(&property.name, name, is_case_sensitive))
+            .map(|property| property.value.clone())
+    }
+
+    fn has_property(&self, object: &ObjectRef, name: &str) -> bool {
+        let mut object = Some(object.clone());
+        // Prototype chains may loop.
+        for _ in 0..256 {
+            match object {
+                Some(current) => {
+                    if self.own_property(&current, name).is_some() {
+                        return true;
+                    }
+                    object = current.0.borrow().prototype.clone();
+                }
+                None => break,
+            }
+        }
+        false
+    }
+
+    fn get_property(&self, object: &ObjectRef, name: &str) -> Value {
+        let mut object = Some(object.clone());
+        for _ in 0..256 {
+            match object {
+                Some(current) => {
+                    if let Some(value) = self.own_property(&current, name) {
+                        return value;
+                    }
+                    object = current.0.borrow().prototype.clone();
+                }
+                None => break,
+            }
+        }
+        Value::Undefined
+    }
+
+    fn set_property(&mut self, object: &ObjectRef, name: &str, value: Value) {
+        let is_case_sensitive = self.is_case_sensitive();
+        let length = match object.0.borrow().kind {
+            ObjectKind::Array(_) if name == "length" => Some(self.to_number(&value)),
+            _ => None,
+        };
+        let mut object = object.0.borrow_mut();
+        if let ObjectKind::Array(ref mut elements) = object.kind {
+            if let Some(length) = length {
+                if length >= 0.0 {
+                    elements.resize(length as usize, Value::Undefined);
+                }
+                return;
+            }
+            if let Some(index) = array_index(name) {
+                if index >= elements.len() {
+                    elements.resize(index + 1, Value::Undefined);
+                }
+                elements[index] = value;
+                return;
+            }
+        }
+        if name == "__proto__" {
+            object.prototype = match value {
+                Value::Object(prototype) => Some(prototype),
+                _ => None,
+            };
+            return;
+        }
+        match object
+            .properties
+            .iter_mut()
+            .find(|property| names_equal(&property.name, name, is_case_sensitive))
+        {
+            Some(property) => property.value = value,
+            None => object.properties.push(Property {
+                name: name.to_string(),
+                value,
+                is_enumerable: true,
+            }),
+        }
+    }
+
+    fn delete_property(&mut self, object: &ObjectRef, name: &str) -> bool {
+        let is_case_sensitive = self.is_case_sensitive();
+        let mut object = object.0.borrow_mut();
+        let len = object.properties.len();
+        object
+            .properties
+            .retain(|property| !names_equal(&property.name, name, is_case_sensitive));
+        object.properties.len() != len
+    }
+
+    /// Returns the names of the enumerable properties of an object and its prototypes, in the
+    /// order that they were added. `for`..`in` loops pop them in reverse.
+    fn enumerate(&self, object: &ObjectRef) -> Vec<String> {
+        let is_case_sensitive = self.is_case_sensitive();
+        let mut names: Vec<String> = vec![];
+        let mut object = Some(object.clone());
+        for _ in 0..256 {
+            let current = match object {
+                Some(current) => current,
+                None => break,
+            };
+            let current = current.0.borrow();
+            if let ObjectKind::Array(ref elements) = current.kind {
+                names.extend((0..elements.len()).map(|i| i.to_string()));
+            }
+            for property in current
+                .properties
+                .iter()
+                .filter(|property| property.is_enumerable)
+            {
+                if !names
+                    .iter()
+                    .any(|name| names_equal(name, &property.name, is_case_sensitive))
+                {
+                    names.push(property.name.clone());
+                }
+            }
+            object = current.prototype.clone();
+        }
+        names
+    }
+
+    fn instance_of(&self, object: &Value, constructor: &Value) -> bool {
+        let (object, constructor) = match (object, constructor) {
+            (Value::Object(object), Value::Object(constructor)) => (object, constructor),
+            _ => return false,
+        };
+        let prototype = match self.get_property(constructor, "prototype") {
+            Value::Object(prototype) => prototype,
+            _ => return false,
+        };
+        let mut object = object.0.borrow().prototype.clone();
+        for _ in 0..256 {
+            match object {
+                Some(ref current) if *current == prototype => return true,
+                Some(current) => object = current.0.borrow().prototype.clone(),
+                None => break,
+            }
+        }
+        false
+    }
+
+    fn new_object(&self) -> ObjectRef {
+        new_object(Some(self.object_prototype.clone()), ObjectKind::Object)
+    }
+
+    fn new_array(&self, elements: Vec<Value>) -> ObjectRef {
+        new_object(
+            Some(self.array_prototype.clone()),
+            ObjectKind::Array(elements),
+        )
+    }
+
+    /// Creates a function object, with a new prototype object for the objects that it
+    /// constructs.
+    fn new_function(&self, callable: Callable) -> ObjectRef {
+        let function = new_object(
+            Some(self.function_prototype.clone()),
+            ObjectKind::Function(callable),
+        );
+        let prototype = self.new_object();
+        set_hidden(&prototype, "constructor", Value::Object(function.clone()));
+        set_hidden(&function, "prototype", Value::Object(prototype));
+        function
+    }
+
+    fn boolean(&self, value: bool) -> Value {
+        // SWF 4 has no booleans.
+        if self.swf_version < 5 {
+            Value::Number(if value { 1.0 } else { 0.0 })
+        } else {
+            Value::Bool(value)
+        }
+    }
+
+    /// Converts a value to a boolean, as for the condition of an `If` action.
+    pub fn to_boolean(&self, value: &Value) -> bool {
+        match *value {
+            Value::Undefined | Value::Null => false,
+            Value::Bool(value) => value,
+            Value::Number(value) => value != 0.0 && !value.is_nan(),
+            // Strings are converted to numbers before SWF 7.
+            Value::Str(ref string) if self.swf_version < 7 => {
+                let value = self.to_number(&Value::Str(string.clone()));
+                value != 0.0 && !value.is_nan()
+            }
+            Value::Str(ref string) => !string.is_empty(),
+            Value::Object(_) => true,
+        }
+    }
+
+    /// Converts a value to a number.
+    pub fn to_number(&self, value: &Value) -> f64 {
+        match *value {
+            // `undefined` and `null` are 0 before SWF 7.
+            Value::Undefined | Value::Null => {
+                if self.swf_version < 7 {
+                    0.0
+                } else {
+                    f64::NAN
+                }
+            }
+            Value::Bool(value) => {
+                if value {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Value::Number(value) => value,
+            Value::Str(ref string) => string_to_number(string, self.swf_version),
+            Value::Object(_) => {
+                let value = self.to_primitive(value);
+                match value {
+                    Value::Object(_) => f64::NAN,
+                    _ => self.to_number(&value),
+                }
+            }
+        }
+    }
+
+    /// Converts a value to a string.
+    pub fn to_string(&self, value: &Value) -> String {
+        self.to_string_at_depth(value, 0)
+    }
+
+    fn to_string_at_depth(&self, value: &Value, depth: usize) -> String {
+        match *value {
+            // `undefined` is the empty string before SWF 7.
+            Value::Undefined => {
+                if self.swf_version < 7 {
+                    String::new()
+                } else {
+                    "undefined".to_string()
+                }
+            }
+            Value::Null => "null".to_string(),
+            Value::Bool(value) => value.to_string(),
+            Value::Number(value) => number_to_string(value),
+            Value::Str(ref string) => string.clone(),
+            Value::Object(ref object) => match object.0.borrow().kind {
+                // Arrays may contain themselves.
+                ObjectKind::Array(ref elements) if depth < 16 => elements
+                    .iter()
+                    .map(|element| self.to_string_at_depth(element, depth + 1))
+                    .collect::<Vec<_>>()
+                    .join(","),
+                ObjectKind::Array(_) => String::new(),
+                ObjectKind::Function(_) => "[type Function]".to_string(),
+                _ => "[object Object]".to_string(),
+            },
+        }
+    }
+
+    /// Converts objects to strings, as for `Add2`.
+    fn to_primitive(&self, value: &Value) -> Value {
+        match *value {
+            Value::Object(_) => Value::Str(self.to_string(value)),
+            _ => value.clone(),
+        }
+    }
+
+    /// Compares two values, as `Equals2` does.
+    fn equals(&self, a: &Value, b: &Value) -> bool {
+        match (a, b) {
+            (Value::Undefined, Value::Undefined)
+            | (Value::Null, Value::Null)
+            | (Value::Undefined, Value::Null)
+            | (Value::Null, Value::Undefined) => true,
+            (Value::Undefined, _) | (Value::Null, _) | (_, Value::Undefined) | (_, Value::Null) => {
+                false
+            }
+            (Value::Object(a), Value::Object(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::Object(_), _) => self.equals(&self.to_primitive(a), b),
+            (_, Value::Object(_)) => self.equals(a, &self.to_primitive(b)),
+            _ => self.to_number(a) == self.to_number(b),
+        }
+    }
+
+    /// Compares two values, as `Less2` does. The result is `undefined` if a value is `NaN`.
+    fn less(&self, a: &Value, b: &Value) -> Value {
+        let (a, b) = (self.to_primitive(a), self.to_primitive(b));
+        if let (Value::Str(a), Value::Str(b)) = (&a, &b) {
+            return Value::Bool(a < b);
+        }
+        let (a, b) = (self.to_number(&a), self.to_number(&b));
+        if a.is_nan() || b.is_nan() {
+            Value::Undefined
+        } else {
+            Value::Bool(a < b)
+        }
+    }
+
+    fn pop_number(&self, frame: &mut Frame) -> f64 {
+        let value = pop(frame);
+        self.to_number(&value)
+    }
+
+    fn pop_string(&self, frame: &mut Frame) -> String {
+        let value = pop(frame);
+        self.to_string(&value)
+    }
+
+    /// Pops the arguments of a function, after their count.
+    fn pop_args(&self, frame: &mut Frame) -> Vec<Value> {
+        let count = self.pop_number(frame);
+        let count = if count.is_nan() || count < 0.0 {
+            0
+        } else {
+            count as usize
+        };
+        (0..count.min(frame.stack.len()))
+            .map(|_| pop(frame))
+            .collect()
+    }
+
+    fn unary_number<F: Fn(f64) -> f64>(&self, frame: &mut Frame, f: F) {
+        let a = self.pop_number(frame);
+        frame.stack.push(Value::Number(f(a)));
+    }
+
+    fn binary_number<F: Fn(f64, f64) -> f64>(&self, frame: &mut Frame, f: F) {
+        let b = self.pop_number(frame);
+        let a = self.pop_number(frame);
+        frame.stack.push(Value::Number(f(a, b)));
+    }
+
+    fn define_globals(&mut self) {
+        let global = self.global.clone();
+        let object = self.define_class(
+            &global,
+            "Object",
+            object_constructor,
+            self.object_prototype.clone(),
+        );
+        self.define_native(&object, "registerClass", |_, _, _| Ok(Value::Bool(true)));
+        let prototype = self.object_prototype.clone();
+        self.define_native(&prototype, "toString", object_to_string);
+        self.define_native(&prototype, "valueOf", |_, this, _| Ok(this));
+        self.define_native(&prototype, "hasOwnProperty", object_has_own_property);
+
+        let prototype = self.function_prototype.clone();
+        self.define_class(
+            &global,
+            "Function",
+            |_, _, _| Ok(Value::Undefined),
+            prototype.clone(),
+        );
+        self.define_native(&prototype, "call", function_call);
+        self.define_native(&prototype, "apply", function_apply);
+
+        let prototype = self.array_prototype.clone();
+        self.define_class(&global, "Array", array_constructor, prototype.clone());
+        self.define_native(&prototype, "push", array_push);
+        self.define_native(&prototype, "pop", array_pop);
+        self.define_native(&prototype, "shift", array_shift);
+        self.define_native(&prototype, "join", array_join);
+        self.define_native(&prototype, "reverse", array_reverse);
+        self.define_native(&prototype, "toString", object_to_string);
+
+        let prototype = self.string_prototype.clone();
+        let string = self.define_class(&global, "String", string_constructor, prototype.clone());
+        self.define_native(&string, "fromCharCode", string_from_char_code);
+        self.define_native(&prototype, "charAt", string_char_at);
+        self.define_native(&prototype, "charCodeAt", string_char_code_at);
+        self.define_native(&prototype, "indexOf", string_index_of);
+        self.define_native(&prototype, "split", string_split);
+        self.define_native(&prototype, "substr", string_substr);
+        self.define_native(&prototype, "substring", string_substring);
+        self.define_native(&prototype, "toLowerCase", |vm, this, _| {
+            Ok(Value::Str(vm.to_string(&this).to_lowercase()))
+        });
+        self.define_native(&prototype, "toUpperCase", |vm, this, _| {
+            Ok(Value::Str(vm.to_string(&this).to_uppercase()))
+        });
+        self.define_native(&prototype, "toString", object_to_string);
+        self.define_native(&prototype, "valueOf", |_, this, _| Ok(this));
+
+        let prototype = self.number_prototype.clone();
+        self.define_class(&global, "Number", number_constructor, prototype.clone());
+        self.define_native(&prototype, "toString", object_to_string);
+        let prototype = self.boolean_prototype.clone();
+        self.define_class(&global, "Boolean", boolean_constructor, prototype.clone());
+        self.define_native(&prototype, "toString", object_to_string);
+
+        let math = self.new_object();
+        set_hidden(&global, "Math", Value::Object(math.clone()));
+        self.define_native(&math, "abs", |vm, _, args| {
+            math_function(vm, &args, f64::abs)
+        });
+        self.define_native(&math, "ceil", |vm, _, args| {
+            math_function(vm, &args, f64::ceil)
+        });
+        self.define_native(&math, "floor", |vm, _, args| {
+            math_function(vm, &args, f64::floor)
+        });
+        self.define_native(&math, "round", |vm, _, args| {
+            math_function(vm, &args, |value| (value + 0.5).floor())
+        });
+        self.define_native(&math, "sqrt", |vm, _, args| {
+            math_function(vm, &args, f64::sqrt)
+        });
+        self.define_native(&math, "min", |vm, _, args| {
+            math_function_2(vm, &args, f64::min)
+        });
+        self.define_native(&math, "max", |vm, _, args| {
+            math_function_2(vm, &args, f64::max)
+        });
+        self.define_native(&math, "pow", |vm, _, args| {
+            math_function_2(vm, &args, f64::powf)
+        });
+        self.define_native(&math, "random", |vm, _, _| {
+            let value = vm.host.random(1 << 30);
+            Ok(Value::Number(f64::from(value) / f64::from(1 << 30)))
+        });
+
+        self.define_native(&global, "parseInt", global_parse_int);
+        self.define_native(&global, "parseFloat", |vm, _, args| {
+            let string = vm.to_string(&arg(&args, 0));
+            Ok(Value::Number(parse_float_prefix(&string)))
+        });
+        self.define_native(&global, "isNaN", |vm, _, args| {
+            Ok(Value::Bool(vm.to_number(&arg(&args, 0)).is_nan()))
+        });
+        self.define_native(&global, "isFinite", |vm, _, args| {
+            Ok(Value::Bool(vm.to_number(&arg(&args, 0)).is_finite()))
+        });
+    }
+
+    /// Defines a constructor with its prototype.
+    fn define_class(
+        &mut self,
+        object: &ObjectRef,
+        name: &str,
+        constructor: NativeFunction,
+        prototype: ObjectRef,
+    ) -> ObjectRef {
+        let function = self.define_native(object, name, constructor);
+        set_hidden(&prototype, "constructor", Value::Object(function.clone()));
+        set_hidden(&function, "prototype", Value::Object(prototype));
+        function
+    }
+
+    fn define_native(
+        &mut self,
+        object: &ObjectRef,
+        name: &str,
+        native: NativeFunction,
+    ) -> ObjectRef {
+        let function = self.new_function(Callable::Native(native));
+        set_hidden(object, name, Value::Object(function.clone()));
+        function
+    }
+}
+
+fn new_object(prototype: Option<ObjectRef>, kind: ObjectKind) -> ObjectRef {
+    ObjectRef(Rc::new(RefCell::new(Object {
+        properties: vec![],
+        prototype,
+        kind,
+    })))
+}
+
+/// Sets a property that is not enumerated, as the properties of built-in objects are.
+fn set_hidden(object: &ObjectRef, name: &str, value: Value) {
+    let mut object = object.0.borrow_mut();
+    object.properties.retain(|property| property.name != name);
+    object.properties.push(Property {
+        name: name.to_string(),
+        value,
+        is_enumerable: false,
+    });
+}
+
+fn names_equal(a: &str, b: &str, is_case_sensitive: bool) -> bool {
+    if is_case_sensitive {
+        a == b
+    } else {
+        a.eq_ignore_ascii_case(b)
+    }
+}
+
+/// Returns the index of an array element from a property name, such as 2 for `"2"`.
+fn array_index(name: &str) -> Option<usize> {
+    if name.is_empty() || (name.len() > 1 && name.starts_with('0')) {
+        return None;
+    }
+    name.parse().ok()
+}
+
+fn pop(frame: &mut Frame) -> Value {
+    // Popping an empty stack pushes `undefined`.
+    frame.stack.pop().unwrap_or(Value::Undefined)
+}
+
+fn set_register(frame: &mut Frame, register: u8, value: Value) {
+    if let Some(slot) = frame.registers.get_mut(usize::from(register)) {
+        *slot = value;
+    }
+}
+
+/// Returns the index after skipping a number of actions, which does not count labels.
+fn skip_actions(actions: &[Action], mut i: usize, num_actions_to_skip: u8) -> usize {
+    let mut num_skipped = 0;
+    while i < actions.len() && num_skipped < num_actions_to_skip {
+        if !matches!(actions[i], Action::Label(_) | Action::OffsetLabel(_)) {
+            num_skipped += 1;
+        }
+        i += 1;
+    }
+    i
+}
+
+fn strict_equals(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Undefined, Value::Undefined) | (Value::Null, Value::Null) => true,
+        (Value::Bool(a), Value::Bool(b)) => a == b,
+        (Value::Number(a), Value::Number(b)) => a == b,
+        (Value::Str(a), Value::Str(b)) => a == b,
+        (Value::Object(a), Value::Object(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Converts a number to a 32-bit integer, as the bitwise actions do.
+fn to_i32(value: f64) -> i32 {
+    if !value.is_finite() {
+        return 0;
+    }
+    let value = value.trunc() % 4_294_967_296.0;
+    value as i64 as u32 as i32
+}
+
+fn string_to_number(string: &str, swf_version: u8) -> f64 {
+    let string = string.trim();
+    if string.is_empty() {
+        // The empty string is 0 before SWF 5.
+        return if swf_version < 5 { 0.0 } else { f64::NAN };
+    }
+    if swf_version >= 6 {
+        let (is_negative, digits) = match string.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, string),
+        };
+        if let Some(hex) = digits
+            .strip_prefix("0x")
+            .or_else(|| digits.strip_prefix("0X"))
+        {
+            return match i64::from_str_radix(hex, 16) {
+                Ok(value) if is_negative => -(value as f64),
+                Ok(value) => value as f64,
+                Err(_) => f64::NAN,
+            };
+        }
+    }
+    // Rust also parses names such as `inf`, which Flash does not.
+    let is_number = string
+        .chars()
+        .all(|c| c.is_ascii_digit() || "+-.eE".contains(c));
+    if !is_number {
+        return f64::NAN;
+    }
+    string.parse().unwrap_or(f64::NAN)
+}
+
+/// Parses the number at the start of a string, as `parseFloat` does.
+fn parse_float_prefix(string: &str) -> f64 {
+    let string = string.trim_start();
+    let mut end = 0;
+    for i in 1..=string.len() {
+        if string.is_char_boundary(i) && string[..i].parse::<f64>().is_ok() {
+            let last = string[..i].chars().last();
+            if last.is_some_and(|c| c.is_ascii_digit() || c == '.') {
+                end = i;
+            }
+        }
+    }
+    string[..end].parse().unwrap_or(f64::NAN)
+}
+
+/// Converts a number to a string, as Flash Player does.
+fn number_to_string(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if value == value.trunc() && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else if value.abs() >= 1e15 || value.abs() < 1e-5 {
+        let string = format!("{:e}", value);
+        match string.find('e') {
+            Some(e) if !string[e + 1..].starts_with('-') => {
+                format!("{}e+{}", &string[..e], &string[e + 1..])
+            }
+            _ => string,
+        }
+    } else {
+        value.to_string()
+    }
+}
+
+fn arg(args: &[Value], i: usize) -> Value {
+    args.get(i).cloned().unwrap_or(Value::Undefined)
+}
+
+fn this_object(this: &Value) -> Option<ObjectRef> {
+    match *this {
+        Value::Object(ref object) => Some(object.clone()),
+        _ => None,
+    }
+}
+
+/// Returns an argument converted to an integer, or a default if it is `undefined`.
+fn int_arg(vm: &Vm, args: &[Value], i: usize, default: i64) -> i64 {
+    match args.get(i) {
+        None | Some(&Value::Undefined) => default,
+        Some(value) => {
+            let value = vm.to_number(value);
+            if value.is_nan() {
+                0
+            } else {
+                value as i64
+            }
+        }
+    }
+}
+
+fn object_constructor(vm: &mut Vm, _this: Value, args: Vec<Value>) -> Run<Value> {
+    Ok(match arg(&args, 0) {
+        value @ Value::Object(_) => value,
+        _ => Value::Object(vm.new_object()),
+    })
+}
+
+fn object_to_string(vm: &mut Vm, this: Value, _args: Vec<Value>) -> Run<Value> {
+    Ok(Value::Str(vm.to_string(&this)))
+}
+
+fn object_has_own_property(vm: &mut Vm, this: Value, args: Vec<Value>) -> Run<Value> {
+    let name = vm.to_string(&arg(&args, 0));
+    Ok(Value::Bool(this_object(&this).is_some_and(|object| {
+        vm.own_property(&object, &name).is_some()
+    })))
+}
+
+fn function_call(vm: &mut Vm, this: Value, args: Vec<Value>) -> Run<Value> {
+    let new_this = arg(&args, 0);
+    let args = args.into_iter().skip(1).collect();
+    vm.call_function(&this, new_this, args)
+}
+
+fn function_apply(vm: &mut Vm, this: Value, args: Vec<Value>) -> Run<Value> {
+    let new_this = arg(&args, 0);
+    let args = match arg(&args, 1) {
+        Value::Object(ref array) => match array.0.borrow().kind {
+            ObjectKind::Array(ref elements) => elements.clone(),
+            _ => vec![],
+        },
+        _ => vec![],
+    };
+    vm.call_function(&this, new_this, args)
+}
+
+fn array_constructor(vm: &mut Vm, _this: Value, args: Vec<Value>) -> Run<Value> {
+    // One number is the length of the array.
+    let elements = match args[..] {
+        [Value::Number(length)] if length >= 0.0 => vec![Value::Undefined; length as usize],
+        _ => args,
+    };
+    Ok(Value::Object(vm.new_array(elements)))
+}
+
+fn with_elements<T, F>(this: &Value, f: F) -> Option<T>
+where
+    F: FnOnce(&mut Vec<Value>) -> T,
+{
+    let object = this_object(this)?;
+    let mut object = object.0.borrow_mut();
+    match object.kind {
+        ObjectKind::Array(ref mut elements) => Some(f(elements)),
+        _ => None,
+    }
+}
+
+fn array_push(_vm: &mut Vm, this: Value, args: Vec<Value>) -> Run<Value> {
+    let length = with_elements(&this, |elements| {
+        elements.extend(args);
+        elements.len()
+    });
+    Ok(length.map_or(Value::Undefined, |length| Value::Number(length as f64)))
+}
+
+fn array_pop(_vm: &mut Vm, this: Value, _args: Vec<Value>) -> Run<Value> {
+    Ok(with_elements(&this, |elements| elements.pop())
+        .and_then(|value| value)
+        .unwrap_or(Value::Undefined))
+}
+
+fn array_shift(_vm: &mut Vm, this: Value, _args: Vec<Value>) -> Run<Value> {
+    let value = with_elements(&this, |elements| {
+        if elements.is_empty() {
+            None
+        } else {
+            Some(elements.remove(0))
+        }
+    });
+    Ok(value.and_then(|value| value).unwrap_or(Value::Undefined))
+}
+
+fn array_join(vm: &mut Vm, this: Value, args: Vec<Value>) -> Run<Value> {
+    let separator = match arg(&args, 0) {
+        Value::Undefined => ",".to_string(),
+        separator => vm.to_string(&separator),
+    };
+    let elements = with_elements(&this, |elements| elements.clone()).unwrap_or_default();
+    let strings: Vec<_> = elements
+        .iter()
+        .map(|element| vm.to_string(element))
+        .collect();
+    Ok(Value::Str(strings.join(&separator)))
+}
+
+fn array_reverse(_vm: &mut Vm, this: Value, _args: Vec<Value>) -> Run<Value> {
+    with_elements(&this, |elements| elements.reverse());
+    Ok(this)
+}
+
+fn string_constructor(vm: &mut Vm, _this: Value, args: Vec<Value>) -> Run<Value> {
+    Ok(Value::Str(match args.first() {
+        Some(value) => vm.to_string(value),
+        None => String::new(),
+    }))
+}
+
+fn number_constructor(vm: &mut Vm, _this: Value, args: Vec<Value>) -> Run<Value> {
+    Ok(Value::Number(match args.first() {
+        Some(value) => vm.to_number(value),
+        None => 0.0,
+    }))
+}
+
+fn boolean_constructor(vm: &mut Vm, _this: Value, args: Vec<Value>) -> Run<Value> {
+    Ok(Value::Bool(
+        args.first().is_some_and(|value| vm.to_boolean(value)),
+    ))
+}
+
+fn string_from_char_code(vm: &mut Vm, _this: Value, args: Vec<Value>) -> Run<Value> {
+    let string = args
+        .iter()
+        .filter_map(|code| ::std::char::from_u32(to_i32(vm.to_number(code)) as u32 & 0xffff))
+        .collect();
+    Ok(Value::Str(string))
+}
+
+fn chars(vm: &Vm, this: &Value) -> Vec<char> {
+    vm.to_string(this).chars().collect()
+}
+
+fn string_char_at(vm: &mut Vm, this: Value, args: Vec<Value>) -> Run<Value> {
+    let chars = chars(vm, &this);
+    let index = int_arg(vm, &args, 0, 0);
+    Ok(Value::Str(
+        chars
+            .get(index as usize)
+            .filter(|_| index >= 0)
+            .map(|c| c.to_string())
+            .unwrap_or_default(),
+    ))
+}
+
+fn string_char_code_at(vm: &mut Vm, this: Value, args: Vec<Value>) -> Run<Value> {
+    let chars = chars(vm, &this);
+    let index = int_arg(vm, &args, 0, 0);
+    Ok(Value::Number(
+        chars
+            .get(index as usize)
+            .filter(|_| index >= 0)
+            .map_or(f64::NAN, |&c| f64::from(u32::from(c))),
+    ))
+}
+
+fn string_index_of(vm: &mut Vm, this: Value, args: Vec<Value>) -> Run<Value> {
+    let chars = chars(vm, &this);
+    let pattern: Vec<char> = vm.to_string(&arg(&args, 0)).chars().collect();
+    let start = int_arg(vm, &args, 1, 0).max(0) as usize;
+    let index = (start..=chars.len())
+        .find(|&i| chars[i..].starts_with(&pattern))
+        .map_or(-1.0, |i| i as f64);
+    Ok(Value::Number(index))
+}
+
+fn string_split(vm: &mut Vm, this: Value, args: Vec<Value>) -> Run<Value> {
+    let string = vm.to_string(&this);
+    let parts = match arg(&args, 0) {
+        Value::Undefined => vec![Value::Str(string)],
+        separator => {
+            let separator = vm.to_string(&separator);
+            if separator.is_empty() {
+                string.chars().map(|c| Value::Str(c.to_string())).collect()
+            } else {
+                string
+                    .split(&separator[..])
+                    .map(|part| Value::Str(part.to_string()))
+                    .collect()
+            }
+        }
+    };
+    Ok(Value::Object(vm.new_array(parts)))
+}
+
+fn string_substr(vm: &mut Vm, this: Value, args: Vec<Value>) -> Run<Value> {
+    let chars = chars(vm, &this);
+    let len = chars.len() as i64;
+    let start = int_arg(vm, &args, 0, 0);
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start.min(len)
+    };
+    let count = int_arg(vm, &args, 1, len).max(0);
+    let end = (start + count).min(len);
+    Ok(Value::Str(
+        chars[start as usize..end as usize].iter().collect(),
+    ))
+}
+
+fn string_substring(vm: &mut Vm, this: Value, args: Vec<Value>) -> Run<Value> {
+    let chars = chars(vm, &this);
+    let len = chars.len() as i64;
+    let start = int_arg(vm, &args, 0, 0).max(0).min(len);
+    let end = int_arg(vm, &args, 1, len).max(0).min(len);
+    let (start, end) = if start <= end {
+        (start, end)
+    } else {
+        (end, start)
+    };
+    Ok(Value::Str(
+        chars[start as usize..end as usize].iter().collect(),
+    ))
+}
+
+fn math_function<F: Fn(f64) -> f64>(vm: &mut Vm, args: &[Value], f: F) -> Run<Value> {
+    Ok(Value::Number(f(vm.to_number(&arg(args, 0)))))
+}
+
+fn math_function_2<F: Fn(f64, f64) -> f64>(vm: &mut Vm, args: &[Value], f: F) -> Run<Value> {
+    let a = vm.to_number(&arg(args, 0));
+    let b = vm.to_number(&arg(args, 1));
+    Ok(Value::Number(f(a, b)))
+}
+
+fn global_parse_int(vm: &mut Vm, _this: Value, args: Vec<Value>) -> Run<Value> {
+    let string = vm.to_string(&arg(&args, 0));
+    let string = string.trim_start();
+    let (sign, string) = match string.strip_prefix('-') {
+        Some(string) => (-1.0, string),
+        None => (1.0, string.strip_prefix('+').unwrap_or(string)),
+    };
+    let (radix, string) = match int_arg(vm, &args, 1, 0) {
+        0 => match string
+            .strip_prefix("0x")
+            .or_else(|| string.strip_prefix("0X"))
+        {
+            Some(hex) => (16, hex),
+            None => (10, string),
+        },
+        radix if (2..=36).contains(&radix) => (radix as u32, string),
+        _ => return Ok(Value::Number(f64::NAN)),
+    };
+    let digits: String = string.chars().take_while(|c| c.is_digit(radix)).collect();
+    if digits.is_empty() {
+        return Ok(Value::Number(f64::NAN));
+    }
+    let value = digits.chars().fold(0.0, |value, c| {
+        value * f64::from(radix) + f64::from(c.to_digit(radix).unwrap())
+    });
+    Ok(Value::Number(sign * value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avm1::assemble::assemble;
+
+    #[derive(Default)]
+    struct TestHost {
+        messages: Vec<String>,
+        frames: Vec<u32>,
+    }
+
+    impl Host for TestHost {
+        fn trace(&mut self, message: &str) {
+            self.messages.push(message.to_string());
+        }
+
+        fn goto_frame(&mut self, frame: u32) {
+            self.frames.push(frame);
+        }
+    }
+
+    fn run(text: &str, swf_version: u8) -> (Result<Value>, Vec<String>) {
+        let actions = assemble(text).unwrap();
+        let mut host = TestHost::default();
+        let result = Vm::new(swf_version, &mut host).run(&actions);
+        (result, host.messages)
+    }
+
+    #[test]
+    fn coerce_by_version() {
+        let text = "
+            push 'a', undefined
+            add2
+            trace
+            push '2', '3'
+            add
+            trace
+            push 'x'
+            not
+            trace
+            push undefined, 0
+            equals2
+            trace
+            push 1, 'a'
+            less2
+            trace
+        ";
+        let (_, messages) = run(text, 6);
+        assert_eq!(messages, vec!["a", "5", "true", "false", "undefined"]);
+        let (_, messages) = run(text, 7);
+        assert_eq!(
+            messages,
+            vec!["aundefined", "5", "false", "false", "undefined"]
+        );
+        let (_, messages) = run("push 1, 2\nequals\ntrace", 4);
+        assert_eq!(messages, vec!["0"]);
+    }
+
+    #[test]
+    fn call_functions_and_methods() {
+        let text = "
+            definefunction2 Point(r:2='x', r:3='y') preload_this {
+                push r:1, 'x', r:2
+                setmember
+                push r:1, 'y', r:3
+                setmember
+            }
+            push 'Point'
+            getvariable
+            push 'prototype'
+            getmember
+            push 'sum'
+            definefunction2 (r:2='scale') preload_this {
+                push r:1, 'x'
+                getmember
+                push r:1, 'y'
+                getmember
+                add2
+                push r:2
+                multiply
+                return
+            }
+            setmember
+            push 'p', 4, 3, 2, 'Point'
+            newobject
+            definelocal
+            push 10, 1, 'p'
+            getvariable
+            push 'sum'
+            callmethod
+            trace
+            push 'c', 'b', 'a', 3
+            initarray
+            storeregister r:0
+            push '-', 1, r:0
+            push 'join'
+            callmethod
+            trace
+            push 'p'
+            getvariable
+            push 'Point'
+            getvariable
+            instanceof
+            trace
+            push 'abc'
+            push 1, 1, 'abc'
+            push 'charAt'
+            callmethod
+            push 'toUpperCase'
+            getmember
+            typeof
+            trace
+        ";
+        let (result, messages) = run(text, 8);
+        assert_eq!(result.unwrap(), Value::Undefined);
+        assert_eq!(messages, vec!["70", "a-b-c", "true", "function"]);
+    }
+
+    #[test]
+    fn try_and_throw() {
+        let text = "
+            try {
+                push 'error'
+                throw
+            } catch r:1 {
+                push r:1
+                trace
+            } finally {
+                push 'finally'
+                trace
+            }
+            push 1
+            gotoframe2 stop
+            push 'uncaught'
+            throw
+        ";
+        let actions = assemble(text).unwrap();
+        let mut host = TestHost::default();
+        let error = Vm::new(8, &mut host).run(&actions).unwrap_err();
+        assert_eq!(error.to_string(), "Uncaught exception: uncaught");
+        assert_eq!(host.messages, vec!["error", "finally"]);
+        assert_eq!(host.frames, vec![0]);
+    }
+
+    #[test]
+    fn run_loops() {
+        let text = "
+            push 's', ''
+            definelocal
+            push 'i', 0
+            definelocal
+        loop:
+            push 'i'
+            getvariable
+            push 5
+            less2
+            not
+            if end
+            push 's', 's'
+            getvariable
+            push 'i'
+            getvariable
+            add2
+            setvariable
+            push 'i', 'i'
+            getvariable
+            increment
+            setvariable
+            jump loop
+        end:
+            push 's'
+            getvariable
+            return
+        ";
+        let (result, _) = run(text, 8);
+        assert_eq!(result.unwrap(), Value::Str("01234".to_string()));
+
+        let actions = assemble("forever:\njump forever").unwrap();
+        let mut host = NullHost;
+        let mut vm = Vm::new(8, &mut host);
+        vm.set_max_actions(100);
+        assert!(vm.run(&actions).is_err());
+    }
+
+    #[test]
+    fn limit_recursion() {
+        let text = "
+            definefunction2 f() {
+                push 0, 'f'
+                callfunction
+                return
+            }
+            push 0, 'f'
+            callfunction
+        ";
+        let (result, _) = run(text, 8);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Too many nested function calls"
+        );
+
+        // Objects with more properties than the stack holds are not built from nothing.
+        let actions = vec![
+            Action::Push(vec![types::Value::Int(0x7fff_ffff)]),
+            Action::InitObject,
+            Action::Return,
+        ];
+        let mut host = NullHost;
+        let mut vm = Vm::new(8, &mut host);
+        vm.set_max_actions(10);
+        assert!(vm.run(&actions).is_ok());
+    }
+}
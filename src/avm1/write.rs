@@ -1,11 +1,22 @@
 use avm1::opcode::OpCode;
+use avm1::read::MAX_NESTING_DEPTH;
 use avm1::types::*;
-use std::io::{Result, Write};
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result, Write};
+use std::rc::Rc;
 use write::SwfWrite;
 
 pub struct Writer<W: Write> {
     inner: W,
     version: u8,
+    /// The positions of the labels of the list being written, relative to its start.
+    labels: Rc<HashMap<Label, i64>>,
+    /// The position of the next action, relative to the start of the list being written.
+    position: i64,
+    /// Whether actions are only written to find their sizes, before the labels are known.
+    is_layout: bool,
+    /// The number of bodies that contain the actions being written.
+    depth: usize,
 }
 
 impl<W: Write> SwfWrite<W> for Writer<W> {
@@ -19,19 +30,154 @@ impl<W: Write> Writer<W> {
         Writer {
             inner: inner,
             version: version,
+            labels: Rc::new(HashMap::new()),
+            position: 0,
+            is_layout: false,
+            depth: 0,
         }
     }
 
+    /// Writes a list of actions, followed by an `End` action.
+    ///
+    /// Branches are written with the offsets of their labels, which must be defined once in
+    /// the list or in the bodies of the actions it contains. The hidden actions of an
+    /// `Action::OffsetLabel` are not written, as they are part of the following action.
     pub fn write_action_list(&mut self, actions: &ActionList) -> Result<()> {
+        let mut labels = HashMap::new();
+        self.layout_actions(actions, self.position, self.depth, &mut labels)?;
+        self.labels = Rc::new(labels);
+        self.write_actions(actions)?;
+        self.write_action(&Action::End)
+    }
+
+    /// Writes actions without an `End` action, as for the body of a function.
+    fn write_actions(&mut self, actions: &[Action]) -> Result<()> {
         for action in actions {
-            try!(self.write_action(&action));
+            self.write_action(action)?;
         }
-        try!(self.write_u8(0)); // End
         Ok(())
     }
 
-    #[cfg_attr(any(feature = "clippy", feature = "cargo-clippy"), allow(cyclomatic_complexity))]
+    /// Writes an action. A branch can only be written as part of a list that defines its label.
     pub fn write_action(&mut self, action: &Action) -> Result<()> {
+        let mut data = vec![];
+        self.nested(&mut data, self.position)
+            .write_action_record(action)?;
+        self.inner.write_all(&data)?;
+        self.position += data.len() as i64;
+        Ok(())
+    }
+
+    /// Returns a writer for part of the list being written, starting at `position`.
+    fn nested<'a>(&self, inner: &'a mut Vec<u8>, position: i64) -> Writer<&'a mut Vec<u8>> {
+        Writer {
+            inner,
+            version: self.version,
+            labels: self.labels.clone(),
+            position,
+            is_layout: self.is_layout,
+            depth: self.depth,
+        }
+    }
+
+    /// Returns a writer for a body of the action being written, starting at `position`.
+    fn body<'a>(&self, inner: &'a mut Vec<u8>, position: i64) -> Result<Writer<&'a mut Vec<u8>>> {
+        if self.depth >= MAX_NESTING_DEPTH {
+            return Err(nested_too_deeply());
+        }
+        let mut writer = self.nested(inner, position);
+        writer.depth += 1;
+        Ok(writer)
+    }
+
+    /// Finds the positions of the labels in a list of actions that starts at `position` and is
+    /// nested in `depth` bodies, and returns the position of its end.
+    fn layout_actions(
+        &self,
+        actions: &[Action],
+        mut position: i64,
+        depth: usize,
+        labels: &mut HashMap<Label, i64>,
+    ) -> Result<i64> {
+        if depth > MAX_NESTING_DEPTH {
+            return Err(nested_too_deeply());
+        }
+        let mut data = vec![];
+        let mut writer = self.nested(&mut data, position);
+        writer.is_layout = true;
+        for action in actions {
+            let label = match *action {
+                Action::Label(label) => Some((label, position)),
+                Action::OffsetLabel(ref label) => {
+                    Some((label.label, position + i64::from(label.offset)))
+                }
+                _ => None,
+            };
+            if let Some((label, label_position)) = label {
+                if labels.insert(label, label_position).is_some() {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "Label is defined more than once",
+                    ));
+                }
+                continue;
+            }
+
+            writer.inner.clear();
+            position = match *action {
+                Action::DefineFunction { ref actions, .. }
+                | Action::DefineFunction2(Function { ref actions, .. })
+                | Action::With { ref actions } => {
+                    writer.write_action_header_for_body(action, 0)?;
+                    let start = position + writer.inner.len() as i64;
+                    writer.layout_actions(actions, start, depth + 1, labels)?
+                }
+                Action::Try(ref try_block) => {
+                    writer.write_try_header(try_block, [0; 3])?;
+                    let mut end = position + writer.inner.len() as i64;
+                    end = writer.layout_actions(&try_block.try, end, depth + 1, labels)?;
+                    if let Some((_, ref catch)) = try_block.catch {
+                        end = writer.layout_actions(catch, end, depth + 1, labels)?;
+                    }
+                    if let Some(ref finally) = try_block.finally {
+                        end = writer.layout_actions(finally, end, depth + 1, labels)?;
+                    }
+                    end
+                }
+                _ => {
+                    writer.write_action_record(action)?;
+                    position + writer.inner.len() as i64
+                }
+            };
+        }
+        Ok(position)
+    }
+
+    /// Returns the offset of a branch at the current position to a label.
+    fn branch_offset(&self, label: Label) -> Result<i16> {
+        if self.is_layout {
+            return Ok(0);
+        }
+        let target = self
+            .labels
+            .get(&label)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Undefined branch label"))?;
+        let offset = target - (self.position + 5);
+        if offset < i64::from(i16::MIN) || offset > i64::from(i16::MAX) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Branch target is out of range",
+            ));
+        }
+        Ok(offset as i16)
+    }
+
+    /// Writes an action record at the current position, with the bodies that follow it.
+    #[cfg_attr(
+        any(feature = "clippy", feature = "cargo-clippy"),
+        allow(cyclomatic_complexity)
+    )]
+    fn write_action_record(&mut self, action: &Action) -> Result<()> {
         match *action {
             Action::Add => self.write_action_header(OpCode::Add, 0)?,
             Action::Add2 => self.write_action_header(OpCode::Add2, 0)?,
@@ -58,86 +204,22 @@ impl<W: Write> Writer<W> {
                 }
             }
             Action::Decrement => self.write_action_header(OpCode::Decrement, 0)?,
-            Action::DefineFunction {
-                ref name,
-                ref params,
-                ref actions,
-            } => {
-                let mut action_buf = vec![];
-                {
-                    let mut fn_writer = Writer::new(&mut action_buf, self.version);
-                    fn_writer.write_action_list(actions)?;
-                }
-                let len = name.len()
-                    + 1
-                    + 2
-                    + params.iter().map(|p| p.len() + 1).sum::<usize>()
-                    + 2
-                    + action_buf.len();
-                self.write_action_header(OpCode::DefineFunction, len)?;
-                self.write_c_string(name)?;
-                self.write_u16(params.len() as u16)?;
-                for param in params {
-                    self.write_c_string(param)?;
-                }
-                self.write_u16(action_buf.len() as u16)?;
-                self.inner.write_all(&action_buf)?;
-            }
-            Action::DefineFunction2(ref function) => {
-                let mut action_buf = vec![];
-                {
-                    let mut fn_writer = Writer::new(&mut action_buf, self.version);
-                    fn_writer.write_action_list(&function.actions)?;
-                }
-                let len = function.name.len() + 1 + 3
-                    + function
-                        .params
-                        .iter()
-                        .map(|p| p.name.len() + 2)
-                        .sum::<usize>() + 4 + action_buf.len();
-                let num_registers = function
-                    .params
-                    .iter()
-                    .map(|p| if p.register_index.is_none() { 1 } else { 0 })
-                    .sum();
-                self.write_action_header(OpCode::DefineFunction2, len)?;
-                self.write_c_string(&function.name)?;
-                self.write_u16(function.params.len() as u16)?;
-                self.write_u8(num_registers)?;
-                let flags =
-                    if function.preload_global {
-                        0b1_00000000
-                    } else {
-                        0
-                    } | if function.preload_parent {
-                        0b10000000
-                    } else {
-                        0
-                    } | if function.preload_root { 0b1000000 } else { 0 }
-                        | if function.suppress_super { 0b100000 } else { 0 }
-                        | if function.preload_super { 0b10000 } else { 0 }
-                        | if function.suppress_arguments {
-                            0b1000
-                        } else {
-                            0
-                        } | if function.preload_arguments { 0b100 } else { 0 }
-                        | if function.suppress_this { 0b10 } else { 0 }
-                        | if function.preload_this { 0b1 } else { 0 };
-                self.write_u16(flags)?;
-                for param in &function.params {
-                    self.write_u8(if let Some(n) = param.register_index {
-                        n
-                    } else {
-                        0
-                    })?;
-                    self.write_c_string(&param.name)?;
-                }
-                self.write_u16(action_buf.len() as u16)?;
-                self.inner.write_all(&action_buf)?;
+            Action::DefineFunction { ref actions, .. }
+            | Action::DefineFunction2(Function { ref actions, .. })
+            | Action::With { ref actions } => {
+                let mut header = vec![];
+                self.nested(&mut header, self.position)
+                    .write_action_header_for_body(action, 0)?;
+                let mut body = vec![];
+                self.body(&mut body, self.position + header.len() as i64)?
+                    .write_actions(actions)?;
+                self.write_action_header_for_body(action, body.len())?;
+                self.inner.write_all(&body)?;
             }
             Action::DefineLocal => self.write_action_header(OpCode::DefineLocal, 0)?,
             Action::DefineLocal2 => self.write_action_header(OpCode::DefineLocal2, 0)?,
             Action::Divide => self.write_action_header(OpCode::Divide, 0)?,
+            Action::End => self.write_action_header(OpCode::End, 0)?,
             Action::Delete => self.write_action_header(OpCode::Delete, 0)?,
             Action::Delete2 => self.write_action_header(OpCode::Delete2, 0)?,
             Action::EndDrag => self.write_action_header(OpCode::EndDrag, 0)?,
@@ -167,7 +249,8 @@ impl<W: Write> Writer<W> {
                     SendVarsMethod::None => 0,
                     SendVarsMethod::Get => 1,
                     SendVarsMethod::Post => 2,
-                } << 6) | if is_target_sprite { 0b10 } else { 0 }
+                } << 6)
+                    | if is_target_sprite { 0b10 } else { 0 }
                     | if is_load_vars { 0b1 } else { 0 };
                 self.write_u8(flags)?;
             }
@@ -182,11 +265,11 @@ impl<W: Write> Writer<W> {
             } => {
                 if scene_offset != 0 {
                     self.write_action_header(OpCode::GotoFrame2, 3)?;
-                    self.write_u8(if set_playing { 0b11 } else { 0b01 })?;
+                    self.write_u8(if set_playing { 0b11 } else { 0b10 })?;
                     self.write_u16(scene_offset)?;
                 } else {
                     self.write_action_header(OpCode::GotoFrame2, 1)?;
-                    self.write_u8(if set_playing { 0b10 } else { 0b00 })?;
+                    self.write_u8(if set_playing { 0b01 } else { 0b00 })?;
                 }
             }
             Action::GotoLabel(ref label) => {
@@ -194,7 +277,8 @@ impl<W: Write> Writer<W> {
                 self.write_c_string(label)?;
             }
             Action::Greater => self.write_action_header(OpCode::Greater, 0)?,
-            Action::If { offset, jump_to: _ } => {
+            Action::If { target } => {
+                let offset = self.branch_offset(target)?;
                 self.write_action_header(OpCode::If, 2)?;
                 self.write_i16(offset)?;
             }
@@ -203,10 +287,12 @@ impl<W: Write> Writer<W> {
             Action::InitArray => self.write_action_header(OpCode::InitArray, 0)?,
             Action::InitObject => self.write_action_header(OpCode::InitObject, 0)?,
             Action::InstanceOf => self.write_action_header(OpCode::InstanceOf, 0)?,
-            Action::Jump { offset, jump_to: _ } => {
+            Action::Jump { target } => {
+                let offset = self.branch_offset(target)?;
                 self.write_action_header(OpCode::Jump, 2)?;
                 self.write_i16(offset)?;
             }
+            Action::Label(_) | Action::OffsetLabel(_) => (),
             Action::Less => self.write_action_header(OpCode::Less, 0)?,
             Action::Less2 => self.write_action_header(OpCode::Less2, 0)?,
             Action::MBAsciiToChar => self.write_action_header(OpCode::MBAsciiToChar, 0)?,
@@ -224,23 +310,7 @@ impl<W: Write> Writer<W> {
             Action::Pop => self.write_action_header(OpCode::Pop, 0)?,
             Action::PreviousFrame => self.write_action_header(OpCode::PreviousFrame, 0)?,
             Action::Push(ref values) => {
-                let len = values
-                    .iter()
-                    .map(|v| match *v {
-                        Value::Str(ref string) => string.len() + 2,
-                        Value::Null | Value::Undefined => 1,
-                        Value::Register(_) | Value::Bool(_) => 2,
-                        Value::Double(_) => 9,
-                        Value::Float(_) | Value::Int(_) => 5,
-                        Value::ConstantPool(v) => {
-                            if v < 256 {
-                                2
-                            } else {
-                                3
-                            }
-                        }
-                    })
-                    .sum();
+                let len = values.iter().map(push_value_length).sum();
                 self.write_action_header(OpCode::Push, len)?;
                 for value in values {
                     self.write_push_value(value)?;
@@ -282,47 +352,26 @@ impl<W: Write> Writer<W> {
             Action::ToString => self.write_action_header(OpCode::ToString, 0)?,
             Action::Trace => self.write_action_header(OpCode::Trace, 0)?,
             Action::Try(ref try_block) => {
-                let try_length;
-                let catch_length;
-                let finally_length;
-                let mut action_buf = vec![];
+                let mut header = vec![];
+                self.nested(&mut header, self.position)
+                    .write_try_header(try_block, [0; 3])?;
+                let mut position = self.position + header.len() as i64;
+                let mut bodies = vec![];
+                let mut lengths = [0; 3];
                 {
-                    let mut fn_writer = Writer::new(&mut action_buf, self.version);
-                    fn_writer.write_action_list(&try_block.try)?;
-                    try_length = fn_writer.inner.len();
-                    if let Some((_, ref catch)) = try_block.catch {
-                        fn_writer.write_action_list(catch)?;
-                    }
-                    catch_length = fn_writer.inner.len() - try_length;
-                    if let Some(ref finally) = try_block.finally {
-                        fn_writer.write_action_list(finally)?;
+                    let catch = try_block.catch.as_ref().map(|(_, catch)| catch);
+                    let blocks = [Some(&try_block.try), catch, try_block.finally.as_ref()];
+                    for (length, actions) in lengths.iter_mut().zip(&blocks) {
+                        if let Some(actions) = *actions {
+                            let start = bodies.len();
+                            self.body(&mut bodies, position)?.write_actions(actions)?;
+                            *length = bodies.len() - start;
+                            position += *length as i64;
+                        }
                     }
-                    finally_length = fn_writer.inner.len() - (try_length + catch_length);
-                }
-                let len = 7 + action_buf.len()
-                    + if let Some((CatchVar::Var(ref name), _)) = try_block.catch {
-                        name.len() + 1
-                    } else {
-                        1
-                    };
-                self.write_action_header(OpCode::Try, len)?;
-                self.write_u8(
-                    if let Some((CatchVar::Register(_), _)) = try_block.catch {
-                        0b100
-                    } else {
-                        0
-                    } | if try_block.finally.is_some() { 0b10 } else { 0 }
-                        | if try_block.catch.is_some() { 0b1 } else { 0 },
-                )?;
-                self.write_u16(try_length as u16)?;
-                self.write_u16(catch_length as u16)?;
-                self.write_u16(finally_length as u16)?;
-                match try_block.catch {
-                    Some((CatchVar::Var(ref name), _)) => self.write_c_string(name)?,
-                    Some((CatchVar::Register(i), _)) => self.write_u8(i)?,
-                    _ => (),
                 }
-                self.inner.write_all(&action_buf)?;
+                self.write_try_header(try_block, lengths)?;
+                self.inner.write_all(&bodies)?;
             }
             Action::TypeOf => self.write_action_header(OpCode::TypeOf, 0)?,
             Action::WaitForFrame {
@@ -339,15 +388,6 @@ impl<W: Write> Writer<W> {
                 self.write_action_header(OpCode::WaitForFrame2, 1)?;
                 self.write_u8(num_actions_to_skip)?;
             }
-            Action::With { ref actions } => {
-                let mut action_buf = vec![];
-                {
-                    let mut fn_writer = Writer::new(&mut action_buf, self.version);
-                    fn_writer.write_action_list(actions)?;
-                }
-                self.write_action_header(OpCode::With, action_buf.len())?;
-                self.inner.write_all(&action_buf)?;
-            }
             Action::Unknown { opcode, ref data } => {
                 self.write_opcode_and_length(opcode, data.len())?;
                 self.inner.write_all(data)?;
@@ -362,17 +402,124 @@ impl<W: Write> Writer<W> {
     }
 
     pub fn write_opcode_and_length(&mut self, opcode: u8, length: usize) -> Result<()> {
+        if opcode < 0x80 && length != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Opcodes less than 0x80 must have length 0",
+            ));
+        }
         try!(self.write_u8(opcode));
-        assert!(
-            opcode >= 0x80 || length == 0,
-            "Opcodes less than 0x80 must have length 0"
-        );
         if opcode >= 0x80 {
-            try!(self.write_u16(length as u16));
+            try!(self.write_u16(to_u16(length)?));
         }
         Ok(())
     }
 
+    /// Writes the record of a `DefineFunction`, `DefineFunction2` or `With` action, whose body
+    /// of `code_length` bytes follows it.
+    pub(crate) fn write_action_header_for_body(
+        &mut self,
+        action: &Action,
+        code_length: usize,
+    ) -> Result<()> {
+        match *action {
+            Action::DefineFunction {
+                ref name,
+                ref params,
+                ..
+            } => {
+                let len =
+                    name.len() + 1 + 2 + params.iter().map(|p| p.len() + 1).sum::<usize>() + 2;
+                self.write_action_header(OpCode::DefineFunction, len)?;
+                self.write_c_string(name)?;
+                self.write_u16(to_u16(params.len())?)?;
+                for param in params {
+                    self.write_c_string(param)?;
+                }
+            }
+            Action::DefineFunction2(ref function) => {
+                let len = function.name.len()
+                    + 1
+                    + 3
+                    + function
+                        .params
+                        .iter()
+                        .map(|p| p.name.len() + 2)
+                        .sum::<usize>()
+                    + 4;
+                self.write_action_header(OpCode::DefineFunction2, len)?;
+                self.write_c_string(&function.name)?;
+                self.write_u16(to_u16(function.params.len())?)?;
+                self.write_u8(function.num_registers)?;
+                let flags = if function.preload_global {
+                    0b1_00000000
+                } else {
+                    0
+                } | if function.preload_parent {
+                    0b10000000
+                } else {
+                    0
+                } | if function.preload_root { 0b1000000 } else { 0 }
+                    | if function.suppress_super { 0b100000 } else { 0 }
+                    | if function.preload_super { 0b10000 } else { 0 }
+                    | if function.suppress_arguments {
+                        0b1000
+                    } else {
+                        0
+                    }
+                    | if function.preload_arguments { 0b100 } else { 0 }
+                    | if function.suppress_this { 0b10 } else { 0 }
+                    | if function.preload_this { 0b1 } else { 0 };
+                self.write_u16(flags)?;
+                for param in &function.params {
+                    self.write_u8(param.register_index.unwrap_or(0))?;
+                    self.write_c_string(&param.name)?;
+                }
+            }
+            Action::With { .. } => self.write_action_header(OpCode::With, 2)?,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "Action does not have a body",
+                ))
+            }
+        }
+        self.write_u16(to_u16(code_length)?)
+    }
+
+    /// Writes the fields of an `ActionTry` before its bodies, which are `lengths` bytes long.
+    pub(crate) fn write_try_header(
+        &mut self,
+        try_block: &TryBlock,
+        lengths: [usize; 3],
+    ) -> Result<()> {
+        let len = 7
+            + lengths.iter().sum::<usize>()
+            + if let Some((CatchVar::Var(ref name), _)) = try_block.catch {
+                name.len() + 1
+            } else {
+                1
+            };
+        self.write_action_header(OpCode::Try, len)?;
+        self.write_u8(
+            if let Some((CatchVar::Register(_), _)) = try_block.catch {
+                0b100
+            } else {
+                0
+            } | if try_block.finally.is_some() { 0b10 } else { 0 }
+                | if try_block.catch.is_some() { 0b1 } else { 0 },
+        )?;
+        for &length in &lengths {
+            self.write_u16(to_u16(length)?)?;
+        }
+        match try_block.catch {
+            Some((CatchVar::Var(ref name), _)) => self.write_c_string(name),
+            Some((CatchVar::Register(i), _)) => self.write_u8(i),
+            // The catch variable is written even without a catch block.
+            None => self.write_u8(0),
+        }
+    }
+
     fn write_push_value(&mut self, value: &Value) -> Result<()> {
         match *value {
             Value::Str(ref string) => {
@@ -419,6 +566,35 @@ impl<W: Write> Writer<W> {
     }
 }
 
+/// Returns the length of a value in the record of an `Action::Push`, with its type.
+pub(crate) fn push_value_length(value: &Value) -> usize {
+    match *value {
+        Value::Str(ref string) => string.len() + 2,
+        Value::Null | Value::Undefined => 1,
+        Value::Register(_) | Value::Bool(_) => 2,
+        Value::Double(_) => 9,
+        Value::Float(_) | Value::Int(_) => 5,
+        Value::ConstantPool(v) => {
+            if v < 256 {
+                2
+            } else {
+                3
+            }
+        }
+    }
+}
+
+fn nested_too_deeply() -> Error {
+    Error::new(ErrorKind::InvalidData, "Actions are nested too deeply")
+}
+
+fn to_u16(length: usize) -> Result<u16> {
+    if length > usize::from(u16::MAX) {
+        return Err(Error::new(ErrorKind::InvalidData, "Action is too long"));
+    }
+    Ok(length as u16)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -439,4 +615,35 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn write_action_list() {
+        for (swf_version, actions, expected_bytes) in test_data::avm1_list_tests() {
+            let mut written_bytes = Vec::new();
+            Writer::new(&mut written_bytes, swf_version)
+                .write_action_list(&actions)
+                .unwrap();
+            if written_bytes != expected_bytes {
+                panic!(
+                    "Error writing actions.\nActions:\n{:?}\n\nWrote:\n{:?}\n\nExpected:\n{:?}",
+                    actions, written_bytes, expected_bytes
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn write_deeply_nested_actions() {
+        let mut actions = vec![Action::Stop];
+        for _ in 0..MAX_NESTING_DEPTH {
+            actions = vec![Action::With { actions }];
+        }
+        let mut written_bytes = Vec::new();
+        let mut writer = Writer::new(&mut written_bytes, 8);
+        assert!(writer.write_action_list(&actions).is_ok());
+
+        let actions = vec![Action::With { actions }];
+        assert!(writer.write_action_list(&actions).is_err());
+        assert!(writer.write_action(&actions[0]).is_err());
+    }
 }
@@ -0,0 +1,392 @@
+//! Lookup of character definitions and the characters they depend on.
+use std::collections::{BTreeMap, BTreeSet};
+use types::*;
+
+/// The ID used for bitmap fills that have no bitmap.
+pub(crate) const NO_BITMAP: CharacterId = 0xffff;
+
+impl Tag {
+    /// Returns the ID of the character defined by this tag, or `None` if it defines none.
+    ///
+    /// Tags that add information to a character defined elsewhere, such as `DefineFontInfo`,
+    /// do not define a character.
+    pub fn character_id(&self) -> Option<CharacterId> {
+        match *self {
+            Tag::DefineBinaryData { id, .. }
+            | Tag::DefineBits { id, .. }
+            | Tag::DefineBitsJpeg2 { id, .. } => Some(id),
+            Tag::DefineBitsJpeg3(ref jpeg) => Some(jpeg.id),
+            Tag::DefineBitsLossless(ref bitmap) => Some(bitmap.id),
+            Tag::DefineButton(ref button) | Tag::DefineButton2(ref button) => Some(button.id),
+            Tag::DefineEditText(ref edit_text) => Some(edit_text.id),
+            Tag::DefineFont(ref font) => Some(font.id),
+            Tag::DefineFont2(ref font) => Some(font.id),
+            Tag::DefineFont4(ref font) => Some(font.id),
+            Tag::DefineMorphShape(ref morph_shape) => Some(morph_shape.id),
+            Tag::DefineShape(ref shape) => Some(shape.id),
+            Tag::DefineSound(ref sound) => Some(sound.id),
+            Tag::DefineSprite(ref sprite) => Some(sprite.id),
+            Tag::DefineText(ref text) => Some(text.id),
+            Tag::DefineVideoStream(ref video) => Some(video.id),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the character that a tag adds information to, for tags that do not define a
+/// character themselves.
+pub(crate) fn character_data_id(tag: &Tag) -> Option<CharacterId> {
+    match *tag {
+        Tag::CsmTextSettings(ref settings) => Some(settings.id),
+        Tag::DefineButtonColorTransform { id, .. }
+        | Tag::DefineFontAlignZones { id, .. }
+        | Tag::DefineFontName { id, .. }
+        | Tag::DefineScalingGrid { id, .. } => Some(id),
+        Tag::DefineButtonSound(ref sounds) => Some(sounds.id),
+        Tag::DefineFontInfo(ref info) => Some(info.id),
+        _ => None,
+    }
+}
+
+/// The characters defined in a tag list, and the characters each of them depends on.
+#[derive(Clone, Debug)]
+pub struct Dictionary<'a> {
+    characters: BTreeMap<CharacterId, &'a Tag>,
+    dependencies: BTreeMap<CharacterId, BTreeSet<CharacterId>>,
+    exports: BTreeMap<String, CharacterId>,
+    roots: BTreeSet<CharacterId>,
+}
+
+impl<'a> Dictionary<'a> {
+    pub fn from_swf(swf: &'a Swf) -> Dictionary<'a> {
+        Dictionary::new(&swf.tags)
+    }
+
+    /// Builds the dictionary of a main timeline's tags.
+    ///
+    /// Characters imported with `ImportAssets` are defined by that tag. Sounds added to a
+    /// button by `DefineButtonSound` are dependencies of the button.
+    pub fn new(tags: &'a [Tag]) -> Dictionary<'a> {
+        let mut characters = BTreeMap::new();
+        let mut dependencies: BTreeMap<CharacterId, BTreeSet<CharacterId>> = BTreeMap::new();
+        let mut exports = BTreeMap::new();
+        let mut roots = BTreeSet::new();
+        for tag in tags {
+            if let Some(id) = tag.character_id() {
+                characters.insert(id, tag);
+                let mut references = BTreeSet::new();
+                add_character_references(tag, &mut references);
+                dependencies.entry(id).or_default().extend(references);
+                continue;
+            }
+            match *tag {
+                Tag::ImportAssets { ref imports, .. } => {
+                    for asset in imports {
+                        characters.insert(asset.id, tag);
+                        dependencies.entry(asset.id).or_default();
+                    }
+                }
+                Tag::DefineButtonSound(ref sounds) => {
+                    let transitions = [
+                        &sounds.over_to_up_sound,
+                        &sounds.up_to_over_sound,
+                        &sounds.over_to_down_sound,
+                        &sounds.down_to_over_sound,
+                    ];
+                    if let Some(dependencies) = dependencies.get_mut(&sounds.id) {
+                        dependencies.extend(
+                            transitions
+                                .iter()
+                                .filter_map(|sound| sound.as_ref().map(|&(id, _)| id))
+                                .filter(|&id| id != 0),
+                        );
+                    }
+                }
+                Tag::ExportAssets(ref assets) => {
+                    for asset in assets {
+                        exports.insert(asset.name.clone(), asset.id);
+                        roots.insert(asset.id);
+                    }
+                }
+                Tag::SymbolClass(ref links) => {
+                    // ID 0 links a class to the main timeline.
+                    for link in links.iter().filter(|link| link.id != 0) {
+                        exports.insert(link.class_name.clone(), link.id);
+                        roots.insert(link.id);
+                    }
+                }
+                Tag::DoInitAction { id, .. } => {
+                    roots.insert(id);
+                }
+                _ => add_timeline_references(tag, &mut roots),
+            }
+        }
+        Dictionary {
+            characters,
+            dependencies,
+            exports,
+            roots,
+        }
+    }
+
+    /// Returns the tag that defines a character.
+    pub fn get(&self, id: CharacterId) -> Option<&'a Tag> {
+        self.characters.get(&id).cloned()
+    }
+
+    /// Returns the IDs of the defined characters, in ascending order.
+    pub fn ids(&self) -> impl Iterator<Item = CharacterId> + '_ {
+        self.characters.keys().cloned()
+    }
+
+    /// Returns the characters that a character refers to directly, or `None` if it is not
+    /// defined. The IDs may include characters that are not defined.
+    pub fn dependencies(&self, id: CharacterId) -> Option<&BTreeSet<CharacterId>> {
+        self.dependencies.get(&id)
+    }
+
+    /// Returns all characters needed to display a character, not including itself.
+    pub fn transitive_dependencies(&self, id: CharacterId) -> BTreeSet<CharacterId> {
+        let mut found = self.with_dependencies(&[id]);
+        found.remove(&id);
+        found
+    }
+
+    /// Returns the character exported under a name by `ExportAssets` or linked to a class by
+    /// `SymbolClass`.
+    pub fn exported_id(&self, name: &str) -> Option<CharacterId> {
+        self.exports.get(name).cloned()
+    }
+
+    /// Returns the names of exported characters, with their IDs.
+    pub fn exports(&self) -> &BTreeMap<String, CharacterId> {
+        &self.exports
+    }
+
+    /// Returns the characters used directly by the main timeline: those placed or started by
+    /// its control tags, exported, linked to a class or initialized by `DoInitAction`.
+    pub fn roots(&self) -> &BTreeSet<CharacterId> {
+        &self.roots
+    }
+
+    /// Returns the roots and all characters they depend on.
+    pub fn reachable(&self) -> BTreeSet<CharacterId> {
+        let roots: Vec<_> = self.roots.iter().cloned().collect();
+        self.with_dependencies(&roots)
+    }
+
+    /// Returns the defined characters that no root or other character refers to.
+    pub fn unreferenced(&self) -> BTreeSet<CharacterId> {
+        let referenced: BTreeSet<_> = self
+            .dependencies
+            .iter()
+            .flat_map(|(&id, dependencies)| dependencies.iter().filter(move |&&dep| dep != id))
+            .chain(self.roots.iter())
+            .cloned()
+            .collect();
+        self.ids().filter(|id| !referenced.contains(id)).collect()
+    }
+
+    /// Returns the IDs that are referred to but not defined.
+    pub fn dangling(&self) -> BTreeSet<CharacterId> {
+        self.dependencies
+            .values()
+            .flat_map(|dependencies| dependencies.iter())
+            .chain(self.roots.iter())
+            .filter(|id| !self.characters.contains_key(id))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the given characters and all characters they depend on.
+    pub fn with_dependencies(&self, ids: &[CharacterId]) -> BTreeSet<CharacterId> {
+        let mut found: BTreeSet<CharacterId> = ids.iter().cloned().collect();
+        let mut pending = ids.to_vec();
+        while let Some(id) = pending.pop() {
+            if let Some(dependencies) = self.dependencies.get(&id) {
+                for &dependency in dependencies {
+                    if found.insert(dependency) {
+                        pending.push(dependency);
+                    }
+                }
+            }
+        }
+        found
+    }
+}
+
+/// Adds the characters referred to by a character definition.
+fn add_character_references(tag: &Tag, references: &mut BTreeSet<CharacterId>) {
+    match *tag {
+        Tag::DefineButton(ref button) | Tag::DefineButton2(ref button) => {
+            references.extend(button.records.iter().map(|record| record.id));
+        }
+        Tag::DefineEditText(ref edit_text) => references.extend(edit_text.font_id),
+        Tag::DefineMorphShape(ref morph_shape) => {
+            for shape in &[&morph_shape.start, &morph_shape.end] {
+                add_style_references(&shape.fill_styles, &shape.line_styles, references);
+                add_record_references(&shape.shape, references);
+            }
+        }
+        Tag::DefineShape(ref shape) => {
+            add_style_references(
+                &shape.styles.fill_styles,
+                &shape.styles.line_styles,
+                references,
+            );
+            add_record_references(&shape.shape, references);
+        }
+        Tag::DefineSprite(ref sprite) => {
+            for tag in &sprite.tags {
+                add_timeline_references(tag, references);
+            }
+        }
+        Tag::DefineText(ref text) => {
+            references.extend(text.records.iter().filter_map(|record| record.font_id));
+        }
+        _ => (),
+    }
+}
+
+/// Adds the characters placed or started by a control tag of a timeline.
+fn add_timeline_references(tag: &Tag, references: &mut BTreeSet<CharacterId>) {
+    match *tag {
+        Tag::PlaceObject(ref place_object) => match place_object.action {
+            PlaceObjectAction::Place(id) | PlaceObjectAction::Replace(id) => {
+                references.insert(id);
+            }
+            PlaceObjectAction::Modify => (),
+        },
+        Tag::StartSound { id, .. } => {
+            references.insert(id);
+        }
+        _ => (),
+    }
+}
+
+fn add_record_references(records: &[ShapeRecord], references: &mut BTreeSet<CharacterId>) {
+    for record in records {
+        if let ShapeRecord::StyleChange(StyleChangeData {
+            new_styles: Some(ref styles),
+            ..
+        }) = *record
+        {
+            add_style_references(&styles.fill_styles, &styles.line_styles, references);
+        }
+    }
+}
+
+fn add_style_references(
+    fill_styles: &[FillStyle],
+    line_styles: &[LineStyle],
+    references: &mut BTreeSet<CharacterId>,
+) {
+    let line_fills = line_styles
+        .iter()
+        .filter_map(|line_style| line_style.fill_style.as_ref());
+    for fill_style in fill_styles.iter().chain(line_fills) {
+        if let FillStyle::Bitmap { id, .. } = *fill_style {
+            if id != NO_BITMAP {
+                references.insert(id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use read::read_swf;
+    use std::fs::File;
+    use test_data;
+
+    #[test]
+    fn button_dependencies() {
+        let file = File::open("tests/swfs/definebuttoncxformsound.swf").unwrap();
+        let swf = read_swf(file).unwrap();
+        let dictionary = Dictionary::from_swf(&swf);
+        match dictionary.get(2) {
+            Some(&Tag::DefineSound(_)) => (),
+            tag => panic!("Expected a sound, got {:?}", tag),
+        }
+        // The button uses a shape and, through DefineButtonSound, a sound.
+        let button_dependencies: Vec<_> = dictionary.dependencies(3).unwrap().iter().collect();
+        assert_eq!(button_dependencies, vec![&1, &2]);
+        assert_eq!(dictionary.reachable().len(), 3);
+        assert!(dictionary.unreferenced().is_empty());
+    }
+
+    #[test]
+    fn sprite_dependencies() {
+        let place = |id| {
+            Tag::PlaceObject(Box::new(test_data::place_object(
+                PlaceObjectAction::Place(id),
+                1,
+            )))
+        };
+        let text = |id, font_id| {
+            Tag::DefineText(Box::new(Text {
+                id,
+                bounds: Rectangle {
+                    x_min: 0.0,
+                    x_max: 0.0,
+                    y_min: 0.0,
+                    y_max: 0.0,
+                },
+                matrix: Matrix::new(),
+                records: vec![TextRecord {
+                    font_id: Some(font_id),
+                    color: None,
+                    x_offset: None,
+                    y_offset: None,
+                    height: Some(240),
+                    glyphs: vec![],
+                }],
+            }))
+        };
+        let tags = vec![
+            Tag::DefineFont(Box::new(FontV1 {
+                id: 1,
+                glyphs: vec![],
+            })),
+            text(2, 1),
+            text(3, 9),
+            Tag::DefineSprite(Sprite {
+                id: 4,
+                num_frames: 1,
+                tags: vec![place(2), place(5), Tag::ShowFrame],
+            }),
+            Tag::ImportAssets {
+                url: "library.swf".to_string(),
+                imports: vec![ExportedAsset {
+                    id: 5,
+                    name: "Imported".to_string(),
+                }],
+            },
+            Tag::ExportAssets(vec![ExportedAsset {
+                id: 4,
+                name: "Clip".to_string(),
+            }]),
+            Tag::DefineFontName {
+                id: 1,
+                name: "Font".to_string(),
+                copyright_info: String::new(),
+            },
+        ];
+        let dictionary = Dictionary::new(&tags);
+        assert_eq!(dictionary.ids().collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+        assert_eq!(dictionary.get(6), None);
+        assert_eq!(dictionary.dependencies(6), None);
+        assert_eq!(dictionary.exported_id("Clip"), Some(4));
+        let clip_dependencies: Vec<_> = dictionary.transitive_dependencies(4).into_iter().collect();
+        assert_eq!(clip_dependencies, vec![1, 2, 5]);
+        assert_eq!(
+            dictionary.unreferenced().into_iter().collect::<Vec<_>>(),
+            vec![3]
+        );
+        assert_eq!(
+            dictionary.dangling().into_iter().collect::<Vec<_>>(),
+            vec![9]
+        );
+    }
+}
@@ -0,0 +1,757 @@
+//! Reads glyph outlines from Compact Font Format (CFF) data.
+use super::read::{invalid_data, read_u16, read_u8, slice};
+use shape_utils::DrawCommand;
+use std::io::Result;
+
+/// Maximum nesting depth of subroutine calls, as defined by the Type 2 charstring format.
+const MAX_SUBR_DEPTH: usize = 10;
+
+/// A parsed CFF font, with Type 2 charstrings.
+pub struct Cff<'a> {
+    char_strings: Vec<&'a [u8]>,
+    global_subrs: Vec<&'a [u8]>,
+    /// Local subroutines for each font dict. Non-CID fonts have a single font dict.
+    local_subrs: Vec<Vec<&'a [u8]>>,
+    /// The font dict used by each glyph, for CID-keyed fonts.
+    fd_select: Option<Vec<u8>>,
+}
+
+impl<'a> Cff<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Cff<'a>> {
+        let header_size = usize::from(read_u8(data, 2)?);
+        let (_names, offset) = read_index(data, header_size)?;
+        let (top_dicts, offset) = read_index(data, offset)?;
+        let (_strings, offset) = read_index(data, offset)?;
+        let (global_subrs, _) = read_index(data, offset)?;
+        let top_dict = parse_dict(
+            top_dicts
+                .first()
+                .ok_or_else(|| invalid_data("No CFF font"))?,
+        )?;
+
+        if let Some(operands) = top_dict.get(CHARSTRING_TYPE) {
+            if operands.first() != Some(&2.0) {
+                return Err(invalid_data("Unsupported CFF charstring type"));
+            }
+        }
+        let char_strings_offset = top_dict
+            .get(CHAR_STRINGS)
+            .and_then(|operands| operands.first())
+            .ok_or_else(|| invalid_data("Missing CFF charstrings"))?;
+        let (char_strings, _) = read_index(data, *char_strings_offset as usize)?;
+
+        let mut local_subrs = vec![];
+        let mut fd_select = None;
+        match top_dict.get(FD_ARRAY).and_then(|operands| operands.first()) {
+            Some(&fd_array_offset) => {
+                // A CID-keyed font, where each font dict has its own private dict.
+                let (font_dicts, _) = read_index(data, fd_array_offset as usize)?;
+                for font_dict in font_dicts {
+                    local_subrs.push(read_local_subrs(data, &parse_dict(font_dict)?)?);
+                }
+                let fd_select_offset = top_dict
+                    .get(FD_SELECT)
+                    .and_then(|operands| operands.first())
+                    .ok_or_else(|| invalid_data("Missing CFF FDSelect"))?;
+                fd_select = Some(read_fd_select(
+                    data,
+                    *fd_select_offset as usize,
+                    char_strings.len(),
+                )?);
+            }
+            None => local_subrs.push(read_local_subrs(data, &top_dict)?),
+        }
+
+        Ok(Cff {
+            char_strings,
+            global_subrs,
+            local_subrs,
+            fd_select,
+        })
+    }
+
+    pub fn num_glyphs(&self) -> usize {
+        self.char_strings.len()
+    }
+
+    /// Returns the outline of a glyph in font units, with cubic curves approximated by
+    /// quadratic curves within `tolerance` font units.
+    pub fn glyph_outline(&self, glyph_id: u16, tolerance: f32) -> Result<Vec<DrawCommand>> {
+        let glyph_id = usize::from(glyph_id);
+        let char_string = self
+            .char_strings
+            .get(glyph_id)
+            .ok_or_else(|| invalid_data("Invalid glyph ID"))?;
+        let fd = match self.fd_select {
+            Some(ref fd_select) => usize::from(fd_select[glyph_id]),
+            None => 0,
+        };
+        let local_subrs = self
+            .local_subrs
+            .get(fd)
+            .ok_or_else(|| invalid_data("Invalid CFF font dict index"))?;
+        let mut interpreter = CharStringInterpreter {
+            global_subrs: &self.global_subrs,
+            local_subrs,
+            tolerance,
+            stack: vec![],
+            num_stems: 0,
+            has_width: false,
+            x: 0.0,
+            y: 0.0,
+            contour_start: None,
+            commands: vec![],
+        };
+        interpreter.run(char_string, 0)?;
+        interpreter.close_contour();
+        Ok(interpreter.commands)
+    }
+}
+
+// DICT operators. Two-byte operators are stored as 1200 + the second byte.
+const CHAR_STRINGS: u16 = 17;
+const PRIVATE: u16 = 18;
+const SUBRS: u16 = 19;
+const CHARSTRING_TYPE: u16 = 1206;
+const FD_ARRAY: u16 = 1236;
+const FD_SELECT: u16 = 1237;
+
+/// A parsed DICT, as a list of operators and their operands.
+struct Dict(Vec<(u16, Vec<f64>)>);
+
+impl Dict {
+    fn get(&self, operator: u16) -> Option<&[f64]> {
+        self.0
+            .iter()
+            .find(|&&(op, _)| op == operator)
+            .map(|(_, operands)| &operands[..])
+    }
+}
+
+/// Reads an INDEX structure, returning its entries and the offset following it.
+fn read_index(data: &[u8], offset: usize) -> Result<(Vec<&[u8]>, usize)> {
+    let count = usize::from(read_u16(data, offset)?);
+    if count == 0 {
+        return Ok((vec![], offset + 2));
+    }
+    let offset_size = usize::from(read_u8(data, offset + 2)?);
+    if !(1..=4).contains(&offset_size) {
+        return Err(invalid_data("Invalid CFF offset size"));
+    }
+    let offsets_start = offset + 3;
+    let read_offset = |i: usize| -> Result<usize> {
+        let bytes = slice(data, offsets_start + i * offset_size, offset_size)?;
+        Ok(bytes.iter().fold(0, |n, &b| (n << 8) | usize::from(b)))
+    };
+    // Offsets are relative to the byte preceding the object data.
+    let data_start = offsets_start + (count + 1) * offset_size - 1;
+    let mut entries = Vec::with_capacity(count);
+    for i in 0..count {
+        let start = read_offset(i)?;
+        let end = read_offset(i + 1)?;
+        if start == 0 || end < start {
+            return Err(invalid_data("Invalid CFF index offset"));
+        }
+        entries.push(slice(data, data_start + start, end - start)?);
+    }
+    let end = data_start + read_offset(count)?;
+    Ok((entries, end))
+}
+
+fn parse_dict(data: &[u8]) -> Result<Dict> {
+    let mut entries = vec![];
+    let mut operands = vec![];
+    let mut i = 0;
+    while i < data.len() {
+        let b0 = data[i];
+        i += 1;
+        match b0 {
+            0..=21 => {
+                let operator = if b0 == 12 {
+                    i += 1;
+                    1200 + u16::from(read_u8(data, i - 1)?)
+                } else {
+                    u16::from(b0)
+                };
+                entries.push((operator, ::std::mem::take(&mut operands)));
+            }
+            28 => {
+                operands.push(f64::from(read_u16(data, i)? as i16));
+                i += 2;
+            }
+            29 => {
+                let bytes = slice(data, i, 4)?;
+                operands.push(f64::from(i32::from_be_bytes([
+                    bytes[0], bytes[1], bytes[2], bytes[3],
+                ])));
+                i += 4;
+            }
+            30 => {
+                let (value, len) = parse_real(&data[i..])?;
+                operands.push(value);
+                i += len;
+            }
+            32..=246 => operands.push(f64::from(b0) - 139.0),
+            247..=250 => {
+                let b1 = f64::from(read_u8(data, i)?);
+                operands.push((f64::from(b0) - 247.0) * 256.0 + b1 + 108.0);
+                i += 1;
+            }
+            251..=254 => {
+                let b1 = f64::from(read_u8(data, i)?);
+                operands.push(-(f64::from(b0) - 251.0) * 256.0 - b1 - 108.0);
+                i += 1;
+            }
+            _ => return Err(invalid_data("Invalid CFF dict data")),
+        }
+    }
+    Ok(Dict(entries))
+}
+
+/// Parses a real number operand, returning its value and length in bytes.
+fn parse_real(data: &[u8]) -> Result<(f64, usize)> {
+    let mut text = String::new();
+    for (i, &byte) in data.iter().enumerate() {
+        for &nibble in &[byte >> 4, byte & 0xf] {
+            match nibble {
+                0..=9 => text.push((b'0' + nibble) as char),
+                0xa => text.push('.'),
+                0xb => text.push('E'),
+                0xc => text.push_str("E-"),
+                0xe => text.push('-'),
+                0xf => {
+                    let value = text.parse().map_err(|_| invalid_data("Invalid CFF real"))?;
+                    return Ok((value, i + 1));
+                }
+                _ => return Err(invalid_data("Invalid CFF real")),
+            }
+        }
+    }
+    Err(invalid_data("Unterminated CFF real"))
+}
+
+fn read_local_subrs<'a>(data: &'a [u8], dict: &Dict) -> Result<Vec<&'a [u8]>> {
+    let (size, offset) = match dict.get(PRIVATE) {
+        Some(&[size, offset]) => (size as usize, offset as usize),
+        _ => return Ok(vec![]),
+    };
+    let private_dict = parse_dict(slice(data, offset, size)?)?;
+    match private_dict
+        .get(SUBRS)
+        .and_then(|operands| operands.first())
+    {
+        // The subroutine offset is relative to the private dict.
+        Some(&subrs_offset) => Ok(read_index(data, offset + subrs_offset as usize)?.0),
+        None => Ok(vec![]),
+    }
+}
+
+fn read_fd_select(data: &[u8], offset: usize, num_glyphs: usize) -> Result<Vec<u8>> {
+    match read_u8(data, offset)? {
+        0 => Ok(slice(data, offset + 1, num_glyphs)?.to_vec()),
+        3 => {
+            let num_ranges = usize::from(read_u16(data, offset + 1)?);
+            let mut fd_select = vec![0; num_glyphs];
+            for i in 0..num_ranges {
+                let range = offset + 3 + i * 3;
+                let first = usize::from(read_u16(data, range)?);
+                let fd = read_u8(data, range + 2)?;
+                let end = usize::from(read_u16(data, range + 3)?);
+                for entry in fd_select.iter_mut().take(end).skip(first) {
+                    *entry = fd;
+                }
+            }
+            Ok(fd_select)
+        }
+        _ => Err(invalid_data("Unsupported CFF FDSelect format")),
+    }
+}
+
+/// Returns the bias added to subroutine numbers.
+fn subr_bias(num_subrs: usize) -> i32 {
+    if num_subrs < 1240 {
+        107
+    } else if num_subrs < 33900 {
+        1131
+    } else {
+        32768
+    }
+}
+
+struct CharStringInterpreter<'a, 'b> {
+    global_subrs: &'b [&'a [u8]],
+    local_subrs: &'b [&'a [u8]],
+    tolerance: f32,
+    stack: Vec<f32>,
+    num_stems: usize,
+    /// Whether the optional advance width at the start of the charstring has been handled.
+    has_width: bool,
+    x: f32,
+    y: f32,
+    contour_start: Option<(f32, f32)>,
+    commands: Vec<DrawCommand>,
+}
+
+impl<'a, 'b> CharStringInterpreter<'a, 'b> {
+    /// Runs a charstring. Returns `true` if `endchar` was reached.
+    fn run(&mut self, data: &[u8], depth: usize) -> Result<bool> {
+        if depth > MAX_SUBR_DEPTH {
+            return Err(invalid_data("CFF subroutines nested too deeply"));
+        }
+        let mut i = 0;
+        while i < data.len() {
+            let b0 = data[i];
+            i += 1;
+            match b0 {
+                // hstem, vstem, hstemhm, vstemhm
+                1 | 3 | 18 | 23 => {
+                    self.take_width(1);
+                    self.num_stems += self.stack.len() / 2;
+                    self.stack.clear();
+                }
+                // hintmask, cntrmask
+                19 | 20 => {
+                    self.take_width(1);
+                    self.num_stems += self.stack.len() / 2;
+                    self.stack.clear();
+                    i += self.num_stems.div_ceil(8);
+                }
+                // rmoveto
+                21 => {
+                    self.take_width(2);
+                    let (dx, dy) = (self.arg(0)?, self.arg(1)?);
+                    self.move_to(dx, dy);
+                }
+                // hmoveto
+                22 => {
+                    self.take_width(1);
+                    let dx = self.arg(0)?;
+                    self.move_to(dx, 0.0);
+                }
+                // vmoveto
+                4 => {
+                    self.take_width(1);
+                    let dy = self.arg(0)?;
+                    self.move_to(0.0, dy);
+                }
+                // rlineto
+                5 => {
+                    for pair in self.stack.clone().chunks(2) {
+                        if let [dx, dy] = *pair {
+                            self.line_to(dx, dy);
+                        }
+                    }
+                    self.stack.clear();
+                }
+                // hlineto, vlineto
+                6 | 7 => {
+                    let mut is_horizontal = b0 == 6;
+                    for d in self.stack.clone() {
+                        if is_horizontal {
+                            self.line_to(d, 0.0);
+                        } else {
+                            self.line_to(0.0, d);
+                        }
+                        is_horizontal = !is_horizontal;
+                    }
+                    self.stack.clear();
+                }
+                // rrcurveto
+                8 => {
+                    for args in self.stack.clone().chunks(6) {
+                        if let [a, b, c, d, e, f] = *args {
+                            self.curve_to(a, b, c, d, e, f);
+                        }
+                    }
+                    self.stack.clear();
+                }
+                // callsubr, callgsubr
+                10 | 29 => {
+                    let index = self
+                        .stack
+                        .pop()
+                        .ok_or_else(|| invalid_data("Stack underflow"))?;
+                    let subrs = if b0 == 10 {
+                        self.local_subrs
+                    } else {
+                        self.global_subrs
+                    };
+                    let index = index as i32 + subr_bias(subrs.len());
+                    let subr = usize_index(index)
+                        .and_then(|index| subrs.get(index))
+                        .ok_or_else(|| invalid_data("Invalid CFF subroutine"))?;
+                    if self.run(subr, depth + 1)? {
+                        return Ok(true);
+                    }
+                }
+                // return
+                11 => return Ok(false),
+                // endchar
+                14 => {
+                    self.take_width(4);
+                    self.stack.clear();
+                    return Ok(true);
+                }
+                // rcurveline
+                24 => {
+                    let args = self.stack.clone();
+                    if args.len() < 8 {
+                        return Err(invalid_data("Stack underflow"));
+                    }
+                    let (curves, line) = args.split_at(args.len() - 2);
+                    for args in curves.chunks(6) {
+                        if let [a, b, c, d, e, f] = *args {
+                            self.curve_to(a, b, c, d, e, f);
+                        }
+                    }
+                    self.line_to(line[0], line[1]);
+                    self.stack.clear();
+                }
+                // rlinecurve
+                25 => {
+                    let args = self.stack.clone();
+                    if args.len() < 8 {
+                        return Err(invalid_data("Stack underflow"));
+                    }
+                    let (lines, curve) = args.split_at(args.len() - 6);
+                    for pair in lines.chunks(2) {
+                        if let [dx, dy] = *pair {
+                            self.line_to(dx, dy);
+                        }
+                    }
+                    self.curve_to(curve[0], curve[1], curve[2], curve[3], curve[4], curve[5]);
+                    self.stack.clear();
+                }
+                // vvcurveto, hhcurveto
+                26 | 27 => {
+                    let mut args = &self.stack.clone()[..];
+                    let mut d1 = 0.0;
+                    if args.len() % 2 == 1 {
+                        d1 = args[0];
+                        args = &args[1..];
+                    }
+                    for args in args.chunks(4) {
+                        if let [a, b, c, d] = *args {
+                            if b0 == 26 {
+                                self.curve_to(d1, a, b, c, 0.0, d);
+                            } else {
+                                self.curve_to(a, d1, b, c, d, 0.0);
+                            }
+                        }
+                        d1 = 0.0;
+                    }
+                    self.stack.clear();
+                }
+                // vhcurveto, hvcurveto
+                30 | 31 => {
+                    let args = self.stack.clone();
+                    let mut is_horizontal = b0 == 31;
+                    let mut i = 0;
+                    while i + 4 <= args.len() {
+                        let last = if i + 5 == args.len() {
+                            args[i + 4]
+                        } else {
+                            0.0
+                        };
+                        let (a, b, c, d) = (args[i], args[i + 1], args[i + 2], args[i + 3]);
+                        if is_horizontal {
+                            self.curve_to(a, 0.0, b, c, last, d);
+                        } else {
+                            self.curve_to(0.0, a, b, c, d, last);
+                        }
+                        is_horizontal = !is_horizontal;
+                        i += 4;
+                    }
+                    self.stack.clear();
+                }
+                12 => {
+                    let b1 = *data
+                        .get(i)
+                        .ok_or_else(|| invalid_data("Invalid charstring"))?;
+                    i += 1;
+                    self.run_escape(b1)?;
+                    self.stack.clear();
+                }
+                28 => {
+                    let n = read_u16(data, i)? as i16;
+                    self.stack.push(f32::from(n));
+                    i += 2;
+                }
+                32..=246 => self.stack.push(f32::from(b0) - 139.0),
+                247..=250 => {
+                    let b1 = f32::from(read_u8(data, i)?);
+                    self.stack
+                        .push((f32::from(b0) - 247.0) * 256.0 + b1 + 108.0);
+                    i += 1;
+                }
+                251..=254 => {
+                    let b1 = f32::from(read_u8(data, i)?);
+                    self.stack
+                        .push(-(f32::from(b0) - 251.0) * 256.0 - b1 - 108.0);
+                    i += 1;
+                }
+                255 => {
+                    let bytes = slice(data, i, 4)?;
+                    let n = i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                    self.stack.push(n as f32 / 65536.0);
+                    i += 4;
+                }
+                _ => return Err(invalid_data("Unsupported charstring operator")),
+            }
+        }
+        Ok(false)
+    }
+
+    /// Handles the two-byte flex operators.
+    fn run_escape(&mut self, operator: u8) -> Result<()> {
+        let args = self.stack.clone();
+        let arg = |i: usize| {
+            args.get(i)
+                .cloned()
+                .ok_or_else(|| invalid_data("Stack underflow"))
+        };
+        match operator {
+            // flex
+            35 => {
+                self.curve_to(arg(0)?, arg(1)?, arg(2)?, arg(3)?, arg(4)?, arg(5)?);
+                self.curve_to(arg(6)?, arg(7)?, arg(8)?, arg(9)?, arg(10)?, arg(11)?);
+            }
+            // hflex
+            34 => {
+                let dy2 = arg(2)?;
+                self.curve_to(arg(0)?, 0.0, arg(1)?, dy2, arg(3)?, 0.0);
+                self.curve_to(arg(4)?, 0.0, arg(5)?, -dy2, arg(6)?, 0.0);
+            }
+            // hflex1
+            36 => {
+                let (dy1, dy2, dy5) = (arg(1)?, arg(3)?, arg(7)?);
+                self.curve_to(arg(0)?, dy1, arg(2)?, dy2, arg(4)?, 0.0);
+                self.curve_to(arg(5)?, 0.0, arg(6)?, dy5, arg(8)?, -(dy1 + dy2 + dy5));
+            }
+            // flex1
+            37 => {
+                let dx: f32 = (0..5)
+                    .map(|i| args.get(i * 2).cloned().unwrap_or(0.0))
+                    .sum();
+                let dy: f32 = (0..5)
+                    .map(|i| args.get(i * 2 + 1).cloned().unwrap_or(0.0))
+                    .sum();
+                let (dx6, dy6) = if dx.abs() > dy.abs() {
+                    (arg(10)?, -dy)
+                } else {
+                    (-dx, arg(10)?)
+                };
+                self.curve_to(arg(0)?, arg(1)?, arg(2)?, arg(3)?, arg(4)?, arg(5)?);
+                self.curve_to(arg(6)?, arg(7)?, arg(8)?, arg(9)?, dx6, dy6);
+            }
+            _ => return Err(invalid_data("Unsupported charstring operator")),
+        }
+        Ok(())
+    }
+
+    /// Drops the advance width that may precede the first stack-clearing operator.
+    fn take_width(&mut self, num_args: usize) {
+        if !self.has_width {
+            self.has_width = true;
+            let has_extra_arg = match num_args {
+                // Stem operators take pairs of arguments.
+                1 => self.stack.len() % 2 == 1,
+                // endchar takes no arguments, or four for an accented character.
+                4 => self.stack.len() == 1 || self.stack.len() == 5,
+                n => self.stack.len() > n,
+            };
+            if has_extra_arg {
+                self.stack.remove(0);
+            }
+        }
+    }
+
+    fn arg(&self, i: usize) -> Result<f32> {
+        self.stack
+            .get(i)
+            .cloned()
+            .ok_or_else(|| invalid_data("Stack underflow"))
+    }
+
+    fn close_contour(&mut self) {
+        if let Some((x, y)) = self.contour_start.take() {
+            if (x, y) != (self.x, self.y) {
+                self.commands.push(DrawCommand::LineTo { x, y });
+            }
+        }
+    }
+
+    fn move_to(&mut self, dx: f32, dy: f32) {
+        self.stack.clear();
+        self.close_contour();
+        self.x += dx;
+        self.y += dy;
+        self.contour_start = Some((self.x, self.y));
+        self.commands.push(DrawCommand::MoveTo {
+            x: self.x,
+            y: self.y,
+        });
+    }
+
+    fn line_to(&mut self, dx: f32, dy: f32) {
+        self.x += dx;
+        self.y += dy;
+        self.commands.push(DrawCommand::LineTo {
+            x: self.x,
+            y: self.y,
+        });
+    }
+
+    fn curve_to(&mut self, dx1: f32, dy1: f32, dx2: f32, dy2: f32, dx3: f32, dy3: f32) {
+        let p0 = (self.x, self.y);
+        let p1 = (p0.0 + dx1, p0.1 + dy1);
+        let p2 = (p1.0 + dx2, p1.1 + dy2);
+        let p3 = (p2.0 + dx3, p2.1 + dy3);
+        cubic_to_quadratics(p0, p1, p2, p3, self.tolerance, &mut self.commands);
+        self.x = p3.0;
+        self.y = p3.1;
+    }
+}
+
+fn usize_index(n: i32) -> Option<usize> {
+    if n >= 0 {
+        Some(n as usize)
+    } else {
+        None
+    }
+}
+
+/// Approximates a cubic Bézier curve with quadratic curves.
+pub fn cubic_to_quadratics(
+    p0: (f32, f32),
+    p1: (f32, f32),
+    p2: (f32, f32),
+    p3: (f32, f32),
+    tolerance: f32,
+    out: &mut Vec<DrawCommand>,
+) {
+    // Approximating a cubic with a single quadratic has an error of at most
+    // sqrt(3) / 36 * |p3 - 3 * p2 + 3 * p1 - p0|, which shrinks with the cube of the number of
+    // pieces the curve is split into.
+    let dx = p3.0 - 3.0 * p2.0 + 3.0 * p1.0 - p0.0;
+    let dy = p3.1 - 3.0 * p2.1 + 3.0 * p1.1 - p0.1;
+    let error = 3f32.sqrt() / 36.0 * (dx * dx + dy * dy).sqrt();
+    let tolerance = tolerance.max(1e-3);
+    let num_pieces = ((error / tolerance).cbrt().ceil() as usize).clamp(1, 64);
+
+    let point = |t: f32| {
+        let mt = 1.0 - t;
+        let a = mt * mt * mt;
+        let b = 3.0 * mt * mt * t;
+        let c = 3.0 * mt * t * t;
+        let d = t * t * t;
+        (
+            a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0,
+            a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1,
+        )
+    };
+    let derivative = |t: f32| {
+        let mt = 1.0 - t;
+        let a = 3.0 * mt * mt;
+        let b = 6.0 * mt * t;
+        let c = 3.0 * t * t;
+        (
+            a * (p1.0 - p0.0) + b * (p2.0 - p1.0) + c * (p3.0 - p2.0),
+            a * (p1.1 - p0.1) + b * (p2.1 - p1.1) + c * (p3.1 - p2.1),
+        )
+    };
+    for i in 0..num_pieces {
+        let t0 = i as f32 / num_pieces as f32;
+        let t1 = (i + 1) as f32 / num_pieces as f32;
+        let dt = (t1 - t0) / 3.0;
+        let q0 = point(t0);
+        let q3 = if i + 1 == num_pieces { p3 } else { point(t1) };
+        let (d0, d1) = (derivative(t0), derivative(t1));
+        let q1 = (q0.0 + d0.0 * dt, q0.1 + d0.1 * dt);
+        let q2 = (q3.0 - d1.0 * dt, q3.1 - d1.1 * dt);
+        out.push(DrawCommand::CurveTo {
+            x1: (3.0 * (q1.0 + q2.0) - q0.0 - q3.0) / 4.0,
+            y1: (3.0 * (q1.1 + q2.1) - q0.1 - q3.1) / 4.0,
+            x2: q3.0,
+            y2: q3.1,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cubic_to_quadratic() {
+        let mut commands = vec![];
+        cubic_to_quadratics(
+            (0.0, 0.0),
+            (0.0, 100.0),
+            (100.0, 100.0),
+            (100.0, 0.0),
+            1.0,
+            &mut commands,
+        );
+        assert!(commands.len() > 1);
+        match commands.last() {
+            Some(&DrawCommand::CurveTo { x2, y2, .. }) => assert_eq!((x2, y2), (100.0, 0.0)),
+            _ => panic!("Expected a curve"),
+        }
+    }
+
+    #[test]
+    fn run_char_string() {
+        // A 100 unit square with a width of 500: 500 0 rmoveto 100 hlineto 100 vlineto
+        // -100 hlineto endchar, with the width encoded before the first moveto.
+        let char_string = [
+            248, 136, // 500 (width)
+            139, 139, 21, // 0 0 rmoveto
+            239, 6, // 100 hlineto
+            239, 7, // 100 vlineto
+            39, 6,  // -100 hlineto
+            14, // endchar
+        ];
+        let subrs: Vec<&[u8]> = vec![];
+        let mut interpreter = CharStringInterpreter {
+            global_subrs: &subrs,
+            local_subrs: &subrs,
+            tolerance: 1.0,
+            stack: vec![],
+            num_stems: 0,
+            has_width: false,
+            x: 0.0,
+            y: 0.0,
+            contour_start: None,
+            commands: vec![],
+        };
+        assert!(interpreter.run(&char_string, 0).unwrap());
+        interpreter.close_contour();
+        assert_eq!(
+            interpreter.commands,
+            vec![
+                DrawCommand::MoveTo { x: 0.0, y: 0.0 },
+                DrawCommand::LineTo { x: 100.0, y: 0.0 },
+                DrawCommand::LineTo { x: 100.0, y: 100.0 },
+                DrawCommand::LineTo { x: 0.0, y: 100.0 },
+                DrawCommand::LineTo { x: 0.0, y: 0.0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_cff() {
+        let data = [
+            1, 0, 4, 1, // Header
+            0, 1, 1, 1, 2, b'A', // Name INDEX
+            0, 1, 1, 1, 5, 28, 0, 23, 17, // Top DICT INDEX, with CharStrings at 23
+            0, 0, // String INDEX
+            0, 0, // Global Subr INDEX
+            0, 1, 1, 1, 11, // CharStrings INDEX
+            139, 139, 21, 239, 6, 239, 7, 39, 6, 14,
+        ];
+        let cff = Cff::parse(&data).unwrap();
+        assert_eq!(cff.glyph_outline(0, 1.0).unwrap().len(), 5);
+        assert!(cff.glyph_outline(1, 1.0).is_err());
+    }
+}
@@ -0,0 +1,297 @@
+//! Validation and export of the OpenType fonts embedded in `DefineFont4` tags.
+use super::read::{invalid_data, read_u16, read_u32, slice, OpenTypeFont};
+use super::write::table_checksum;
+use std::io::{Result, Write};
+use types::*;
+
+/// Tables that every CFF-flavored OpenType font must contain.
+const REQUIRED_TABLES: [&[u8; 4]; 9] = [
+    b"CFF ", b"OS/2", b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"post",
+];
+
+/// A summary of the OpenType font embedded in a `DefineFont4` tag.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpenTypeInfo {
+    pub family: String,
+    pub style: String,
+    pub num_glyphs: u16,
+    /// The Unicode code points mapped by the font's `cmap` table, in ascending order.
+    pub code_points: Vec<u32>,
+    pub is_bold: bool,
+    pub is_italic: bool,
+    /// The ways in which the `DefineFont4` tag disagrees with the font data.
+    pub mismatches: Vec<Font4Mismatch>,
+}
+
+/// A difference between a `DefineFont4` tag and its embedded font.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Font4Mismatch {
+    /// The tag's name is neither the family name nor the full name of the font.
+    Name { tag_name: String, font_name: String },
+    /// The tag's bold flag differs from the font's `OS/2` table.
+    Bold {
+        tag_is_bold: bool,
+        font_is_bold: bool,
+    },
+    /// The tag's italic flag differs from the font's `OS/2` table.
+    Italic {
+        tag_is_italic: bool,
+        font_is_italic: bool,
+    },
+}
+
+impl Font4 {
+    /// Validates the embedded OpenType font and returns a summary of it.
+    ///
+    /// Returns `None` if the tag has no font data, and an error if the data is not a
+    /// well-formed CFF-flavored OpenType font: the table directory must be sorted and in
+    /// bounds, the required tables must be present, and the `CFF ` table must have an outline
+    /// for every glyph. Bad table checksums are only logged as warnings.
+    pub fn open_type_info(&self) -> Result<Option<OpenTypeInfo>> {
+        let data = match self.data {
+            Some(ref data) => data,
+            None => return Ok(None),
+        };
+        let font = validate_cff_font(data)?;
+
+        let family = font.name(16).or_else(|| font.name(1)).unwrap_or_default();
+        let style = font.name(17).or_else(|| font.name(2)).unwrap_or_default();
+        let full_name = font.name(4).unwrap_or_default();
+        let tag_name = self.name.trim_end_matches('\0');
+        let (is_bold, is_italic) = (font.is_bold(), font.is_italic());
+
+        let mut mismatches = vec![];
+        if tag_name != family && tag_name != full_name {
+            mismatches.push(Font4Mismatch::Name {
+                tag_name: tag_name.to_string(),
+                font_name: family.clone(),
+            });
+        }
+        if self.is_bold != is_bold {
+            mismatches.push(Font4Mismatch::Bold {
+                tag_is_bold: self.is_bold,
+                font_is_bold: is_bold,
+            });
+        }
+        if self.is_italic != is_italic {
+            mismatches.push(Font4Mismatch::Italic {
+                tag_is_italic: self.is_italic,
+                font_is_italic: is_italic,
+            });
+        }
+
+        Ok(Some(OpenTypeInfo {
+            family,
+            style,
+            num_glyphs: font.num_glyphs,
+            code_points: font.char_map.keys().cloned().collect(),
+            is_bold,
+            is_italic,
+            mismatches,
+        }))
+    }
+}
+
+/// Writes the OpenType font embedded in a `DefineFont4` tag as a standalone .otf file.
+///
+/// The font data is validated first, and an error is returned if the tag has no font data.
+pub fn write_otf<W: Write>(font: &Font4, mut output: W) -> Result<()> {
+    let data = font
+        .data
+        .as_ref()
+        .ok_or_else(|| invalid_data("DefineFont4 has no font data"))?;
+    validate_cff_font(data)?;
+    output.write_all(data)
+}
+
+fn validate_cff_font(data: &[u8]) -> Result<OpenTypeFont<'_>> {
+    if read_u32(data, 0)? != 0x4F54_544F {
+        return Err(invalid_data("Font data is not a CFF OpenType font"));
+    }
+    let num_tables = usize::from(read_u16(data, 4)?);
+    let mut last_tag: Option<&[u8]> = None;
+    for i in 0..num_tables {
+        let record = 12 + i * 16;
+        let tag = slice(data, record, 4)?;
+        if last_tag.map(|last| last >= tag).unwrap_or(false) {
+            return Err(invalid_data("Font tables are not sorted"));
+        }
+        last_tag = Some(tag);
+
+        let checksum = read_u32(data, record + 4)?;
+        let offset = read_u32(data, record + 8)? as usize;
+        let length = read_u32(data, record + 12)? as usize;
+        let mut table = slice(data, offset, length)?.to_vec();
+        if tag == b"head" {
+            if read_u32(&table, 12)? != 0x5F0F_3CF5 {
+                return Err(invalid_data("Invalid head table"));
+            }
+            // The checksum adjustment is excluded from the head table's checksum.
+            table[8..12].copy_from_slice(&[0; 4]);
+        }
+        // Fonts with stale checksums are common and still load, so they aren't rejected.
+        if table_checksum(&table) != checksum {
+            warn!("Bad checksum for {} table", String::from_utf8_lossy(tag));
+        }
+    }
+
+    let font = OpenTypeFont::parse(data)?;
+    for tag in REQUIRED_TABLES.iter() {
+        if font.table(tag).is_none() {
+            return Err(invalid_data(&format!(
+                "Missing {} table",
+                String::from_utf8_lossy(&tag[..])
+            )));
+        }
+    }
+    if font.num_outlines() != usize::from(font.num_glyphs) {
+        return Err(invalid_data("CFF glyph count does not match maxp table"));
+    }
+    Ok(font)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::write::write_sfnt;
+    use super::super::{write_ttf, EmbeddedFont};
+    use super::*;
+
+    /// Returns a CFF OpenType font with glyphs for 'A' and 'B', built by replacing the TrueType
+    /// outlines of an exported font with a `CFF ` table.
+    fn test_otf(num_char_strings: u8) -> Vec<u8> {
+        let glyph = |code| Glyph {
+            shape_records: vec![],
+            code,
+            advance: Some(500),
+            bounds: None,
+        };
+        let font = EmbeddedFont {
+            font: Font {
+                version: 3,
+                id: 1,
+                name: "Test".to_string(),
+                language: Language::Latin,
+                layout: Some(FontLayout {
+                    ascent: 800 * 20,
+                    descent: 200 * 20,
+                    leading: 0,
+                    kerning: vec![],
+                }),
+                glyphs: vec![glyph(65), glyph(66)],
+                is_small_text: false,
+                is_shift_jis: false,
+                is_ansi: false,
+                is_bold: true,
+                is_italic: false,
+            },
+            full_name: Some("Test Bold".to_string()),
+            copyright: None,
+            align_zones: vec![],
+        };
+        let mut ttf = vec![];
+        write_ttf(&font, &mut ttf).unwrap();
+
+        let mut cff = vec![
+            1, 0, 4, 1, // Header
+            0, 1, 1, 1, 2, b'A', // Name INDEX
+            0, 1, 1, 1, 5, 28, 0, 23, 17, // Top DICT INDEX, with CharStrings at 23
+            0, 0, // String INDEX
+            0, 0, // Global Subr INDEX
+        ];
+        // CharStrings INDEX, with an empty outline for each glyph.
+        cff.extend(&[0, num_char_strings, 1]);
+        cff.extend((1..=num_char_strings + 1).collect::<Vec<_>>());
+        cff.extend(vec![14; usize::from(num_char_strings)]);
+
+        let ttf = OpenTypeFont::parse(&ttf).unwrap();
+        let mut tables: Vec<([u8; 4], Vec<u8>)> = REQUIRED_TABLES
+            .iter()
+            .filter(|&&tag| tag != b"CFF ")
+            .map(|&&tag| (tag, ttf.table(&tag).unwrap().to_vec()))
+            .collect();
+        // The checksum adjustment is recalculated by `write_sfnt`.
+        for &mut (tag, ref mut data) in &mut tables {
+            if &tag == b"head" {
+                data[8..12].copy_from_slice(&[0; 4]);
+            }
+        }
+        tables.push((*b"CFF ", cff));
+        tables.sort();
+        write_sfnt(0x4F54_544F, &tables).unwrap()
+    }
+
+    fn font4(name: &str, is_italic: bool, data: Vec<u8>) -> Font4 {
+        Font4 {
+            id: 1,
+            is_italic,
+            is_bold: true,
+            name: name.to_string(),
+            data: Some(data),
+        }
+    }
+
+    #[test]
+    fn open_type_info() {
+        let info = font4("Test", false, test_otf(3))
+            .open_type_info()
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.family, "Test");
+        assert_eq!(info.num_glyphs, 3);
+        assert_eq!(info.code_points, vec![65, 66]);
+        assert!(info.is_bold);
+        assert!(!info.is_italic);
+        assert!(info.mismatches.is_empty());
+
+        let info = font4("Test Bold\0", false, test_otf(3))
+            .open_type_info()
+            .unwrap()
+            .unwrap();
+        assert!(info.mismatches.is_empty());
+
+        let info = font4("Other", true, test_otf(3))
+            .open_type_info()
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            info.mismatches,
+            vec![
+                Font4Mismatch::Name {
+                    tag_name: "Other".to_string(),
+                    font_name: "Test".to_string(),
+                },
+                Font4Mismatch::Italic {
+                    tag_is_italic: true,
+                    font_is_italic: false,
+                },
+            ]
+        );
+
+        let mut font = font4("Test", false, vec![]);
+        font.data = None;
+        assert_eq!(font.open_type_info().unwrap(), None);
+        assert!(write_otf(&font, vec![]).is_err());
+    }
+
+    #[test]
+    fn validate_open_type_data() {
+        let data = test_otf(3);
+        let mut out = vec![];
+        write_otf(&font4("Test", false, data.clone()), &mut out).unwrap();
+        assert_eq!(out, data);
+
+        // Glyph count mismatch between the CFF and maxp tables.
+        assert!(font4("Test", false, test_otf(2)).open_type_info().is_err());
+
+        // Table data that no longer matches its checksum is still accepted.
+        let mut corrupted = data.clone();
+        let last = corrupted.len() - 8;
+        corrupted[last] ^= 0xff;
+        assert!(font4("Test", false, corrupted).open_type_info().is_ok());
+
+        // TrueType outlines.
+        let mut ttf = data;
+        ttf[..4].copy_from_slice(&[0, 1, 0, 0]);
+        assert!(font4("Test", false, ttf).open_type_info().is_err());
+    }
+}
@@ -0,0 +1,245 @@
+//! Conversion between SWF fonts and TrueType/OpenType fonts.
+mod cff;
+mod font4;
+mod read;
+mod write;
+
+pub use self::font4::{write_otf, Font4Mismatch, OpenTypeInfo};
+pub use self::read::import_font;
+pub use self::write::write_ttf;
+
+use encoding_rs::SHIFT_JIS;
+use types::*;
+
+/// A font definition together with the tags that add information to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EmbeddedFont {
+    /// The font definition. `DefineFont` tags are converted using their `DefineFontInfo`.
+    pub font: Font,
+    /// The full name of the font from `DefineFontName`.
+    pub full_name: Option<String>,
+    /// The copyright notice from `DefineFontName`.
+    pub copyright: Option<String>,
+    /// The alignment zones from `DefineFontAlignZones`, one per glyph.
+    pub align_zones: Vec<FontAlignZone>,
+}
+
+impl EmbeddedFont {
+    pub fn new(font: Font) -> EmbeddedFont {
+        EmbeddedFont {
+            font,
+            full_name: None,
+            copyright: None,
+            align_zones: vec![],
+        }
+    }
+
+    /// Combines a `DefineFont` tag with its optional `DefineFontInfo` tag.
+    ///
+    /// `DefineFont` has no layout information, so the resulting font has no layout, and glyph
+    /// codes come from the code table in `info`.
+    pub fn from_font_v1(font: &FontV1, info: Option<&FontInfo>) -> EmbeddedFont {
+        let glyphs = font
+            .glyphs
+            .iter()
+            .enumerate()
+            .map(|(i, shape_records)| Glyph {
+                shape_records: shape_records.clone(),
+                code: info
+                    .and_then(|info| info.code_table.get(i).cloned())
+                    .unwrap_or(0),
+                advance: None,
+                bounds: None,
+            })
+            .collect();
+        EmbeddedFont::new(Font {
+            version: 1,
+            id: font.id,
+            name: info.map(|info| info.name.clone()).unwrap_or_default(),
+            language: info.map(|info| info.language).unwrap_or(Language::Unknown),
+            layout: None,
+            glyphs,
+            is_small_text: info.map(|info| info.is_small_text).unwrap_or(false),
+            is_shift_jis: info.map(|info| info.is_shift_jis).unwrap_or(false),
+            is_ansi: info.map(|info| info.is_ansi).unwrap_or(false),
+            is_bold: info.map(|info| info.is_bold).unwrap_or(false),
+            is_italic: info.map(|info| info.is_italic).unwrap_or(false),
+        })
+    }
+
+    /// Returns the tags that define this font: a `DefineFont2` or `DefineFont3` tag, followed by
+    /// a `DefineFontName` tag if the font has a full name or copyright notice, and a
+    /// `DefineFontAlignZones` tag if it has alignment zones.
+    pub fn to_tags(&self) -> Vec<Tag> {
+        let mut tags = vec![Tag::DefineFont2(Box::new(self.font.clone()))];
+        if self.full_name.is_some() || self.copyright.is_some() {
+            tags.push(Tag::DefineFontName {
+                id: self.font.id,
+                name: self.full_name.clone().unwrap_or_default(),
+                copyright_info: self.copyright.clone().unwrap_or_default(),
+            });
+        }
+        if !self.align_zones.is_empty() {
+            tags.push(Tag::DefineFontAlignZones {
+                id: self.font.id,
+                thickness: FontThickness::Thin,
+                zones: self.align_zones.clone(),
+            });
+        }
+        tags
+    }
+}
+
+/// Returns the Unicode character for a glyph code of a font, or `None` if it has no mapping.
+///
+/// Codes are UCS-2, except in Shift-JIS fonts, where codes above 0xFF are double-byte
+/// Shift-JIS characters.
+pub fn code_to_char(font: &Font, code: u16) -> Option<char> {
+    if font.is_shift_jis && code > 0xff {
+        let bytes = [(code >> 8) as u8, code as u8];
+        let (text, had_errors) = SHIFT_JIS.decode_without_bom_handling(&bytes);
+        let mut chars = text.chars();
+        match (chars.next(), chars.next(), had_errors) {
+            (Some(c), None, false) => Some(c),
+            _ => None,
+        }
+    } else {
+        ::std::char::from_u32(u32::from(code))
+    }
+}
+
+/// Converts an IEEE 754 half-precision float to `f32`.
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = i32::from((bits >> 10) & 0x1f);
+    let mantissa = f32::from(bits & 0x3ff);
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+/// Converts an `f32` to the nearest IEEE 754 half-precision float.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    if value.is_nan() {
+        return 0x7e00;
+    }
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent < -14 {
+        // Subnormal, or too small to represent.
+        return sign | (value.abs() * 2f32.powi(24)).round() as u16;
+    }
+    if exponent > 15 {
+        return sign | 0x7c00;
+    }
+    // Rounding may carry into the exponent, which is still correct, up to infinity.
+    let half = (((exponent + 15) as u32) << 10 | (mantissa >> 13)) + ((mantissa >> 12) & 1);
+    sign | half.min(0x7c00) as u16
+}
+
+/// Collects the fonts with glyph outlines defined in a tag list.
+///
+/// `DefineFontInfo`, `DefineFontName` and `DefineFontAlignZones` tags are merged into the font
+/// they refer to.
+pub fn embedded_fonts(tags: &[Tag]) -> Vec<EmbeddedFont> {
+    let mut fonts: Vec<EmbeddedFont> = tags
+        .iter()
+        .filter_map(|tag| match *tag {
+            Tag::DefineFont(ref font) => {
+                let info = tags
+                    .iter()
+                    .filter_map(|tag| match *tag {
+                        Tag::DefineFontInfo(ref info) if info.id == font.id => Some(&**info),
+                        _ => None,
+                    })
+                    .next_back();
+                Some(EmbeddedFont::from_font_v1(font, info))
+            }
+            Tag::DefineFont2(ref font) => Some(EmbeddedFont::new((**font).clone())),
+            _ => None,
+        })
+        .collect();
+
+    for tag in tags {
+        match *tag {
+            Tag::DefineFontName {
+                id,
+                ref name,
+                ref copyright_info,
+            } => {
+                for font in fonts.iter_mut().filter(|font| font.font.id == id) {
+                    font.full_name = Some(name.clone());
+                    font.copyright = Some(copyright_info.clone());
+                }
+            }
+            Tag::DefineFontAlignZones { id, ref zones, .. } => {
+                for font in fonts.iter_mut().filter(|font| font.font.id == id) {
+                    font.align_zones = zones.clone();
+                }
+            }
+            _ => (),
+        }
+    }
+    fonts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_font_tags() {
+        let tags = vec![
+            Tag::DefineFont(Box::new(FontV1 {
+                id: 1,
+                glyphs: vec![vec![], vec![]],
+            })),
+            Tag::DefineFontInfo(Box::new(FontInfo {
+                id: 1,
+                version: 2,
+                name: "Verdana".to_string(),
+                is_small_text: false,
+                is_shift_jis: false,
+                is_ansi: false,
+                is_bold: true,
+                is_italic: false,
+                language: Language::Latin,
+                code_table: vec![65, 66],
+            })),
+            Tag::DefineFontName {
+                id: 1,
+                name: "Verdana Bold".to_string(),
+                copyright_info: "Copyright".to_string(),
+            },
+        ];
+        let fonts = embedded_fonts(&tags);
+        assert_eq!(fonts.len(), 1);
+        let font = &fonts[0];
+        assert_eq!(font.font.name, "Verdana");
+        assert!(font.font.is_bold);
+        assert_eq!(
+            font.font.glyphs.iter().map(|g| g.code).collect::<Vec<_>>(),
+            vec![65, 66]
+        );
+        assert_eq!(font.full_name, Some("Verdana Bold".to_string()));
+        assert_eq!(font.copyright, Some("Copyright".to_string()));
+    }
+
+    #[test]
+    fn convert_f16() {
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x5640), 100.0);
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(100.0), 0x5640);
+        assert_eq!(f32_to_f16(0.0), 0);
+        assert_eq!(f32_to_f16(1e6), 0x7c00);
+        assert_eq!(f16_to_f32(f32_to_f16(-0.25)), -0.25);
+    }
+}
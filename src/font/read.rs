@@ -0,0 +1,1169 @@
+//! Reads TrueType and OpenType fonts.
+use super::cff::Cff;
+use super::{f32_to_f16, EmbeddedFont};
+use shape_utils::DrawCommand;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{Error, ErrorKind, Result};
+use types::*;
+
+/// Maximum nesting depth of composite TrueType glyphs.
+const MAX_COMPONENT_DEPTH: usize = 8;
+
+pub fn invalid_data(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+pub fn slice(data: &[u8], offset: usize, len: usize) -> Result<&[u8]> {
+    offset
+        .checked_add(len)
+        .and_then(|end| data.get(offset..end))
+        .ok_or_else(|| invalid_data("Unexpected end of font data"))
+}
+
+pub fn read_u8(data: &[u8], offset: usize) -> Result<u8> {
+    Ok(slice(data, offset, 1)?[0])
+}
+
+pub fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    let bytes = slice(data, offset, 2)?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+pub fn read_i16(data: &[u8], offset: usize) -> Result<i16> {
+    Ok(read_u16(data, offset)? as i16)
+}
+
+pub fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = slice(data, offset, 4)?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// The glyph outline data of a font.
+enum Outlines<'a> {
+    TrueType { glyf: &'a [u8], loca: Vec<usize> },
+    Cff(Cff<'a>),
+}
+
+/// A parsed TrueType or OpenType font.
+pub struct OpenTypeFont<'a> {
+    tables: Vec<([u8; 4], &'a [u8])>,
+    pub units_per_em: u16,
+    pub num_glyphs: u16,
+    pub ascender: i16,
+    pub descender: i16,
+    pub line_gap: i16,
+    advances: Vec<u16>,
+    /// Unicode code point to glyph ID.
+    pub char_map: BTreeMap<u32, u16>,
+    outlines: Outlines<'a>,
+}
+
+impl<'a> OpenTypeFont<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<OpenTypeFont<'a>> {
+        match read_u32(data, 0)? {
+            // TrueType outlines, CFF outlines ("OTTO") or Apple TrueType ("true").
+            0x0001_0000 | 0x4F54_544F | 0x7472_7565 => (),
+            _ => return Err(invalid_data("Not a TrueType or OpenType font")),
+        }
+        let num_tables = usize::from(read_u16(data, 4)?);
+        let mut tables = Vec::with_capacity(num_tables);
+        for i in 0..num_tables {
+            let record = 12 + i * 16;
+            let mut tag = [0; 4];
+            tag.copy_from_slice(slice(data, record, 4)?);
+            let offset = read_u32(data, record + 8)? as usize;
+            let length = read_u32(data, record + 12)? as usize;
+            tables.push((tag, slice(data, offset, length)?));
+        }
+        let table = |tag: &[u8; 4]| {
+            tables
+                .iter()
+                .find(|&&(t, _)| &t == tag)
+                .map(|&(_, data)| data)
+                .ok_or_else(|| {
+                    invalid_data(&format!("Missing {} table", String::from_utf8_lossy(tag)))
+                })
+        };
+
+        let head = table(b"head")?;
+        let units_per_em = read_u16(head, 18)?;
+        if units_per_em == 0 {
+            return Err(invalid_data("Invalid units per EM"));
+        }
+        let num_glyphs = read_u16(table(b"maxp")?, 4)?;
+
+        let hhea = table(b"hhea")?;
+        let num_h_metrics = usize::from(read_u16(hhea, 34)?);
+        let hmtx = table(b"hmtx")?;
+        let mut advances = Vec::with_capacity(num_h_metrics);
+        for i in 0..num_h_metrics {
+            advances.push(read_u16(hmtx, i * 4)?);
+        }
+
+        let outlines = match table(b"CFF ") {
+            Ok(cff) => Outlines::Cff(Cff::parse(cff)?),
+            Err(_) => {
+                let long_offsets = read_i16(head, 50)? != 0;
+                let loca_data = table(b"loca")?;
+                let mut loca = Vec::with_capacity(usize::from(num_glyphs) + 1);
+                for i in 0..=usize::from(num_glyphs) {
+                    loca.push(if long_offsets {
+                        read_u32(loca_data, i * 4)? as usize
+                    } else {
+                        usize::from(read_u16(loca_data, i * 2)?) * 2
+                    });
+                }
+                Outlines::TrueType {
+                    glyf: table(b"glyf")?,
+                    loca,
+                }
+            }
+        };
+
+        let char_map = read_cmap(table(b"cmap")?)?;
+        Ok(OpenTypeFont {
+            units_per_em,
+            num_glyphs,
+            ascender: read_i16(hhea, 4)?,
+            descender: read_i16(hhea, 6)?,
+            line_gap: read_i16(hhea, 8)?,
+            advances,
+            char_map,
+            outlines,
+            tables,
+        })
+    }
+
+    pub fn table(&self, tag: &[u8; 4]) -> Option<&'a [u8]> {
+        self.tables
+            .iter()
+            .find(|&&(t, _)| &t == tag)
+            .map(|&(_, data)| data)
+    }
+
+    /// Returns the number of glyphs with outline data.
+    pub fn num_outlines(&self) -> usize {
+        match self.outlines {
+            Outlines::Cff(ref cff) => cff.num_glyphs(),
+            Outlines::TrueType { ref loca, .. } => loca.len() - 1,
+        }
+    }
+
+    /// Returns the advance width of a glyph, in font units.
+    pub fn advance(&self, glyph_id: u16) -> u16 {
+        self.advances
+            .get(usize::from(glyph_id))
+            .or_else(|| self.advances.last())
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    /// Returns a string from the `name` table, preferring English Unicode entries.
+    pub fn name(&self, name_id: u16) -> Option<String> {
+        let name = self.table(b"name")?;
+        let count = usize::from(read_u16(name, 2).ok()?);
+        let storage = usize::from(read_u16(name, 4).ok()?);
+        let mut best: Option<(u8, String)> = None;
+        for i in 0..count {
+            let record = 6 + i * 12;
+            let (platform, encoding, language, id, length, offset) = (
+                read_u16(name, record).ok()?,
+                read_u16(name, record + 2).ok()?,
+                read_u16(name, record + 4).ok()?,
+                read_u16(name, record + 6).ok()?,
+                usize::from(read_u16(name, record + 8).ok()?),
+                usize::from(read_u16(name, record + 10).ok()?),
+            );
+            if id != name_id {
+                continue;
+            }
+            let bytes = match slice(name, storage + offset, length) {
+                Ok(bytes) => bytes,
+                Err(_) => continue,
+            };
+            let (rank, value) = match (platform, encoding) {
+                (3, 0) | (3, 1) | (3, 10) | (0, _) => {
+                    let units: Vec<u16> = bytes
+                        .chunks(2)
+                        .filter(|chunk| chunk.len() == 2)
+                        .map(|chunk| u16::from_be_bytes([chunk[0], chunk[1]]))
+                        .collect();
+                    let rank = if platform == 3 && language == 0x409 {
+                        0
+                    } else {
+                        1
+                    };
+                    (rank, String::from_utf16_lossy(&units))
+                }
+                // Mac Roman matches ASCII for the names this is used for.
+                (1, 0) => (2, bytes.iter().map(|&b| b as char).collect()),
+                _ => continue,
+            };
+            if best.as_ref().map(|&(r, _)| rank < r).unwrap_or(true) {
+                best = Some((rank, value));
+            }
+        }
+        best.map(|(_, value)| value)
+    }
+
+    /// Returns whether the font is bold, from the `OS/2` table or the `head` table.
+    pub fn is_bold(&self) -> bool {
+        match self.table(b"OS/2").and_then(|os2| read_u16(os2, 62).ok()) {
+            Some(selection) => selection & 0b10_0000 != 0,
+            None => self.mac_style() & 0b1 != 0,
+        }
+    }
+
+    /// Returns whether the font is italic, from the `OS/2` table or the `head` table.
+    pub fn is_italic(&self) -> bool {
+        match self.table(b"OS/2").and_then(|os2| read_u16(os2, 62).ok()) {
+            Some(selection) => selection & 0b1 != 0,
+            None => self.mac_style() & 0b10 != 0,
+        }
+    }
+
+    fn mac_style(&self) -> u16 {
+        self.table(b"head")
+            .and_then(|head| read_u16(head, 44).ok())
+            .unwrap_or(0)
+    }
+
+    /// Returns the outline of a glyph in font units, with the y-axis pointing up.
+    ///
+    /// Each contour starts with a `MoveTo` and ends at its starting point. Cubic curves in CFF
+    /// outlines are approximated with quadratic curves within `tolerance` font units.
+    pub fn glyph_outline(&self, glyph_id: u16, tolerance: f32) -> Result<Vec<DrawCommand>> {
+        match self.outlines {
+            Outlines::Cff(ref cff) => cff.glyph_outline(glyph_id, tolerance),
+            Outlines::TrueType { glyf, ref loca } => {
+                let mut commands = vec![];
+                for contour in read_glyf_contours(glyf, loca, glyph_id, 0)? {
+                    contour_to_commands(&contour, &mut commands);
+                }
+                Ok(commands)
+            }
+        }
+    }
+
+    /// Returns the kerning adjustments between pairs of the given glyphs, in font units.
+    ///
+    /// Pair adjustments come from the `kern` feature of the `GPOS` table, and from the `kern`
+    /// table for pairs not covered by `GPOS`.
+    pub fn kerning(&self, glyphs: &BTreeSet<u16>) -> Result<BTreeMap<(u16, u16), i16>> {
+        let mut pairs = BTreeMap::new();
+        if let Some(gpos) = self.table(b"GPOS") {
+            read_gpos_kerning(gpos, glyphs, &mut pairs)?;
+        }
+        if let Some(kern) = self.table(b"kern") {
+            read_kern_table(kern, glyphs, &mut pairs)?;
+        }
+        pairs.retain(|_, adjustment| *adjustment != 0);
+        Ok(pairs)
+    }
+}
+
+/// Reads the best Unicode subtable of a `cmap` table.
+fn read_cmap(cmap: &[u8]) -> Result<BTreeMap<u32, u16>> {
+    let num_subtables = usize::from(read_u16(cmap, 2)?);
+    let mut best: Option<(u8, usize)> = None;
+    for i in 0..num_subtables {
+        let record = 4 + i * 8;
+        let platform = read_u16(cmap, record)?;
+        let encoding = read_u16(cmap, record + 2)?;
+        let offset = read_u32(cmap, record + 4)? as usize;
+        let format = read_u16(cmap, offset)?;
+        let rank = match (platform, encoding, format) {
+            (3, 10, 12) | (0, _, 12) => 0,
+            (3, 1, 4) | (0, _, 4) => 1,
+            (3, 0, 4) => 2,
+            _ => continue,
+        };
+        if best.map(|(r, _)| rank < r).unwrap_or(true) {
+            best = Some((rank, offset));
+        }
+    }
+    let offset = match best {
+        Some((_, offset)) => offset,
+        None => return Err(invalid_data("No supported cmap subtable")),
+    };
+
+    let mut char_map = BTreeMap::new();
+    if read_u16(cmap, offset)? == 12 {
+        let num_groups = read_u32(cmap, offset + 12)? as usize;
+        for i in 0..num_groups {
+            let group = offset + 16 + i * 12;
+            let start = read_u32(cmap, group)?;
+            let end = read_u32(cmap, group + 4)?;
+            let start_glyph = read_u32(cmap, group + 8)?;
+            if end < start || end > 0x10_ffff {
+                return Err(invalid_data("Invalid cmap group"));
+            }
+            // Groups that go past the last 16-bit glyph ID are skipped.
+            match start_glyph.checked_add(end - start) {
+                Some(last_glyph) if last_glyph <= u32::from(u16::MAX) => (),
+                _ => continue,
+            }
+            for c in start..=end {
+                char_map.insert(c, (start_glyph + (c - start)) as u16);
+            }
+        }
+    } else {
+        let seg_count = usize::from(read_u16(cmap, offset + 6)? / 2);
+        let end_codes = offset + 14;
+        let start_codes = end_codes + seg_count * 2 + 2;
+        let deltas = start_codes + seg_count * 2;
+        let range_offsets = deltas + seg_count * 2;
+        for i in 0..seg_count {
+            let end = read_u16(cmap, end_codes + i * 2)?;
+            let start = read_u16(cmap, start_codes + i * 2)?;
+            let delta = read_u16(cmap, deltas + i * 2)?;
+            let range_offset = usize::from(read_u16(cmap, range_offsets + i * 2)?);
+            if start > end {
+                continue;
+            }
+            for c in u32::from(start)..=u32::from(end) {
+                if c == 0xffff {
+                    continue;
+                }
+                let glyph = if range_offset == 0 {
+                    (c as u16).wrapping_add(delta)
+                } else {
+                    // The offset is relative to the location of the range offset itself.
+                    let address =
+                        range_offsets + i * 2 + range_offset + (c as usize - start as usize) * 2;
+                    match read_u16(cmap, address)? {
+                        0 => 0,
+                        glyph => glyph.wrapping_add(delta),
+                    }
+                };
+                if glyph != 0 {
+                    char_map.insert(c, glyph);
+                }
+            }
+        }
+    }
+    Ok(char_map)
+}
+
+/// A point on a TrueType contour: (x, y, is_on_curve).
+type ContourPoint = (f32, f32, bool);
+
+/// Reads the contours of a glyph from the `glyf` table, resolving composite glyphs.
+fn read_glyf_contours(
+    glyf: &[u8],
+    loca: &[usize],
+    glyph_id: u16,
+    depth: usize,
+) -> Result<Vec<Vec<ContourPoint>>> {
+    if depth > MAX_COMPONENT_DEPTH {
+        return Err(invalid_data("Composite glyphs nested too deeply"));
+    }
+    let glyph_id = usize::from(glyph_id);
+    let (start, end) = match (loca.get(glyph_id), loca.get(glyph_id + 1)) {
+        (Some(&start), Some(&end)) if start <= end => (start, end),
+        _ => return Err(invalid_data("Invalid glyph ID")),
+    };
+    if start == end {
+        return Ok(vec![]);
+    }
+    let data = slice(glyf, start, end - start)?;
+    let num_contours = read_i16(data, 0)?;
+    if num_contours >= 0 {
+        read_simple_glyph(data, num_contours as usize)
+    } else {
+        read_composite_glyph(glyf, loca, data, depth)
+    }
+}
+
+fn read_simple_glyph(data: &[u8], num_contours: usize) -> Result<Vec<Vec<ContourPoint>>> {
+    let mut end_points = Vec::with_capacity(num_contours);
+    for i in 0..num_contours {
+        end_points.push(usize::from(read_u16(data, 10 + i * 2)?));
+    }
+    let num_points = end_points.last().map(|&end| end + 1).unwrap_or(0);
+    let instructions_len = usize::from(read_u16(data, 10 + num_contours * 2)?);
+    let mut offset = 12 + num_contours * 2 + instructions_len;
+
+    let mut flags = Vec::with_capacity(num_points);
+    while flags.len() < num_points {
+        let flag = read_u8(data, offset)?;
+        offset += 1;
+        flags.push(flag);
+        if flag & 0b1000 != 0 {
+            let repeat = read_u8(data, offset)?;
+            offset += 1;
+            for _ in 0..repeat {
+                flags.push(flag);
+            }
+        }
+    }
+    flags.truncate(num_points);
+
+    // Coordinates are deltas, either one unsigned byte with a sign flag, or a signed short.
+    let mut read_coordinates = |short_flag: u8, same_flag: u8| -> Result<Vec<f32>> {
+        let mut value = 0i32;
+        let mut values = Vec::with_capacity(num_points);
+        for &flag in &flags {
+            if flag & short_flag != 0 {
+                let delta = i32::from(read_u8(data, offset)?);
+                offset += 1;
+                value += if flag & same_flag != 0 { delta } else { -delta };
+            } else if flag & same_flag == 0 {
+                value += i32::from(read_i16(data, offset)?);
+                offset += 2;
+            }
+            values.push(value as f32);
+        }
+        Ok(values)
+    };
+    let xs = read_coordinates(0b10, 0b1_0000)?;
+    let ys = read_coordinates(0b100, 0b10_0000)?;
+
+    let mut contours = Vec::with_capacity(num_contours);
+    let mut start = 0;
+    for end in end_points {
+        if end < start || end >= num_points {
+            return Err(invalid_data("Invalid glyph contour"));
+        }
+        contours.push(
+            (start..=end)
+                .map(|i| (xs[i], ys[i], flags[i] & 1 != 0))
+                .collect(),
+        );
+        start = end + 1;
+    }
+    Ok(contours)
+}
+
+fn read_composite_glyph(
+    glyf: &[u8],
+    loca: &[usize],
+    data: &[u8],
+    depth: usize,
+) -> Result<Vec<Vec<ContourPoint>>> {
+    const ARGS_ARE_WORDS: u16 = 0x1;
+    const ARGS_ARE_XY_VALUES: u16 = 0x2;
+    const HAS_SCALE: u16 = 0x8;
+    const MORE_COMPONENTS: u16 = 0x20;
+    const HAS_X_AND_Y_SCALE: u16 = 0x40;
+    const HAS_TWO_BY_TWO: u16 = 0x80;
+
+    let f2dot14 = |offset| -> Result<f32> { Ok(f32::from(read_i16(data, offset)?) / 16384.0) };
+    let mut contours = vec![];
+    let mut offset = 10;
+    loop {
+        let flags = read_u16(data, offset)?;
+        let glyph_id = read_u16(data, offset + 2)?;
+        offset += 4;
+        let (arg1, arg2) = if flags & ARGS_ARE_WORDS != 0 {
+            offset += 4;
+            (read_i16(data, offset - 4)?, read_i16(data, offset - 2)?)
+        } else {
+            offset += 2;
+            (
+                i16::from(read_u8(data, offset - 2)? as i8),
+                i16::from(read_u8(data, offset - 1)? as i8),
+            )
+        };
+        // Components aligned by matching points are placed without an offset.
+        let (dx, dy) = if flags & ARGS_ARE_XY_VALUES != 0 {
+            (f32::from(arg1), f32::from(arg2))
+        } else {
+            (0.0, 0.0)
+        };
+        let (a, b, c, d) = if flags & HAS_SCALE != 0 {
+            offset += 2;
+            let scale = f2dot14(offset - 2)?;
+            (scale, 0.0, 0.0, scale)
+        } else if flags & HAS_X_AND_Y_SCALE != 0 {
+            offset += 4;
+            (f2dot14(offset - 4)?, 0.0, 0.0, f2dot14(offset - 2)?)
+        } else if flags & HAS_TWO_BY_TWO != 0 {
+            offset += 8;
+            (
+                f2dot14(offset - 8)?,
+                f2dot14(offset - 6)?,
+                f2dot14(offset - 4)?,
+                f2dot14(offset - 2)?,
+            )
+        } else {
+            (1.0, 0.0, 0.0, 1.0)
+        };
+
+        for contour in read_glyf_contours(glyf, loca, glyph_id, depth + 1)? {
+            contours.push(
+                contour
+                    .into_iter()
+                    .map(|(x, y, is_on_curve)| {
+                        (a * x + c * y + dx, b * x + d * y + dy, is_on_curve)
+                    })
+                    .collect(),
+            );
+        }
+        if flags & MORE_COMPONENTS == 0 {
+            break;
+        }
+    }
+    Ok(contours)
+}
+
+/// Converts a TrueType contour into drawing commands.
+///
+/// Consecutive off-curve points have an implied on-curve point halfway between them.
+fn contour_to_commands(contour: &[ContourPoint], commands: &mut Vec<DrawCommand>) {
+    if contour.is_empty() {
+        return;
+    }
+    let midpoint = |a: ContourPoint, b: ContourPoint| ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+    let len = contour.len();
+    let (start, first) = match contour.iter().position(|p| p.2) {
+        Some(i) => ((contour[i].0, contour[i].1), i + 1),
+        None => (midpoint(contour[len - 1], contour[0]), 0),
+    };
+    commands.push(DrawCommand::MoveTo {
+        x: start.0,
+        y: start.1,
+    });
+    let mut control: Option<(f32, f32)> = None;
+    for i in first..first + len {
+        let point = contour[i % len];
+        match (point.2, control) {
+            (true, Some((x1, y1))) => {
+                commands.push(DrawCommand::CurveTo {
+                    x1,
+                    y1,
+                    x2: point.0,
+                    y2: point.1,
+                });
+                control = None;
+            }
+            (true, None) => commands.push(DrawCommand::LineTo {
+                x: point.0,
+                y: point.1,
+            }),
+            (false, Some((x1, y1))) => {
+                let (x2, y2) = midpoint((x1, y1, false), point);
+                commands.push(DrawCommand::CurveTo { x1, y1, x2, y2 });
+                control = Some((point.0, point.1));
+            }
+            (false, None) => control = Some((point.0, point.1)),
+        }
+    }
+    // Close the contour if it didn't end on its starting point.
+    match control {
+        Some((x1, y1)) => commands.push(DrawCommand::CurveTo {
+            x1,
+            y1,
+            x2: start.0,
+            y2: start.1,
+        }),
+        None if first == 0 => commands.push(DrawCommand::LineTo {
+            x: start.0,
+            y: start.1,
+        }),
+        None => (),
+    }
+}
+
+/// Reads format 0 subtables of a `kern` table, in either the Microsoft or Apple layout.
+fn read_kern_table(
+    kern: &[u8],
+    glyphs: &BTreeSet<u16>,
+    pairs: &mut BTreeMap<(u16, u16), i16>,
+) -> Result<()> {
+    let is_apple = read_u16(kern, 0)? == 1;
+    let (num_subtables, mut offset) = if is_apple {
+        (read_u32(kern, 4)? as usize, 8)
+    } else {
+        (usize::from(read_u16(kern, 2)?), 4)
+    };
+    for _ in 0..num_subtables {
+        let (length, is_horizontal_format_0, header_len) = if is_apple {
+            let coverage = read_u16(kern, offset + 4)?;
+            (read_u32(kern, offset)? as usize, coverage & 0xe0ff == 0, 8)
+        } else {
+            let coverage = read_u16(kern, offset + 4)?;
+            (
+                usize::from(read_u16(kern, offset + 2)?),
+                coverage & 0xff07 == 0x0001,
+                6,
+            )
+        };
+        if is_horizontal_format_0 {
+            let body = offset + header_len;
+            let num_pairs = usize::from(read_u16(kern, body)?);
+            for i in 0..num_pairs {
+                let pair = body + 8 + i * 6;
+                let left = read_u16(kern, pair)?;
+                let right = read_u16(kern, pair + 2)?;
+                if glyphs.contains(&left) && glyphs.contains(&right) {
+                    let value = read_i16(kern, pair + 4)?;
+                    pairs.entry((left, right)).or_insert(value);
+                }
+            }
+        }
+        if length == 0 {
+            break;
+        }
+        offset += length;
+    }
+    Ok(())
+}
+
+/// Reads pair adjustments from the lookups of the `kern` feature in a `GPOS` table.
+fn read_gpos_kerning(
+    gpos: &[u8],
+    glyphs: &BTreeSet<u16>,
+    pairs: &mut BTreeMap<(u16, u16), i16>,
+) -> Result<()> {
+    let feature_list = usize::from(read_u16(gpos, 6)?);
+    let lookup_list = usize::from(read_u16(gpos, 8)?);
+
+    let mut lookup_indices = BTreeSet::new();
+    let num_features = usize::from(read_u16(gpos, feature_list)?);
+    for i in 0..num_features {
+        let record = feature_list + 2 + i * 6;
+        if slice(gpos, record, 4)? != b"kern" {
+            continue;
+        }
+        let feature = feature_list + usize::from(read_u16(gpos, record + 4)?);
+        let num_lookups = usize::from(read_u16(gpos, feature + 2)?);
+        for j in 0..num_lookups {
+            lookup_indices.insert(usize::from(read_u16(gpos, feature + 4 + j * 2)?));
+        }
+    }
+
+    let num_lookups = usize::from(read_u16(gpos, lookup_list)?);
+    for index in lookup_indices {
+        if index >= num_lookups {
+            continue;
+        }
+        let lookup = lookup_list + usize::from(read_u16(gpos, lookup_list + 2 + index * 2)?);
+        let lookup_type = read_u16(gpos, lookup)?;
+        let num_subtables = usize::from(read_u16(gpos, lookup + 4)?);
+        for i in 0..num_subtables {
+            let mut subtable = lookup + usize::from(read_u16(gpos, lookup + 6 + i * 2)?);
+            let mut subtable_type = lookup_type;
+            if lookup_type == 9 {
+                // Extension subtables point to the real subtable with a 32-bit offset.
+                subtable_type = read_u16(gpos, subtable + 2)?;
+                subtable = subtable
+                    .checked_add(read_u32(gpos, subtable + 4)? as usize)
+                    .ok_or_else(|| invalid_data("Invalid GPOS subtable offset"))?;
+            }
+            if subtable_type == 2 {
+                let data = gpos
+                    .get(subtable..)
+                    .ok_or_else(|| invalid_data("Invalid GPOS subtable offset"))?;
+                read_pair_pos(data, glyphs, pairs)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the size of a value record and the offset of its x advance, if it has one.
+fn value_record_layout(format: u16) -> (usize, Option<usize>) {
+    let size = format.count_ones() as usize * 2;
+    let x_advance = if format & 0b100 != 0 {
+        Some((format & 0b11).count_ones() as usize * 2)
+    } else {
+        None
+    };
+    (size, x_advance)
+}
+
+fn read_pair_pos(
+    data: &[u8],
+    glyphs: &BTreeSet<u16>,
+    pairs: &mut BTreeMap<(u16, u16), i16>,
+) -> Result<()> {
+    let format = read_u16(data, 0)?;
+    let coverage = usize::from(read_u16(data, 2)?);
+    let (size1, x_advance) = value_record_layout(read_u16(data, 4)?);
+    let (size2, _) = value_record_layout(read_u16(data, 6)?);
+    let x_advance = match x_advance {
+        Some(offset) => offset,
+        None => return Ok(()),
+    };
+
+    match format {
+        1 => {
+            let num_pair_sets = usize::from(read_u16(data, 8)?);
+            for &first in glyphs {
+                let index = match coverage_index(data, coverage, first)? {
+                    Some(index) if index < num_pair_sets => index,
+                    _ => continue,
+                };
+                let pair_set = usize::from(read_u16(data, 10 + index * 2)?);
+                let num_pairs = usize::from(read_u16(data, pair_set)?);
+                for i in 0..num_pairs {
+                    let record = pair_set + 2 + i * (2 + size1 + size2);
+                    let second = read_u16(data, record)?;
+                    if glyphs.contains(&second) {
+                        let value = read_i16(data, record + 2 + x_advance)?;
+                        pairs.entry((first, second)).or_insert(value);
+                    }
+                }
+            }
+        }
+        2 => {
+            let class_def_1 = usize::from(read_u16(data, 8)?);
+            let class_def_2 = usize::from(read_u16(data, 10)?);
+            let num_classes_1 = usize::from(read_u16(data, 12)?);
+            let num_classes_2 = usize::from(read_u16(data, 14)?);
+            let mut second_classes = HashMap::new();
+            for &second in glyphs {
+                second_classes.insert(second, glyph_class(data, class_def_2, second)?);
+            }
+            for &first in glyphs {
+                if coverage_index(data, coverage, first)?.is_none() {
+                    continue;
+                }
+                let class_1 = glyph_class(data, class_def_1, first)?;
+                if class_1 >= num_classes_1 {
+                    continue;
+                }
+                for (&second, &class_2) in &second_classes {
+                    if class_2 >= num_classes_2 {
+                        continue;
+                    }
+                    let record = 16 + (class_1 * num_classes_2 + class_2) * (size1 + size2);
+                    let value = read_i16(data, record + x_advance)?;
+                    pairs.entry((first, second)).or_insert(value);
+                }
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Returns the coverage index of a glyph in a coverage table.
+fn coverage_index(data: &[u8], offset: usize, glyph: u16) -> Result<Option<usize>> {
+    let count = usize::from(read_u16(data, offset + 2)?);
+    match read_u16(data, offset)? {
+        1 => {
+            for i in 0..count {
+                if read_u16(data, offset + 4 + i * 2)? == glyph {
+                    return Ok(Some(i));
+                }
+            }
+        }
+        2 => {
+            for i in 0..count {
+                let range = offset + 4 + i * 6;
+                let start = read_u16(data, range)?;
+                let end = read_u16(data, range + 2)?;
+                if (start..=end).contains(&glyph) {
+                    let start_index = usize::from(read_u16(data, range + 4)?);
+                    return Ok(Some(start_index + usize::from(glyph - start)));
+                }
+            }
+        }
+        _ => (),
+    }
+    Ok(None)
+}
+
+/// Returns the class of a glyph in a class definition table.
+fn glyph_class(data: &[u8], offset: usize, glyph: u16) -> Result<usize> {
+    // A missing class definition puts every glyph in class 0.
+    if offset == 0 {
+        return Ok(0);
+    }
+    match read_u16(data, offset)? {
+        1 => {
+            let start = read_u16(data, offset + 2)?;
+            let count = read_u16(data, offset + 4)?;
+            if glyph >= start && glyph - start < count {
+                let index = usize::from(glyph - start);
+                return Ok(usize::from(read_u16(data, offset + 6 + index * 2)?));
+            }
+        }
+        2 => {
+            let count = usize::from(read_u16(data, offset + 2)?);
+            for i in 0..count {
+                let range = offset + 4 + i * 6;
+                if (read_u16(data, range)?..=read_u16(data, range + 2)?).contains(&glyph) {
+                    return Ok(usize::from(read_u16(data, range + 4)?));
+                }
+            }
+        }
+        _ => (),
+    }
+    Ok(0)
+}
+
+/// Builds a `DefineFont3` font from a TrueType or OpenType font.
+///
+/// Only glyphs for the characters in `chars` are included; characters outside the Basic
+/// Multilingual Plane or missing from the font are skipped. Outlines are scaled to the 1024 × 20
+/// twip EM square of `DefineFont3`, with cubic CFF curves approximated by quadratic curves.
+/// Ascent, descent and leading come from the `hhea` table, and kerning from the `GPOS` and
+/// `kern` tables.
+///
+/// The returned font also has its full name and copyright from the `name` table and an
+/// alignment zone for each glyph, for writing `DefineFontName` and `DefineFontAlignZones` tags
+/// with `EmbeddedFont::to_tags`. Clear them to leave those tags out.
+///
+/// Glyphs are filled with the even-odd rule in SWF, so overlapping contours will show holes.
+pub fn import_font(data: &[u8], id: CharacterId, chars: &str) -> Result<EmbeddedFont> {
+    let font = OpenTypeFont::parse(data)?;
+    let units_per_em = f32::from(font.units_per_em);
+    // Glyph shapes are in pixels on a 1024 pixel EM square.
+    let shape_scale = 1024.0 / units_per_em;
+    // Layout values are in twips on a 20480 twip EM square.
+    let layout_scale = shape_scale * 20.0;
+    let tolerance = units_per_em / 2048.0;
+
+    let mut codes: Vec<u16> = chars
+        .chars()
+        .filter_map(|c| {
+            let c = c as u32;
+            if c <= 0xffff {
+                Some(c as u16)
+            } else {
+                None
+            }
+        })
+        .collect();
+    codes.sort_unstable();
+    codes.dedup();
+
+    let mut glyphs = vec![];
+    let mut glyph_ids = vec![];
+    for code in codes {
+        let glyph_id = match font.char_map.get(&u32::from(code)) {
+            Some(&glyph_id) => glyph_id,
+            None => continue,
+        };
+        let commands = font.glyph_outline(glyph_id, tolerance)?;
+        let (shape_records, bounds) = commands_to_records(&commands, shape_scale);
+        let advance = f32::from(font.advance(glyph_id)) * layout_scale;
+        glyphs.push(Glyph {
+            shape_records,
+            code,
+            advance: Some(advance.round().min(f32::from(i16::MAX)) as i16),
+            bounds: Some(bounds),
+        });
+        glyph_ids.push(glyph_id);
+    }
+
+    let glyph_set: BTreeSet<u16> = glyph_ids.iter().cloned().collect();
+    let mut kerning = vec![];
+    for ((left, right), adjustment) in font.kerning(&glyph_set)? {
+        let adjustment = (f32::from(adjustment) * layout_scale)
+            .round()
+            .clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16;
+        for (i, _) in glyph_ids.iter().enumerate().filter(|&(_, &g)| g == left) {
+            for (j, _) in glyph_ids.iter().enumerate().filter(|&(_, &g)| g == right) {
+                kerning.push(KerningRecord {
+                    left_code: glyphs[i].code,
+                    right_code: glyphs[j].code,
+                    adjustment,
+                });
+            }
+        }
+    }
+
+    let to_layout = |value: i16| (f32::from(value) * layout_scale).round();
+    let layout = FontLayout {
+        ascent: to_layout(font.ascender).clamp(0.0, 65535.0) as u16,
+        descent: (-to_layout(font.descender)).clamp(0.0, 65535.0) as u16,
+        leading: to_layout(font.line_gap).clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16,
+        kerning,
+    };
+
+    let align_zones = glyphs
+        .iter()
+        .map(|glyph| {
+            let bounds = glyph.bounds.as_ref().unwrap();
+            // Vertical zones are measured upwards from the baseline.
+            FontAlignZone {
+                left: f32_to_f16(bounds.x_min) as i16,
+                width: f32_to_f16(bounds.x_max - bounds.x_min) as i16,
+                bottom: f32_to_f16(-bounds.y_max) as i16,
+                height: f32_to_f16(bounds.y_max - bounds.y_min) as i16,
+            }
+        })
+        .collect();
+
+    let name = font.name(16).or_else(|| font.name(1)).unwrap_or_default();
+    Ok(EmbeddedFont {
+        font: Font {
+            version: 3,
+            id,
+            name,
+            language: Language::Unknown,
+            layout: Some(layout),
+            glyphs,
+            is_small_text: false,
+            is_shift_jis: false,
+            is_ansi: false,
+            is_bold: font.is_bold(),
+            is_italic: font.is_italic(),
+        },
+        full_name: font.name(4),
+        copyright: font.name(0),
+        align_zones,
+    })
+}
+
+/// Converts an outline in font units to glyph shape records, returning the records and their
+/// bounds.
+fn commands_to_records(commands: &[DrawCommand], scale: f32) -> (Vec<ShapeRecord>, Rectangle) {
+    // Work in whole twips so that edge deltas add up to exact positions.
+    let to_twips = |x: f32, y: f32| {
+        (
+            (x * scale * 20.0).round() as i32,
+            (-y * scale * 20.0).round() as i32,
+        )
+    };
+    let to_pixels = |n: i32| n as f32 / 20.0;
+    let mut records = vec![];
+    let mut position = (0, 0);
+    let mut bounds: Option<(i32, i32, i32, i32)> = None;
+    let mut extend = |x: i32, y: i32| {
+        bounds = Some(match bounds {
+            Some((x_min, x_max, y_min, y_max)) => {
+                (x_min.min(x), x_max.max(x), y_min.min(y), y_max.max(y))
+            }
+            None => (x, x, y, y),
+        });
+    };
+    for command in commands {
+        match *command {
+            DrawCommand::MoveTo { x, y } => {
+                position = to_twips(x, y);
+                extend(position.0, position.1);
+                records.push(ShapeRecord::StyleChange(StyleChangeData {
+                    move_to: Some((to_pixels(position.0), to_pixels(position.1))),
+                    fill_style_0: None,
+                    fill_style_1: if records.is_empty() { Some(1) } else { None },
+                    line_style: None,
+                    new_styles: None,
+                }));
+            }
+            DrawCommand::LineTo { x, y } => {
+                let end = to_twips(x, y);
+                if end != position {
+                    records.push(ShapeRecord::StraightEdge {
+                        delta_x: to_pixels(end.0 - position.0),
+                        delta_y: to_pixels(end.1 - position.1),
+                    });
+                    extend(end.0, end.1);
+                    position = end;
+                }
+            }
+            DrawCommand::CurveTo { x1, y1, x2, y2 } => {
+                let control = to_twips(x1, y1);
+                let end = to_twips(x2, y2);
+                if control == position && end == position {
+                    continue;
+                }
+                records.push(ShapeRecord::CurvedEdge {
+                    control_delta_x: to_pixels(control.0 - position.0),
+                    control_delta_y: to_pixels(control.1 - position.1),
+                    anchor_delta_x: to_pixels(end.0 - control.0),
+                    anchor_delta_y: to_pixels(end.1 - control.1),
+                });
+                // Include the curve's extremes in the bounds.
+                for &(p0, p1, p2) in &[
+                    (position.0, control.0, end.0),
+                    (position.1, control.1, end.1),
+                ] {
+                    let denominator = p0 - 2 * p1 + p2;
+                    if denominator != 0 {
+                        let t = (p0 - p1) as f32 / denominator as f32;
+                        if t > 0.0 && t < 1.0 {
+                            let mt = 1.0 - t;
+                            let x = mt * mt * position.0 as f32
+                                + 2.0 * mt * t * control.0 as f32
+                                + t * t * end.0 as f32;
+                            let y = mt * mt * position.1 as f32
+                                + 2.0 * mt * t * control.1 as f32
+                                + t * t * end.1 as f32;
+                            extend(x.round() as i32, y.round() as i32);
+                        }
+                    }
+                }
+                extend(end.0, end.1);
+                position = end;
+            }
+        }
+    }
+    let (x_min, x_max, y_min, y_max) = bounds.unwrap_or((0, 0, 0, 0));
+    let bounds = Rectangle {
+        x_min: to_pixels(x_min),
+        x_max: to_pixels(x_max),
+        y_min: to_pixels(y_min),
+        y_max: to_pixels(y_max),
+    };
+    (records, bounds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use font::write_ttf;
+    use test_data::square_glyph;
+
+    fn test_ttf() -> Vec<u8> {
+        let font = EmbeddedFont {
+            font: Font {
+                version: 3,
+                id: 1,
+                name: "Test".to_string(),
+                language: Language::Latin,
+                layout: Some(FontLayout {
+                    ascent: 800 * 20,
+                    descent: 200 * 20,
+                    leading: 40,
+                    kerning: vec![KerningRecord {
+                        left_code: 65,
+                        right_code: 66,
+                        adjustment: -50 * 20,
+                    }],
+                }),
+                glyphs: vec![
+                    square_glyph(65, 500.0, false),
+                    square_glyph(66, 500.0, false),
+                ],
+                is_small_text: false,
+                is_shift_jis: false,
+                is_ansi: false,
+                is_bold: true,
+                is_italic: false,
+            },
+            full_name: Some("Test Bold".to_string()),
+            copyright: None,
+            align_zones: vec![],
+        };
+        let mut data = vec![];
+        write_ttf(&font, &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn read_exported_font() {
+        let data = test_ttf();
+        let font = OpenTypeFont::parse(&data).unwrap();
+        assert_eq!(font.units_per_em, 1024);
+        assert_eq!(font.char_map.get(&65), Some(&1));
+        assert_eq!(font.char_map.get(&66), Some(&2));
+        assert_eq!(font.advance(1), 505);
+        assert_eq!(font.name(1), Some("Test".to_string()));
+        assert_eq!(font.name(4), Some("Test Bold".to_string()));
+        assert!(font.is_bold());
+        assert!(!font.is_italic());
+        let glyphs = [1, 2].iter().cloned().collect();
+        let kerning = font.kerning(&glyphs).unwrap();
+        assert_eq!(kerning.get(&(1, 2)), Some(&-50));
+    }
+
+    #[test]
+    fn import_exported_font() {
+        let data = test_ttf();
+        let font = import_font(&data, 5, "BAx").unwrap();
+        assert_eq!(font.font.id, 5);
+        assert_eq!(font.font.version, 3);
+        assert!(font.font.is_bold);
+        let codes: Vec<u16> = font.font.glyphs.iter().map(|g| g.code).collect();
+        assert_eq!(codes, vec![65, 66]);
+        let layout = font.font.layout.as_ref().unwrap();
+        assert_eq!(
+            (layout.ascent, layout.descent, layout.leading),
+            (800 * 20, 200 * 20, 40)
+        );
+        assert_eq!(
+            layout.kerning,
+            vec![KerningRecord {
+                left_code: 65,
+                right_code: 66,
+                adjustment: -50 * 20,
+            }]
+        );
+        assert_eq!(font.align_zones.len(), 2);
+
+        let glyph = &font.font.glyphs[0];
+        assert_eq!(glyph.advance, Some(505 * 20));
+        assert_eq!(
+            glyph.bounds,
+            Some(Rectangle {
+                x_min: 0.0,
+                x_max: 500.0,
+                y_min: -500.0,
+                y_max: 0.0,
+            })
+        );
+        assert_eq!(glyph.shape_records.len(), 5);
+    }
+
+    #[test]
+    fn implied_on_curve_points() {
+        let mut commands = vec![];
+        contour_to_commands(
+            &[(0.0, 0.0, false), (10.0, 0.0, false), (10.0, 10.0, true)],
+            &mut commands,
+        );
+        assert_eq!(
+            commands,
+            vec![
+                DrawCommand::MoveTo { x: 10.0, y: 10.0 },
+                DrawCommand::CurveTo {
+                    x1: 0.0,
+                    y1: 0.0,
+                    x2: 5.0,
+                    y2: 0.0,
+                },
+                DrawCommand::CurveTo {
+                    x1: 10.0,
+                    y1: 0.0,
+                    x2: 10.0,
+                    y2: 10.0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_tables() {
+        fn u16s(values: &[u16]) -> Vec<u8> {
+            values
+                .iter()
+                .flat_map(|value| value.to_be_bytes())
+                .collect()
+        }
+        fn u32s(values: &[u32]) -> Vec<u8> {
+            values
+                .iter()
+                .flat_map(|value| value.to_be_bytes())
+                .collect()
+        }
+
+        // A format 12 cmap with a group whose glyph IDs overflow, and a valid group.
+        let cmap = [
+            u16s(&[0, 1, 3, 10]),
+            u32s(&[12]),
+            u16s(&[12, 0]),
+            u32s(&[40, 0, 2, 0x20, 0x40, 0xffff_fff0, 0x41, 0x42, 1]),
+        ]
+        .concat();
+        let char_map = read_cmap(&cmap).unwrap();
+        assert_eq!(char_map, vec![(0x41, 1), (0x42, 2)].into_iter().collect());
+
+        // A kern feature with an extension subtable that points past the end of the table.
+        let gpos = [
+            u32s(&[0x1_0000]),
+            u16s(&[0, 10, 24, 1]),
+            b"kern".to_vec(),
+            u16s(&[8, 0, 1, 0, 1, 4, 9, 0, 1, 8, 1, 2]),
+            u32s(&[0x1000]),
+        ]
+        .concat();
+        let glyphs = [1, 2].iter().cloned().collect();
+        let error = read_gpos_kerning(&gpos, &glyphs, &mut BTreeMap::new()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,979 @@
+//! Builds TrueType fonts from SWF font definitions.
+use super::{code_to_char, f16_to_f32, EmbeddedFont};
+use byteorder::{BigEndian, WriteBytesExt};
+use shape_utils::{flatten_quadratic, glyph_to_commands, winding_number, DrawCommand};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Result, Write};
+use types::*;
+
+/// The EM square size of the exported font.
+///
+/// This matches `DefineFont` and `DefineFont2`, where glyphs are defined in twips on a 1024
+/// twip EM square. `DefineFont3` glyphs use a 20480 twip EM square and are scaled down.
+const UNITS_PER_EM: u16 = 1024;
+
+/// A point on a TrueType contour.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Point {
+    x: i16,
+    y: i16,
+    is_on_curve: bool,
+}
+
+/// A glyph converted to TrueType coordinates, with the y-axis pointing up.
+struct TtfGlyph {
+    contours: Vec<Vec<Point>>,
+    advance: u16,
+}
+
+impl TtfGlyph {
+    /// Returns the glyph's bounds as (x_min, y_min, x_max, y_max).
+    fn bounds(&self) -> Option<(i16, i16, i16, i16)> {
+        let mut points = self.contours.iter().flat_map(|contour| contour.iter());
+        let first = points.next()?;
+        Some(points.fold(
+            (first.x, first.y, first.x, first.y),
+            |(x_min, y_min, x_max, y_max), p| {
+                (
+                    x_min.min(p.x),
+                    y_min.min(p.y),
+                    x_max.max(p.x),
+                    y_max.max(p.y),
+                )
+            },
+        ))
+    }
+
+    fn num_points(&self) -> usize {
+        self.contours.iter().map(|contour| contour.len()).sum()
+    }
+}
+
+/// Writes a font as a TrueType font file.
+///
+/// Glyph outlines become `glyf` contours, with contour directions fixed up so that the even-odd
+/// fills of SWF glyphs render the same with the non-zero rule used by TrueType. Glyph codes are
+/// mapped through a Unicode `cmap`; codes of Shift-JIS fonts are converted to Unicode. Fonts
+/// without layout information get advances based on the glyph bounds.
+///
+/// Alignment zones, if present, provide the x-height and cap height in the `OS/2` table.
+pub fn write_ttf<W: Write>(font: &EmbeddedFont, mut output: W) -> Result<()> {
+    let data = TtfBuilder::new(font)?.build()?;
+    output.write_all(&data)
+}
+
+struct TtfBuilder<'a> {
+    font: &'a EmbeddedFont,
+    /// Multiplier from glyph shape coordinates to font units.
+    scale: f32,
+    /// Glyph 0 is the `.notdef` glyph; SWF glyph `i` becomes glyph `i + 1`.
+    glyphs: Vec<TtfGlyph>,
+    /// Unicode code point to glyph index.
+    char_map: BTreeMap<u32, u16>,
+}
+
+impl<'a> TtfBuilder<'a> {
+    fn new(font: &'a EmbeddedFont) -> Result<TtfBuilder<'a>> {
+        if font.font.glyphs.len() >= 0xffff {
+            return Err(Error::new(ErrorKind::InvalidData, "Too many glyphs"));
+        }
+
+        // Shape coordinates are twips / 20, so scale back up to twips on a 1024 twip EM square.
+        let scale = if font.font.version >= 3 { 1.0 } else { 20.0 };
+        let mut glyphs = vec![TtfGlyph {
+            contours: vec![],
+            advance: UNITS_PER_EM / 2,
+        }];
+        let mut char_map = BTreeMap::new();
+        for (i, glyph) in font.font.glyphs.iter().enumerate() {
+            let contours = convert_contours(&glyph.shape_records, scale);
+            let mut ttf_glyph = TtfGlyph {
+                contours,
+                advance: 0,
+            };
+            ttf_glyph.advance = match (&font.font.layout, glyph.advance) {
+                (&Some(_), Some(advance)) => {
+                    let advance = if font.font.version >= 3 {
+                        f32::from(advance) / 20.0
+                    } else {
+                        f32::from(advance)
+                    };
+                    advance.round().max(0.0) as u16
+                }
+                _ => ttf_glyph
+                    .bounds()
+                    .map(|(_, _, x_max, _)| x_max.max(0) as u16)
+                    .unwrap_or(UNITS_PER_EM / 4),
+            };
+            glyphs.push(ttf_glyph);
+
+            if let Some(c) = code_to_char(&font.font, glyph.code) {
+                char_map.entry(u32::from(c)).or_insert(i as u16 + 1);
+            }
+        }
+        Ok(TtfBuilder {
+            font,
+            scale,
+            glyphs,
+            char_map,
+        })
+    }
+
+    fn build(&self) -> Result<Vec<u8>> {
+        let (glyf, loca) = self.glyf_and_loca()?;
+        let mut tables: Vec<([u8; 4], Vec<u8>)> = vec![
+            (*b"OS/2", self.os2()?),
+            (*b"cmap", self.cmap()?),
+            (*b"glyf", glyf),
+            (*b"head", self.head()?),
+            (*b"hhea", self.hhea()?),
+            (*b"hmtx", self.hmtx()?),
+            (*b"loca", loca),
+            (*b"maxp", self.maxp()?),
+            (*b"name", self.name()?),
+            (*b"post", self.post()?),
+        ];
+        let kern = self.kern()?;
+        if let Some(kern) = kern {
+            tables.push((*b"kern", kern));
+        }
+        tables.sort_by_key(|&(tag, _)| tag);
+        write_sfnt(0x0001_0000, &tables)
+    }
+
+    fn ascent(&self) -> i16 {
+        match self.font.font.layout {
+            Some(ref layout) => self.layout_value(i32::from(layout.ascent)),
+            None => self.glyph_extent(|(_, _, _, y_max)| y_max).max(0),
+        }
+    }
+
+    fn descent(&self) -> i16 {
+        match self.font.font.layout {
+            Some(ref layout) => -self.layout_value(i32::from(layout.descent)),
+            None => -self.glyph_extent(|(_, y_min, _, _)| -y_min).max(0),
+        }
+    }
+
+    fn leading(&self) -> i16 {
+        match self.font.font.layout {
+            Some(ref layout) => self.layout_value(i32::from(layout.leading)),
+            None => 0,
+        }
+    }
+
+    /// Converts a layout value, which is in twips on the font's EM square, to font units.
+    fn layout_value(&self, value: i32) -> i16 {
+        let value = if self.font.font.version >= 3 {
+            (value as f32 / 20.0).round() as i32
+        } else {
+            value
+        };
+        value.clamp(i32::from(i16::MIN), i32::from(i16::MAX)) as i16
+    }
+
+    /// Returns the largest value of `f` over the bounds of every glyph.
+    fn glyph_extent<F: Fn((i16, i16, i16, i16)) -> i16>(&self, f: F) -> i16 {
+        self.glyphs
+            .iter()
+            .filter_map(|glyph| glyph.bounds())
+            .map(f)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn font_bounds(&self) -> (i16, i16, i16, i16) {
+        self.glyphs
+            .iter()
+            .filter_map(|glyph| glyph.bounds())
+            .fold(
+                None::<(i16, i16, i16, i16)>,
+                |bounds, (x_min, y_min, x_max, y_max)| {
+                    Some(match bounds {
+                        Some((a, b, c, d)) => {
+                            (a.min(x_min), b.min(y_min), c.max(x_max), d.max(y_max))
+                        }
+                        None => (x_min, y_min, x_max, y_max),
+                    })
+                },
+            )
+            .unwrap_or((0, 0, 0, 0))
+    }
+
+    /// Returns the height of the glyph for `c`, preferring its alignment zone if it has one.
+    fn char_height(&self, c: char) -> i16 {
+        let glyph_index = match self.char_map.get(&(c as u32)) {
+            Some(&i) => i as usize,
+            None => return 0,
+        };
+        if let Some(zone) = self.font.align_zones.get(glyph_index - 1) {
+            let bottom = f16_to_f32(zone.bottom as u16);
+            let top = bottom + f16_to_f32(zone.height as u16);
+            let height = bottom.abs().max(top.abs()) * self.scale;
+            if height.is_finite() && height > 0.0 {
+                return height.round().min(f32::from(i16::MAX)) as i16;
+            }
+        }
+        self.glyphs[glyph_index]
+            .bounds()
+            .map(|(_, _, _, y_max)| y_max.max(0))
+            .unwrap_or(0)
+    }
+
+    fn head(&self) -> Result<Vec<u8>> {
+        let (x_min, y_min, x_max, y_max) = self.font_bounds();
+        let mut out = vec![];
+        out.write_u32::<BigEndian>(0x0001_0000)?; // Version 1.0
+        out.write_u32::<BigEndian>(0x0001_0000)?; // Font revision
+        out.write_u32::<BigEndian>(0)?; // Checksum adjustment, filled in by `write_sfnt`.
+        out.write_u32::<BigEndian>(0x5F0F_3CF5)?; // Magic number
+        out.write_u16::<BigEndian>(0b1011)?; // Flags: baseline at 0, lsb at 0, integer ppem.
+        out.write_u16::<BigEndian>(UNITS_PER_EM)?;
+        out.write_i64::<BigEndian>(0)?; // Created
+        out.write_i64::<BigEndian>(0)?; // Modified
+        out.write_i16::<BigEndian>(x_min)?;
+        out.write_i16::<BigEndian>(y_min)?;
+        out.write_i16::<BigEndian>(x_max)?;
+        out.write_i16::<BigEndian>(y_max)?;
+        out.write_u16::<BigEndian>(self.mac_style())?;
+        out.write_u16::<BigEndian>(8)?; // Smallest readable size in pixels
+        out.write_i16::<BigEndian>(2)?; // Font direction hint
+        out.write_i16::<BigEndian>(1)?; // Long loca offsets
+        out.write_i16::<BigEndian>(0)?; // Glyph data format
+        Ok(out)
+    }
+
+    fn mac_style(&self) -> u16 {
+        let mut style = 0;
+        if self.font.font.is_bold {
+            style |= 0b1;
+        }
+        if self.font.font.is_italic {
+            style |= 0b10;
+        }
+        style
+    }
+
+    fn hhea(&self) -> Result<Vec<u8>> {
+        let mut out = vec![];
+        out.write_u32::<BigEndian>(0x0001_0000)?;
+        out.write_i16::<BigEndian>(self.ascent())?;
+        out.write_i16::<BigEndian>(self.descent())?;
+        out.write_i16::<BigEndian>(self.leading())?;
+        let advance_max = self.glyphs.iter().map(|g| g.advance).max().unwrap_or(0);
+        out.write_u16::<BigEndian>(advance_max)?;
+        let mut min_lsb = 0;
+        let mut min_rsb = 0;
+        let mut max_extent = 0;
+        for glyph in &self.glyphs {
+            if let Some((x_min, _, x_max, _)) = glyph.bounds() {
+                min_lsb = min_lsb.min(x_min);
+                min_rsb = min_rsb.min(glyph.advance as i16 - x_max);
+                max_extent = max_extent.max(x_max);
+            }
+        }
+        out.write_i16::<BigEndian>(min_lsb)?;
+        out.write_i16::<BigEndian>(min_rsb)?;
+        out.write_i16::<BigEndian>(max_extent)?;
+        out.write_i16::<BigEndian>(1)?; // Caret slope rise
+        out.write_i16::<BigEndian>(0)?; // Caret slope run
+        out.write_i16::<BigEndian>(0)?; // Caret offset
+        for _ in 0..4 {
+            out.write_i16::<BigEndian>(0)?; // Reserved
+        }
+        out.write_i16::<BigEndian>(0)?; // Metric data format
+        out.write_u16::<BigEndian>(self.glyphs.len() as u16)?;
+        Ok(out)
+    }
+
+    fn hmtx(&self) -> Result<Vec<u8>> {
+        let mut out = vec![];
+        for glyph in &self.glyphs {
+            out.write_u16::<BigEndian>(glyph.advance)?;
+            let lsb = glyph.bounds().map(|(x_min, _, _, _)| x_min).unwrap_or(0);
+            out.write_i16::<BigEndian>(lsb)?;
+        }
+        Ok(out)
+    }
+
+    fn maxp(&self) -> Result<Vec<u8>> {
+        let max_points = self.glyphs.iter().map(|g| g.num_points()).max();
+        let max_contours = self.glyphs.iter().map(|g| g.contours.len()).max();
+        let mut out = vec![];
+        out.write_u32::<BigEndian>(0x0001_0000)?;
+        out.write_u16::<BigEndian>(self.glyphs.len() as u16)?;
+        out.write_u16::<BigEndian>(max_points.unwrap_or(0) as u16)?;
+        out.write_u16::<BigEndian>(max_contours.unwrap_or(0) as u16)?;
+        out.write_u16::<BigEndian>(0)?; // Max composite points
+        out.write_u16::<BigEndian>(0)?; // Max composite contours
+        out.write_u16::<BigEndian>(2)?; // Max zones
+        for _ in 0..8 {
+            // Twilight points, storage, function defs, instruction defs, stack elements,
+            // size of instructions, component elements, component depth.
+            out.write_u16::<BigEndian>(0)?;
+        }
+        Ok(out)
+    }
+
+    fn os2(&self) -> Result<Vec<u8>> {
+        let font = &self.font.font;
+        let ascent = self.ascent();
+        let descent = self.descent();
+        let advances: Vec<u32> = self.glyphs[1..]
+            .iter()
+            .filter(|g| g.advance > 0)
+            .map(|g| u32::from(g.advance))
+            .collect();
+        let avg_width = if advances.is_empty() {
+            0
+        } else {
+            advances.iter().sum::<u32>() / advances.len() as u32
+        };
+        let em = UNITS_PER_EM as i16;
+
+        let mut out = vec![];
+        out.write_u16::<BigEndian>(4)?; // Version
+        out.write_i16::<BigEndian>(avg_width as i16)?;
+        out.write_u16::<BigEndian>(if font.is_bold { 700 } else { 400 })?;
+        out.write_u16::<BigEndian>(5)?; // Normal width
+        out.write_u16::<BigEndian>(0)?; // Installable embedding
+                                        // Subscript and superscript size and offset.
+        for &value in &[
+            em * 2 / 3,
+            em * 2 / 3,
+            0,
+            em / 8,
+            em * 2 / 3,
+            em * 2 / 3,
+            0,
+            em / 3,
+        ] {
+            out.write_i16::<BigEndian>(value)?;
+        }
+        out.write_i16::<BigEndian>(em / 20)?; // Strikeout size
+        out.write_i16::<BigEndian>(em / 4)?; // Strikeout position
+        out.write_i16::<BigEndian>(0)?; // Family class
+        out.write_all(&[0; 10])?; // PANOSE
+        for _ in 0..4 {
+            out.write_u32::<BigEndian>(0)?; // Unicode ranges
+        }
+        out.write_all(b"NONE")?; // Vendor ID
+        let mut selection = 0;
+        if font.is_italic {
+            selection |= 0b1;
+        }
+        if font.is_bold {
+            selection |= 0b10_0000;
+        }
+        if selection == 0 {
+            selection = 0b100_0000;
+        }
+        out.write_u16::<BigEndian>(selection)?;
+        let first_char = self.char_map.keys().next().cloned().unwrap_or(0);
+        let last_char = self.char_map.keys().last().cloned().unwrap_or(0);
+        out.write_u16::<BigEndian>(first_char.min(0xffff) as u16)?;
+        out.write_u16::<BigEndian>(last_char.min(0xffff) as u16)?;
+        out.write_i16::<BigEndian>(ascent)?;
+        out.write_i16::<BigEndian>(descent)?;
+        out.write_i16::<BigEndian>(self.leading())?;
+        out.write_u16::<BigEndian>(ascent.max(0) as u16)?;
+        out.write_u16::<BigEndian>((-i32::from(descent)).max(0) as u16)?;
+        out.write_u32::<BigEndian>(1)?; // Latin 1 code page
+        out.write_u32::<BigEndian>(0)?;
+        out.write_i16::<BigEndian>(self.char_height('x'))?;
+        out.write_i16::<BigEndian>(self.char_height('H'))?;
+        out.write_u16::<BigEndian>(0)?; // Default char
+        out.write_u16::<BigEndian>(0x20)?; // Break char
+        out.write_u16::<BigEndian>(2)?; // Max context, for kerning pairs
+        Ok(out)
+    }
+
+    fn cmap(&self) -> Result<Vec<u8>> {
+        // A format 4 subtable covers the Basic Multilingual Plane, unless it has too many
+        // segments for its 16-bit length, as sparse CJK fonts may. A format 12 subtable of
+        // 32-bit groups is used instead.
+        let (encoding, subtable) = match self.cmap_format_4()? {
+            Some(subtable) => (1, subtable),      // Unicode BMP encoding
+            None => (10, self.cmap_format_12()?), // Unicode full encoding
+        };
+        let mut out = vec![];
+        out.write_u16::<BigEndian>(0)?; // Version
+        out.write_u16::<BigEndian>(1)?; // Number of subtables
+        out.write_u16::<BigEndian>(3)?; // Windows platform
+        out.write_u16::<BigEndian>(encoding)?;
+        out.write_u32::<BigEndian>(12)?; // Subtable offset
+        out.write_all(&subtable)?;
+        Ok(out)
+    }
+
+    fn cmap_format_4(&self) -> Result<Option<Vec<u8>>> {
+        let mut segments: Vec<(u16, u16, u16)> = vec![]; // (start code, end code, start glyph)
+        for (&c, &glyph) in &self.char_map {
+            if c >= 0xffff {
+                continue;
+            }
+            let c = c as u16;
+            if let Some(last) = segments.last_mut() {
+                if last.1 + 1 == c && last.2.checked_add(c - last.0) == Some(glyph) {
+                    last.1 = c;
+                    continue;
+                }
+            }
+            segments.push((c, c, glyph));
+        }
+        segments.push((0xffff, 0xffff, 0));
+
+        let length = 16 + segments.len() * 8;
+        if length > usize::from(u16::MAX) {
+            return Ok(None);
+        }
+        let seg_count = segments.len() as u16;
+        let (search_range, entry_selector, range_shift) = search_params(seg_count, 2);
+        let mut subtable = vec![];
+        subtable.write_u16::<BigEndian>(4)?; // Format
+        subtable.write_u16::<BigEndian>(length as u16)?;
+        subtable.write_u16::<BigEndian>(0)?; // Language
+        subtable.write_u16::<BigEndian>(seg_count * 2)?;
+        subtable.write_u16::<BigEndian>(search_range)?;
+        subtable.write_u16::<BigEndian>(entry_selector)?;
+        subtable.write_u16::<BigEndian>(range_shift)?;
+        for segment in &segments {
+            subtable.write_u16::<BigEndian>(segment.1)?;
+        }
+        subtable.write_u16::<BigEndian>(0)?; // Reserved
+        for segment in &segments {
+            subtable.write_u16::<BigEndian>(segment.0)?;
+        }
+        for segment in &segments {
+            let delta = if segment.0 == 0xffff {
+                1
+            } else {
+                segment.2.wrapping_sub(segment.0)
+            };
+            subtable.write_u16::<BigEndian>(delta)?;
+        }
+        for _ in &segments {
+            subtable.write_u16::<BigEndian>(0)?; // Range offset
+        }
+        Ok(Some(subtable))
+    }
+
+    fn cmap_format_12(&self) -> Result<Vec<u8>> {
+        let mut groups: Vec<(u32, u32, u32)> = vec![]; // (start code, end code, start glyph)
+        for (&c, &glyph) in &self.char_map {
+            let glyph = u32::from(glyph);
+            if let Some(last) = groups.last_mut() {
+                if last.1 + 1 == c && last.2 + (c - last.0) == glyph {
+                    last.1 = c;
+                    continue;
+                }
+            }
+            groups.push((c, c, glyph));
+        }
+
+        let mut subtable = vec![];
+        subtable.write_u16::<BigEndian>(12)?; // Format
+        subtable.write_u16::<BigEndian>(0)?; // Reserved
+        subtable.write_u32::<BigEndian>(16 + groups.len() as u32 * 12)?; // Length
+        subtable.write_u32::<BigEndian>(0)?; // Language
+        subtable.write_u32::<BigEndian>(groups.len() as u32)?;
+        for group in &groups {
+            subtable.write_u32::<BigEndian>(group.0)?;
+            subtable.write_u32::<BigEndian>(group.1)?;
+            subtable.write_u32::<BigEndian>(group.2)?;
+        }
+        Ok(subtable)
+    }
+
+    fn glyf_and_loca(&self) -> Result<(Vec<u8>, Vec<u8>)> {
+        let mut glyf = vec![];
+        let mut loca = vec![];
+        for glyph in &self.glyphs {
+            loca.write_u32::<BigEndian>(glyf.len() as u32)?;
+            let (x_min, y_min, x_max, y_max) = match glyph.bounds() {
+                Some(bounds) => bounds,
+                // Empty glyphs have no data.
+                None => continue,
+            };
+            glyf.write_i16::<BigEndian>(glyph.contours.len() as i16)?;
+            glyf.write_i16::<BigEndian>(x_min)?;
+            glyf.write_i16::<BigEndian>(y_min)?;
+            glyf.write_i16::<BigEndian>(x_max)?;
+            glyf.write_i16::<BigEndian>(y_max)?;
+            let mut end_point = 0;
+            for contour in &glyph.contours {
+                end_point += contour.len();
+                glyf.write_u16::<BigEndian>(end_point as u16 - 1)?;
+            }
+            glyf.write_u16::<BigEndian>(0)?; // Instruction length
+            let points = glyph.contours.iter().flat_map(|contour| contour.iter());
+            for point in points.clone() {
+                glyf.write_u8(if point.is_on_curve { 1 } else { 0 })?;
+            }
+            let mut last = 0;
+            for point in points.clone() {
+                glyf.write_i16::<BigEndian>(coordinate_delta(point.x, &mut last)?)?;
+            }
+            last = 0;
+            for point in points {
+                glyf.write_i16::<BigEndian>(coordinate_delta(point.y, &mut last)?)?;
+            }
+            while glyf.len() % 4 != 0 {
+                glyf.write_u8(0)?;
+            }
+        }
+        loca.write_u32::<BigEndian>(glyf.len() as u32)?;
+        Ok((glyf, loca))
+    }
+
+    fn name(&self) -> Result<Vec<u8>> {
+        let font = &self.font.font;
+        let mut family = font.name.trim_end_matches('\0').trim().to_string();
+        if family.is_empty() {
+            family = format!("Font{}", font.id);
+        }
+        let subfamily = match (font.is_bold, font.is_italic) {
+            (false, false) => "Regular",
+            (true, false) => "Bold",
+            (false, true) => "Italic",
+            (true, true) => "Bold Italic",
+        };
+        let full_name = match self.font.full_name {
+            Some(ref name) if !name.trim_end_matches('\0').is_empty() => {
+                name.trim_end_matches('\0').to_string()
+            }
+            _ if subfamily == "Regular" => family.clone(),
+            _ => format!("{} {}", family, subfamily),
+        };
+        let postscript_name: String = full_name
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+            .collect();
+        let postscript_name = if postscript_name.is_empty() {
+            format!("Font{}", font.id)
+        } else {
+            postscript_name
+        };
+
+        let mut names: Vec<(u16, String)> = vec![];
+        if let Some(ref copyright) = self.font.copyright {
+            let copyright = copyright.trim_end_matches('\0');
+            if !copyright.is_empty() {
+                names.push((0, copyright.to_string()));
+            }
+        }
+        names.push((1, family));
+        names.push((2, subfamily.to_string()));
+        names.push((3, full_name.clone()));
+        names.push((4, full_name));
+        names.push((6, postscript_name));
+
+        let mut records = vec![];
+        let mut strings = vec![];
+        for (name_id, value) in names {
+            let encoded: Vec<u16> = value.encode_utf16().collect();
+            records.write_u16::<BigEndian>(3)?; // Windows platform
+            records.write_u16::<BigEndian>(1)?; // Unicode BMP encoding
+            records.write_u16::<BigEndian>(0x409)?; // English (United States)
+            records.write_u16::<BigEndian>(name_id)?;
+            records.write_u16::<BigEndian>(name_length(encoded.len() * 2)?)?;
+            records.write_u16::<BigEndian>(name_length(strings.len())?)?;
+            for unit in encoded {
+                strings.write_u16::<BigEndian>(unit)?;
+            }
+        }
+        let count = records.len() as u16 / 12;
+        let mut out = vec![];
+        out.write_u16::<BigEndian>(0)?; // Format
+        out.write_u16::<BigEndian>(count)?;
+        out.write_u16::<BigEndian>(6 + records.len() as u16)?; // String storage offset
+        out.write_all(&records)?;
+        out.write_all(&strings)?;
+        Ok(out)
+    }
+
+    fn post(&self) -> Result<Vec<u8>> {
+        let mut out = vec![];
+        out.write_u32::<BigEndian>(0x0003_0000)?; // Version 3.0: no glyph names
+        let italic_angle: i32 = if self.font.font.is_italic {
+            -12 << 16
+        } else {
+            0
+        };
+        out.write_i32::<BigEndian>(italic_angle)?;
+        out.write_i16::<BigEndian>(-(UNITS_PER_EM as i16) / 10)?; // Underline position
+        out.write_i16::<BigEndian>(UNITS_PER_EM as i16 / 20)?; // Underline thickness
+        out.write_u32::<BigEndian>(0)?; // Not fixed pitch
+        for _ in 0..4 {
+            out.write_u32::<BigEndian>(0)?; // Memory usage
+        }
+        Ok(out)
+    }
+
+    fn kern(&self) -> Result<Option<Vec<u8>>> {
+        let layout = match self.font.font.layout {
+            Some(ref layout) if !layout.kerning.is_empty() => layout,
+            _ => return Ok(None),
+        };
+        let glyph_index = |code: u16| {
+            self.font
+                .font
+                .glyphs
+                .iter()
+                .position(|glyph| glyph.code == code)
+                .map(|i| i as u16 + 1)
+        };
+        let mut pairs = BTreeMap::new();
+        for record in &layout.kerning {
+            if let (Some(left), Some(right)) = (
+                glyph_index(record.left_code),
+                glyph_index(record.right_code),
+            ) {
+                pairs
+                    .entry((left, right))
+                    .or_insert_with(|| self.layout_value(i32::from(record.adjustment)));
+            }
+        }
+        if pairs.is_empty() || pairs.len() > 0x1fff {
+            return Ok(None);
+        }
+
+        let num_pairs = pairs.len() as u16;
+        let (search_range, entry_selector, range_shift) = search_params(num_pairs, 6);
+        let mut out = vec![];
+        out.write_u16::<BigEndian>(0)?; // Version
+        out.write_u16::<BigEndian>(1)?; // Number of subtables
+        out.write_u16::<BigEndian>(0)?; // Subtable version
+        out.write_u16::<BigEndian>(14 + num_pairs * 6)?; // Subtable length
+        out.write_u16::<BigEndian>(1)?; // Coverage: horizontal, format 0
+        out.write_u16::<BigEndian>(num_pairs)?;
+        out.write_u16::<BigEndian>(search_range)?;
+        out.write_u16::<BigEndian>(entry_selector)?;
+        out.write_u16::<BigEndian>(range_shift)?;
+        for ((left, right), adjustment) in pairs {
+            out.write_u16::<BigEndian>(left)?;
+            out.write_u16::<BigEndian>(right)?;
+            out.write_i16::<BigEndian>(adjustment)?;
+        }
+        Ok(Some(out))
+    }
+}
+
+/// Converts glyph shape records to TrueType contours.
+fn convert_contours(records: &[ShapeRecord], scale: f32) -> Vec<Vec<Point>> {
+    let to_point = |x: f32, y: f32, is_on_curve| Point {
+        x: clamp_i16(x * scale),
+        y: clamp_i16(-y * scale),
+        is_on_curve,
+    };
+    let mut contours: Vec<Vec<Point>> = vec![];
+    for command in glyph_to_commands(records) {
+        match command {
+            DrawCommand::MoveTo { x, y } => contours.push(vec![to_point(x, y, true)]),
+            DrawCommand::LineTo { x, y } => {
+                if let Some(contour) = contours.last_mut() {
+                    contour.push(to_point(x, y, true));
+                }
+            }
+            DrawCommand::CurveTo { x1, y1, x2, y2 } => {
+                if let Some(contour) = contours.last_mut() {
+                    contour.push(to_point(x1, y1, false));
+                    contour.push(to_point(x2, y2, true));
+                }
+            }
+        }
+    }
+
+    // Contours are implicitly closed.
+    for contour in &mut contours {
+        if contour.len() > 1 && contour.last() == contour.first() {
+            contour.pop();
+        }
+    }
+    contours.retain(|contour| contour.len() >= 3);
+
+    // SWF glyphs are filled with the even-odd rule, while TrueType uses the non-zero rule.
+    // Orient contours by nesting depth so that both rules agree: contours at an even depth run
+    // clockwise, and contours nested at an odd depth run counter-clockwise.
+    let polygons: Vec<Vec<(f32, f32)>> = contours.iter().map(|c| contour_polygon(c)).collect();
+    for (i, contour) in contours.iter_mut().enumerate() {
+        let (x, y) = (f32::from(contour[0].x), f32::from(contour[0].y));
+        let depth = polygons
+            .iter()
+            .enumerate()
+            .filter(|&(j, polygon)| j != i && winding_number(polygon, x, y) != 0)
+            .count();
+        let is_clockwise = signed_area(&polygons[i]) < 0.0;
+        if is_clockwise != (depth % 2 == 0) {
+            contour[1..].reverse();
+        }
+    }
+    contours
+}
+
+/// Approximates a contour with a polygon.
+fn contour_polygon(contour: &[Point]) -> Vec<(f32, f32)> {
+    let to_f32 = |p: &Point| (f32::from(p.x), f32::from(p.y));
+    let mut polygon = vec![to_f32(&contour[0])];
+    let mut i = 1;
+    while i <= contour.len() {
+        let point = &contour[i % contour.len()];
+        if point.is_on_curve {
+            polygon.push(to_f32(point));
+            i += 1;
+        } else {
+            let (x0, y0) = *polygon.last().unwrap();
+            let (cx, cy) = to_f32(point);
+            let (x1, y1) = to_f32(&contour[(i + 1) % contour.len()]);
+            flatten_quadratic((x0, y0), (cx, cy), (x1, y1), 1.0, &mut polygon);
+            i += 2;
+        }
+    }
+    polygon
+}
+
+/// Returns the signed area of a polygon, positive if it runs counter-clockwise with y up.
+fn signed_area(polygon: &[(f32, f32)]) -> f32 {
+    let mut area = 0.0;
+    for (i, &(x0, y0)) in polygon.iter().enumerate() {
+        let (x1, y1) = polygon[(i + 1) % polygon.len()];
+        area += x0 * y1 - x1 * y0;
+    }
+    area / 2.0
+}
+
+/// Returns the difference between a glyph coordinate and the previous one, which is written
+/// as a 16-bit number.
+fn coordinate_delta(value: i16, last: &mut i16) -> Result<i16> {
+    let delta = i32::from(value) - i32::from(*last);
+    *last = value;
+    i16::try_from(delta)
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "Glyph points are too far apart"))
+}
+
+/// Converts the length or offset of a string of the `name` table to 16 bits.
+fn name_length(length: usize) -> Result<u16> {
+    u16::try_from(length).map_err(|_| Error::new(ErrorKind::InvalidData, "Font name is too long"))
+}
+
+fn clamp_i16(n: f32) -> i16 {
+    n.round().clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16
+}
+
+/// Returns the binary search parameters used by several tables for `count` entries of `size`
+/// bytes: (search range, entry selector, range shift).
+fn search_params(count: u16, size: u16) -> (u16, u16, u16) {
+    let mut entry_selector = 0;
+    while (2u32 << entry_selector) <= u32::from(count) {
+        entry_selector += 1;
+    }
+    let search_range = (1u16 << entry_selector) * size;
+    (search_range, entry_selector, count * size - search_range)
+}
+
+/// Returns the checksum of a table, treating it as a sequence of big-endian `u32`s.
+pub fn table_checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+/// Writes an sfnt font file from its tables, which must be sorted by tag.
+pub fn write_sfnt(version: u32, tables: &[([u8; 4], Vec<u8>)]) -> Result<Vec<u8>> {
+    let num_tables = tables.len() as u16;
+    let (search_range, entry_selector, range_shift) = search_params(num_tables, 16);
+    let mut out = vec![];
+    out.write_u32::<BigEndian>(version)?;
+    out.write_u16::<BigEndian>(num_tables)?;
+    out.write_u16::<BigEndian>(search_range)?;
+    out.write_u16::<BigEndian>(entry_selector)?;
+    out.write_u16::<BigEndian>(range_shift)?;
+
+    let mut offset = 12 + 16 * tables.len();
+    let mut head_offset = None;
+    for &(tag, ref data) in tables {
+        if &tag == b"head" {
+            head_offset = Some(offset);
+        }
+        out.write_all(&tag)?;
+        out.write_u32::<BigEndian>(table_checksum(data))?;
+        out.write_u32::<BigEndian>(offset as u32)?;
+        out.write_u32::<BigEndian>(data.len() as u32)?;
+        offset += (data.len() + 3) & !3;
+    }
+    for (_, data) in tables {
+        out.write_all(data)?;
+        while out.len() % 4 != 0 {
+            out.write_u8(0)?;
+        }
+    }
+
+    if let Some(head_offset) = head_offset {
+        let adjustment = 0xB1B0_AFBAu32.wrapping_sub(table_checksum(&out));
+        out[head_offset + 8..head_offset + 12].copy_from_slice(&adjustment.to_be_bytes());
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_data::square_glyph;
+
+    fn test_font() -> EmbeddedFont {
+        EmbeddedFont::new(Font {
+            version: 3,
+            id: 1,
+            name: "Test".to_string(),
+            language: Language::Latin,
+            layout: Some(FontLayout {
+                ascent: 900 * 20,
+                descent: 200 * 20,
+                leading: 0,
+                kerning: vec![KerningRecord {
+                    left_code: 65,
+                    right_code: 66,
+                    adjustment: -40,
+                }],
+            }),
+            glyphs: vec![
+                square_glyph(65, 500.0, false),
+                square_glyph(66, 600.0, true),
+            ],
+            is_small_text: false,
+            is_shift_jis: false,
+            is_ansi: false,
+            is_bold: false,
+            is_italic: false,
+        })
+    }
+
+    fn read_u16(data: &[u8], offset: usize) -> u16 {
+        u16::from_be_bytes([data[offset], data[offset + 1]])
+    }
+
+    fn read_u32(data: &[u8], offset: usize) -> u32 {
+        u32::from_be_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ])
+    }
+
+    fn find_table<'a>(data: &'a [u8], tag: &[u8]) -> &'a [u8] {
+        let num_tables = read_u16(data, 4) as usize;
+        for i in 0..num_tables {
+            let record = 12 + i * 16;
+            if &data[record..record + 4] == tag {
+                let offset = read_u32(data, record + 8) as usize;
+                let length = read_u32(data, record + 12) as usize;
+                return &data[offset..offset + length];
+            }
+        }
+        panic!("Missing table");
+    }
+
+    #[test]
+    fn write_font_tables() {
+        let mut data = vec![];
+        write_ttf(&test_font(), &mut data).unwrap();
+        assert_eq!(read_u32(&data, 0), 0x0001_0000);
+        assert_eq!(table_checksum(&data), 0xB1B0_AFBA);
+
+        let head = find_table(&data, b"head");
+        assert_eq!(read_u16(head, 18), 1024);
+        let maxp = find_table(&data, b"maxp");
+        assert_eq!(read_u16(maxp, 4), 3);
+
+        // DefineFont3 layout values are scaled down by 20.
+        let hhea = find_table(&data, b"hhea");
+        assert_eq!(read_u16(hhea, 4) as i16, 900);
+        assert_eq!(read_u16(hhea, 6) as i16, -200);
+        let hmtx = find_table(&data, b"hmtx");
+        assert_eq!(read_u16(hmtx, 4), 505);
+
+        let kern = find_table(&data, b"kern");
+        assert_eq!(read_u16(kern, 10), 1);
+        assert_eq!(&kern[18..24], &[0, 1, 0, 2, 0xff, 0xfe]);
+    }
+
+    #[test]
+    fn cmap_segments() {
+        let mut data = vec![];
+        write_ttf(&test_font(), &mut data).unwrap();
+        let cmap = find_table(&data, b"cmap");
+        let subtable = &cmap[12..];
+        assert_eq!(read_u16(subtable, 0), 4);
+        // 'A' and 'B' map to consecutive glyphs and share a segment.
+        assert_eq!(read_u16(subtable, 6), 4);
+        assert_eq!(read_u16(subtable, 14), 66); // End code
+        assert_eq!(read_u16(subtable, 20), 65); // Start code
+        assert_eq!(read_u16(subtable, 24), 1u16.wrapping_sub(65)); // Delta
+    }
+
+    #[test]
+    fn sparse_cmap() {
+        // Characters that are not consecutive need too many segments for format 4.
+        let mut font = test_font().font;
+        font.glyphs = (0..9000)
+            .map(|i| Glyph {
+                shape_records: vec![],
+                code: 0x4e00 + i * 2,
+                advance: Some(0),
+                bounds: None,
+            })
+            .collect();
+        let mut data = vec![];
+        write_ttf(&EmbeddedFont::new(font), &mut data).unwrap();
+        let cmap = find_table(&data, b"cmap");
+        assert_eq!(read_u16(cmap, 6), 10);
+        let subtable = &cmap[12..];
+        assert_eq!(read_u16(subtable, 0), 12);
+        assert_eq!(read_u32(subtable, 12), 9000);
+        assert_eq!(
+            &subtable[16 + 12 * 8999..],
+            &[0, 0, 0x94, 0x4e, 0, 0, 0x94, 0x4e, 0, 0, 0x23, 0x28]
+        );
+    }
+
+    #[test]
+    fn far_apart_points() {
+        let mut font = test_font().font;
+        // A rectangle from x = -20000 to 20000.
+        let mut glyph = square_glyph(65, 500.0, false);
+        if let ShapeRecord::StyleChange(ref mut style_change) = glyph.shape_records[0] {
+            style_change.move_to = Some((-20000.0, 0.0));
+        }
+        glyph.shape_records[1] = ShapeRecord::StraightEdge {
+            delta_x: 40000.0,
+            delta_y: 0.0,
+        };
+        glyph.shape_records[3] = ShapeRecord::StraightEdge {
+            delta_x: -40000.0,
+            delta_y: 0.0,
+        };
+        font.glyphs = vec![glyph];
+        let error = write_ttf(&EmbeddedFont::new(font), &mut vec![]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn contour_orientation() {
+        let glyph = square_glyph(66, 600.0, true);
+        let contours = convert_contours(&glyph.shape_records, 1.0);
+        assert_eq!(contours.len(), 2);
+        let areas: Vec<f32> = contours
+            .iter()
+            .map(|contour| signed_area(&contour_polygon(contour)))
+            .collect();
+        // The outer contour runs clockwise and the hole runs counter-clockwise.
+        assert!(areas[0] < 0.0);
+        assert!(areas[1] > 0.0);
+        assert_eq!(contours[0][0].y, 0);
+        assert_eq!(contours[0].iter().map(|p| p.y).max(), Some(600));
+    }
+}
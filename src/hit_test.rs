@@ -0,0 +1,222 @@
+//! Point-in-shape queries for shapes and buttons.
+use shape_utils::{
+    flatten_commands, shape_to_paths, stroke_polyline, winding_number, DrawPath, WindingRule,
+};
+use types::*;
+
+/// Maximum distance, in pixels, between a flattened curve and the real curve.
+const FLATTEN_TOLERANCE: f32 = 0.01;
+
+impl Shape {
+    /// Returns whether a point, in shape coordinates, lies inside a fill or stroke of the shape.
+    ///
+    /// Fills are tested with the given winding rule; `WindingRule::from_shape` returns the rule
+    /// Flash uses for this shape. Strokes are tested using the width of their `LineStyle`, with
+    /// hairlines treated as one pixel wide.
+    pub fn hit_test(&self, x: f32, y: f32, winding: WindingRule) -> bool {
+        shape_to_paths(self).iter().any(|path| match *path {
+            DrawPath::Fill { ref commands, .. } => {
+                let total_winding: i32 = flatten_commands(commands, FLATTEN_TOLERANCE)
+                    .iter()
+                    .map(|polygon| winding_number(polygon, x, y))
+                    .sum();
+                winding.is_inside(total_winding)
+            }
+            DrawPath::Stroke {
+                style,
+                is_closed,
+                ref commands,
+            } => {
+                let half_width = (f32::from(style.width) / 20.0).max(1.0) / 2.0;
+                flatten_commands(commands, FLATTEN_TOLERANCE)
+                    .iter()
+                    .flat_map(|polyline| stroke_polyline(polyline, is_closed, half_width, style))
+                    .any(|polygon| winding_number(&polygon, x, y) != 0)
+            }
+        })
+    }
+}
+
+impl Button {
+    /// Returns the records that make up the button's hit area, ordered by depth.
+    pub fn hit_test_records(&self) -> Vec<&ButtonRecord> {
+        let mut records: Vec<&ButtonRecord> = self
+            .records
+            .iter()
+            .filter(|record| record.states.contains(&ButtonState::HitTest))
+            .collect();
+        records.sort_by_key(|record| record.depth);
+        records
+    }
+
+    /// Returns whether a point, in button coordinates, lies inside the button's hit area.
+    ///
+    /// `hit_test_character` is called for each hit area record with the record's character ID
+    /// and the point transformed into that character's coordinate space.
+    pub fn hit_test<F>(&self, x: f32, y: f32, mut hit_test_character: F) -> bool
+    where
+        F: FnMut(CharacterId, f32, f32) -> bool,
+    {
+        self.hit_test_records().iter().any(|record| {
+            match record.matrix.invert() {
+                Some(inverse) => {
+                    let (local_x, local_y) = inverse.transform_point(x, y);
+                    hit_test_character(record.id, local_x, local_y)
+                }
+                // A degenerate matrix collapses the character to nothing.
+                None => false,
+            }
+        })
+    }
+
+    /// Returns the bounding box of the button's hit area in button coordinates.
+    ///
+    /// `character_bounds` returns the bounds of a character in its own coordinate space.
+    /// Returns `None` if the hit area is empty.
+    pub fn hit_area_bounds<F>(&self, mut character_bounds: F) -> Option<Rectangle>
+    where
+        F: FnMut(CharacterId) -> Option<Rectangle>,
+    {
+        let mut bounds: Option<Rectangle> = None;
+        for record in self.hit_test_records() {
+            let record_bounds = match character_bounds(record.id) {
+                Some(rect) => transform_bounds(&record.matrix, &rect),
+                None => continue,
+            };
+            bounds = Some(match bounds {
+                Some(rect) => Rectangle {
+                    x_min: rect.x_min.min(record_bounds.x_min),
+                    x_max: rect.x_max.max(record_bounds.x_max),
+                    y_min: rect.y_min.min(record_bounds.y_min),
+                    y_max: rect.y_max.max(record_bounds.y_max),
+                },
+                None => record_bounds,
+            });
+        }
+        bounds
+    }
+}
+
+/// Returns the axis-aligned bounds of a transformed rectangle.
+pub(crate) fn transform_bounds(matrix: &Matrix, rect: &Rectangle) -> Rectangle {
+    let corners = [
+        matrix.transform_point(rect.x_min, rect.y_min),
+        matrix.transform_point(rect.x_max, rect.y_min),
+        matrix.transform_point(rect.x_min, rect.y_max),
+        matrix.transform_point(rect.x_max, rect.y_max),
+    ];
+    let mut bounds = Rectangle {
+        x_min: corners[0].0,
+        x_max: corners[0].0,
+        y_min: corners[0].1,
+        y_max: corners[0].1,
+    };
+    for &(x, y) in &corners[1..] {
+        bounds.x_min = bounds.x_min.min(x);
+        bounds.x_max = bounds.x_max.max(x);
+        bounds.y_min = bounds.y_min.min(y);
+        bounds.y_max = bounds.y_max.max(y);
+    }
+    bounds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+    use test_data::{rectangle, square_records};
+
+    fn shape(records: Vec<ShapeRecord>) -> Shape {
+        let black = Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        Shape {
+            version: 1,
+            id: 1,
+            shape_bounds: rectangle(0.0, 20.0, 0.0, 20.0),
+            edge_bounds: rectangle(0.0, 20.0, 0.0, 20.0),
+            has_fill_winding_rule: false,
+            has_non_scaling_strokes: false,
+            has_scaling_strokes: true,
+            styles: ShapeStyles {
+                fill_styles: vec![FillStyle::Color(black.clone())],
+                line_styles: vec![LineStyle::new_v1(80, black)],
+            },
+            shape: records,
+        }
+    }
+
+    #[test]
+    fn hit_test_fill() {
+        let shape = shape(square_records(0.0, 0.0, 10.0, None));
+        let winding = WindingRule::from_shape(&shape);
+        assert!(shape.hit_test(5.0, 5.0, winding));
+        assert!(shape.hit_test(9.9, 9.9, winding));
+        assert!(!shape.hit_test(10.5, 5.0, winding));
+        assert!(!shape.hit_test(-0.5, 5.0, winding));
+    }
+
+    #[test]
+    fn hit_test_winding_rule() {
+        let mut records = square_records(0.0, 0.0, 10.0, None);
+        records.extend(square_records(5.0, 5.0, 10.0, None));
+        let shape = shape(records);
+        assert!(!shape.hit_test(7.0, 7.0, WindingRule::EvenOdd));
+        assert!(shape.hit_test(7.0, 7.0, WindingRule::NonZero));
+        assert!(shape.hit_test(2.0, 2.0, WindingRule::EvenOdd));
+    }
+
+    #[test]
+    fn hit_test_stroke() {
+        let shape = shape(square_records(5.0, 5.0, 10.0, Some(1)));
+        // The stroke is 4px wide, centered on the edge at x = 5.
+        assert!(shape.hit_test(3.5, 10.0, WindingRule::EvenOdd));
+        assert!(!shape.hit_test(2.5, 10.0, WindingRule::EvenOdd));
+        // Round joins extend past the corner.
+        assert!(shape.hit_test(3.8, 3.8, WindingRule::EvenOdd));
+        assert!(!shape.hit_test(3.3, 3.3, WindingRule::EvenOdd));
+    }
+
+    #[test]
+    fn hit_test_button() {
+        let mut shapes = HashMap::new();
+        shapes.insert(1, shape(square_records(0.0, 0.0, 10.0, None)));
+
+        let record = |states: &[ButtonState], translate_x: f32| ButtonRecord {
+            states: states.iter().cloned().collect::<HashSet<_>>(),
+            id: 1,
+            depth: 1,
+            matrix: Matrix {
+                translate_x,
+                ..Matrix::new()
+            },
+            color_transform: ColorTransform::new(),
+            filters: vec![],
+            blend_mode: BlendMode::Normal,
+        };
+        let button = Button {
+            id: 2,
+            is_track_as_menu: false,
+            records: vec![
+                record(&[ButtonState::Up, ButtonState::Over], 0.0),
+                record(&[ButtonState::HitTest], 100.0),
+            ],
+            actions: vec![],
+        };
+        let hit_test = |x, y| {
+            button.hit_test(x, y, |id, x, y| {
+                shapes[&id].hit_test(x, y, WindingRule::from_shape(&shapes[&id]))
+            })
+        };
+        assert_eq!(button.hit_test_records().len(), 1);
+        assert!(!hit_test(5.0, 5.0));
+        assert!(hit_test(105.0, 5.0));
+        assert_eq!(
+            button.hit_area_bounds(|id| Some(shapes[&id].shape_bounds.clone())),
+            Some(rectangle(100.0, 120.0, 0.0, 20.0))
+        );
+    }
+}
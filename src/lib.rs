@@ -20,8 +20,18 @@ extern crate log;
 
 pub mod avm1;
 pub mod avm2;
+pub mod font;
+pub mod dictionary;
+mod hit_test;
+pub mod merge;
+pub mod optimize;
 pub mod read;
+pub mod render;
+pub mod replace;
+pub mod shape_utils;
 mod tag_codes;
+pub mod text;
+pub mod timeline;
 mod types;
 pub mod write;
 
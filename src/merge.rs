@@ -0,0 +1,478 @@
+//! Renumbering of character IDs, and merging of asset libraries into an SWF.
+use dictionary::{character_data_id, Dictionary, NO_BITMAP};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Error, ErrorKind, Result};
+use types::*;
+
+/// The changes made by `merge_swf` to the characters and names of the merged library.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MergeReport {
+    /// The IDs of the library's characters that collided with the base, and their new IDs.
+    pub id_map: BTreeMap<CharacterId, CharacterId>,
+    /// The export names of the library that collided with the base, and their new names.
+    pub renamed: Vec<(String, String)>,
+}
+
+/// Replaces every character ID in a tag list with the ID returned by `map`.
+///
+/// This covers the IDs of character definitions and the tags that add to them, the characters
+/// used by other definitions, and the characters placed, removed, started, exported, imported,
+/// linked or initialized by control tags, including those inside sprites. The main timeline's
+/// `SymbolClass` ID 0 and the "no bitmap" fill ID 0xFFFF are left unchanged.
+pub fn remap_character_ids<F>(tags: &mut [Tag], mut map: F)
+where
+    F: FnMut(CharacterId) -> CharacterId,
+{
+    remap_tags(tags, &mut map);
+}
+
+fn remap_tags(tags: &mut [Tag], map: &mut dyn FnMut(CharacterId) -> CharacterId) {
+    for tag in tags {
+        remap_tag(tag, map);
+    }
+}
+
+fn remap_tag(tag: &mut Tag, map: &mut dyn FnMut(CharacterId) -> CharacterId) {
+    match *tag {
+        Tag::CsmTextSettings(ref mut settings) => settings.id = map(settings.id),
+        Tag::DefineBinaryData { ref mut id, .. }
+        | Tag::DefineBits { ref mut id, .. }
+        | Tag::DefineBitsJpeg2 { ref mut id, .. }
+        | Tag::DefineButtonColorTransform { ref mut id, .. }
+        | Tag::DefineFontAlignZones { ref mut id, .. }
+        | Tag::DefineFontName { ref mut id, .. }
+        | Tag::DefineScalingGrid { ref mut id, .. }
+        | Tag::DoInitAction { ref mut id, .. }
+        | Tag::StartSound { ref mut id, .. } => *id = map(*id),
+        Tag::DefineBitsJpeg3(ref mut jpeg) => jpeg.id = map(jpeg.id),
+        Tag::DefineBitsLossless(ref mut bitmap) => bitmap.id = map(bitmap.id),
+        Tag::DefineButton(ref mut button) | Tag::DefineButton2(ref mut button) => {
+            button.id = map(button.id);
+            for record in &mut button.records {
+                record.id = map(record.id);
+            }
+        }
+        Tag::DefineButtonSound(ref mut sounds) => {
+            sounds.id = map(sounds.id);
+            let transitions = [
+                &mut sounds.over_to_up_sound,
+                &mut sounds.up_to_over_sound,
+                &mut sounds.over_to_down_sound,
+                &mut sounds.down_to_over_sound,
+            ];
+            for sound in transitions {
+                if let Some((ref mut id, _)) = *sound {
+                    if *id != 0 {
+                        *id = map(*id);
+                    }
+                }
+            }
+        }
+        Tag::DefineEditText(ref mut edit_text) => {
+            edit_text.id = map(edit_text.id);
+            edit_text.font_id = edit_text.font_id.map(&mut *map);
+        }
+        Tag::DefineFont(ref mut font) => font.id = map(font.id),
+        Tag::DefineFont2(ref mut font) => font.id = map(font.id),
+        Tag::DefineFont4(ref mut font) => font.id = map(font.id),
+        Tag::DefineFontInfo(ref mut info) => info.id = map(info.id),
+        Tag::DefineMorphShape(ref mut morph_shape) => {
+            morph_shape.id = map(morph_shape.id);
+            for shape in [&mut morph_shape.start, &mut morph_shape.end] {
+                remap_styles(&mut shape.fill_styles, &mut shape.line_styles, map);
+                remap_records(&mut shape.shape, map);
+            }
+        }
+        Tag::DefineShape(ref mut shape) => {
+            shape.id = map(shape.id);
+            remap_styles(
+                &mut shape.styles.fill_styles,
+                &mut shape.styles.line_styles,
+                map,
+            );
+            remap_records(&mut shape.shape, map);
+        }
+        Tag::DefineSound(ref mut sound) => sound.id = map(sound.id),
+        Tag::DefineSprite(ref mut sprite) => {
+            sprite.id = map(sprite.id);
+            remap_tags(&mut sprite.tags, map);
+        }
+        Tag::DefineText(ref mut text) => {
+            text.id = map(text.id);
+            for record in &mut text.records {
+                record.font_id = record.font_id.map(&mut *map);
+            }
+        }
+        Tag::DefineVideoStream(ref mut video) => video.id = map(video.id),
+        Tag::ExportAssets(ref mut assets)
+        | Tag::ImportAssets {
+            imports: ref mut assets,
+            ..
+        } => {
+            for asset in assets {
+                asset.id = map(asset.id);
+            }
+        }
+        Tag::PlaceObject(ref mut place_object) => match place_object.action {
+            PlaceObjectAction::Place(ref mut id) | PlaceObjectAction::Replace(ref mut id) => {
+                *id = map(*id);
+            }
+            PlaceObjectAction::Modify => (),
+        },
+        Tag::RemoveObject {
+            ref mut character_id,
+            ..
+        } => *character_id = character_id.map(&mut *map),
+        Tag::SymbolClass(ref mut links) => {
+            // ID 0 links a class to the main timeline.
+            for link in links.iter_mut().filter(|link| link.id != 0) {
+                link.id = map(link.id);
+            }
+        }
+        Tag::VideoFrame(ref mut frame) => frame.stream_id = map(frame.stream_id),
+        _ => (),
+    }
+}
+
+fn remap_records(records: &mut [ShapeRecord], map: &mut dyn FnMut(CharacterId) -> CharacterId) {
+    for record in records {
+        if let ShapeRecord::StyleChange(StyleChangeData {
+            new_styles: Some(ref mut styles),
+            ..
+        }) = *record
+        {
+            remap_styles(&mut styles.fill_styles, &mut styles.line_styles, map);
+        }
+    }
+}
+
+fn remap_styles(
+    fill_styles: &mut [FillStyle],
+    line_styles: &mut [LineStyle],
+    map: &mut dyn FnMut(CharacterId) -> CharacterId,
+) {
+    let line_fills = line_styles
+        .iter_mut()
+        .filter_map(|line_style| line_style.fill_style.as_mut());
+    for fill_style in fill_styles.iter_mut().chain(line_fills) {
+        if let FillStyle::Bitmap { ref mut id, .. } = *fill_style {
+            if *id != NO_BITMAP {
+                *id = map(*id);
+            }
+        }
+    }
+}
+
+/// Merges the characters of an asset library into `base`.
+///
+/// The library's character definitions, the tags that add to them, and its `ImportAssets`,
+/// `ExportAssets`, `SymbolClass`, `DoInitAction` and `DoAbc` tags are inserted before the first
+/// `ShowFrame` of `base`; the rest of the library's main timeline is dropped, along with its
+/// main timeline class. `DefineBits` images are combined with the library's `JpegTables` into
+/// `DefineBitsJpeg2` tags, so that they do not use the tables of `base`.
+///
+/// Library characters whose IDs are already used in `base` are given unused IDs. Export names
+/// that are already used in `base` are renamed by appending a number, such as `name_2`;
+/// ActionScript code that refers to these names is not updated. Class names cannot be renamed,
+/// as the library's `DoAbc` code defines the classes under their names, so an error is returned
+/// if the library links a class that is already linked in `base`, and `base` is left unchanged.
+pub fn merge_swf(base: &mut Swf, library: &Swf) -> Result<MergeReport> {
+    let (mut used_ids, mut used_names) = {
+        let dictionary = Dictionary::from_swf(base);
+        let ids: BTreeSet<CharacterId> = dictionary.ids().collect();
+        let names: BTreeSet<String> = dictionary.exports().keys().cloned().collect();
+        (ids, names)
+    };
+    let base_classes: BTreeSet<&str> = base
+        .tags
+        .iter()
+        .filter_map(|tag| match *tag {
+            Tag::SymbolClass(ref links) => Some(links),
+            _ => None,
+        })
+        .flatten()
+        .map(|link| &link.class_name[..])
+        .collect();
+    for tag in &library.tags {
+        if let Tag::SymbolClass(ref links) = *tag {
+            let conflict = links
+                .iter()
+                .find(|link| link.id != 0 && base_classes.contains(&link.class_name[..]));
+            if let Some(link) = conflict {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Class {} is linked in both SWFs", link.class_name),
+                ));
+            }
+        }
+    }
+
+    let mut id_map = BTreeMap::new();
+    let mut next_id = 1;
+    for id in Dictionary::from_swf(library).ids() {
+        if !used_ids.contains(&id) {
+            used_ids.insert(id);
+            continue;
+        }
+        while used_ids.contains(&next_id) {
+            next_id = next_id
+                .checked_add(1)
+                .filter(|&id| id != NO_BITMAP)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Too many characters."))?;
+        }
+        used_ids.insert(next_id);
+        id_map.insert(id, next_id);
+    }
+
+    let jpeg_tables = library.tags.iter().find_map(|tag| match *tag {
+        Tag::JpegTables(ref tables) => Some(&tables[..]),
+        _ => None,
+    });
+    let mut tags: Vec<Tag> = library
+        .tags
+        .iter()
+        .filter(|tag| is_library_tag(tag))
+        .cloned()
+        .collect();
+    remap_character_ids(&mut tags, |id| *id_map.get(&id).unwrap_or(&id));
+
+    let mut new_names: BTreeMap<String, String> = BTreeMap::new();
+    let mut renamed = vec![];
+    let mut rename = |name: &mut String| {
+        let new_name = new_names.entry(name.clone()).or_insert_with(|| {
+            let mut new_name = name.clone();
+            let mut suffix = 2;
+            while used_names.contains(&new_name) {
+                new_name = format!("{}_{}", name, suffix);
+                suffix += 1;
+            }
+            if new_name != *name {
+                renamed.push((name.clone(), new_name.clone()));
+            }
+            used_names.insert(new_name.clone());
+            new_name
+        });
+        *name = new_name.clone();
+    };
+    for tag in &mut tags {
+        match *tag {
+            Tag::DefineBits { id, ref jpeg_data } => {
+                if let Some(tables) = jpeg_tables {
+                    *tag = Tag::DefineBitsJpeg2 {
+                        id,
+                        jpeg_data: jpeg_with_tables(tables, jpeg_data),
+                    };
+                }
+            }
+            Tag::ExportAssets(ref mut assets) => {
+                for asset in assets {
+                    rename(&mut asset.name);
+                }
+            }
+            Tag::SymbolClass(ref mut links) => links.retain(|link| link.id != 0),
+            _ => (),
+        }
+    }
+
+    let index = base
+        .tags
+        .iter()
+        .position(|tag| *tag == Tag::ShowFrame)
+        .unwrap_or(base.tags.len());
+    base.tags.splice(index..index, tags);
+    base.version = base.version.max(library.version);
+    Ok(MergeReport { id_map, renamed })
+}
+
+/// Returns whether a tag of a library's main timeline is kept by `merge_swf`.
+fn is_library_tag(tag: &Tag) -> bool {
+    if tag.character_id().is_some() || character_data_id(tag).is_some() {
+        return true;
+    }
+    match *tag {
+        Tag::DoAbc(_)
+        | Tag::DoInitAction { .. }
+        | Tag::ExportAssets(_)
+        | Tag::ImportAssets { .. } => true,
+        Tag::SymbolClass(ref links) => links.iter().any(|link| link.id != 0),
+        _ => false,
+    }
+}
+
+/// Combines the data of a `DefineBits` tag with the `JpegTables` it uses into a single JPEG
+/// stream, as used by `DefineBitsJpeg2`.
+fn jpeg_with_tables(tables: &[u8], jpeg_data: &[u8]) -> Vec<u8> {
+    // Both parts may begin with an erroneous end and start of image marker.
+    fn strip_header(data: &[u8]) -> &[u8] {
+        data.strip_prefix(&[0xff, 0xd9, 0xff, 0xd8][..])
+            .unwrap_or(data)
+    }
+    let tables = strip_header(tables);
+    let tables = tables.strip_suffix(&[0xff, 0xd9][..]).unwrap_or(tables);
+    let image = strip_header(jpeg_data);
+    let image = image.strip_prefix(&[0xff, 0xd8][..]).unwrap_or(image);
+    let mut data = Vec::with_capacity(tables.len() + image.len());
+    data.extend_from_slice(tables);
+    data.extend_from_slice(image);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_data;
+
+    fn shape(id: CharacterId, bitmap_id: CharacterId) -> Tag {
+        let bounds = Rectangle {
+            x_min: 0.0,
+            x_max: 10.0,
+            y_min: 0.0,
+            y_max: 10.0,
+        };
+        Tag::DefineShape(Shape {
+            version: 1,
+            id,
+            shape_bounds: bounds.clone(),
+            edge_bounds: bounds,
+            has_fill_winding_rule: false,
+            has_non_scaling_strokes: false,
+            has_scaling_strokes: true,
+            styles: ShapeStyles {
+                fill_styles: vec![FillStyle::Bitmap {
+                    id: bitmap_id,
+                    matrix: Matrix::new(),
+                    is_smoothed: true,
+                    is_repeating: false,
+                }],
+                line_styles: vec![],
+            },
+            shape: vec![],
+        })
+    }
+
+    fn bitmap(id: CharacterId, jpeg_data: Vec<u8>) -> Tag {
+        Tag::DefineBits { id, jpeg_data }
+    }
+
+    fn export(id: CharacterId, name: &str) -> Tag {
+        Tag::ExportAssets(vec![ExportedAsset {
+            id,
+            name: name.to_string(),
+        }])
+    }
+
+    #[test]
+    fn remap_ids() {
+        let mut tags = vec![
+            shape(1, 2),
+            shape(3, NO_BITMAP),
+            Tag::DefineSprite(Sprite {
+                id: 4,
+                num_frames: 1,
+                tags: vec![
+                    Tag::RemoveObject {
+                        depth: 1,
+                        character_id: Some(1),
+                    },
+                    Tag::ShowFrame,
+                ],
+            }),
+            Tag::SymbolClass(vec![
+                SymbolClassLink {
+                    id: 0,
+                    class_name: "Main".to_string(),
+                },
+                SymbolClassLink {
+                    id: 4,
+                    class_name: "Clip".to_string(),
+                },
+            ]),
+        ];
+        remap_character_ids(&mut tags, |id| id + 100);
+        assert_eq!(tags[0], shape(101, 102));
+        assert_eq!(tags[1], shape(103, NO_BITMAP));
+        match tags[2] {
+            Tag::DefineSprite(ref sprite) => {
+                assert_eq!(sprite.id, 104);
+                assert_eq!(
+                    sprite.tags[0],
+                    Tag::RemoveObject {
+                        depth: 1,
+                        character_id: Some(101),
+                    }
+                );
+            }
+            _ => panic!("Expected sprite"),
+        }
+        match tags[3] {
+            Tag::SymbolClass(ref links) => {
+                assert_eq!(links[0].id, 0);
+                assert_eq!(links[1].id, 104);
+            }
+            _ => panic!("Expected SymbolClass"),
+        }
+    }
+
+    #[test]
+    fn merge_libraries() {
+        let mut base = test_data::swf(vec![
+            Tag::JpegTables(vec![0xff, 0xd8, 1, 0xff, 0xd9]),
+            bitmap(1, vec![0xff, 0xd8, 2, 0xff, 0xd9]),
+            shape(2, 1),
+            export(2, "square"),
+            Tag::ShowFrame,
+        ]);
+        let library = test_data::swf(vec![
+            Tag::JpegTables(vec![0xff, 0xd8, 3, 0xff, 0xd9]),
+            bitmap(1, vec![0xff, 0xd8, 4, 0xff, 0xd9]),
+            shape(5, 1),
+            export(5, "square"),
+            Tag::PlaceObject(Box::new(test_data::place_object(
+                PlaceObjectAction::Place(5),
+                1,
+            ))),
+            Tag::ShowFrame,
+        ]);
+        let report = merge_swf(&mut base, &library).unwrap();
+        assert_eq!(report.id_map, vec![(1, 3)].into_iter().collect());
+        assert_eq!(
+            report.renamed,
+            vec![("square".to_string(), "square_2".to_string())]
+        );
+        assert_eq!(
+            base.tags,
+            vec![
+                Tag::JpegTables(vec![0xff, 0xd8, 1, 0xff, 0xd9]),
+                bitmap(1, vec![0xff, 0xd8, 2, 0xff, 0xd9]),
+                shape(2, 1),
+                export(2, "square"),
+                Tag::DefineBitsJpeg2 {
+                    id: 3,
+                    jpeg_data: vec![0xff, 0xd8, 3, 4, 0xff, 0xd9],
+                },
+                shape(5, 3),
+                export(5, "square_2"),
+                Tag::ShowFrame,
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_class_conflict() {
+        let link = |id, class_name: &str| {
+            Tag::SymbolClass(vec![SymbolClassLink {
+                id,
+                class_name: class_name.to_string(),
+            }])
+        };
+        let mut base = test_data::swf(vec![shape(1, NO_BITMAP), link(1, "Foo"), Tag::ShowFrame]);
+        let library = test_data::swf(vec![shape(1, NO_BITMAP), link(1, "Foo"), Tag::ShowFrame]);
+        let tags = base.tags.clone();
+        assert!(merge_swf(&mut base, &library).is_err());
+        assert_eq!(base.tags, tags);
+
+        // The library's main timeline class is dropped, so it does not conflict.
+        let library = test_data::swf(vec![shape(1, NO_BITMAP), link(0, "Foo"), Tag::ShowFrame]);
+        assert!(merge_swf(&mut base, &library).is_ok());
+    }
+}
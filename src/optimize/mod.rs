@@ -0,0 +1,6 @@
+//! Passes that reduce the size of SWF data.
+mod shape;
+mod unused;
+
+pub use self::shape::{optimize_morph_shape, optimize_shape};
+pub use self::unused::{remove_unused_characters, RemovedCharacter, SizeReport};
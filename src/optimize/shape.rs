@@ -0,0 +1,726 @@
+//! Lossless and lossy size optimizations for shape definitions.
+use std::collections::HashSet;
+use types::*;
+
+/// Largest magnitude of an edge delta, in twips, that fits in an SWF edge record.
+const MAX_EDGE_DELTA: i64 = 65535;
+
+/// Optimizes a shape in place to reduce its encoded size.
+///
+/// Duplicate and unused fill and line styles are removed and style indices are remapped,
+/// which in turn reduces the number of bits used for style indices. Redundant style change
+/// records, zero-length edges and unnecessary `new_styles` tables are dropped, and collinear
+/// straight edges are joined.
+///
+/// With a `tolerance` (in pixels), straight edges are also joined when the dropped points are
+/// within `tolerance` of the new edge, and nearly flat curves are replaced by straight edges.
+/// Without a tolerance the output renders identically to the input.
+pub fn optimize_shape(shape: &mut Shape, tolerance: Option<f32>) {
+    remove_unused_style_tables(shape);
+    dedupe_shape_styles(shape);
+    remove_redundant_style_changes(&mut shape.shape, true);
+    let tolerance = tolerance.unwrap_or(0.0) * 20.0;
+    let records = ::std::mem::take(&mut shape.shape);
+    let runs = split_runs(&records);
+    let mut edges: Vec<Vec<Edge>> = runs.iter().map(|run| run.edges.clone()).collect();
+    for run_edges in &mut edges {
+        let mut parallel = vec![::std::mem::take(run_edges)];
+        simplify_edges(&mut parallel, tolerance);
+        *run_edges = parallel.pop().unwrap();
+    }
+    shape.shape = join_runs(&records, &runs, &edges);
+}
+
+/// Optimizes a morph shape in place to reduce its encoded size.
+///
+/// This performs the same optimizations as `optimize_shape`. Styles are only merged when both
+/// their start and end states are equal, and edges are only joined or dropped when they can be
+/// in both the start and end shapes.
+pub fn optimize_morph_shape(morph_shape: &mut DefineMorphShape, tolerance: Option<f32>) {
+    dedupe_morph_styles(morph_shape);
+    // Move records in the end shape correspond to those in the start shape, so keep them.
+    remove_redundant_style_changes(&mut morph_shape.start.shape, false);
+
+    let start_records = ::std::mem::take(&mut morph_shape.start.shape);
+    let end_records = ::std::mem::take(&mut morph_shape.end.shape);
+    let start_runs = split_runs(&start_records);
+    let end_runs = split_runs(&end_records);
+    let is_matching = start_runs.len() == end_runs.len()
+        && start_runs
+            .iter()
+            .zip(&end_runs)
+            .all(|(start, end)| start.edges.len() == end.edges.len());
+    if !is_matching {
+        // The start and end shapes don't line up, so leave the edges alone.
+        morph_shape.start.shape = start_records;
+        morph_shape.end.shape = end_records;
+        return;
+    }
+
+    let tolerance = tolerance.unwrap_or(0.0) * 20.0;
+    let mut start_edges = vec![];
+    let mut end_edges = vec![];
+    for (start, end) in start_runs.iter().zip(&end_runs) {
+        let mut parallel = vec![start.edges.clone(), end.edges.clone()];
+        simplify_edges(&mut parallel, tolerance);
+        end_edges.push(parallel.pop().unwrap());
+        start_edges.push(parallel.pop().unwrap());
+    }
+    morph_shape.start.shape = join_runs(&start_records, &start_runs, &start_edges);
+    morph_shape.end.shape = join_runs(&end_records, &end_runs, &end_edges);
+}
+
+/// Drops style tables that no edge is drawn with.
+///
+/// A `new_styles` table replaces the active one, so if no edges were drawn since the active
+/// table was set, the new table can take its place.
+fn remove_unused_style_tables(shape: &mut Shape) {
+    // Index of the record that set the active style table, or `None` for the initial styles.
+    let mut table_record: Option<usize> = None;
+    let mut has_edges = false;
+    for i in 0..shape.shape.len() {
+        let new_styles = match shape.shape[i] {
+            ShapeRecord::StyleChange(ref mut style_change) => {
+                if style_change.new_styles.is_some() && !has_edges {
+                    // Style selections are reset by new styles, so make that explicit.
+                    style_change.fill_style_0 = style_change.fill_style_0.or(Some(0));
+                    style_change.fill_style_1 = style_change.fill_style_1.or(Some(0));
+                    style_change.line_style = style_change.line_style.or(Some(0));
+                    style_change.new_styles.take()
+                } else {
+                    if style_change.new_styles.is_some() {
+                        table_record = Some(i);
+                        has_edges = false;
+                    }
+                    None
+                }
+            }
+            _ => {
+                has_edges = true;
+                None
+            }
+        };
+        if let Some(new_styles) = new_styles {
+            match table_record {
+                Some(j) => {
+                    if let ShapeRecord::StyleChange(ref mut style_change) = shape.shape[j] {
+                        style_change.new_styles = Some(new_styles);
+                    }
+                }
+                None => shape.styles = new_styles,
+            }
+        }
+    }
+}
+
+/// Returns the fill and line style indices referenced with each style table.
+fn referenced_styles(records: &[ShapeRecord]) -> Vec<(HashSet<u32>, HashSet<u32>)> {
+    let mut tables = vec![(HashSet::new(), HashSet::new())];
+    for record in records {
+        if let ShapeRecord::StyleChange(ref style_change) = *record {
+            if style_change.new_styles.is_some() {
+                tables.push((HashSet::new(), HashSet::new()));
+            }
+            let table = tables.last_mut().unwrap();
+            for &i in style_change
+                .fill_style_0
+                .iter()
+                .chain(style_change.fill_style_1.iter())
+            {
+                table.0.insert(i);
+            }
+            if let Some(i) = style_change.line_style {
+                table.1.insert(i);
+            }
+        }
+    }
+    tables
+}
+
+/// Removes duplicate and unreferenced styles.
+///
+/// Returns the new styles and a table mapping each old 1-based style index to its new index.
+fn dedupe_styles<T: PartialEq + Clone>(styles: &[T], used: &HashSet<u32>) -> (Vec<T>, Vec<u32>) {
+    let mut new_styles: Vec<T> = vec![];
+    let mut remap = vec![0; styles.len() + 1];
+    for (i, style) in styles.iter().enumerate() {
+        if !used.contains(&(i as u32 + 1)) {
+            continue;
+        }
+        remap[i + 1] = match new_styles.iter().position(|s| s == style) {
+            Some(j) => j as u32 + 1,
+            None => {
+                new_styles.push(style.clone());
+                new_styles.len() as u32
+            }
+        };
+    }
+    (new_styles, remap)
+}
+
+fn remap_index(index: &mut Option<u32>, remap: &[u32]) {
+    if let Some(ref mut i) = *index {
+        // Out of range indices are invalid, so treat them as no style.
+        *i = remap.get(*i as usize).cloned().unwrap_or(0);
+    }
+}
+
+fn dedupe_shape_styles(shape: &mut Shape) {
+    let referenced = referenced_styles(&shape.shape);
+    let mut tables = referenced.iter();
+    let (fills, lines) = tables.next().unwrap();
+    let (fill_styles, mut fill_remap) = dedupe_styles(&shape.styles.fill_styles, fills);
+    let (line_styles, mut line_remap) = dedupe_styles(&shape.styles.line_styles, lines);
+    shape.styles.fill_styles = fill_styles;
+    shape.styles.line_styles = line_styles;
+
+    for record in &mut shape.shape {
+        if let ShapeRecord::StyleChange(ref mut style_change) = *record {
+            if let Some(ref mut new_styles) = style_change.new_styles {
+                let (fills, lines) = tables.next().unwrap();
+                let (fill_styles, new_fill_remap) = dedupe_styles(&new_styles.fill_styles, fills);
+                let (line_styles, new_line_remap) = dedupe_styles(&new_styles.line_styles, lines);
+                new_styles.fill_styles = fill_styles;
+                new_styles.line_styles = line_styles;
+                fill_remap = new_fill_remap;
+                line_remap = new_line_remap;
+            }
+            remap_index(&mut style_change.fill_style_0, &fill_remap);
+            remap_index(&mut style_change.fill_style_1, &fill_remap);
+            remap_index(&mut style_change.line_style, &line_remap);
+        }
+    }
+}
+
+fn dedupe_morph_styles(morph_shape: &mut DefineMorphShape) {
+    let start = &mut morph_shape.start;
+    let end = &mut morph_shape.end;
+    if start.fill_styles.len() != end.fill_styles.len()
+        || start.line_styles.len() != end.line_styles.len()
+    {
+        return;
+    }
+    let referenced = referenced_styles(&start.shape);
+    let (ref fills, ref lines) = referenced[0];
+
+    let fill_pairs: Vec<(FillStyle, FillStyle)> = start
+        .fill_styles
+        .iter()
+        .cloned()
+        .zip(end.fill_styles.iter().cloned())
+        .collect();
+    let (fill_pairs, fill_remap) = dedupe_styles(&fill_pairs, fills);
+    let (start_fills, end_fills) = fill_pairs.into_iter().unzip();
+    start.fill_styles = start_fills;
+    end.fill_styles = end_fills;
+
+    let line_pairs: Vec<(LineStyle, LineStyle)> = start
+        .line_styles
+        .iter()
+        .cloned()
+        .zip(end.line_styles.iter().cloned())
+        .collect();
+    let (line_pairs, line_remap) = dedupe_styles(&line_pairs, lines);
+    let (start_lines, end_lines) = line_pairs.into_iter().unzip();
+    start.line_styles = start_lines;
+    end.line_styles = end_lines;
+
+    for record in &mut start.shape {
+        if let ShapeRecord::StyleChange(ref mut style_change) = *record {
+            remap_index(&mut style_change.fill_style_0, &fill_remap);
+            remap_index(&mut style_change.fill_style_1, &fill_remap);
+            remap_index(&mut style_change.line_style, &line_remap);
+        }
+    }
+}
+
+/// Drops style change fields that don't change anything and merges adjacent style changes.
+///
+/// Moves to the current position are only dropped when `remove_moves` is set and no line is
+/// being drawn, because a move splits a stroke and changes how its caps and joins are drawn.
+fn remove_redundant_style_changes(records: &mut Vec<ShapeRecord>, remove_moves: bool) {
+    let mut fill_style_0 = 0;
+    let mut fill_style_1 = 0;
+    let mut line_style = 0;
+    let mut position = (0, 0);
+    let mut out: Vec<ShapeRecord> = Vec::with_capacity(records.len());
+    for record in records.drain(..) {
+        match record {
+            ShapeRecord::StyleChange(mut style_change) => {
+                if style_change.new_styles.is_some() {
+                    fill_style_0 = 0;
+                    fill_style_1 = 0;
+                    line_style = 0;
+                }
+                if style_change.fill_style_0 == Some(fill_style_0) {
+                    style_change.fill_style_0 = None;
+                }
+                if style_change.fill_style_1 == Some(fill_style_1) {
+                    style_change.fill_style_1 = None;
+                }
+                if style_change.line_style == Some(line_style) {
+                    style_change.line_style = None;
+                }
+                fill_style_0 = style_change.fill_style_0.unwrap_or(fill_style_0);
+                fill_style_1 = style_change.fill_style_1.unwrap_or(fill_style_1);
+                line_style = style_change.line_style.unwrap_or(line_style);
+                if let Some((x, y)) = style_change.move_to {
+                    let new_position = (to_twips(x), to_twips(y));
+                    if remove_moves && new_position == position && line_style == 0 {
+                        style_change.move_to = None;
+                    }
+                    position = new_position;
+                }
+
+                // Merge with a directly preceding style change.
+                if style_change.new_styles.is_none() {
+                    if let Some(&mut ShapeRecord::StyleChange(ref mut prev)) = out.last_mut() {
+                        prev.move_to = style_change.move_to.or(prev.move_to);
+                        prev.fill_style_0 = style_change.fill_style_0.or(prev.fill_style_0);
+                        prev.fill_style_1 = style_change.fill_style_1.or(prev.fill_style_1);
+                        prev.line_style = style_change.line_style.or(prev.line_style);
+                        continue;
+                    }
+                }
+
+                let is_empty = style_change.move_to.is_none()
+                    && style_change.fill_style_0.is_none()
+                    && style_change.fill_style_1.is_none()
+                    && style_change.line_style.is_none()
+                    && style_change.new_styles.is_none();
+                if !is_empty {
+                    out.push(ShapeRecord::StyleChange(style_change));
+                }
+            }
+            edge => {
+                if let Some(edge_twips) = Edge::from_record(&edge) {
+                    let (dx, dy) = edge_twips.delta();
+                    position = (position.0 + dx, position.1 + dy);
+                }
+                out.push(edge);
+            }
+        }
+    }
+    *records = out;
+}
+
+fn to_twips(n: f32) -> i32 {
+    (n * 20.0).round() as i32
+}
+
+/// An edge with deltas in twips.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Edge {
+    Straight(i32, i32),
+    Curved(i32, i32, i32, i32),
+}
+
+impl Edge {
+    fn from_record(record: &ShapeRecord) -> Option<Edge> {
+        match *record {
+            ShapeRecord::StraightEdge { delta_x, delta_y } => {
+                Some(Edge::Straight(to_twips(delta_x), to_twips(delta_y)))
+            }
+            ShapeRecord::CurvedEdge {
+                control_delta_x,
+                control_delta_y,
+                anchor_delta_x,
+                anchor_delta_y,
+            } => Some(Edge::Curved(
+                to_twips(control_delta_x),
+                to_twips(control_delta_y),
+                to_twips(anchor_delta_x),
+                to_twips(anchor_delta_y),
+            )),
+            ShapeRecord::StyleChange(_) => None,
+        }
+    }
+
+    fn to_record(self) -> ShapeRecord {
+        match self {
+            Edge::Straight(dx, dy) => ShapeRecord::StraightEdge {
+                delta_x: dx as f32 / 20.0,
+                delta_y: dy as f32 / 20.0,
+            },
+            Edge::Curved(cx, cy, ax, ay) => ShapeRecord::CurvedEdge {
+                control_delta_x: cx as f32 / 20.0,
+                control_delta_y: cy as f32 / 20.0,
+                anchor_delta_x: ax as f32 / 20.0,
+                anchor_delta_y: ay as f32 / 20.0,
+            },
+        }
+    }
+
+    /// The offset from the start to the end of the edge.
+    fn delta(self) -> (i32, i32) {
+        match self {
+            Edge::Straight(dx, dy) => (dx, dy),
+            Edge::Curved(cx, cy, ax, ay) => (cx + ax, cy + ay),
+        }
+    }
+
+    fn is_zero_length(self) -> bool {
+        match self {
+            Edge::Straight(dx, dy) => dx == 0 && dy == 0,
+            Edge::Curved(cx, cy, ax, ay) => cx == 0 && cy == 0 && ax == 0 && ay == 0,
+        }
+    }
+
+    /// Returns whether a curve can be replaced with a straight edge.
+    fn is_flat(self, tolerance: f64) -> bool {
+        match self {
+            Edge::Straight(..) => true,
+            Edge::Curved(cx, cy, ax, ay) => {
+                let end = (i64::from(cx + ax), i64::from(cy + ay));
+                // The curve deviates from its chord by at most half the control point distance.
+                is_on_chord((i64::from(cx), i64::from(cy)), end, tolerance * 2.0)
+            }
+        }
+    }
+}
+
+/// Returns whether `point` lies within `tolerance` of the line segment from the origin to `end`.
+fn is_on_chord(point: (i64, i64), end: (i64, i64), tolerance: f64) -> bool {
+    let length_sq = end.0 * end.0 + end.1 * end.1;
+    if length_sq == 0 {
+        return false;
+    }
+    let cross = (end.0 * point.1 - end.1 * point.0) as f64;
+    let dot = end.0 * point.0 + end.1 * point.1;
+    cross.abs() <= tolerance * (length_sq as f64).sqrt() && dot >= 0 && dot <= length_sq
+}
+
+/// A run of consecutive edge records.
+struct Run {
+    /// Index of the first edge record.
+    start: usize,
+    edges: Vec<Edge>,
+}
+
+fn split_runs(records: &[ShapeRecord]) -> Vec<Run> {
+    let mut runs: Vec<Run> = vec![];
+    let mut in_run = false;
+    for (i, record) in records.iter().enumerate() {
+        match Edge::from_record(record) {
+            Some(edge) => {
+                if !in_run {
+                    runs.push(Run {
+                        start: i,
+                        edges: vec![],
+                    });
+                    in_run = true;
+                }
+                runs.last_mut().unwrap().edges.push(edge);
+            }
+            None => in_run = false,
+        }
+    }
+    runs
+}
+
+/// Rebuilds a record list, replacing each run of edges with its simplified edges.
+fn join_runs(records: &[ShapeRecord], runs: &[Run], new_edges: &[Vec<Edge>]) -> Vec<ShapeRecord> {
+    let mut out = Vec::with_capacity(records.len());
+    let mut i = 0;
+    for (run, edges) in runs.iter().zip(new_edges) {
+        out.extend_from_slice(&records[i..run.start]);
+        out.extend(edges.iter().map(|edge| edge.to_record()));
+        i = run.start + run.edges.len();
+    }
+    out.extend_from_slice(&records[i..]);
+    out
+}
+
+/// Simplifies parallel runs of edges.
+///
+/// All runs have the same length, and an edge is only changed if the change is possible in
+/// every run; this keeps the start and end shapes of a morph shape in step.
+fn simplify_edges(runs: &mut [Vec<Edge>], tolerance: f32) {
+    let tolerance = f64::from(tolerance);
+    let len = runs[0].len();
+
+    // Straighten flat curves.
+    for i in 0..len {
+        if runs.iter().all(|run| run[i].is_flat(tolerance)) {
+            for run in runs.iter_mut() {
+                let (dx, dy) = run[i].delta();
+                run[i] = Edge::Straight(dx, dy);
+            }
+        }
+    }
+
+    // Drop zero-length edges.
+    let keep: Vec<bool> = (0..len)
+        .map(|i| !runs.iter().all(|run| run[i].is_zero_length()))
+        .collect();
+    for run in runs.iter_mut() {
+        let mut keep = keep.iter();
+        run.retain(|_| *keep.next().unwrap());
+    }
+
+    // Join straight edges.
+    let len = runs[0].len();
+    let mut joined: Vec<Vec<Edge>> = vec![vec![]; runs.len()];
+    let mut i = 0;
+    while i < len {
+        let mut end = i + 1;
+        while end < len && can_join(runs, i, end + 1, tolerance) {
+            end += 1;
+        }
+        for (run, out) in runs.iter().zip(joined.iter_mut()) {
+            if end == i + 1 {
+                out.push(run[i]);
+            } else {
+                let (dx, dy) = run[i..end].iter().fold((0, 0), |(x, y), edge| {
+                    let (dx, dy) = edge.delta();
+                    (x + dx, y + dy)
+                });
+                out.push(Edge::Straight(dx, dy));
+            }
+        }
+        i = end;
+    }
+    for (run, out) in runs.iter_mut().zip(joined) {
+        *run = out;
+    }
+}
+
+/// Returns whether the straight edges in `start..end` can be joined into one edge in every run.
+fn can_join(runs: &[Vec<Edge>], start: usize, end: usize, tolerance: f64) -> bool {
+    runs.iter().all(|run| {
+        let edges = &run[start..end];
+        let mut points = Vec::with_capacity(edges.len());
+        let mut position = (0i64, 0i64);
+        for edge in edges {
+            match *edge {
+                Edge::Straight(dx, dy) => {
+                    position = (position.0 + i64::from(dx), position.1 + i64::from(dy));
+                    points.push(position);
+                }
+                Edge::Curved(..) => return false,
+            }
+        }
+        let end_point = position;
+        if end_point.0.abs() > MAX_EDGE_DELTA || end_point.1.abs() > MAX_EDGE_DELTA {
+            return false;
+        }
+        // Every intermediate point must lie on the new edge, in order.
+        let mut last_dot = 0;
+        points[..points.len() - 1].iter().all(|&point| {
+            let dot = end_point.0 * point.0 + end_point.1 * point.1;
+            let is_ordered = dot >= last_dot;
+            last_dot = dot;
+            is_ordered && is_on_chord(point, end_point, tolerance)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shape_utils::WindingRule;
+    use test_data::rectangle;
+
+    fn color(r: u8) -> FillStyle {
+        FillStyle::Color(Color {
+            r,
+            g: 0,
+            b: 0,
+            a: 255,
+        })
+    }
+
+    fn style_change(
+        move_to: Option<(f32, f32)>,
+        fill_style_1: Option<u32>,
+        line_style: Option<u32>,
+    ) -> ShapeRecord {
+        ShapeRecord::StyleChange(StyleChangeData {
+            move_to,
+            fill_style_0: None,
+            fill_style_1,
+            line_style,
+            new_styles: None,
+        })
+    }
+
+    fn straight(delta_x: f32, delta_y: f32) -> ShapeRecord {
+        ShapeRecord::StraightEdge { delta_x, delta_y }
+    }
+
+    fn test_shape() -> Shape {
+        Shape {
+            version: 3,
+            id: 1,
+            shape_bounds: rectangle(0.0, 20.0, 0.0, 20.0),
+            edge_bounds: rectangle(0.0, 20.0, 0.0, 20.0),
+            has_fill_winding_rule: false,
+            has_non_scaling_strokes: false,
+            has_scaling_strokes: true,
+            styles: ShapeStyles {
+                // Style 3 duplicates style 1, and style 2 is unused.
+                fill_styles: vec![color(255), color(128), color(255)],
+                line_styles: vec![],
+            },
+            shape: vec![
+                style_change(Some((0.0, 0.0)), Some(1), None),
+                straight(5.0, 0.0),
+                straight(0.0, 0.0),
+                straight(5.0, 0.0),
+                ShapeRecord::CurvedEdge {
+                    control_delta_x: 0.0,
+                    control_delta_y: 2.0,
+                    anchor_delta_x: 0.0,
+                    anchor_delta_y: 8.0,
+                },
+                straight(-10.0, 0.0),
+                style_change(None, Some(1), None),
+                style_change(Some((0.0, 10.0)), None, None),
+                straight(0.0, -4.0),
+                straight(0.0, -6.0),
+                style_change(Some((10.0, 10.0)), Some(3), None),
+                straight(10.0, 0.0),
+                straight(0.0, 10.0),
+                straight(-10.0, 0.0),
+                straight(0.0, -10.0),
+            ],
+        }
+    }
+
+    fn hit_test_grid(shape: &Shape) -> Vec<bool> {
+        let mut hits = vec![];
+        for y in 0..40 {
+            for x in 0..40 {
+                let (x, y) = (x as f32 * 0.5 + 0.25, y as f32 * 0.5 + 0.25);
+                hits.push(shape.hit_test(x, y, WindingRule::from_shape(shape)));
+            }
+        }
+        hits
+    }
+
+    #[test]
+    fn optimize_lossless() {
+        let original = test_shape();
+        let mut shape = original.clone();
+        optimize_shape(&mut shape, None);
+        assert_eq!(shape.styles.fill_styles, vec![color(255)]);
+        assert_eq!(
+            shape.shape,
+            vec![
+                style_change(None, Some(1), None),
+                straight(10.0, 0.0),
+                straight(0.0, 10.0),
+                straight(-10.0, 0.0),
+                straight(0.0, -10.0),
+                style_change(Some((10.0, 10.0)), None, None),
+                straight(10.0, 0.0),
+                straight(0.0, 10.0),
+                straight(-10.0, 0.0),
+                straight(0.0, -10.0),
+            ]
+        );
+        assert_eq!(hit_test_grid(&shape), hit_test_grid(&original));
+    }
+
+    #[test]
+    fn optimize_lossy() {
+        let mut shape = test_shape();
+        shape.shape = vec![
+            style_change(Some((0.0, 0.0)), Some(1), None),
+            straight(5.0, 0.1),
+            straight(5.0, -0.1),
+            ShapeRecord::CurvedEdge {
+                control_delta_x: 0.1,
+                control_delta_y: 5.0,
+                anchor_delta_x: -0.1,
+                anchor_delta_y: 5.0,
+            },
+        ];
+        let mut lossless = shape.clone();
+        optimize_shape(&mut lossless, None);
+        assert_eq!(lossless.shape.len(), 4);
+
+        optimize_shape(&mut shape, Some(0.1));
+        assert_eq!(
+            shape.shape,
+            vec![
+                style_change(None, Some(1), None),
+                straight(10.0, 0.0),
+                straight(0.0, 10.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn optimize_new_styles() {
+        let mut shape = test_shape();
+        shape.shape.insert(
+            0,
+            ShapeRecord::StyleChange(StyleChangeData {
+                move_to: None,
+                fill_style_0: None,
+                fill_style_1: None,
+                line_style: None,
+                new_styles: Some(ShapeStyles {
+                    fill_styles: vec![color(255), color(1), color(255)],
+                    line_styles: vec![],
+                }),
+            }),
+        );
+        optimize_shape(&mut shape, None);
+        // The initial styles were never used, so the new styles replace them.
+        assert_eq!(shape.styles.fill_styles, vec![color(255)]);
+        assert_eq!(shape.shape[0], style_change(None, Some(1), None));
+    }
+
+    #[test]
+    fn optimize_morph() {
+        let start = MorphShape {
+            shape_bounds: rectangle(0.0, 10.0, 0.0, 10.0),
+            edge_bounds: rectangle(0.0, 10.0, 0.0, 10.0),
+            fill_styles: vec![color(1), color(2), color(1)],
+            line_styles: vec![],
+            shape: vec![
+                style_change(Some((0.0, 0.0)), Some(3), None),
+                straight(5.0, 0.0),
+                straight(5.0, 0.0),
+                straight(0.0, 5.0),
+                straight(0.0, 5.0),
+            ],
+        };
+        let end = MorphShape {
+            fill_styles: vec![color(3), color(4), color(3)],
+            shape: vec![
+                straight(5.0, 0.0),
+                straight(5.0, 0.0),
+                straight(0.0, 5.0),
+                straight(1.0, 5.0),
+            ],
+            ..start.clone()
+        };
+        let mut morph_shape = DefineMorphShape {
+            version: 1,
+            id: 1,
+            has_non_scaling_strokes: false,
+            has_scaling_strokes: true,
+            start,
+            end,
+        };
+        optimize_morph_shape(&mut morph_shape, None);
+        assert_eq!(morph_shape.start.fill_styles, vec![color(1)]);
+        assert_eq!(morph_shape.end.fill_styles, vec![color(3)]);
+        assert_eq!(
+            morph_shape.start.shape,
+            vec![
+                style_change(Some((0.0, 0.0)), Some(1), None),
+                straight(10.0, 0.0),
+                straight(0.0, 5.0),
+                straight(0.0, 5.0),
+            ]
+        );
+        assert_eq!(
+            morph_shape.end.shape,
+            vec![straight(10.0, 0.0), straight(0.0, 5.0), straight(1.0, 5.0)]
+        );
+    }
+}
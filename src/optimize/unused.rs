@@ -0,0 +1,256 @@
+//! Removal of character definitions that can never be displayed or used.
+use dictionary::{character_data_id, Dictionary};
+use std::collections::BTreeSet;
+use std::io::Result;
+use text::HtmlNode;
+use types::*;
+use write::write_tag;
+
+/// The sizes of the tags removed by `remove_unused_characters`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SizeReport {
+    /// The removed characters, in tag order.
+    pub removed: Vec<RemovedCharacter>,
+    /// The size of the encoded tags before removal, in bytes, before compression.
+    pub original_size: usize,
+    /// The size of the encoded tags after removal, in bytes, before compression.
+    pub optimized_size: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemovedCharacter {
+    pub id: CharacterId,
+    /// The encoded size of the character's definition and the tags that add to it.
+    pub size: usize,
+}
+
+/// Removes the characters that are not reachable from the main timeline.
+///
+/// Characters are reachable if they are placed or started on the main timeline, exported with
+/// `ExportAssets`, linked to a class with `SymbolClass`, initialized by `DoInitAction`, or used
+/// by a reachable character. Fonts named by a `<font face>` in a reachable HTML text field are
+/// also kept. The definitions of the other characters are removed along with the tags that add
+/// to them, such as `DefineFontAlignZones`, `DefineScalingGrid`, `DefineButtonSound` and
+/// `CsmTextSettings`.
+///
+/// Characters that ActionScript refers to by ID rather than through an export are not
+/// detected.
+pub fn remove_unused_characters(swf: &mut Swf) -> Result<SizeReport> {
+    let reachable = {
+        let dictionary = Dictionary::from_swf(swf);
+        let mut roots: Vec<CharacterId> = dictionary.reachable().into_iter().collect();
+        let faces = html_font_faces(&swf.tags, &roots);
+        roots.extend(
+            swf.tags
+                .iter()
+                .filter(|tag| font_name(tag, &swf.tags).is_some_and(|name| faces.contains(name)))
+                .filter_map(|tag| tag.character_id()),
+        );
+        dictionary.with_dependencies(&roots)
+    };
+
+    let mut removed: Vec<RemovedCharacter> = vec![];
+    let mut original_size = 0;
+    let mut optimized_size = 0;
+    let mut tags = Vec::with_capacity(swf.tags.len());
+    for tag in ::std::mem::take(&mut swf.tags) {
+        let mut encoded = vec![];
+        write_tag(&tag, swf.version, &mut encoded)?;
+        original_size += encoded.len();
+        let id = match tag.character_id().or_else(|| character_data_id(&tag)) {
+            Some(id) if !reachable.contains(&id) => id,
+            _ => {
+                optimized_size += encoded.len();
+                tags.push(tag);
+                continue;
+            }
+        };
+        match removed.iter_mut().find(|character| character.id == id) {
+            Some(character) => character.size += encoded.len(),
+            None => removed.push(RemovedCharacter {
+                id,
+                size: encoded.len(),
+            }),
+        }
+    }
+    swf.tags = tags;
+    Ok(SizeReport {
+        removed,
+        original_size,
+        optimized_size,
+    })
+}
+
+/// Returns the name of the font defined by a tag, using `DefineFontInfo` for `DefineFont`.
+fn font_name<'a>(tag: &'a Tag, tags: &'a [Tag]) -> Option<&'a str> {
+    let name = match *tag {
+        Tag::DefineFont(ref font) => tags.iter().find_map(|tag| match *tag {
+            Tag::DefineFontInfo(ref info) if info.id == font.id => Some(&info.name),
+            _ => None,
+        })?,
+        Tag::DefineFont2(ref font) => &font.name,
+        Tag::DefineFont4(ref font) => &font.name,
+        _ => return None,
+    };
+    Some(name.trim_end_matches('\0'))
+}
+
+/// Returns the font faces used by the HTML text fields among `ids`.
+fn html_font_faces(tags: &[Tag], ids: &[CharacterId]) -> BTreeSet<String> {
+    fn add_faces(nodes: &[HtmlNode], faces: &mut BTreeSet<String>) {
+        for node in nodes {
+            if let HtmlNode::Element {
+                ref name,
+                ref attributes,
+                ref children,
+            } = *node
+            {
+                if name.eq_ignore_ascii_case("font") {
+                    faces.extend(
+                        attributes
+                            .iter()
+                            .filter(|&(key, _)| key.eq_ignore_ascii_case("face"))
+                            .map(|(_, value)| value.clone()),
+                    );
+                }
+                add_faces(children, faces);
+            }
+        }
+    }
+
+    let mut faces = BTreeSet::new();
+    for tag in tags {
+        if let Tag::DefineEditText(ref edit_text) = *tag {
+            if ids.contains(&edit_text.id) {
+                if let Some(nodes) = edit_text.html_nodes() {
+                    add_faces(&nodes, &mut faces);
+                }
+            }
+        }
+    }
+    faces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use read::read_swf;
+    use std::fs::File;
+    use test_data;
+    use write::write_swf;
+
+    #[test]
+    fn remove_unused_shape() {
+        let file = File::open("tests/swfs/DefineBitsLossless2-CC.swf").unwrap();
+        let mut swf = read_swf(file).unwrap();
+        let mut unused_shape = swf
+            .tags
+            .iter()
+            .find_map(|tag| match *tag {
+                Tag::DefineShape(ref shape) => Some(shape.clone()),
+                _ => None,
+            })
+            .unwrap();
+        unused_shape.id = 10;
+        let splitter_rect = unused_shape.shape_bounds.clone();
+        swf.tags.insert(0, Tag::DefineShape(unused_shape));
+        swf.tags.insert(
+            1,
+            Tag::DefineScalingGrid {
+                id: 10,
+                splitter_rect,
+            },
+        );
+        let num_tags = swf.tags.len();
+
+        let report = remove_unused_characters(&mut swf).unwrap();
+        assert_eq!(swf.tags.len(), num_tags - 2);
+        assert_eq!(report.removed.len(), 1);
+        assert_eq!(report.removed[0].id, 10);
+        assert_eq!(
+            report.original_size - report.optimized_size,
+            report.removed[0].size
+        );
+        assert!(swf.tags.iter().all(|tag| match *tag {
+            Tag::DefineShape(ref shape) => shape.id != 10,
+            Tag::DefineScalingGrid { .. } => false,
+            _ => true,
+        }));
+
+        // The result still loads, and nothing else is removed.
+        let mut data = vec![];
+        write_swf(&swf, &mut data).unwrap();
+        let mut reloaded = read_swf(&data[..]).unwrap();
+        assert_eq!(reloaded.tags, swf.tags);
+        let report = remove_unused_characters(&mut reloaded).unwrap();
+        assert!(report.removed.is_empty());
+        assert_eq!(report.original_size, report.optimized_size);
+    }
+
+    #[test]
+    fn keep_html_fonts() {
+        let font = |id, name: &str| {
+            Tag::DefineFont2(Box::new(Font {
+                version: 3,
+                id,
+                name: name.to_string(),
+                language: Language::Latin,
+                layout: None,
+                glyphs: vec![],
+                is_small_text: false,
+                is_shift_jis: false,
+                is_ansi: false,
+                is_bold: false,
+                is_italic: false,
+            }))
+        };
+        let edit_text = EditText {
+            id: 3,
+            bounds: Rectangle {
+                x_min: 0.0,
+                x_max: 100.0,
+                y_min: 0.0,
+                y_max: 20.0,
+            },
+            font_id: None,
+            font_class_name: None,
+            height: None,
+            color: None,
+            max_length: None,
+            layout: None,
+            variable_name: String::new(),
+            initial_text: Some("<font face=\"Used\">Hi</font>".to_string()),
+            is_word_wrap: false,
+            is_multiline: false,
+            is_password: false,
+            is_read_only: false,
+            is_auto_size: false,
+            is_selectable: true,
+            has_border: false,
+            was_static: false,
+            is_html: true,
+            is_device_font: false,
+        };
+        let mut swf = test_data::swf(vec![
+            font(1, "Used"),
+            font(2, "Unused"),
+            Tag::DefineFontName {
+                id: 2,
+                name: "Unused".to_string(),
+                copyright_info: String::new(),
+            },
+            Tag::DefineEditText(Box::new(edit_text)),
+            Tag::ExportAssets(vec![ExportedAsset {
+                id: 3,
+                name: "field".to_string(),
+            }]),
+            Tag::ShowFrame,
+        ]);
+        let report = remove_unused_characters(&mut swf).unwrap();
+        assert_eq!(
+            report.removed.iter().map(|c| c.id).collect::<Vec<_>>(),
+            vec![2]
+        );
+        assert_eq!(swf.tags.len(), 4);
+    }
+}
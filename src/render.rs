@@ -0,0 +1,871 @@
+//! A CPU rasterizer for rendering shapes to RGBA bitmaps.
+//!
+//! The rasterizer samples each pixel row with several sub-scanlines and computes exact
+//! horizontal coverage, giving anti-aliased output without a GPU. It is intended for
+//! thumbnails and visual regression tests rather than real-time playback.
+
+use libflate::zlib::Decoder;
+use shape_utils::{flatten_commands, shape_to_paths, stroke_polyline, DrawPath, WindingRule};
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Read, Result};
+use types::*;
+
+/// Number of sub-scanlines sampled per pixel row.
+const SUBSCANLINES: usize = 16;
+
+/// Maximum distance, in device pixels, between a flattened curve and the real curve.
+const FLATTEN_TOLERANCE: f32 = 0.1;
+
+/// A decoded RGBA image with straight (non-premultiplied) alpha.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bitmap {
+    pub width: u32,
+    pub height: u32,
+    /// Pixel data in RGBA order, row by row from the top.
+    pub data: Vec<u8>,
+}
+
+/// Decoded bitmaps used by bitmap fills, keyed by the character ID of the bitmap.
+pub type BitmapLibrary = HashMap<CharacterId, Bitmap>;
+
+impl Bitmap {
+    /// Creates a fully transparent bitmap.
+    pub fn new(width: u32, height: u32) -> Bitmap {
+        Bitmap {
+            width,
+            height,
+            data: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    /// Decodes the zlib-compressed pixel data of a `DefineBitsLossless` or
+    /// `DefineBitsLossless2` tag.
+    pub fn from_define_bits_lossless(bitmap: &DefineBitsLossless) -> Result<Bitmap> {
+        let mut decoded = Vec::new();
+        Decoder::new(&bitmap.data[..])?.read_to_end(&mut decoded)?;
+
+        let width = bitmap.width as usize;
+        let height = bitmap.height as usize;
+        let has_alpha = bitmap.version >= 2;
+        let mut out = Bitmap::new(width as u32, height as u32);
+        let invalid_data = || Error::new(ErrorKind::InvalidData, "Bitmap data is too short.");
+        match bitmap.format {
+            BitmapFormat::ColorMap8 => {
+                let num_colors = bitmap.num_colors as usize + 1;
+                let entry_len = if has_alpha { 4 } else { 3 };
+                let palette_len = num_colors * entry_len;
+                let row_len = (width + 3) & !3;
+                if decoded.len() < palette_len + row_len * height {
+                    return Err(invalid_data());
+                }
+                let (palette, pixels) = decoded.split_at(palette_len);
+                for y in 0..height {
+                    for x in 0..width {
+                        let i = pixels[y * row_len + x] as usize;
+                        let color = if i < num_colors {
+                            let entry = &palette[i * entry_len..(i + 1) * entry_len];
+                            if has_alpha {
+                                unmultiply([entry[0], entry[1], entry[2], entry[3]])
+                            } else {
+                                [entry[0], entry[1], entry[2], 255]
+                            }
+                        } else {
+                            [0, 0, 0, 0]
+                        };
+                        out.set_pixel(x, y, color);
+                    }
+                }
+            }
+            BitmapFormat::Rgb15 => {
+                let row_len = (width * 2 + 3) & !3;
+                if decoded.len() < row_len * height {
+                    return Err(invalid_data());
+                }
+                for y in 0..height {
+                    for x in 0..width {
+                        let i = y * row_len + x * 2;
+                        let pixel = (u16::from(decoded[i]) << 8) | u16::from(decoded[i + 1]);
+                        let expand = |c: u16| ((c << 3) | (c >> 2)) as u8;
+                        out.set_pixel(
+                            x,
+                            y,
+                            [
+                                expand((pixel >> 10) & 0x1f),
+                                expand((pixel >> 5) & 0x1f),
+                                expand(pixel & 0x1f),
+                                255,
+                            ],
+                        );
+                    }
+                }
+            }
+            BitmapFormat::Rgb32 => {
+                if decoded.len() < width * height * 4 {
+                    return Err(invalid_data());
+                }
+                for y in 0..height {
+                    for x in 0..width {
+                        let p = &decoded[(y * width + x) * 4..(y * width + x + 1) * 4];
+                        let color = if has_alpha {
+                            unmultiply([p[1], p[2], p[3], p[0]])
+                        } else {
+                            [p[1], p[2], p[3], 255]
+                        };
+                        out.set_pixel(x, y, color);
+                    }
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// Returns the RGBA value of the pixel at the given position.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        [
+            self.data[i],
+            self.data[i + 1],
+            self.data[i + 2],
+            self.data[i + 3],
+        ]
+    }
+
+    fn set_pixel(&mut self, x: usize, y: usize, color: [u8; 4]) {
+        let i = (y * self.width as usize + x) * 4;
+        self.data[i..i + 4].copy_from_slice(&color);
+    }
+
+    /// Returns the premultiplied color of a pixel, wrapping or clamping out-of-range positions.
+    fn sample(&self, x: i64, y: i64, is_repeating: bool) -> [f32; 4] {
+        let (w, h) = (i64::from(self.width), i64::from(self.height));
+        let (x, y) = if is_repeating {
+            (x.rem_euclid(w), y.rem_euclid(h))
+        } else {
+            (x.max(0).min(w - 1), y.max(0).min(h - 1))
+        };
+        let p = self.pixel(x as u32, y as u32);
+        premultiply(p)
+    }
+}
+
+/// Converts premultiplied RGBA to straight alpha.
+fn unmultiply(c: [u8; 4]) -> [u8; 4] {
+    if c[3] == 0 {
+        [0, 0, 0, 0]
+    } else {
+        let a = u32::from(c[3]);
+        let f = |v: u8| (u32::from(v) * 255 / a).min(255) as u8;
+        [f(c[0]), f(c[1]), f(c[2]), c[3]]
+    }
+}
+
+fn premultiply(c: [u8; 4]) -> [f32; 4] {
+    let a = f32::from(c[3]) / 255.0;
+    [
+        f32::from(c[0]) / 255.0 * a,
+        f32::from(c[1]) / 255.0 * a,
+        f32::from(c[2]) / 255.0 * a,
+        a,
+    ]
+}
+
+fn color_to_premultiplied(color: &Color) -> [f32; 4] {
+    premultiply([color.r, color.g, color.b, color.a])
+}
+
+/// A drawing surface with premultiplied floating point pixels.
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<[f32; 4]>,
+}
+
+impl Canvas {
+    /// Creates a transparent canvas.
+    pub fn new(width: u32, height: u32) -> Canvas {
+        Canvas {
+            width,
+            height,
+            pixels: vec![[0.0; 4]; width as usize * height as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Fills the entire canvas with a color.
+    pub fn clear(&mut self, color: &Color) {
+        let color = color_to_premultiplied(color);
+        for pixel in &mut self.pixels {
+            *pixel = color;
+        }
+    }
+
+    /// Draws a shape, transformed by `matrix` from shape coordinates to canvas pixels.
+    ///
+    /// Bitmap fills whose bitmap is missing from `bitmaps` are skipped.
+    pub fn draw_shape(&mut self, shape: &Shape, matrix: &Matrix, bitmaps: &BitmapLibrary) {
+        let winding_rule = WindingRule::from_shape(shape);
+        let tolerance = FLATTEN_TOLERANCE / matrix_scale(matrix).max(1e-6);
+        for path in shape_to_paths(shape) {
+            match path {
+                DrawPath::Fill { style, commands } => {
+                    let paint = match Paint::new(style, matrix, bitmaps) {
+                        Some(paint) => paint,
+                        None => continue,
+                    };
+                    let polygons: Vec<Vec<(f32, f32)>> = flatten_commands(&commands, tolerance)
+                        .into_iter()
+                        .map(|polygon| transform_points(matrix, &polygon))
+                        .collect();
+                    self.fill_polygons(&polygons, winding_rule, &paint);
+                }
+                DrawPath::Stroke {
+                    style,
+                    is_closed,
+                    commands,
+                } => {
+                    let paint = match style.fill_style {
+                        Some(ref fill_style) => Paint::new(fill_style, matrix, bitmaps),
+                        None => Some(Paint::Solid(color_to_premultiplied(&style.color))),
+                    };
+                    let paint = match paint {
+                        Some(paint) => paint,
+                        None => continue,
+                    };
+                    // Hairlines are always drawn at least one device pixel wide.
+                    let min_width = 1.0 / matrix_scale(matrix).max(1e-6);
+                    let half_width = (f32::from(style.width) / 20.0).max(min_width) / 2.0;
+                    let mut polygons = vec![];
+                    for polyline in flatten_commands(&commands, tolerance) {
+                        for polygon in stroke_polyline(&polyline, is_closed, half_width, style) {
+                            polygons.push(transform_points(matrix, &polygon));
+                        }
+                    }
+                    self.fill_polygons(&polygons, WindingRule::NonZero, &paint);
+                }
+            }
+        }
+    }
+
+    /// Converts the canvas to a bitmap with straight alpha.
+    pub fn to_bitmap(&self) -> Bitmap {
+        let mut bitmap = Bitmap::new(self.width, self.height);
+        for (pixel, out) in self.pixels.iter().zip(bitmap.data.chunks_mut(4)) {
+            let a = pixel[3].clamp(0.0, 1.0);
+            let to_u8 = |v: f32| (v * 255.0 + 0.5).clamp(0.0, 255.0) as u8;
+            if a > 0.0 {
+                out[0] = to_u8(pixel[0] / a);
+                out[1] = to_u8(pixel[1] / a);
+                out[2] = to_u8(pixel[2] / a);
+            }
+            out[3] = to_u8(a);
+        }
+        bitmap
+    }
+
+    fn fill_polygons(&mut self, polygons: &[Vec<(f32, f32)>], rule: WindingRule, paint: &Paint) {
+        let mut edges = vec![];
+        for polygon in polygons {
+            for i in 0..polygon.len() {
+                let p0 = polygon[i];
+                let p1 = polygon[(i + 1) % polygon.len()];
+                if p0.1 == p1.1 {
+                    continue;
+                }
+                if !p0.0.is_finite() || !p0.1.is_finite() || !p1.0.is_finite() || !p1.1.is_finite()
+                {
+                    continue;
+                }
+                edges.push(if p0.1 < p1.1 {
+                    RasterEdge {
+                        x0: p0.0,
+                        y0: p0.1,
+                        x1: p1.0,
+                        y1: p1.1,
+                        direction: 1,
+                    }
+                } else {
+                    RasterEdge {
+                        x0: p1.0,
+                        y0: p1.1,
+                        x1: p0.0,
+                        y1: p0.1,
+                        direction: -1,
+                    }
+                });
+            }
+        }
+        if edges.is_empty() || self.width == 0 || self.height == 0 {
+            return;
+        }
+        edges.sort_by(|a, b| a.y0.partial_cmp(&b.y0).unwrap());
+
+        let y_min = edges[0].y0.floor().max(0.0) as usize;
+        let y_max = edges
+            .iter()
+            .map(|edge| edge.y1)
+            .fold(0.0f32, f32::max)
+            .ceil()
+            .min(self.height as f32) as usize;
+
+        let width = self.width as usize;
+        let mut coverage = vec![0.0f32; width];
+        let mut crossings: Vec<(f32, i32)> = vec![];
+        let mut active: Vec<usize> = vec![];
+        let mut next_edge = 0;
+        for y in y_min..y_max {
+            for c in coverage.iter_mut() {
+                *c = 0.0;
+            }
+            let mut span_min = width;
+            let mut span_max = 0;
+            for sub in 0..SUBSCANLINES {
+                let sample_y = y as f32 + (sub as f32 + 0.5) / SUBSCANLINES as f32;
+                while next_edge < edges.len() && edges[next_edge].y0 <= sample_y {
+                    active.push(next_edge);
+                    next_edge += 1;
+                }
+                active.retain(|&i| edges[i].y1 > sample_y);
+
+                crossings.clear();
+                for &i in &active {
+                    let edge = &edges[i];
+                    if edge.y0 <= sample_y {
+                        let t = (sample_y - edge.y0) / (edge.y1 - edge.y0);
+                        crossings.push((edge.x0 + t * (edge.x1 - edge.x0), edge.direction));
+                    }
+                }
+                crossings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+                let mut winding = 0;
+                for pair in crossings.windows(2) {
+                    winding += pair[0].1;
+                    if rule.is_inside(winding) {
+                        let (x0, x1) = (pair[0].0, pair[1].0);
+                        if let Some((start, end)) =
+                            accumulate_span(&mut coverage, x0, x1, 1.0 / SUBSCANLINES as f32)
+                        {
+                            span_min = span_min.min(start);
+                            span_max = span_max.max(end);
+                        }
+                    }
+                }
+            }
+
+            for (x, &c) in coverage
+                .iter()
+                .enumerate()
+                .take(span_max + 1)
+                .skip(span_min)
+            {
+                if c <= 0.0 {
+                    continue;
+                }
+                let c = c.min(1.0);
+                let src = paint.color_at(x as f32 + 0.5, y as f32 + 0.5);
+                let dst = &mut self.pixels[y * width + x];
+                let inv_a = 1.0 - src[3] * c;
+                for i in 0..4 {
+                    dst[i] = src[i] * c + dst[i] * inv_a;
+                }
+            }
+        }
+    }
+}
+
+/// Renders a shape to a bitmap sized to fit the shape's bounds at the given scale.
+pub fn render_shape(shape: &Shape, scale: f32, bitmaps: &BitmapLibrary) -> Bitmap {
+    let bounds = &shape.shape_bounds;
+    let width = ((bounds.x_max - bounds.x_min) * scale).ceil().max(1.0) as u32;
+    let height = ((bounds.y_max - bounds.y_min) * scale).ceil().max(1.0) as u32;
+    let matrix = Matrix {
+        translate_x: -bounds.x_min * scale,
+        translate_y: -bounds.y_min * scale,
+        scale_x: scale,
+        scale_y: scale,
+        rotate_skew_0: 0.0,
+        rotate_skew_1: 0.0,
+    };
+    let mut canvas = Canvas::new(width, height);
+    canvas.draw_shape(shape, &matrix, bitmaps);
+    canvas.to_bitmap()
+}
+
+/// Adds coverage for the horizontal span `[x0, x1)`, returning the touched pixel range.
+fn accumulate_span(coverage: &mut [f32], x0: f32, x1: f32, amount: f32) -> Option<(usize, usize)> {
+    let width = coverage.len() as f32;
+    let x0 = x0.max(0.0);
+    let x1 = x1.min(width);
+    if x1 <= x0 {
+        return None;
+    }
+    let start = x0.floor() as usize;
+    let end = (x1.ceil() as usize).min(coverage.len()) - 1;
+    if start == end {
+        coverage[start] += (x1 - x0) * amount;
+    } else {
+        coverage[start] += (start as f32 + 1.0 - x0) * amount;
+        for c in &mut coverage[start + 1..end] {
+            *c += amount;
+        }
+        coverage[end] += (x1 - end as f32) * amount;
+    }
+    Some((start, end))
+}
+
+struct RasterEdge {
+    x0: f32,
+    y0: f32,
+    x1: f32,
+    y1: f32,
+    direction: i32,
+}
+
+enum Paint<'a> {
+    Solid([f32; 4]),
+    Gradient {
+        inverse_matrix: Matrix,
+        kind: GradientKind,
+        spread: GradientSpread,
+        colors: Vec<[f32; 4]>,
+    },
+    Bitmap {
+        inverse_matrix: Matrix,
+        bitmap: &'a Bitmap,
+        is_smoothed: bool,
+        is_repeating: bool,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum GradientKind {
+    Linear,
+    Radial,
+    Focal(f32),
+}
+
+/// The gradient square spans -16384 to 16384 twips in gradient space.
+const GRADIENT_SIZE: f32 = 16384.0;
+
+impl<'a> Paint<'a> {
+    fn new(style: &FillStyle, matrix: &Matrix, bitmaps: &'a BitmapLibrary) -> Option<Paint<'a>> {
+        let gradient_paint = |gradient: &Gradient, kind: GradientKind| {
+            let paint_matrix = paint_matrix_to_pixels(&(*matrix * gradient.matrix));
+            paint_matrix.invert().map(|inverse_matrix| Paint::Gradient {
+                inverse_matrix,
+                kind,
+                spread: gradient.spread,
+                colors: gradient_ramp(gradient),
+            })
+        };
+        match *style {
+            FillStyle::Color(ref color) => Some(Paint::Solid(color_to_premultiplied(color))),
+            FillStyle::LinearGradient(ref gradient) => {
+                gradient_paint(gradient, GradientKind::Linear)
+            }
+            FillStyle::RadialGradient(ref gradient) => {
+                gradient_paint(gradient, GradientKind::Radial)
+            }
+            FillStyle::FocalGradient {
+                ref gradient,
+                focal_point,
+            } => gradient_paint(
+                gradient,
+                GradientKind::Focal(focal_point.clamp(-0.99, 0.99)),
+            ),
+            FillStyle::Bitmap {
+                id,
+                matrix: ref bitmap_matrix,
+                is_smoothed,
+                is_repeating,
+            } => {
+                let bitmap = bitmaps.get(&id)?;
+                if bitmap.width == 0 || bitmap.height == 0 {
+                    return None;
+                }
+                let paint_matrix = paint_matrix_to_pixels(&(*matrix * *bitmap_matrix));
+                paint_matrix.invert().map(|inverse_matrix| Paint::Bitmap {
+                    inverse_matrix,
+                    bitmap,
+                    is_smoothed,
+                    is_repeating,
+                })
+            }
+        }
+    }
+
+    fn color_at(&self, x: f32, y: f32) -> [f32; 4] {
+        match *self {
+            Paint::Solid(color) => color,
+            Paint::Gradient {
+                ref inverse_matrix,
+                kind,
+                spread,
+                ref colors,
+            } => {
+                let (gx, gy) = inverse_matrix.transform_point(x, y);
+                let (gx, gy) = (gx / GRADIENT_SIZE, gy / GRADIENT_SIZE);
+                let t = match kind {
+                    GradientKind::Linear => (gx + 1.0) / 2.0,
+                    GradientKind::Radial => (gx * gx + gy * gy).sqrt(),
+                    GradientKind::Focal(focal_point) => focal_ratio(focal_point, gx, gy),
+                };
+                let t = match spread {
+                    GradientSpread::Pad => t.clamp(0.0, 1.0),
+                    GradientSpread::Repeat => t - t.floor(),
+                    GradientSpread::Reflect => {
+                        let t = t.abs() % 2.0;
+                        if t > 1.0 {
+                            2.0 - t
+                        } else {
+                            t
+                        }
+                    }
+                };
+                colors[(t * 255.0 + 0.5) as usize]
+            }
+            Paint::Bitmap {
+                ref inverse_matrix,
+                bitmap,
+                is_smoothed,
+                is_repeating,
+            } => {
+                let (u, v) = inverse_matrix.transform_point(x, y);
+                if !is_smoothed {
+                    return bitmap.sample(u.floor() as i64, v.floor() as i64, is_repeating);
+                }
+                let (u, v) = (u - 0.5, v - 0.5);
+                let (x0, y0) = (u.floor(), v.floor());
+                let (fx, fy) = (u - x0, v - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let c00 = bitmap.sample(x0, y0, is_repeating);
+                let c10 = bitmap.sample(x0 + 1, y0, is_repeating);
+                let c01 = bitmap.sample(x0, y0 + 1, is_repeating);
+                let c11 = bitmap.sample(x0 + 1, y0 + 1, is_repeating);
+                let mut out = [0.0; 4];
+                for i in 0..4 {
+                    let top = c00[i] + (c10[i] - c00[i]) * fx;
+                    let bottom = c01[i] + (c11[i] - c01[i]) * fx;
+                    out[i] = top + (bottom - top) * fy;
+                }
+                out
+            }
+        }
+    }
+}
+
+/// Returns the gradient ratio of a point in a focal gradient, in normalized gradient space.
+fn focal_ratio(focal_point: f32, x: f32, y: f32) -> f32 {
+    // Find where the ray from the focal point through (x, y) hits the unit circle.
+    let (dx, dy) = (x - focal_point, y);
+    let d2 = dx * dx + dy * dy;
+    if d2 == 0.0 {
+        return 0.0;
+    }
+    let b = focal_point * dx;
+    let c = focal_point * focal_point - 1.0;
+    let lambda = (-b + (b * b - d2 * c).max(0.0).sqrt()) / d2;
+    if lambda <= 0.0 {
+        1.0
+    } else {
+        1.0 / lambda
+    }
+}
+
+/// Builds a 256-entry premultiplied color ramp for a gradient.
+fn gradient_ramp(gradient: &Gradient) -> Vec<[f32; 4]> {
+    let is_linear_rgb = gradient.interpolation == GradientInterpolation::LinearRGB;
+    let to_space = |color: &Color| {
+        let c = [
+            f32::from(color.r) / 255.0,
+            f32::from(color.g) / 255.0,
+            f32::from(color.b) / 255.0,
+            f32::from(color.a) / 255.0,
+        ];
+        if is_linear_rgb {
+            [
+                srgb_to_linear(c[0]),
+                srgb_to_linear(c[1]),
+                srgb_to_linear(c[2]),
+                c[3],
+            ]
+        } else {
+            c
+        }
+    };
+    let records = &gradient.records;
+    let mut ramp = Vec::with_capacity(256);
+    for i in 0..256 {
+        let color = if records.is_empty() {
+            [0.0; 4]
+        } else if i <= i32::from(records[0].ratio) {
+            to_space(&records[0].color)
+        } else if i >= i32::from(records[records.len() - 1].ratio) {
+            to_space(&records[records.len() - 1].color)
+        } else {
+            let next = records
+                .iter()
+                .position(|record| i32::from(record.ratio) >= i)
+                .unwrap_or(records.len() - 1)
+                .max(1);
+            let (a, b) = (&records[next - 1], &records[next]);
+            let range = (i32::from(b.ratio) - i32::from(a.ratio)).max(1) as f32;
+            let t = (i - i32::from(a.ratio)) as f32 / range;
+            let (ca, cb) = (to_space(&a.color), to_space(&b.color));
+            [
+                ca[0] + (cb[0] - ca[0]) * t,
+                ca[1] + (cb[1] - ca[1]) * t,
+                ca[2] + (cb[2] - ca[2]) * t,
+                ca[3] + (cb[3] - ca[3]) * t,
+            ]
+        };
+        let color = if is_linear_rgb {
+            [
+                linear_to_srgb(color[0]),
+                linear_to_srgb(color[1]),
+                linear_to_srgb(color[2]),
+                color[3],
+            ]
+        } else {
+            color
+        };
+        ramp.push([
+            color[0] * color[3],
+            color[1] * color[3],
+            color[2] * color[3],
+            color[3],
+        ]);
+    }
+    ramp
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Converts a fill style matrix, whose linear part is in twips, to one in pixels.
+///
+/// This is applied after concatenating with the device matrix so that the inverse keeps
+/// sub-pixel precision in gradient and bitmap space.
+fn paint_matrix_to_pixels(m: &Matrix) -> Matrix {
+    Matrix {
+        translate_x: m.translate_x,
+        translate_y: m.translate_y,
+        scale_x: m.scale_x / 20.0,
+        scale_y: m.scale_y / 20.0,
+        rotate_skew_0: m.rotate_skew_0 / 20.0,
+        rotate_skew_1: m.rotate_skew_1 / 20.0,
+    }
+}
+
+fn transform_points(m: &Matrix, points: &[(f32, f32)]) -> Vec<(f32, f32)> {
+    points
+        .iter()
+        .map(|&(x, y)| m.transform_point(x, y))
+        .collect()
+}
+
+/// Returns the average scale factor of a matrix.
+fn matrix_scale(m: &Matrix) -> f32 {
+    (m.scale_x * m.scale_y - m.rotate_skew_0 * m.rotate_skew_1)
+        .abs()
+        .sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use read::read_swf;
+    use std::fs::File;
+    use test_data::{rectangle, square_records};
+
+    fn shape(fill_style: FillStyle, records: Vec<ShapeRecord>) -> Shape {
+        Shape {
+            version: 3,
+            id: 1,
+            shape_bounds: rectangle(0.0, 10.0, 0.0, 10.0),
+            edge_bounds: rectangle(0.0, 10.0, 0.0, 10.0),
+            has_fill_winding_rule: false,
+            has_non_scaling_strokes: false,
+            has_scaling_strokes: true,
+            styles: ShapeStyles {
+                fill_styles: vec![fill_style],
+                line_styles: vec![],
+            },
+            shape: records,
+        }
+    }
+
+    fn red() -> FillStyle {
+        FillStyle::Color(Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        })
+    }
+
+    #[test]
+    fn render_solid_square() {
+        let shape = shape(red(), square_records(0.0, 0.0, 5.5, None));
+        let bitmap = render_shape(&shape, 1.0, &BitmapLibrary::new());
+        assert_eq!((bitmap.width, bitmap.height), (10, 10));
+        assert_eq!(bitmap.pixel(2, 2), [255, 0, 0, 255]);
+        assert_eq!(bitmap.pixel(8, 8), [0, 0, 0, 0]);
+        // The right edge covers half of pixel 5.
+        assert_eq!(bitmap.pixel(5, 2)[3], 128);
+        assert_eq!(bitmap.pixel(5, 5)[3], 64);
+    }
+
+    #[test]
+    fn render_scaled() {
+        let shape = shape(red(), square_records(0.0, 0.0, 5.0, None));
+        let bitmap = render_shape(&shape, 2.0, &BitmapLibrary::new());
+        assert_eq!((bitmap.width, bitmap.height), (20, 20));
+        assert_eq!(bitmap.pixel(9, 9), [255, 0, 0, 255]);
+        assert_eq!(bitmap.pixel(10, 10), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn render_winding_rules() {
+        let mut records = square_records(0.0, 0.0, 6.0, None);
+        records.extend(square_records(4.0, 4.0, 6.0, None));
+        let mut shape = shape(red(), records);
+        let bitmap = render_shape(&shape, 1.0, &BitmapLibrary::new());
+        assert_eq!(bitmap.pixel(5, 5)[3], 0);
+        assert_eq!(bitmap.pixel(2, 2)[3], 255);
+
+        shape.has_fill_winding_rule = true;
+        let bitmap = render_shape(&shape, 1.0, &BitmapLibrary::new());
+        assert_eq!(bitmap.pixel(5, 5)[3], 255);
+    }
+
+    #[test]
+    fn render_linear_gradient() {
+        let gradient = Gradient {
+            // Map the gradient square onto the 10px-wide shape.
+            matrix: Matrix {
+                translate_x: 5.0,
+                translate_y: 5.0,
+                scale_x: 10.0 * 20.0 / 32768.0,
+                scale_y: 10.0 * 20.0 / 32768.0,
+                rotate_skew_0: 0.0,
+                rotate_skew_1: 0.0,
+            },
+            spread: GradientSpread::Pad,
+            interpolation: GradientInterpolation::RGB,
+            records: vec![
+                GradientRecord {
+                    ratio: 0,
+                    color: Color {
+                        r: 0,
+                        g: 0,
+                        b: 0,
+                        a: 255,
+                    },
+                },
+                GradientRecord {
+                    ratio: 255,
+                    color: Color {
+                        r: 255,
+                        g: 255,
+                        b: 255,
+                        a: 255,
+                    },
+                },
+            ],
+        };
+        let shape = shape(
+            FillStyle::LinearGradient(gradient),
+            square_records(0.0, 0.0, 10.0, None),
+        );
+        let bitmap = render_shape(&shape, 1.0, &BitmapLibrary::new());
+        assert!(bitmap.pixel(0, 5)[0] < 20);
+        assert!(bitmap.pixel(9, 5)[0] > 235);
+        assert!(bitmap.pixel(4, 5)[0] < bitmap.pixel(5, 5)[0]);
+    }
+
+    #[test]
+    fn render_stroke() {
+        let mut shape = shape(red(), square_records(2.0, 2.0, 6.0, None));
+        shape.styles.line_styles.push(LineStyle::new_v1(
+            40,
+            Color {
+                r: 0,
+                g: 0,
+                b: 255,
+                a: 255,
+            },
+        ));
+        if let ShapeRecord::StyleChange(ref mut style_change) = shape.shape[0] {
+            style_change.line_style = Some(1);
+        }
+        let bitmap = render_shape(&shape, 1.0, &BitmapLibrary::new());
+        // The 2px stroke straddles the edge at x = 2.
+        assert_eq!(bitmap.pixel(1, 5), [0, 0, 255, 255]);
+        assert_eq!(bitmap.pixel(2, 5), [0, 0, 255, 255]);
+        assert_eq!(bitmap.pixel(5, 5), [255, 0, 0, 255]);
+        assert_eq!(bitmap.pixel(0, 5)[3], 0);
+    }
+
+    #[test]
+    fn render_bitmap_fill() {
+        let file = File::open("tests/swfs/DefineBitsLossless2-CC.swf").unwrap();
+        let swf = read_swf(file).unwrap();
+        let bitmap = swf
+            .tags
+            .iter()
+            .filter_map(|tag| match *tag {
+                Tag::DefineBitsLossless(ref bitmap) => Some(bitmap),
+                _ => None,
+            })
+            .next()
+            .unwrap();
+        let decoded = Bitmap::from_define_bits_lossless(bitmap).unwrap();
+        assert_eq!(decoded.width, u32::from(bitmap.width));
+        assert_eq!(
+            decoded.data.len(),
+            bitmap.width as usize * bitmap.height as usize * 4
+        );
+
+        let mut bitmaps = BitmapLibrary::new();
+        bitmaps.insert(bitmap.id, decoded.clone());
+        let shape = shape(
+            FillStyle::Bitmap {
+                id: bitmap.id,
+                matrix: Matrix {
+                    scale_x: 20.0,
+                    scale_y: 20.0,
+                    ..Matrix::new()
+                },
+                is_smoothed: false,
+                is_repeating: true,
+            },
+            square_records(0.0, 0.0, 10.0, None),
+        );
+        let rendered = render_shape(&shape, 1.0, &bitmaps);
+        let expected = decoded.pixel(1 % decoded.width, 1 % decoded.height);
+        let actual = rendered.pixel(1, 1);
+        for i in 0..4 {
+            assert!((i32::from(expected[i]) - i32::from(actual[i])).abs() <= 1);
+        }
+    }
+}
@@ -0,0 +1,437 @@
+//! Replacement of character definitions, keeping the IDs that other tags use to refer to them.
+use dictionary::{character_data_id, Dictionary};
+use hit_test::transform_bounds;
+use std::io::{Error, ErrorKind, Result};
+use timeline::Timeline;
+use types::*;
+
+/// The result of replacing a character definition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replacement {
+    /// The previous definition of the character.
+    pub old_definition: Tag,
+    /// The bounds of the character before the replacement, or `None` if they are not known
+    /// without decoding the character's data.
+    pub old_bounds: Option<Rectangle>,
+    /// The bounds of the character after the replacement. If they changed, instances placed on
+    /// a timeline may appear in the wrong place or at the wrong size.
+    pub new_bounds: Option<Rectangle>,
+}
+
+/// Replaces the definition of the character exported under `name` by `ExportAssets` or
+/// `SymbolClass`, as `replace_character` does.
+pub fn replace_exported_character(
+    swf: &mut Swf,
+    name: &str,
+    definition: Tag,
+) -> Result<Replacement> {
+    let id = Dictionary::from_swf(swf)
+        .exported_id(name)
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "Exported character not found."))?;
+    replace_character(swf, id, definition)
+}
+
+/// Replaces the definition of a character of the main timeline with another definition, which
+/// may be a different type of tag. Returns the previous definition, and the bounds of the
+/// character before and after.
+///
+/// The new definition is given the ID of the replaced character and takes its place in the tag
+/// list, so the characters it uses must be defined before it. Tags that add to the character
+/// are kept if they apply to the new definition and removed otherwise:
+///
+/// * A `DefineScalingGrid` is kept for sprites and buttons, and scaled from the old bounds of
+///   the character to the new bounds.
+/// * A `DefineFontAlignZones` is kept for a `DefineFont3` with the same number of glyphs.
+/// * `DefineFontInfo`, `DefineFontName`, `CsmTextSettings`, `DefineButtonSound` and
+///   `DefineButtonColorTransform` are kept for the types of character they apply to.
+///
+/// A warning is also logged if the bounds of the character change.
+pub fn replace_character(
+    swf: &mut Swf,
+    id: CharacterId,
+    mut definition: Tag,
+) -> Result<Replacement> {
+    if !set_character_id(&mut definition, id) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Replacement does not define a character.",
+        ));
+    }
+    let index = swf
+        .tags
+        .iter()
+        .position(|tag| tag.character_id() == Some(id))
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "Character not found."))?;
+
+    let old_bounds = character_bounds(&swf.tags, id);
+    let old_definition = ::std::mem::replace(&mut swf.tags[index], definition);
+    let new_bounds = character_bounds(&swf.tags, id);
+    let bounds_change = match (old_bounds.as_ref(), new_bounds.as_ref()) {
+        (Some(old_bounds), Some(new_bounds)) if old_bounds != new_bounds => {
+            warn!(
+                "Bounds of character {} changed from {:?} to {:?}",
+                id, old_bounds, new_bounds
+            );
+            Some((old_bounds.clone(), new_bounds.clone()))
+        }
+        _ => None,
+    };
+
+    let keep: Vec<bool> = {
+        let (before, rest) = swf.tags.split_at_mut(index);
+        let (definition, after) = rest.split_first_mut().unwrap();
+        let mut keep: Vec<bool> = before
+            .iter_mut()
+            .map(|tag| fix_up_data_tag(tag, id, definition, &bounds_change))
+            .collect();
+        keep.push(true);
+        keep.extend(
+            after
+                .iter_mut()
+                .map(|tag| fix_up_data_tag(tag, id, definition, &bounds_change)),
+        );
+        keep
+    };
+    let mut keep = keep.into_iter();
+    swf.tags.retain(|_| keep.next().unwrap_or(true));
+    Ok(Replacement {
+        old_definition,
+        old_bounds,
+        new_bounds,
+    })
+}
+
+/// Sets the ID of the character defined by a tag. Returns `false` if the tag defines none.
+fn set_character_id(tag: &mut Tag, new_id: CharacterId) -> bool {
+    match *tag {
+        Tag::DefineBinaryData { ref mut id, .. }
+        | Tag::DefineBits { ref mut id, .. }
+        | Tag::DefineBitsJpeg2 { ref mut id, .. } => *id = new_id,
+        Tag::DefineBitsJpeg3(ref mut jpeg) => jpeg.id = new_id,
+        Tag::DefineBitsLossless(ref mut bitmap) => bitmap.id = new_id,
+        Tag::DefineButton(ref mut button) | Tag::DefineButton2(ref mut button) => {
+            button.id = new_id
+        }
+        Tag::DefineEditText(ref mut edit_text) => edit_text.id = new_id,
+        Tag::DefineFont(ref mut font) => font.id = new_id,
+        Tag::DefineFont2(ref mut font) => font.id = new_id,
+        Tag::DefineFont4(ref mut font) => font.id = new_id,
+        Tag::DefineMorphShape(ref mut morph_shape) => morph_shape.id = new_id,
+        Tag::DefineShape(ref mut shape) => shape.id = new_id,
+        Tag::DefineSound(ref mut sound) => sound.id = new_id,
+        Tag::DefineSprite(ref mut sprite) => sprite.id = new_id,
+        Tag::DefineText(ref mut text) => text.id = new_id,
+        Tag::DefineVideoStream(ref mut video) => video.id = new_id,
+        _ => return false,
+    }
+    true
+}
+
+/// Updates a tag that adds to the character `id` for its new definition. Returns `false` if
+/// the tag does not apply to the new definition and should be removed.
+fn fix_up_data_tag(
+    tag: &mut Tag,
+    id: CharacterId,
+    definition: &Tag,
+    bounds_change: &Option<(Rectangle, Rectangle)>,
+) -> bool {
+    if character_data_id(tag) != Some(id) {
+        return true;
+    }
+    match *tag {
+        Tag::CsmTextSettings(_) => {
+            matches!(*definition, Tag::DefineEditText(_) | Tag::DefineText(_))
+        }
+        Tag::DefineButtonColorTransform { .. } => matches!(*definition, Tag::DefineButton(_)),
+        Tag::DefineButtonSound(_) => {
+            matches!(*definition, Tag::DefineButton(_) | Tag::DefineButton2(_))
+        }
+        Tag::DefineFontAlignZones { ref zones, .. } => match *definition {
+            Tag::DefineFont2(ref font) => font.version == 3 && font.glyphs.len() == zones.len(),
+            _ => false,
+        },
+        Tag::DefineFontInfo(_) => matches!(*definition, Tag::DefineFont(_)),
+        Tag::DefineFontName { .. } => {
+            matches!(*definition, Tag::DefineFont2(_) | Tag::DefineFont4(_))
+        }
+        Tag::DefineScalingGrid {
+            ref mut splitter_rect,
+            ..
+        } => match *definition {
+            Tag::DefineButton(_) | Tag::DefineButton2(_) | Tag::DefineSprite(_) => {
+                if let Some((ref old_bounds, ref new_bounds)) = *bounds_change {
+                    *splitter_rect = scale_rectangle(splitter_rect, old_bounds, new_bounds);
+                }
+                true
+            }
+            _ => false,
+        },
+        _ => true,
+    }
+}
+
+/// Maps a rectangle inside `from` to the same relative position inside `to`.
+fn scale_rectangle(rect: &Rectangle, from: &Rectangle, to: &Rectangle) -> Rectangle {
+    let scale = |value: f32, from_min: f32, from_max: f32, to_min: f32, to_max: f32| {
+        if from_max > from_min {
+            to_min + (value - from_min) * (to_max - to_min) / (from_max - from_min)
+        } else {
+            to_min + (value - from_min)
+        }
+    };
+    Rectangle {
+        x_min: scale(rect.x_min, from.x_min, from.x_max, to.x_min, to.x_max),
+        x_max: scale(rect.x_max, from.x_min, from.x_max, to.x_min, to.x_max),
+        y_min: scale(rect.y_min, from.y_min, from.y_max, to.y_min, to.y_max),
+        y_max: scale(rect.y_max, from.y_min, from.y_max, to.y_min, to.y_max),
+    }
+}
+
+/// Returns the bounds of a character in its own coordinate space, or `None` if they are not
+/// known without decoding the character's data.
+///
+/// The bounds of sprites and buttons include every frame and state.
+fn character_bounds(tags: &[Tag], id: CharacterId) -> Option<Rectangle> {
+    dictionary_bounds(&Dictionary::new(tags), id, &mut vec![])
+}
+
+fn dictionary_bounds(
+    dictionary: &Dictionary,
+    id: CharacterId,
+    visiting: &mut Vec<CharacterId>,
+) -> Option<Rectangle> {
+    let tag = dictionary.get(id)?;
+    if visiting.contains(&id) {
+        return None;
+    }
+    visiting.push(id);
+    let bounds = match *tag {
+        Tag::DefineBits { ref jpeg_data, .. } | Tag::DefineBitsJpeg2 { ref jpeg_data, .. } => {
+            image_bounds(jpeg_data)
+        }
+        Tag::DefineBitsJpeg3(ref jpeg) => image_bounds(&jpeg.data),
+        Tag::DefineBitsLossless(ref bitmap) => Some(Rectangle {
+            x_min: 0.0,
+            x_max: f32::from(bitmap.width),
+            y_min: 0.0,
+            y_max: f32::from(bitmap.height),
+        }),
+        Tag::DefineButton(ref button) | Tag::DefineButton2(ref button) => {
+            button.records.iter().fold(None, |bounds, record| {
+                match dictionary_bounds(dictionary, record.id, visiting) {
+                    Some(rect) => Some(union(bounds, transform_bounds(&record.matrix, &rect))),
+                    None => bounds,
+                }
+            })
+        }
+        Tag::DefineEditText(ref edit_text) => Some(edit_text.bounds.clone()),
+        Tag::DefineMorphShape(ref morph_shape) => Some(morph_shape.start.shape_bounds.clone()),
+        Tag::DefineShape(ref shape) => Some(shape.shape_bounds.clone()),
+        Tag::DefineSprite(ref sprite) => {
+            Timeline::new(&sprite.tags)
+                .frames()
+                .flatten()
+                .fold(None, |bounds, object| {
+                    match dictionary_bounds(dictionary, object.character_id, visiting) {
+                        Some(rect) => Some(union(bounds, transform_bounds(&object.matrix, &rect))),
+                        None => bounds,
+                    }
+                })
+        }
+        Tag::DefineText(ref text) => Some(text.bounds.clone()),
+        Tag::DefineVideoStream(ref video) => Some(Rectangle {
+            x_min: 0.0,
+            x_max: f32::from(video.width),
+            y_min: 0.0,
+            y_max: f32::from(video.height),
+        }),
+        _ => None,
+    };
+    visiting.pop();
+    bounds
+}
+
+/// Returns the bounds of the image data of a `DefineBits`, `DefineBitsJpeg2` or
+/// `DefineBitsJpeg3` tag, which is a JPEG, PNG or GIF image.
+fn image_bounds(data: &[u8]) -> Option<Rectangle> {
+    let (width, height) = if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        // The size is at the start of the IHDR chunk, which comes first.
+        let size = data.get(16..24)?;
+        (
+            u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as f32,
+            u32::from_be_bytes([size[4], size[5], size[6], size[7]]) as f32,
+        )
+    } else if data.starts_with(b"GIF8") {
+        let size = data.get(6..10)?;
+        (
+            f32::from(u16::from_le_bytes([size[0], size[1]])),
+            f32::from(u16::from_le_bytes([size[2], size[3]])),
+        )
+    } else {
+        let (width, height) = jpeg_size(data)?;
+        (f32::from(width), f32::from(height))
+    };
+    Some(Rectangle {
+        x_min: 0.0,
+        x_max: width,
+        y_min: 0.0,
+        y_max: height,
+    })
+}
+
+/// Returns the width and height of a JPEG image from its start of frame marker.
+///
+/// The data may contain the encoding tables and the image as separate JPEG streams, and may
+/// begin with an erroneous end and start of image marker, as in SWF files.
+fn jpeg_size(data: &[u8]) -> Option<(u16, u16)> {
+    let mut position = 0;
+    loop {
+        if *data.get(position)? != 0xff {
+            return None;
+        }
+        let marker = *data.get(position + 1)?;
+        position += 2;
+        match marker {
+            // Fill bytes before a marker.
+            0xff => position -= 1,
+            // Markers without a segment: start and end of image, and restarts.
+            0x01 | 0xd0..=0xd9 => (),
+            // The entropy-coded data follows the start of scan, and the frame header comes
+            // before it.
+            0xda => return None,
+            _ => {
+                let segment = data.get(position..position + 2)?;
+                let length = usize::from(u16::from_be_bytes([segment[0], segment[1]]));
+                // Start of frame markers, apart from the table and arithmetic coding markers
+                // in the same range.
+                if matches!(marker, 0xc0..=0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf) {
+                    let frame = data.get(position + 3..position + 7)?;
+                    let height = u16::from_be_bytes([frame[0], frame[1]]);
+                    let width = u16::from_be_bytes([frame[2], frame[3]]);
+                    return Some((width, height));
+                }
+                position += length;
+            }
+        }
+    }
+}
+
+fn union(bounds: Option<Rectangle>, rect: Rectangle) -> Rectangle {
+    match bounds {
+        Some(bounds) => Rectangle {
+            x_min: bounds.x_min.min(rect.x_min),
+            x_max: bounds.x_max.max(rect.x_max),
+            y_min: bounds.y_min.min(rect.y_min),
+            y_max: bounds.y_max.max(rect.y_max),
+        },
+        None => rect,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_data;
+    use test_data::rectangle;
+
+    fn bitmap(id: CharacterId, width: u16, height: u16) -> Tag {
+        Tag::DefineBitsLossless(DefineBitsLossless {
+            version: 2,
+            id,
+            format: BitmapFormat::Rgb32,
+            width,
+            height,
+            num_colors: 0,
+            data: vec![],
+        })
+    }
+
+    fn sprite(id: CharacterId, child_id: CharacterId) -> Tag {
+        Tag::DefineSprite(Sprite {
+            id,
+            num_frames: 1,
+            tags: vec![
+                Tag::PlaceObject(Box::new(PlaceObject {
+                    matrix: Some(Matrix::new()),
+                    ..test_data::place_object(PlaceObjectAction::Place(child_id), 1)
+                })),
+                Tag::ShowFrame,
+            ],
+        })
+    }
+
+    /// A JPEG image that begins with an erroneous header and has a table before its frame.
+    fn jpeg(width: u8, height: u8) -> Vec<u8> {
+        vec![
+            0xff, 0xd9, 0xff, 0xd8, 0xff, 0xdb, 0, 3, 0, 0xff, 0xc0, 0, 8, 8, 0, height, 0, width,
+            0, 0xff, 0xd9,
+        ]
+    }
+
+    #[test]
+    fn replace_exported_bitmap() {
+        let mut swf = test_data::swf(vec![
+            bitmap(1, 10, 10),
+            Tag::ExportAssets(vec![ExportedAsset {
+                id: 1,
+                name: "logo".to_string(),
+            }]),
+            Tag::ShowFrame,
+        ]);
+        let definition = Tag::DefineBitsJpeg2 {
+            id: 5,
+            jpeg_data: jpeg(20, 10),
+        };
+        let replacement = replace_exported_character(&mut swf, "logo", definition).unwrap();
+        assert_eq!(replacement.old_definition, bitmap(1, 10, 10));
+        assert_eq!(
+            replacement.old_bounds,
+            Some(rectangle(0.0, 10.0, 0.0, 10.0))
+        );
+        assert_eq!(
+            replacement.new_bounds,
+            Some(rectangle(0.0, 20.0, 0.0, 10.0))
+        );
+        assert_eq!(
+            swf.tags[0],
+            Tag::DefineBitsJpeg2 {
+                id: 1,
+                jpeg_data: jpeg(20, 10),
+            }
+        );
+        assert_eq!(swf.tags.len(), 3);
+
+        let error = replace_exported_character(&mut swf, "missing", bitmap(1, 1, 1));
+        assert_eq!(error.unwrap_err().kind(), ErrorKind::NotFound);
+        let error = replace_character(&mut swf, 1, Tag::ShowFrame);
+        assert_eq!(error.unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn fix_up_scaling_grid() {
+        let mut swf = test_data::swf(vec![
+            bitmap(1, 30, 30),
+            bitmap(2, 60, 90),
+            sprite(3, 1),
+            Tag::DefineScalingGrid {
+                id: 3,
+                splitter_rect: rectangle(10.0, 20.0, 10.0, 20.0),
+            },
+            Tag::ShowFrame,
+        ]);
+        replace_character(&mut swf, 3, sprite(4, 2)).unwrap();
+        assert_eq!(swf.tags[2], sprite(3, 2));
+        assert_eq!(
+            swf.tags[3],
+            Tag::DefineScalingGrid {
+                id: 3,
+                splitter_rect: rectangle(20.0, 40.0, 30.0, 60.0),
+            }
+        );
+
+        // A grid does not apply to a bitmap.
+        replace_character(&mut swf, 3, bitmap(3, 60, 90)).unwrap();
+        assert_eq!(swf.tags.len(), 4);
+        assert_eq!(swf.tags[3], Tag::ShowFrame);
+    }
+}
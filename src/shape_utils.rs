@@ -0,0 +1,764 @@
+//! Helpers for turning SWF shape records into drawable paths.
+//!
+//! SWF shapes are stored as a soup of edges, each tagged with up to two fill styles (the fill
+//! on the left and right side of the edge) and a line style. The functions in this module
+//! regroup those edges into closed contours per fill style and connected polylines per line
+//! style, which is the representation renderers and geometry queries want.
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use types::*;
+
+/// A single drawing command, in the same pixel units used by `ShapeRecord`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DrawCommand {
+    MoveTo { x: f32, y: f32 },
+    LineTo { x: f32, y: f32 },
+    CurveTo { x1: f32, y1: f32, x2: f32, y2: f32 },
+}
+
+/// A path sharing a single fill or line style.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DrawPath<'a> {
+    /// A set of closed contours filled with `style`.
+    Fill {
+        style: &'a FillStyle,
+        commands: Vec<DrawCommand>,
+    },
+    /// A connected polyline stroked with `style`.
+    Stroke {
+        style: &'a LineStyle,
+        is_closed: bool,
+        commands: Vec<DrawCommand>,
+    },
+}
+
+/// The rule used to decide which regions enclosed by a path are filled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindingRule {
+    /// A point is inside if a ray from it crosses the path an odd number of times.
+    EvenOdd,
+    /// A point is inside if the path winds around it a non-zero number of times.
+    NonZero,
+}
+
+impl WindingRule {
+    /// Returns the winding rule used to fill `shape`.
+    pub fn from_shape(shape: &Shape) -> WindingRule {
+        if shape.has_fill_winding_rule {
+            WindingRule::NonZero
+        } else {
+            WindingRule::EvenOdd
+        }
+    }
+
+    /// Returns whether a point with the given winding number is inside the path.
+    pub fn is_inside(self, winding: i32) -> bool {
+        match self {
+            WindingRule::EvenOdd => winding % 2 != 0,
+            WindingRule::NonZero => winding != 0,
+        }
+    }
+}
+
+/// Converts the records of a `Shape` into fill and stroke paths.
+///
+/// Paths are returned in drawing order: for each style table (the shape's initial styles
+/// followed by any `new_styles` in style change records), all fills come first, then all
+/// strokes.
+pub fn shape_to_paths(shape: &Shape) -> Vec<DrawPath<'_>> {
+    records_to_paths(&shape.styles, &shape.shape)
+}
+
+/// Converts a list of shape records into fill and stroke paths using the given initial styles.
+///
+/// Style indices that are out of range for the active style table are ignored.
+pub fn records_to_paths<'a>(
+    styles: &'a ShapeStyles,
+    records: &'a [ShapeRecord],
+) -> Vec<DrawPath<'a>> {
+    let mut converter = PathConverter::new(&styles.fill_styles, &styles.line_styles);
+    for record in records {
+        converter.record(record);
+    }
+    converter.finish()
+}
+
+/// Converts a glyph outline into a list of contours.
+///
+/// Glyph shapes have no style tables of their own; every edge with any fill style set is
+/// considered part of the glyph.
+pub fn glyph_to_commands(records: &[ShapeRecord]) -> Vec<DrawCommand> {
+    let styles = ShapeStyles {
+        fill_styles: vec![FillStyle::Color(Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        })],
+        line_styles: vec![],
+    };
+    let mut commands = vec![];
+    let records: Vec<ShapeRecord> = records
+        .iter()
+        .map(|record| match *record {
+            ShapeRecord::StyleChange(ref style_change) => {
+                let mut style_change = style_change.clone();
+                // Fonts only ever use a single fill, so collapse every fill to the first style.
+                style_change.fill_style_0 = style_change.fill_style_0.map(|i| i.min(1));
+                style_change.fill_style_1 = style_change.fill_style_1.map(|i| i.min(1));
+                style_change.line_style = None;
+                style_change.new_styles = None;
+                ShapeRecord::StyleChange(style_change)
+            }
+            ref record => record.clone(),
+        })
+        .collect();
+    for path in records_to_paths(&styles, &records) {
+        if let DrawPath::Fill {
+            commands: path_commands,
+            ..
+        } = path
+        {
+            commands.extend(path_commands);
+        }
+    }
+    commands
+}
+
+/// Flattens a list of draw commands into polylines.
+///
+/// Each `MoveTo` starts a new polyline. Curves are subdivided so that the polyline never
+/// deviates from the curve by more than `tolerance`.
+pub fn flatten_commands(commands: &[DrawCommand], tolerance: f32) -> Vec<Vec<(f32, f32)>> {
+    let mut polylines = vec![];
+    let mut current: Vec<(f32, f32)> = vec![];
+    let mut cursor = (0.0, 0.0);
+    for command in commands {
+        match *command {
+            DrawCommand::MoveTo { x, y } => {
+                if current.len() > 1 {
+                    polylines.push(current);
+                }
+                current = vec![(x, y)];
+                cursor = (x, y);
+            }
+            DrawCommand::LineTo { x, y } => {
+                if current.is_empty() {
+                    current.push(cursor);
+                }
+                current.push((x, y));
+                cursor = (x, y);
+            }
+            DrawCommand::CurveTo { x1, y1, x2, y2 } => {
+                if current.is_empty() {
+                    current.push(cursor);
+                }
+                flatten_quadratic(cursor, (x1, y1), (x2, y2), tolerance, &mut current);
+                cursor = (x2, y2);
+            }
+        }
+    }
+    if current.len() > 1 {
+        polylines.push(current);
+    }
+    polylines
+}
+
+/// Appends a flattened quadratic Bézier curve to `out`, excluding the start point.
+pub fn flatten_quadratic(
+    p0: (f32, f32),
+    p1: (f32, f32),
+    p2: (f32, f32),
+    tolerance: f32,
+    out: &mut Vec<(f32, f32)>,
+) {
+    // The maximum deviation of a uniformly subdivided quadratic is |p0 - 2p1 + p2| / (8n^2).
+    let ddx = p0.0 - 2.0 * p1.0 + p2.0;
+    let ddy = p0.1 - 2.0 * p1.1 + p2.1;
+    let dd = (ddx * ddx + ddy * ddy).sqrt();
+    let tolerance = tolerance.max(1e-6);
+    let num_segments = ((dd / (8.0 * tolerance)).sqrt().ceil() as usize).clamp(1, 1000);
+    for i in 1..=num_segments {
+        let t = i as f32 / num_segments as f32;
+        let mt = 1.0 - t;
+        out.push((
+            mt * mt * p0.0 + 2.0 * mt * t * p1.0 + t * t * p2.0,
+            mt * mt * p0.1 + 2.0 * mt * t * p1.1 + t * t * p2.1,
+        ));
+    }
+}
+
+/// Returns the winding number of a closed polygon around a point.
+///
+/// The polygon is implicitly closed between its last and first points.
+pub fn winding_number(polygon: &[(f32, f32)], x: f32, y: f32) -> i32 {
+    let mut winding = 0;
+    for i in 0..polygon.len() {
+        let p0 = polygon[i];
+        let p1 = polygon[(i + 1) % polygon.len()];
+        // Half-open interval so that vertices on the ray are only counted once.
+        if (p0.1 <= y) != (p1.1 <= y) {
+            let t = (y - p0.1) / (p1.1 - p0.1);
+            if p0.0 + t * (p1.0 - p0.0) > x {
+                winding += if p1.1 > p0.1 { 1 } else { -1 };
+            }
+        }
+    }
+    winding
+}
+
+/// Converts a stroked polyline into a set of convex polygons with a consistent winding.
+///
+/// The union of the polygons, filled with the non-zero rule, covers the stroke.
+pub fn stroke_polyline(
+    points: &[(f32, f32)],
+    is_closed: bool,
+    half_width: f32,
+    style: &LineStyle,
+) -> Vec<Vec<(f32, f32)>> {
+    let mut points: Vec<(f32, f32)> = points.to_vec();
+    points.dedup();
+    if is_closed && points.len() > 1 && points[0] == points[points.len() - 1] {
+        points.pop();
+    }
+
+    let mut polygons = vec![];
+    if points.len() == 1 {
+        // A zero-length line is only visible with a cap.
+        let p = points[0];
+        match style.start_cap {
+            LineCapStyle::Round => polygons.push(circle(p, half_width)),
+            LineCapStyle::Square => polygons.push(vec![
+                (p.0 - half_width, p.1 - half_width),
+                (p.0 + half_width, p.1 - half_width),
+                (p.0 + half_width, p.1 + half_width),
+                (p.0 - half_width, p.1 + half_width),
+            ]),
+            LineCapStyle::None => (),
+        }
+        return polygons;
+    }
+
+    let num_segments = if is_closed {
+        points.len()
+    } else {
+        points.len() - 1
+    };
+    for i in 0..num_segments {
+        let mut p0 = points[i];
+        let mut p1 = points[(i + 1) % points.len()];
+        let d = direction(p0, p1);
+        if !is_closed && i == 0 && style.start_cap == LineCapStyle::Square {
+            p0 = (p0.0 - d.0 * half_width, p0.1 - d.1 * half_width);
+        }
+        if !is_closed && i == num_segments - 1 && style.end_cap == LineCapStyle::Square {
+            p1 = (p1.0 + d.0 * half_width, p1.1 + d.1 * half_width);
+        }
+        let n = (-d.1 * half_width, d.0 * half_width);
+        polygons.push(vec![
+            (p0.0 + n.0, p0.1 + n.1),
+            (p1.0 + n.0, p1.1 + n.1),
+            (p1.0 - n.0, p1.1 - n.1),
+            (p0.0 - n.0, p0.1 - n.1),
+        ]);
+    }
+
+    // Joins between consecutive segments.
+    let (first_join, last_join) = if is_closed {
+        (0, points.len())
+    } else {
+        (1, points.len() - 1)
+    };
+    for i in first_join..last_join {
+        let prev = points[(i + points.len() - 1) % points.len()];
+        let p = points[i];
+        let next = points[(i + 1) % points.len()];
+        if let Some(join) = stroke_join(prev, p, next, half_width, style.join_style) {
+            polygons.push(join);
+        }
+    }
+
+    if !is_closed {
+        if style.start_cap == LineCapStyle::Round {
+            polygons.push(circle(points[0], half_width));
+        }
+        if style.end_cap == LineCapStyle::Round {
+            polygons.push(circle(points[points.len() - 1], half_width));
+        }
+    }
+
+    for polygon in &mut polygons {
+        if signed_area(polygon) < 0.0 {
+            polygon.reverse();
+        }
+    }
+    polygons
+}
+
+fn stroke_join(
+    prev: (f32, f32),
+    p: (f32, f32),
+    next: (f32, f32),
+    half_width: f32,
+    join_style: LineJoinStyle,
+) -> Option<Vec<(f32, f32)>> {
+    let d0 = direction(prev, p);
+    let d1 = direction(p, next);
+    let cross = d0.0 * d1.1 - d0.1 * d1.0;
+    if cross.abs() < 1e-6 && d0.0 * d1.0 + d0.1 * d1.1 > 0.0 {
+        // Collinear segments need no join.
+        return None;
+    }
+    if let LineJoinStyle::Round = join_style {
+        return Some(circle(p, half_width));
+    }
+
+    // The join fills the gap on the outside of the turn.
+    let side = if cross > 0.0 { -1.0 } else { 1.0 };
+    let n0 = (-d0.1 * half_width * side, d0.0 * half_width * side);
+    let n1 = (-d1.1 * half_width * side, d1.0 * half_width * side);
+    let a = (p.0 + n0.0, p.1 + n0.1);
+    let b = (p.0 + n1.0, p.1 + n1.1);
+    let miter_limit = match join_style {
+        LineJoinStyle::Miter(limit) => limit,
+        _ => return Some(vec![p, a, b]),
+    };
+
+    let bisector = direction((0.0, 0.0), (n0.0 + n1.0, n0.1 + n1.1));
+    let cos_half = (bisector.0 * n0.0 + bisector.1 * n0.1) / half_width;
+    if cos_half <= 1e-6 {
+        return Some(vec![p, a, b]);
+    }
+    let miter_length = half_width / cos_half;
+    let max_length = miter_limit.max(1.0) * half_width;
+    if miter_length <= max_length {
+        let tip = (
+            p.0 + bisector.0 * miter_length,
+            p.1 + bisector.1 * miter_length,
+        );
+        Some(vec![p, a, tip, b])
+    } else {
+        // Cut off the miter at the limit.
+        let along = |n: (f32, f32), d: (f32, f32)| {
+            let t = (max_length - (n.0 * bisector.0 + n.1 * bisector.1))
+                / (d.0 * bisector.0 + d.1 * bisector.1);
+            (p.0 + n.0 + d.0 * t, p.1 + n.1 + d.1 * t)
+        };
+        Some(vec![p, a, along(n0, d0), along(n1, (-d1.0, -d1.1)), b])
+    }
+}
+
+fn direction(from: (f32, f32), to: (f32, f32)) -> (f32, f32) {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let len = (dx * dx + dy * dy).sqrt();
+    if len == 0.0 {
+        (1.0, 0.0)
+    } else {
+        (dx / len, dy / len)
+    }
+}
+
+fn circle(center: (f32, f32), radius: f32) -> Vec<(f32, f32)> {
+    let num_points = ((radius * 2.0 * PI).ceil() as usize).clamp(8, 256);
+    (0..num_points)
+        .map(|i| {
+            let angle = i as f32 / num_points as f32 * 2.0 * PI;
+            (
+                center.0 + radius * angle.cos(),
+                center.1 + radius * angle.sin(),
+            )
+        })
+        .collect()
+}
+
+fn signed_area(polygon: &[(f32, f32)]) -> f32 {
+    let mut area = 0.0;
+    for i in 0..polygon.len() {
+        let p0 = polygon[i];
+        let p1 = polygon[(i + 1) % polygon.len()];
+        area += p0.0 * p1.1 - p1.0 * p0.1;
+    }
+    area / 2.0
+}
+
+/// An edge in absolute coordinates.
+#[derive(Clone, Copy, Debug)]
+struct Edge {
+    start: (f32, f32),
+    control: Option<(f32, f32)>,
+    end: (f32, f32),
+}
+
+impl Edge {
+    fn reversed(&self) -> Edge {
+        Edge {
+            start: self.end,
+            control: self.control,
+            end: self.start,
+        }
+    }
+}
+
+/// Key used to match edge endpoints; shape coordinates are always whole twips.
+fn point_key(point: (f32, f32)) -> (i32, i32) {
+    (
+        (point.0 * 20.0).round() as i32,
+        (point.1 * 20.0).round() as i32,
+    )
+}
+
+struct Polyline {
+    line_style: usize,
+    start: (f32, f32),
+    end: (f32, f32),
+    commands: Vec<DrawCommand>,
+}
+
+struct PathConverter<'a> {
+    fill_styles: &'a [FillStyle],
+    line_styles: &'a [LineStyle],
+    fill_edges: Vec<Vec<Edge>>,
+    polylines: Vec<Polyline>,
+    current_polyline: Option<Polyline>,
+    cursor: (f32, f32),
+    fill_style_0: usize,
+    fill_style_1: usize,
+    line_style: usize,
+    paths: Vec<DrawPath<'a>>,
+}
+
+impl<'a> PathConverter<'a> {
+    fn new(fill_styles: &'a [FillStyle], line_styles: &'a [LineStyle]) -> PathConverter<'a> {
+        PathConverter {
+            fill_styles,
+            line_styles,
+            fill_edges: vec![vec![]; fill_styles.len()],
+            polylines: vec![],
+            current_polyline: None,
+            cursor: (0.0, 0.0),
+            fill_style_0: 0,
+            fill_style_1: 0,
+            line_style: 0,
+            paths: vec![],
+        }
+    }
+
+    fn record(&mut self, record: &'a ShapeRecord) {
+        match *record {
+            ShapeRecord::StyleChange(ref style_change) => {
+                self.finish_polyline();
+                if let Some(ref new_styles) = style_change.new_styles {
+                    self.flush_styles();
+                    self.fill_styles = &new_styles.fill_styles;
+                    self.line_styles = &new_styles.line_styles;
+                    self.fill_edges = vec![vec![]; new_styles.fill_styles.len()];
+                    self.fill_style_0 = 0;
+                    self.fill_style_1 = 0;
+                    self.line_style = 0;
+                }
+                if let Some((x, y)) = style_change.move_to {
+                    self.cursor = (x, y);
+                }
+                if let Some(i) = style_change.fill_style_0 {
+                    self.fill_style_0 = i as usize;
+                }
+                if let Some(i) = style_change.fill_style_1 {
+                    self.fill_style_1 = i as usize;
+                }
+                if let Some(i) = style_change.line_style {
+                    self.line_style = i as usize;
+                }
+            }
+            ShapeRecord::StraightEdge { delta_x, delta_y } => {
+                let end = (self.cursor.0 + delta_x, self.cursor.1 + delta_y);
+                self.edge(Edge {
+                    start: self.cursor,
+                    control: None,
+                    end,
+                });
+            }
+            ShapeRecord::CurvedEdge {
+                control_delta_x,
+                control_delta_y,
+                anchor_delta_x,
+                anchor_delta_y,
+            } => {
+                let control = (
+                    self.cursor.0 + control_delta_x,
+                    self.cursor.1 + control_delta_y,
+                );
+                let end = (control.0 + anchor_delta_x, control.1 + anchor_delta_y);
+                self.edge(Edge {
+                    start: self.cursor,
+                    control: Some(control),
+                    end,
+                });
+            }
+        }
+    }
+
+    fn edge(&mut self, edge: Edge) {
+        self.cursor = edge.end;
+        if self.fill_style_1 > 0 && self.fill_style_1 <= self.fill_edges.len() {
+            self.fill_edges[self.fill_style_1 - 1].push(edge);
+        }
+        if self.fill_style_0 > 0 && self.fill_style_0 <= self.fill_edges.len() {
+            // Fill style 0 is on the left side of the edge, so reverse it to keep a consistent
+            // winding direction with fill style 1 edges.
+            self.fill_edges[self.fill_style_0 - 1].push(edge.reversed());
+        }
+        if self.line_style > 0 && self.line_style <= self.line_styles.len() {
+            let is_connected = match self.current_polyline {
+                Some(ref polyline) => {
+                    polyline.line_style == self.line_style
+                        && point_key(polyline.end) == point_key(edge.start)
+                }
+                None => false,
+            };
+            if !is_connected {
+                self.finish_polyline();
+                self.current_polyline = Some(Polyline {
+                    line_style: self.line_style,
+                    start: edge.start,
+                    end: edge.start,
+                    commands: vec![DrawCommand::MoveTo {
+                        x: edge.start.0,
+                        y: edge.start.1,
+                    }],
+                });
+            }
+            if let Some(ref mut polyline) = self.current_polyline {
+                polyline.commands.push(edge_command(&edge));
+                polyline.end = edge.end;
+            }
+        }
+    }
+
+    fn finish_polyline(&mut self) {
+        if let Some(polyline) = self.current_polyline.take() {
+            self.polylines.push(polyline);
+        }
+    }
+
+    /// Emits paths for the current style table.
+    fn flush_styles(&mut self) {
+        self.finish_polyline();
+        let fill_styles = self.fill_styles;
+        let fill_edges = ::std::mem::take(&mut self.fill_edges);
+        for (style, edges) in fill_styles.iter().zip(fill_edges) {
+            if !edges.is_empty() {
+                self.paths.push(DrawPath::Fill {
+                    style,
+                    commands: link_edges(&edges),
+                });
+            }
+        }
+
+        let line_styles = self.line_styles;
+        let mut polylines = ::std::mem::take(&mut self.polylines);
+        // Stable sort so that lines are drawn by style index, then in shape order.
+        polylines.sort_by_key(|polyline| polyline.line_style);
+        for polyline in polylines {
+            self.paths.push(DrawPath::Stroke {
+                style: &line_styles[polyline.line_style - 1],
+                is_closed: point_key(polyline.start) == point_key(polyline.end),
+                commands: polyline.commands,
+            });
+        }
+    }
+
+    fn finish(mut self) -> Vec<DrawPath<'a>> {
+        self.flush_styles();
+        self.paths
+    }
+}
+
+fn edge_command(edge: &Edge) -> DrawCommand {
+    match edge.control {
+        Some((x1, y1)) => DrawCommand::CurveTo {
+            x1,
+            y1,
+            x2: edge.end.0,
+            y2: edge.end.1,
+        },
+        None => DrawCommand::LineTo {
+            x: edge.end.0,
+            y: edge.end.1,
+        },
+    }
+}
+
+/// Links a soup of edges into contours by matching endpoints.
+fn link_edges(edges: &[Edge]) -> Vec<DrawCommand> {
+    let mut edges_by_start: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+    for (i, edge) in edges.iter().enumerate().rev() {
+        edges_by_start
+            .entry(point_key(edge.start))
+            .or_default()
+            .push(i);
+    }
+
+    let mut is_used = vec![false; edges.len()];
+    let mut commands = vec![];
+    for first in 0..edges.len() {
+        if is_used[first] {
+            continue;
+        }
+        let start_key = point_key(edges[first].start);
+        commands.push(DrawCommand::MoveTo {
+            x: edges[first].start.0,
+            y: edges[first].start.1,
+        });
+        let mut i = first;
+        loop {
+            is_used[i] = true;
+            commands.push(edge_command(&edges[i]));
+            let end_key = point_key(edges[i].end);
+            if end_key == start_key {
+                break;
+            }
+            let next = edges_by_start.get_mut(&end_key).and_then(|candidates| {
+                while let Some(candidate) = candidates.pop() {
+                    if !is_used[candidate] {
+                        return Some(candidate);
+                    }
+                }
+                None
+            });
+            match next {
+                Some(next) => i = next,
+                None => break,
+            }
+        }
+    }
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square_shape(fill_style_0: Option<u32>, fill_style_1: Option<u32>) -> Shape {
+        Shape {
+            version: 1,
+            id: 1,
+            shape_bounds: Rectangle {
+                x_min: 0.0,
+                x_max: 10.0,
+                y_min: 0.0,
+                y_max: 10.0,
+            },
+            edge_bounds: Rectangle {
+                x_min: 0.0,
+                x_max: 10.0,
+                y_min: 0.0,
+                y_max: 10.0,
+            },
+            has_fill_winding_rule: false,
+            has_non_scaling_strokes: false,
+            has_scaling_strokes: true,
+            styles: ShapeStyles {
+                fill_styles: vec![FillStyle::Color(Color {
+                    r: 255,
+                    g: 0,
+                    b: 0,
+                    a: 255,
+                })],
+                line_styles: vec![LineStyle::new_v1(
+                    20,
+                    Color {
+                        r: 0,
+                        g: 0,
+                        b: 0,
+                        a: 255,
+                    },
+                )],
+            },
+            shape: vec![
+                ShapeRecord::StyleChange(StyleChangeData {
+                    move_to: Some((0.0, 0.0)),
+                    fill_style_0,
+                    fill_style_1,
+                    line_style: Some(1),
+                    new_styles: None,
+                }),
+                // Store the edges out of order to exercise edge linking.
+                ShapeRecord::StraightEdge {
+                    delta_x: 10.0,
+                    delta_y: 0.0,
+                },
+                ShapeRecord::StraightEdge {
+                    delta_x: 0.0,
+                    delta_y: 10.0,
+                },
+                ShapeRecord::StyleChange(StyleChangeData {
+                    move_to: Some((0.0, 10.0)),
+                    fill_style_0: None,
+                    fill_style_1: None,
+                    line_style: None,
+                    new_styles: None,
+                }),
+                ShapeRecord::StraightEdge {
+                    delta_x: 0.0,
+                    delta_y: -10.0,
+                },
+                ShapeRecord::StyleChange(StyleChangeData {
+                    move_to: Some((10.0, 10.0)),
+                    fill_style_0: None,
+                    fill_style_1: None,
+                    line_style: None,
+                    new_styles: None,
+                }),
+                ShapeRecord::StraightEdge {
+                    delta_x: -10.0,
+                    delta_y: 0.0,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn link_fill_edges() {
+        let shape = square_shape(None, Some(1));
+        let paths = shape_to_paths(&shape);
+        assert_eq!(
+            paths[0],
+            DrawPath::Fill {
+                style: &shape.styles.fill_styles[0],
+                commands: vec![
+                    DrawCommand::MoveTo { x: 0.0, y: 0.0 },
+                    DrawCommand::LineTo { x: 10.0, y: 0.0 },
+                    DrawCommand::LineTo { x: 10.0, y: 10.0 },
+                    DrawCommand::LineTo { x: 0.0, y: 10.0 },
+                    DrawCommand::LineTo { x: 0.0, y: 0.0 },
+                ],
+            }
+        );
+        // Three separate polylines: two edges, one edge, one edge.
+        assert_eq!(paths.len(), 4);
+    }
+
+    #[test]
+    fn reverse_fill_style_0_edges() {
+        let shape = square_shape(Some(1), None);
+        let paths = shape_to_paths(&shape);
+        if let DrawPath::Fill { ref commands, .. } = paths[0] {
+            assert_eq!(commands[0], DrawCommand::MoveTo { x: 10.0, y: 0.0 });
+            assert_eq!(commands[1], DrawCommand::LineTo { x: 0.0, y: 0.0 });
+            assert_eq!(commands.len(), 5);
+        } else {
+            panic!("Expected fill path");
+        }
+    }
+
+    #[test]
+    fn flatten_curve() {
+        let mut points = vec![];
+        flatten_quadratic((0.0, 0.0), (10.0, 10.0), (20.0, 0.0), 0.1, &mut points);
+        assert!(points.len() > 2);
+        assert_eq!(*points.last().unwrap(), (20.0, 0.0));
+        for &(_, y) in &points {
+            assert!((0.0..=5.0).contains(&y));
+        }
+    }
+}
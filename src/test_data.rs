@@ -17,9 +17,119 @@ pub fn echo_swf(filename: &str) {
     write_swf(&swf, out_file).unwrap();
 }
 
+/// Returns an SWF with a 100 by 100 stage that contains `tags`.
+pub fn swf(tags: Vec<Tag>) -> Swf {
+    Swf {
+        version: 10,
+        compression: Compression::None,
+        stage_size: Rectangle {
+            x_min: 0.0,
+            x_max: 100.0,
+            y_min: 0.0,
+            y_max: 100.0,
+        },
+        frame_rate: 24.0,
+        num_frames: tags.iter().filter(|tag| **tag == Tag::ShowFrame).count() as u16,
+        tags,
+    }
+}
+
+/// Returns a `PlaceObject2` with no optional fields.
+#[allow(dead_code)]
+pub fn place_object(action: PlaceObjectAction, depth: Depth) -> PlaceObject {
+    PlaceObject {
+        version: 2,
+        action,
+        depth,
+        matrix: None,
+        color_transform: None,
+        ratio: None,
+        name: None,
+        clip_depth: None,
+        class_name: None,
+        filters: vec![],
+        background_color: None,
+        blend_mode: BlendMode::Normal,
+        clip_actions: vec![],
+        is_image: false,
+        is_bitmap_cached: false,
+        is_visible: true,
+        amf_data: None,
+    }
+}
+
+#[allow(dead_code)]
+pub fn rectangle(x_min: f32, x_max: f32, y_min: f32, y_max: f32) -> Rectangle {
+    Rectangle {
+        x_min,
+        x_max,
+        y_min,
+        y_max,
+    }
+}
+
+/// Returns the records of a square filled with fill style 1, from its top left corner.
+#[allow(dead_code)]
+pub fn square_records(x: f32, y: f32, size: f32, line_style: Option<u32>) -> Vec<ShapeRecord> {
+    vec![
+        ShapeRecord::StyleChange(StyleChangeData {
+            move_to: Some((x, y)),
+            fill_style_0: None,
+            fill_style_1: Some(1),
+            line_style,
+            new_styles: None,
+        }),
+        ShapeRecord::StraightEdge {
+            delta_x: size,
+            delta_y: 0.0,
+        },
+        ShapeRecord::StraightEdge {
+            delta_x: 0.0,
+            delta_y: size,
+        },
+        ShapeRecord::StraightEdge {
+            delta_x: -size,
+            delta_y: 0.0,
+        },
+        ShapeRecord::StraightEdge {
+            delta_x: 0.0,
+            delta_y: -size,
+        },
+    ]
+}
+
+/// Returns a glyph with a square outline on the baseline and, optionally, a square hole.
+#[allow(dead_code)]
+pub fn square_glyph(code: u16, size: f32, hole: bool) -> Glyph {
+    let mut shape_records = square_records(0.0, 0.0, size, None);
+    if hole {
+        shape_records.extend(square_records(size / 4.0, size / 4.0, size / 2.0, None));
+    }
+    // Glyphs are drawn upwards from the baseline.
+    for record in &mut shape_records {
+        match *record {
+            ShapeRecord::StyleChange(StyleChangeData {
+                move_to: Some((_, ref mut y)),
+                ..
+            })
+            | ShapeRecord::StraightEdge {
+                delta_y: ref mut y, ..
+            } => *y = -*y,
+            _ => (),
+        }
+    }
+    Glyph {
+        shape_records,
+        code,
+        advance: Some((size * 20.0) as i16 + 100),
+        bounds: None,
+    }
+}
+
 pub type TestData<T> = (u8, T, Vec<u8>);
 pub type TagTestData = TestData<Tag>;
 pub type Avm1TestData = TestData<Action>;
+pub type Avm1ListTestData = TestData<ActionList>;
 pub type Avm2TestData = TestData<AbcFile>;
 
 pub fn tag_tests() -> Vec<TagTestData> {
@@ -2326,6 +2436,28 @@ pub fn avm1_tests() -> Vec<Avm1TestData> {
         (4, Action::AsciiToChar, vec![0x33]),
         (4, Action::Call, vec![0x9E, 0, 0]),
         (4, Action::CharToAscii, vec![0x32]),
+        (
+            7,
+            Action::DefineFunction2(Function {
+                name: "f".to_string(),
+                params: vec![FunctionParam {
+                    name: "a".to_string(),
+                    register_index: Some(1),
+                }],
+                num_registers: 2,
+                preload_parent: false,
+                preload_root: false,
+                suppress_super: false,
+                preload_super: false,
+                suppress_arguments: false,
+                preload_arguments: false,
+                suppress_this: false,
+                preload_this: false,
+                preload_global: false,
+                actions: vec![Action::End],
+            }),
+            vec![0x8E, 12, 0, b'f', 0, 1, 0, 2, 0, 0, 1, b'a', 0, 1, 0, 0],
+        ),
         (4, Action::Divide, vec![0x0D]),
         (4, Action::Equals, vec![0x0E]),
         (4, Action::GetTime, vec![0x34]),
@@ -2348,6 +2480,22 @@ pub fn avm1_tests() -> Vec<Avm1TestData> {
         ),
         (4, Action::GetVariable, vec![0x1C]),
         (3, Action::GotoFrame(11), vec![0x81, 2, 0, 11, 0]),
+        (
+            4,
+            Action::GotoFrame2 {
+                set_playing: true,
+                scene_offset: 0,
+            },
+            vec![0x9F, 1, 0, 0b01],
+        ),
+        (
+            4,
+            Action::GotoFrame2 {
+                set_playing: false,
+                scene_offset: 1,
+            },
+            vec![0x9F, 3, 0, 0b10, 1, 0],
+        ),
         (
             4,
             Action::GotoFrame2 {
@@ -2369,22 +2517,6 @@ pub fn avm1_tests() -> Vec<Avm1TestData> {
             Action::GotoLabel("testb".to_string()),
             vec![0x8C, 6, 0, 116, 101, 115, 116, 98, 0],
         ),
-        (
-            4,
-            Action::If {
-                offset: 1,
-                jump_to: 0,
-            },
-            vec![0x9D, 2, 0, 1, 0],
-        ),
-        (
-            4,
-            Action::Jump {
-                offset: 1,
-                jump_to: 0,
-            },
-            vec![0x99, 2, 0, 1, 0],
-        ),
         (4, Action::Less, vec![0x0F]),
         (4, Action::MBAsciiToChar, vec![0x37]),
         (4, Action::MBCharToAscii, vec![0x36]),
@@ -2507,6 +2639,21 @@ pub fn avm1_tests() -> Vec<Avm1TestData> {
     ]
 }
 
+pub fn avm1_list_tests() -> Vec<Avm1ListTestData> {
+    vec![
+        (
+            4,
+            vec![Action::If { target: 0 }, Action::Stop, Action::Label(0)],
+            vec![0x9D, 2, 0, 1, 0, 0x07, 0],
+        ),
+        (
+            4,
+            vec![Action::Jump { target: 0 }, Action::Stop, Action::Label(0)],
+            vec![0x99, 2, 0, 1, 0, 0x07, 0],
+        ),
+    ]
+}
+
 pub fn avm2_tests() -> Vec<Avm2TestData> {
     vec![(
         10,
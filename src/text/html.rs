@@ -0,0 +1,557 @@
+//! Parsing and writing of the HTML subset used by `DefineEditText` fields.
+//!
+//! Flash Player is lenient with this markup, and so is the parser: unknown elements and
+//! attributes are kept so that they survive being written back, unclosed elements are closed at
+//! the end of the text, and stray closing tags are ignored.
+use types::*;
+
+/// Elements that never have children.
+const VOID_ELEMENTS: [&str; 3] = ["br", "img", "sbr"];
+
+/// The formatting of a run of text, resolved from the elements around it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextFormat {
+    /// The font face from a `<font face>` element, or `None` for the field's own font.
+    pub face: Option<String>,
+    /// The font size, in twips.
+    pub height: u16,
+    pub color: Color,
+    pub is_bold: bool,
+    pub is_italic: bool,
+    pub is_underline: bool,
+    pub url: Option<String>,
+    pub target: Option<String>,
+    pub align: TextAlign,
+    // Paragraph spacing, in pixels.
+    pub left_margin: f32,
+    pub right_margin: f32,
+    pub indent: f32,
+    pub block_indent: f32,
+    pub leading: f32,
+    pub is_bullet: bool,
+}
+
+impl TextFormat {
+    /// Returns the format of text in an edit text field before any HTML is applied.
+    ///
+    /// Flash Player uses 12 point black text when the field does not set a height or color.
+    pub fn from_edit_text(edit_text: &EditText) -> TextFormat {
+        let layout = edit_text.layout.as_ref();
+        TextFormat {
+            face: None,
+            height: edit_text.height.unwrap_or(240),
+            color: edit_text.color.clone().unwrap_or(Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 255,
+            }),
+            is_bold: false,
+            is_italic: false,
+            is_underline: false,
+            url: None,
+            target: None,
+            align: layout.map(|layout| layout.align).unwrap_or(TextAlign::Left),
+            left_margin: layout.map(|layout| layout.left_margin).unwrap_or(0.0),
+            right_margin: layout.map(|layout| layout.right_margin).unwrap_or(0.0),
+            indent: layout.map(|layout| layout.indent).unwrap_or(0.0),
+            block_indent: 0.0,
+            leading: layout.map(|layout| layout.leading).unwrap_or(0.0),
+            is_bullet: false,
+        }
+    }
+
+    /// Returns the format of the children of an element with this format.
+    fn apply(&self, name: &str, attributes: &[(String, String)]) -> TextFormat {
+        let mut format = self.clone();
+        let name = name.to_ascii_lowercase();
+        match &name[..] {
+            "b" => format.is_bold = true,
+            "i" => format.is_italic = true,
+            "u" => format.is_underline = true,
+            "li" => format.is_bullet = true,
+            _ => (),
+        }
+        for (key, value) in attributes {
+            match (&name[..], &key.to_ascii_lowercase()[..]) {
+                ("p", "align") => {
+                    format.align = match &value.to_ascii_lowercase()[..] {
+                        "left" => TextAlign::Left,
+                        "center" => TextAlign::Center,
+                        "right" => TextAlign::Right,
+                        "justify" => TextAlign::Justify,
+                        _ => format.align,
+                    }
+                }
+                ("font", "face") => format.face = Some(value.clone()),
+                ("font", "size") => {
+                    if let Some(height) = parse_font_size(value, format.height) {
+                        format.height = height;
+                    }
+                }
+                ("font", "color") => {
+                    if let Some(color) = parse_color(value) {
+                        format.color = color;
+                    }
+                }
+                ("a", "href") => format.url = Some(value.clone()),
+                ("a", "target") => format.target = Some(value.clone()),
+                ("textformat", attribute) => {
+                    let value = match value.trim().parse::<f32>() {
+                        Ok(value) => value,
+                        Err(_) => continue,
+                    };
+                    match attribute {
+                        "leftmargin" => format.left_margin = value,
+                        "rightmargin" => format.right_margin = value,
+                        "indent" => format.indent = value,
+                        "blockindent" => format.block_indent = value,
+                        "leading" => format.leading = value,
+                        _ => (),
+                    }
+                }
+                _ => (),
+            }
+        }
+        format
+    }
+}
+
+/// A node of a parsed HTML text field.
+#[derive(Clone, Debug, PartialEq)]
+pub enum HtmlNode {
+    /// An element, with its name and attributes as written.
+    Element {
+        name: String,
+        attributes: Vec<(String, String)>,
+        children: Vec<HtmlNode>,
+    },
+    /// Text with entities decoded, and the format resolved from the enclosing elements.
+    Text { text: String, format: TextFormat },
+}
+
+impl EditText {
+    /// Parses the initial text of an HTML text field.
+    ///
+    /// Returns `None` if the field is not an HTML field or has no initial text.
+    pub fn html_nodes(&self) -> Option<Vec<HtmlNode>> {
+        match self.initial_text {
+            Some(ref text) if self.is_html => {
+                Some(parse_html(text, &TextFormat::from_edit_text(self)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// An element whose closing tag has not been parsed yet.
+struct OpenElement {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<HtmlNode>,
+    /// The format of the element's children.
+    format: TextFormat,
+}
+
+/// Parses Flash HTML text, resolving the format of each text node from `default_format`.
+pub fn parse_html(html: &str, default_format: &TextFormat) -> Vec<HtmlNode> {
+    // The bottom of the stack is a root element holding the top-level nodes.
+    let mut stack = vec![OpenElement {
+        name: String::new(),
+        attributes: vec![],
+        children: vec![],
+        format: default_format.clone(),
+    }];
+    let mut rest = html;
+    while !rest.is_empty() {
+        // A '<' that does not start a name or is not closed by a '>' is text.
+        let tag = rest.strip_prefix('<').and_then(|after| {
+            let is_name = after
+                .chars()
+                .next()
+                .map(|c| c.is_ascii_alphabetic() || c == '/' || c == '!' || c == '?')
+                .unwrap_or(false);
+            let end = after.find('>')?;
+            if is_name && !after[..end].contains('<') {
+                Some((&after[..end], end + 2))
+            } else {
+                None
+            }
+        });
+        let (tag, tag_len) = match tag {
+            Some(tag) => tag,
+            None => {
+                let end = match rest.strip_prefix('<') {
+                    Some(after) => after.find('<').map(|i| i + 1),
+                    None => rest.find('<'),
+                };
+                let end = end.unwrap_or(rest.len());
+                let parent = stack.last_mut().unwrap();
+                let text = decode_entities(&rest[..end]);
+                match parent.children.last_mut() {
+                    Some(&mut HtmlNode::Text {
+                        text: ref mut prev, ..
+                    }) => prev.push_str(&text),
+                    _ => parent.children.push(HtmlNode::Text {
+                        text,
+                        format: parent.format.clone(),
+                    }),
+                }
+                rest = &rest[end..];
+                continue;
+            }
+        };
+        rest = &rest[tag_len..];
+
+        if let Some(name) = tag.strip_prefix('/') {
+            let name = name.trim();
+            if let Some(depth) = stack
+                .iter()
+                .skip(1)
+                .rposition(|element| element.name.eq_ignore_ascii_case(name))
+            {
+                while stack.len() > depth + 1 {
+                    close_element(&mut stack);
+                }
+            }
+            continue;
+        }
+        if tag.starts_with('!') || tag.starts_with('?') {
+            // Comments and declarations are not supported by Flash Player.
+            continue;
+        }
+
+        let is_self_closing = tag.ends_with('/');
+        let tag = tag.trim_end_matches('/');
+        let name_end = tag.find(|c: char| c.is_whitespace()).unwrap_or(tag.len());
+        let name = tag[..name_end].to_string();
+        let attributes = parse_attributes(&tag[name_end..]);
+        let is_void = VOID_ELEMENTS
+            .iter()
+            .any(|void| name.eq_ignore_ascii_case(void));
+        let format = stack.last().unwrap().format.apply(&name, &attributes);
+        stack.push(OpenElement {
+            name,
+            attributes,
+            children: vec![],
+            format,
+        });
+        if is_self_closing || is_void {
+            close_element(&mut stack);
+        }
+    }
+    while stack.len() > 1 {
+        close_element(&mut stack);
+    }
+    stack.pop().unwrap().children
+}
+
+fn close_element(stack: &mut Vec<OpenElement>) {
+    let element = stack.pop().unwrap();
+    stack.last_mut().unwrap().children.push(HtmlNode::Element {
+        name: element.name,
+        attributes: element.attributes,
+        children: element.children,
+    });
+}
+
+fn parse_attributes(mut text: &str) -> Vec<(String, String)> {
+    let mut attributes = vec![];
+    loop {
+        text = text.trim_start();
+        let name_end = text
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(text.len());
+        if name_end == 0 {
+            break;
+        }
+        let name = text[..name_end].to_string();
+        text = text[name_end..].trim_start();
+        if !text.starts_with('=') {
+            attributes.push((name, String::new()));
+            continue;
+        }
+        text = text[1..].trim_start();
+        let value = match text.chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => {
+                let end = text[1..].find(quote).map(|i| i + 1).unwrap_or(text.len());
+                let value = &text[1..end];
+                text = &text[(end + 1).min(text.len())..];
+                value
+            }
+            _ => {
+                let end = text.find(|c: char| c.is_whitespace()).unwrap_or(text.len());
+                let value = &text[..end];
+                text = &text[end..];
+                value
+            }
+        };
+        attributes.push((name, decode_entities(value)));
+    }
+    attributes
+}
+
+/// Parses a `<font size>` value in points, which may be relative to the current size.
+fn parse_font_size(value: &str, height: u16) -> Option<u16> {
+    let value = value.trim();
+    let size: f32 = value.trim_start_matches('+').parse().ok()?;
+    let points = if value.starts_with('+') || value.starts_with('-') {
+        f32::from(height) / 20.0 + size
+    } else {
+        size
+    };
+    Some((points * 20.0).round().clamp(0.0, f32::from(u16::MAX)) as u16)
+}
+
+/// Parses a `#RRGGBB` color.
+fn parse_color(value: &str) -> Option<Color> {
+    let value = value.trim();
+    if !value.starts_with('#') || value.len() != 7 {
+        return None;
+    }
+    let rgb = u32::from_str_radix(&value[1..], 16).ok()?;
+    Some(Color {
+        r: (rgb >> 16) as u8,
+        g: (rgb >> 8) as u8,
+        b: rgb as u8,
+        a: 255,
+    })
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "lt" => '<',
+                "gt" => '>',
+                "amp" => '&',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => '\u{a0}',
+                _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+                    ::std::char::from_u32(u32::from_str_radix(&entity[2..], 16).ok()?)?
+                }
+                _ if entity.starts_with('#') => ::std::char::from_u32(entity[1..].parse().ok()?)?,
+                _ => return None,
+            };
+            Some((c, end + 1))
+        });
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn encode_entities(text: &str, out: &mut String, is_attribute: bool) {
+    for c in text.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' if is_attribute => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+/// Writes HTML nodes back to Flash HTML text.
+///
+/// Elements and attributes keep their names and order, but the markup is normalized: empty void
+/// elements such as `<BR>` are written as `<BR/>`, attributes without a value are written as
+/// `name=""`, and special characters are escaped as entities. The formats of text nodes are
+/// ignored.
+pub fn write_html(nodes: &[HtmlNode]) -> String {
+    let mut out = String::new();
+    write_nodes(nodes, &mut out);
+    out
+}
+
+fn write_nodes(nodes: &[HtmlNode], out: &mut String) {
+    for node in nodes {
+        match *node {
+            HtmlNode::Element {
+                ref name,
+                ref attributes,
+                ref children,
+            } => {
+                out.push('<');
+                out.push_str(name);
+                for (key, value) in attributes {
+                    out.push(' ');
+                    out.push_str(key);
+                    out.push_str("=\"");
+                    encode_entities(value, out, true);
+                    out.push('"');
+                }
+                let is_void = VOID_ELEMENTS
+                    .iter()
+                    .any(|void| name.eq_ignore_ascii_case(void));
+                if is_void && children.is_empty() {
+                    out.push_str("/>");
+                } else {
+                    out.push('>');
+                    write_nodes(children, out);
+                    out.push_str("</");
+                    out.push_str(name);
+                    out.push('>');
+                }
+            }
+            HtmlNode::Text { ref text, .. } => encode_entities(text, out, false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit_text(initial_text: &str) -> EditText {
+        EditText {
+            id: 1,
+            bounds: Rectangle {
+                x_min: 0.0,
+                x_max: 100.0,
+                y_min: 0.0,
+                y_max: 20.0,
+            },
+            font_id: Some(2),
+            font_class_name: None,
+            height: Some(360),
+            color: Some(Color {
+                r: 0,
+                g: 0,
+                b: 255,
+                a: 255,
+            }),
+            max_length: None,
+            layout: Some(TextLayout {
+                align: TextAlign::Right,
+                left_margin: 1.0,
+                right_margin: 2.0,
+                indent: 3.0,
+                leading: 4.0,
+            }),
+            variable_name: String::new(),
+            initial_text: Some(initial_text.to_string()),
+            is_word_wrap: false,
+            is_multiline: true,
+            is_password: false,
+            is_read_only: false,
+            is_auto_size: false,
+            is_selectable: true,
+            has_border: false,
+            was_static: false,
+            is_html: true,
+            is_device_font: false,
+        }
+    }
+
+    /// Returns the text nodes of a tree, in order.
+    fn text_nodes(nodes: &[HtmlNode]) -> Vec<(&str, &TextFormat)> {
+        let mut out = vec![];
+        for node in nodes {
+            match *node {
+                HtmlNode::Element { ref children, .. } => out.extend(text_nodes(children)),
+                HtmlNode::Text {
+                    ref text,
+                    ref format,
+                } => out.push((&text[..], format)),
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn resolve_formats() {
+        let html = "<P ALIGN=\"CENTER\"><FONT FACE=\"Arial\" SIZE=\"+2\" COLOR=\"#FF0000\">\
+                    Hello <B>bold <I>world</I></B></FONT></P>\
+                    <TEXTFORMAT LEADING='6'><LI><A HREF=\"http://example.com\" TARGET=\"_blank\">\
+                    &lt;link&gt;&#x263a;</A></LI></TEXTFORMAT>plain";
+        let field = edit_text(html);
+        let nodes = field.html_nodes().unwrap();
+        let texts = text_nodes(&nodes);
+        let strings: Vec<_> = texts.iter().map(|&(text, _)| text).collect();
+        assert_eq!(
+            strings,
+            vec!["Hello ", "bold ", "world", "<link>\u{263a}", "plain"]
+        );
+
+        let default = TextFormat::from_edit_text(&field);
+        assert_eq!(default.height, 360);
+        assert_eq!(default.align, TextAlign::Right);
+        assert_eq!(default.leading, 4.0);
+
+        let hello = texts[0].1;
+        assert_eq!(hello.face, Some("Arial".to_string()));
+        assert_eq!(hello.height, 400);
+        assert_eq!(hello.color.r, 255);
+        assert_eq!(hello.align, TextAlign::Center);
+        assert!(!hello.is_bold);
+        let world = texts[2].1;
+        assert!(world.is_bold && world.is_italic);
+        let link = texts[3].1;
+        assert_eq!(link.url, Some("http://example.com".to_string()));
+        assert_eq!(link.target, Some("_blank".to_string()));
+        assert!(link.is_bullet);
+        assert_eq!(link.leading, 6.0);
+        assert_eq!(link.align, TextAlign::Right);
+        assert_eq!(texts[4].1, &default);
+    }
+
+    #[test]
+    fn lenient_parsing() {
+        let format = TextFormat::from_edit_text(&edit_text(""));
+        let nodes = parse_html("<b>a</i>b<br>c < d &bogus; <u>e", &format);
+        assert_eq!(
+            write_html(&nodes),
+            "<b>ab<br/>c &lt; d &amp;bogus; <u>e</u></b>"
+        );
+        assert_eq!(edit_text("").html_nodes(), Some(vec![]));
+        let mut plain = edit_text("<b>a</b>");
+        plain.is_html = false;
+        assert_eq!(plain.html_nodes(), None);
+    }
+
+    #[test]
+    fn replace_text() {
+        let html = "<P ALIGN=\"LEFT\"><FONT FACE=\"Times\" SIZE=\"12\">Hello &amp; \
+                    welcome</FONT></P><P ALIGN=\"LEFT\"><A HREF=\"a?b=1&amp;c=&quot;2&quot;\">\
+                    Click</A><BR/></P>";
+        let format = TextFormat::from_edit_text(&edit_text(""));
+        let mut nodes = parse_html(html, &format);
+        assert_eq!(write_html(&nodes), html);
+
+        if let HtmlNode::Element {
+            ref mut children, ..
+        } = nodes[0]
+        {
+            if let HtmlNode::Element {
+                ref mut children, ..
+            } = children[0]
+            {
+                children[0] = HtmlNode::Text {
+                    text: "Bonjour <3".to_string(),
+                    format,
+                };
+            }
+        }
+        assert_eq!(
+            write_html(&nodes),
+            html.replace("Hello &amp; welcome", "Bonjour &lt;3")
+        );
+    }
+}
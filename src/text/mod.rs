@@ -0,0 +1,8 @@
+//! Text extraction and layout for `DefineText` and `DefineEditText` characters.
+mod html;
+mod outline;
+mod static_text;
+
+pub use self::html::{parse_html, write_html, HtmlNode, TextFormat};
+pub use self::outline::{edit_text_to_shape, text_to_shape};
+pub use self::static_text::{static_text_runs, text_runs, TextRun};
@@ -0,0 +1,654 @@
+//! Conversion of text characters to shapes using the outlines of their embedded fonts.
+use super::static_text::local_text_runs;
+use font::{code_to_char, embedded_fonts, EmbeddedFont};
+use std::collections::HashMap;
+use types::*;
+
+/// Distance, in pixels, between the bounds of an edit text field and its text.
+const GUTTER: f32 = 2.0;
+
+/// Converts a `DefineText` character to a shape, using the fonts defined in `tags`.
+///
+/// The shape is in the coordinate space of the text's parent: the text's matrix is applied to
+/// the glyphs. Glyphs whose font is not defined in `tags` are left out.
+pub fn text_to_shape(text: &Text, tags: &[Tag]) -> Shape {
+    let fonts = embedded_fonts(tags);
+    let mut builder = ShapeBuilder::new(text.matrix);
+    for run in local_text_runs(text, &fonts) {
+        let font = match fonts.iter().find(|font| font.font.id == run.font_id) {
+            Some(font) => &font.font,
+            None => continue,
+        };
+        let scale = glyph_scale(font, run.height);
+        let mut x = run.x;
+        for entry in &run.glyphs {
+            if let Some(glyph) = font.glyphs.get(entry.index as usize) {
+                builder.add_glyph(glyph, x, run.y, scale, &run.color);
+            }
+            x += entry.advance as f32 / 20.0;
+        }
+    }
+    builder.into_shape(text.id)
+}
+
+/// Converts the initial text of a plain `DefineEditText` field to a shape, using the fonts
+/// defined in `tags`.
+///
+/// The text is laid out as Flash Player does, using the field's `TextLayout` and the font's
+/// advances, kerning, ascent and descent: lines are broken at newlines and, for word-wrapped
+/// fields, at the last space that fits. Returns `None` for HTML fields and for fields whose
+/// font has no outlines in `tags`.
+pub fn edit_text_to_shape(edit_text: &EditText, tags: &[Tag]) -> Option<Shape> {
+    if edit_text.is_html {
+        return None;
+    }
+    let fonts = embedded_fonts(tags);
+    let font_id = edit_text.font_id?;
+    let font = fonts
+        .iter()
+        .find(|font| font.font.id == font_id && !font.font.glyphs.is_empty())?;
+    let height = edit_text.height.unwrap_or(240);
+    let color = edit_text.color.clone().unwrap_or(Color {
+        r: 0,
+        g: 0,
+        b: 0,
+        a: 255,
+    });
+    let layout = edit_text.layout.clone().unwrap_or(TextLayout {
+        align: TextAlign::Left,
+        left_margin: 0.0,
+        right_margin: 0.0,
+        indent: 0.0,
+        leading: 0.0,
+    });
+    let text = match edit_text.initial_text {
+        Some(ref text) if edit_text.is_password => text
+            .chars()
+            .map(|c| if c == '\r' || c == '\n' { c } else { '*' })
+            .collect(),
+        Some(ref text) => text.clone(),
+        None => String::new(),
+    };
+
+    let metrics = FontMetrics::new(font, height);
+    let bounds = &edit_text.bounds;
+    let left = bounds.x_min + GUTTER + layout.left_margin;
+    let right = bounds.x_max - GUTTER - layout.right_margin;
+    let mut builder = ShapeBuilder::new(Matrix::new());
+    let mut y = bounds.y_min + GUTTER + metrics.ascent;
+    let paragraphs = text.split("\r\n").flat_map(|text| text.split(['\r', '\n']));
+    for paragraph in paragraphs {
+        let chars = metrics.layout_chars(paragraph);
+        let lines = if edit_text.is_word_wrap {
+            wrap_lines(&chars, right - left, layout.indent)
+        } else {
+            wrap_lines(&chars, f32::INFINITY, 0.0)
+        };
+        for (i, line) in lines.iter().enumerate() {
+            let chars = &chars[line.clone()];
+            let is_last_line = i + 1 == lines.len();
+            let indent = if i == 0 { layout.indent } else { 0.0 };
+            let width = line_width(chars);
+            let free_space = right - left - indent - width;
+            let mut x = left
+                + indent
+                + match layout.align {
+                    TextAlign::Left | TextAlign::Justify => 0.0,
+                    TextAlign::Center => free_space / 2.0,
+                    TextAlign::Right => free_space,
+                };
+            let num_spaces = chars.iter().filter(|c| c.is_space).count();
+            let space_stretch = if layout.align == TextAlign::Justify
+                && !is_last_line
+                && num_spaces > 0
+                && free_space > 0.0
+            {
+                free_space / num_spaces as f32
+            } else {
+                0.0
+            };
+            for c in chars {
+                if let Some(glyph) = c.glyph {
+                    builder.add_glyph(glyph, x, y, metrics.scale, &color);
+                }
+                x += c.advance;
+                if c.is_space {
+                    x += space_stretch;
+                }
+            }
+            y += metrics.line_height(layout.leading);
+        }
+        if lines.is_empty() {
+            y += metrics.line_height(layout.leading);
+        }
+    }
+    Some(builder.into_shape(edit_text.id))
+}
+
+/// Returns the factor that scales glyph shape records to a font size, in twips.
+///
+/// The EM square of `DefineFont3` glyphs is 20 times larger than that of older fonts.
+fn glyph_scale(font: &Font, height: u16) -> f32 {
+    let em = if font.version >= 3 { 20480.0 } else { 1024.0 };
+    f32::from(height) / em
+}
+
+/// A character laid out on a line.
+struct LaidOutChar<'a> {
+    glyph: Option<&'a Glyph>,
+    /// The advance, including kerning with the next character, in pixels.
+    advance: f32,
+    is_space: bool,
+}
+
+/// The scaled metrics of a font at a font size.
+struct FontMetrics<'a> {
+    scale: f32,
+    /// The font size, in pixels.
+    em: f32,
+    ascent: f32,
+    descent: f32,
+    glyphs: HashMap<char, &'a Glyph>,
+    kerning: HashMap<(u16, u16), i16>,
+}
+
+impl<'a> FontMetrics<'a> {
+    fn new(font: &'a EmbeddedFont, height: u16) -> FontMetrics<'a> {
+        let font = &font.font;
+        let scale = glyph_scale(font, height);
+        let glyphs = font
+            .glyphs
+            .iter()
+            .filter_map(|glyph| code_to_char(font, glyph.code).map(|c| (c, glyph)))
+            .collect();
+        let (ascent, descent, kerning) = match font.layout {
+            Some(ref layout) => (
+                f32::from(layout.ascent) / 20.0 * scale,
+                f32::from(layout.descent) / 20.0 * scale,
+                layout
+                    .kerning
+                    .iter()
+                    .map(|record| ((record.left_code, record.right_code), record.adjustment))
+                    .collect(),
+            ),
+            // Fonts without layout information use typical proportions of the EM square.
+            None => (
+                f32::from(height) / 20.0 * 0.8,
+                f32::from(height) / 20.0 * 0.2,
+                HashMap::new(),
+            ),
+        };
+        FontMetrics {
+            scale,
+            em: f32::from(height) / 20.0,
+            ascent,
+            descent,
+            glyphs,
+            kerning,
+        }
+    }
+
+    fn line_height(&self, leading: f32) -> f32 {
+        self.ascent + self.descent + leading
+    }
+
+    /// Returns the glyphs and advances of a paragraph. Characters without a glyph are skipped,
+    /// except for spaces, which are often not included in embedded fonts.
+    fn layout_chars(&self, text: &str) -> Vec<LaidOutChar<'a>> {
+        let mut chars: Vec<LaidOutChar<'a>> = vec![];
+        let mut prev_code = None;
+        for c in text.chars() {
+            let glyph = self.glyphs.get(&c).cloned();
+            let advance = match glyph {
+                Some(glyph) => self.glyph_advance(glyph),
+                None if c == ' ' => self.em / 4.0,
+                None => continue,
+            };
+            let code = glyph.map(|glyph| glyph.code);
+            if let (Some(prev_code), Some(code), Some(last)) = (prev_code, code, chars.last_mut()) {
+                if let Some(&adjustment) = self.kerning.get(&(prev_code, code)) {
+                    last.advance += f32::from(adjustment) / 20.0 * self.scale;
+                }
+            }
+            prev_code = code;
+            chars.push(LaidOutChar {
+                glyph,
+                advance,
+                is_space: c == ' ',
+            });
+        }
+        chars
+    }
+
+    /// Returns the advance of a glyph, in pixels. Glyphs without an advance use the width of
+    /// their bounds.
+    fn glyph_advance(&self, glyph: &Glyph) -> f32 {
+        match (glyph.advance, glyph.bounds.as_ref()) {
+            (Some(advance), _) => f32::from(advance) / 20.0 * self.scale,
+            (None, Some(bounds)) => bounds.x_max / 20.0 * self.scale,
+            (None, None) => {
+                let mut x = 0.0f32;
+                let mut x_max = 0.0f32;
+                for record in &glyph.shape_records {
+                    match *record {
+                        ShapeRecord::StyleChange(StyleChangeData {
+                            move_to: Some((move_x, _)),
+                            ..
+                        }) => x = move_x,
+                        ShapeRecord::StraightEdge { delta_x, .. } => x += delta_x,
+                        ShapeRecord::CurvedEdge {
+                            control_delta_x,
+                            anchor_delta_x,
+                            ..
+                        } => x += control_delta_x + anchor_delta_x,
+                        _ => (),
+                    }
+                    x_max = x_max.max(x);
+                }
+                x_max * self.scale
+            }
+        }
+    }
+}
+
+/// Returns the width of a line, not counting trailing spaces.
+fn line_width(chars: &[LaidOutChar]) -> f32 {
+    let end = chars.iter().rposition(|c| !c.is_space).map_or(0, |i| i + 1);
+    chars[..end].iter().map(|c| c.advance).sum()
+}
+
+/// Breaks a paragraph into lines that fit in `width`, after the last space that fits, or
+/// between characters if a word is wider than a line.
+fn wrap_lines(chars: &[LaidOutChar], width: f32, indent: f32) -> Vec<::std::ops::Range<usize>> {
+    let mut lines = vec![];
+    let mut start = 0;
+    while start < chars.len() {
+        let available = if lines.is_empty() {
+            width - indent
+        } else {
+            width
+        };
+        let mut end = start;
+        let mut line_break = None;
+        let mut x = 0.0;
+        while end < chars.len() {
+            let c = &chars[end];
+            if !c.is_space && x + c.advance > available && end > start {
+                break;
+            }
+            x += c.advance;
+            end += 1;
+            if c.is_space {
+                line_break = Some(end);
+            }
+        }
+        if end < chars.len() {
+            if let Some(line_break) = line_break {
+                end = line_break;
+            }
+        }
+        lines.push(start..end);
+        start = end;
+    }
+    lines
+}
+
+/// Builds a shape from transformed glyph outlines.
+struct ShapeBuilder {
+    matrix: Matrix,
+    fill_styles: Vec<FillStyle>,
+    records: Vec<ShapeRecord>,
+    /// The current position, in twips.
+    pen: (i32, i32),
+    /// The bounds of the shape, in twips.
+    bounds: Option<(f32, f32, f32, f32)>,
+}
+
+impl ShapeBuilder {
+    fn new(matrix: Matrix) -> ShapeBuilder {
+        ShapeBuilder {
+            matrix,
+            fill_styles: vec![],
+            records: vec![],
+            pen: (0, 0),
+            bounds: None,
+        }
+    }
+
+    /// Adds a glyph with its origin at `(x, y)` and its outline scaled by `scale`.
+    ///
+    /// The glyph's edges keep their fill sides, so the shape is filled as the glyph is.
+    fn add_glyph(&mut self, glyph: &Glyph, x: f32, y: f32, scale: f32, color: &Color) {
+        let fill_style = match self
+            .fill_styles
+            .iter()
+            .position(|style| *style == FillStyle::Color(color.clone()))
+        {
+            Some(i) => i as u32 + 1,
+            None => {
+                self.fill_styles.push(FillStyle::Color(color.clone()));
+                self.fill_styles.len() as u32
+            }
+        };
+        // A mirroring matrix swaps the sides of the edges.
+        let matrix = &self.matrix;
+        let is_mirrored =
+            matrix.scale_x * matrix.scale_y - matrix.rotate_skew_0 * matrix.rotate_skew_1 < 0.0;
+        let to_twips = |glyph_x: f32, glyph_y: f32| {
+            let (x, y) = matrix.transform_point(x + glyph_x * scale, y + glyph_y * scale);
+            ((x * 20.0).round() as i32, (y * 20.0).round() as i32)
+        };
+
+        let mut glyph_pos = (0.0, 0.0);
+        let mut fills = (0, 0);
+        let mut needs_move = true;
+        let mut records = vec![];
+        for record in &glyph.shape_records {
+            match *record {
+                ShapeRecord::StyleChange(ref style_change) => {
+                    if let Some(fill) = style_change.fill_style_0 {
+                        fills.0 = fill;
+                    }
+                    if let Some(fill) = style_change.fill_style_1 {
+                        fills.1 = fill;
+                    }
+                    if let Some(move_to) = style_change.move_to {
+                        glyph_pos = move_to;
+                    }
+                    needs_move = true;
+                }
+                ShapeRecord::StraightEdge { delta_x, delta_y } => {
+                    let start = to_twips(glyph_pos.0, glyph_pos.1);
+                    glyph_pos = (glyph_pos.0 + delta_x, glyph_pos.1 + delta_y);
+                    records.push((
+                        needs_move,
+                        fills,
+                        start,
+                        None,
+                        to_twips(glyph_pos.0, glyph_pos.1),
+                    ));
+                    needs_move = false;
+                }
+                ShapeRecord::CurvedEdge {
+                    control_delta_x,
+                    control_delta_y,
+                    anchor_delta_x,
+                    anchor_delta_y,
+                } => {
+                    let start = to_twips(glyph_pos.0, glyph_pos.1);
+                    let control = (glyph_pos.0 + control_delta_x, glyph_pos.1 + control_delta_y);
+                    glyph_pos = (control.0 + anchor_delta_x, control.1 + anchor_delta_y);
+                    records.push((
+                        needs_move,
+                        fills,
+                        start,
+                        Some(to_twips(control.0, control.1)),
+                        to_twips(glyph_pos.0, glyph_pos.1),
+                    ));
+                    needs_move = false;
+                }
+            }
+        }
+
+        for (needs_move, fills, start, control, anchor) in records {
+            if needs_move {
+                let fill = |style: u32| if style == 0 { 0 } else { fill_style };
+                let (fill_0, fill_1) = if is_mirrored {
+                    (fill(fills.1), fill(fills.0))
+                } else {
+                    (fill(fills.0), fill(fills.1))
+                };
+                self.records.push(ShapeRecord::StyleChange(StyleChangeData {
+                    move_to: Some((start.0 as f32 / 20.0, start.1 as f32 / 20.0)),
+                    fill_style_0: Some(fill_0),
+                    fill_style_1: Some(fill_1),
+                    line_style: None,
+                    new_styles: None,
+                }));
+                self.pen = start;
+                self.include_point(start);
+            }
+            self.add_edge(control, anchor);
+        }
+    }
+
+    fn add_edge(&mut self, control: Option<(i32, i32)>, anchor: (i32, i32)) {
+        let start = self.pen;
+        let delta = |from: (i32, i32), to: (i32, i32)| {
+            ((to.0 - from.0) as f32 / 20.0, (to.1 - from.1) as f32 / 20.0)
+        };
+        match control {
+            Some(control) if control != start && control != anchor => {
+                let (control_delta_x, control_delta_y) = delta(start, control);
+                let (anchor_delta_x, anchor_delta_y) = delta(control, anchor);
+                self.records.push(ShapeRecord::CurvedEdge {
+                    control_delta_x,
+                    control_delta_y,
+                    anchor_delta_x,
+                    anchor_delta_y,
+                });
+                let extremum = |p0: i32, p1: i32, p2: i32| {
+                    let denominator = (p0 - 2 * p1 + p2) as f32;
+                    let t = (p0 - p1) as f32 / denominator;
+                    if denominator != 0.0 && t > 0.0 && t < 1.0 {
+                        let s = 1.0 - t;
+                        Some(s * s * p0 as f32 + 2.0 * s * t * p1 as f32 + t * t * p2 as f32)
+                    } else {
+                        None
+                    }
+                };
+                let on_curve = |p0: i32, p1: i32, p2: i32, other: i32| {
+                    extremum(p0, p1, p2).map(|value| (value, other as f32))
+                };
+                if let Some((x, y)) = on_curve(start.0, control.0, anchor.0, anchor.1) {
+                    self.include(x, y);
+                }
+                if let Some((y, x)) = on_curve(start.1, control.1, anchor.1, anchor.0) {
+                    self.include(x, y);
+                }
+            }
+            _ if anchor != start => {
+                let (delta_x, delta_y) = delta(start, anchor);
+                self.records
+                    .push(ShapeRecord::StraightEdge { delta_x, delta_y });
+            }
+            _ => return,
+        }
+        self.pen = anchor;
+        self.include_point(anchor);
+    }
+
+    fn include_point(&mut self, (x, y): (i32, i32)) {
+        self.include(x as f32, y as f32);
+    }
+
+    fn include(&mut self, x: f32, y: f32) {
+        self.bounds = Some(match self.bounds {
+            Some((x_min, y_min, x_max, y_max)) => {
+                (x_min.min(x), y_min.min(y), x_max.max(x), y_max.max(y))
+            }
+            None => (x, y, x, y),
+        });
+    }
+
+    fn into_shape(self, id: CharacterId) -> Shape {
+        let (x_min, y_min, x_max, y_max) = self.bounds.unwrap_or((0.0, 0.0, 0.0, 0.0));
+        let bounds = Rectangle {
+            x_min: x_min.floor() / 20.0,
+            x_max: x_max.ceil() / 20.0,
+            y_min: y_min.floor() / 20.0,
+            y_max: y_max.ceil() / 20.0,
+        };
+        Shape {
+            version: 3,
+            id,
+            shape_bounds: bounds.clone(),
+            edge_bounds: bounds,
+            has_fill_winding_rule: false,
+            has_non_scaling_strokes: false,
+            has_scaling_strokes: false,
+            styles: ShapeStyles {
+                fill_styles: self.fill_styles,
+                line_styles: vec![],
+            },
+            shape: self.records,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_data::{rectangle, square_records};
+
+    /// Returns a `DefineFont3` font with a square glyph for 'a', half an EM wide.
+    fn square_font() -> Tag {
+        let glyph = Glyph {
+            shape_records: square_records(0.0, -512.0, 512.0, None),
+            code: 97,
+            advance: Some(10240),
+            bounds: None,
+        };
+        Tag::DefineFont2(Box::new(Font {
+            version: 3,
+            id: 1,
+            name: "Square".to_string(),
+            language: Language::Latin,
+            layout: Some(FontLayout {
+                ascent: 16384,
+                descent: 4096,
+                leading: 0,
+                kerning: vec![KerningRecord {
+                    left_code: 97,
+                    right_code: 97,
+                    adjustment: -1024,
+                }],
+            }),
+            glyphs: vec![glyph],
+            is_small_text: false,
+            is_shift_jis: false,
+            is_ansi: false,
+            is_bold: false,
+            is_italic: false,
+        }))
+    }
+
+    #[test]
+    fn static_text_shape() {
+        let color = Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let text = Text {
+            id: 2,
+            bounds: rectangle(0.0, 100.0, 0.0, 100.0),
+            matrix: Matrix {
+                translate_x: 10.0,
+                translate_y: 20.0,
+                ..Matrix::new()
+            },
+            records: vec![TextRecord {
+                font_id: Some(1),
+                color: Some(color.clone()),
+                x_offset: Some(0.0),
+                y_offset: Some(0.0),
+                height: Some(400),
+                glyphs: vec![
+                    GlyphEntry {
+                        index: 0,
+                        advance: 300,
+                    },
+                    GlyphEntry {
+                        index: 0,
+                        advance: 300,
+                    },
+                ],
+            }],
+        };
+        let shape = text_to_shape(&text, &[square_font()]);
+        assert_eq!(shape.id, 2);
+        assert_eq!(shape.shape_bounds, rectangle(10.0, 35.0, 10.0, 20.0));
+        assert_eq!(shape.styles.fill_styles, vec![FillStyle::Color(color)]);
+        assert_eq!(shape.shape.len(), 10);
+        assert_eq!(
+            shape.shape[5],
+            ShapeRecord::StyleChange(StyleChangeData {
+                move_to: Some((25.0, 10.0)),
+                fill_style_0: Some(0),
+                fill_style_1: Some(1),
+                line_style: None,
+                new_styles: None,
+            })
+        );
+
+        // Mirrored text swaps the fill sides of the edges.
+        let mut mirrored = text;
+        mirrored.matrix.scale_x = -1.0;
+        let shape = text_to_shape(&mirrored, &[square_font()]);
+        assert_eq!(shape.shape_bounds, rectangle(-15.0, 10.0, 10.0, 20.0));
+        match shape.shape[0] {
+            ShapeRecord::StyleChange(ref style_change) => {
+                assert_eq!(style_change.fill_style_0, Some(1));
+                assert_eq!(style_change.fill_style_1, Some(0));
+            }
+            _ => panic!("Expected a style change"),
+        }
+    }
+
+    #[test]
+    fn edit_text_layout() {
+        let mut edit_text = EditText {
+            id: 2,
+            bounds: rectangle(0.0, 29.0, 0.0, 100.0),
+            font_id: Some(1),
+            font_class_name: None,
+            height: Some(400),
+            color: None,
+            max_length: None,
+            layout: Some(TextLayout {
+                align: TextAlign::Right,
+                left_margin: 0.0,
+                right_margin: 0.0,
+                indent: 0.0,
+                leading: 0.0,
+            }),
+            variable_name: String::new(),
+            initial_text: Some("aa aa".to_string()),
+            is_word_wrap: true,
+            is_multiline: true,
+            is_password: false,
+            is_read_only: false,
+            is_auto_size: false,
+            is_selectable: false,
+            has_border: false,
+            was_static: false,
+            is_html: false,
+            is_device_font: false,
+        };
+        let shape = edit_text_to_shape(&edit_text, &[square_font()]).unwrap();
+        // The first line is "aa ", right-aligned without its trailing space and kerned.
+        assert_eq!(shape.shape_bounds, rectangle(8.0, 27.0, 8.0, 38.0));
+        let moves: Vec<_> = shape
+            .shape
+            .iter()
+            .filter_map(|record| match *record {
+                ShapeRecord::StyleChange(ref style_change) => style_change.move_to,
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            moves,
+            vec![(8.0, 8.0), (17.0, 8.0), (8.0, 28.0), (17.0, 28.0)]
+        );
+
+        edit_text.is_word_wrap = false;
+        edit_text.initial_text = Some("a\r\ra".to_string());
+        let shape = edit_text_to_shape(&edit_text, &[square_font()]).unwrap();
+        assert_eq!(shape.shape_bounds, rectangle(17.0, 27.0, 8.0, 58.0));
+
+        edit_text.is_html = true;
+        assert_eq!(edit_text_to_shape(&edit_text, &[square_font()]), None);
+    }
+}
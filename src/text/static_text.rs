@@ -0,0 +1,225 @@
+//! Recovers the strings and positions of the text runs in `DefineText` characters.
+use font::{code_to_char, embedded_fonts, EmbeddedFont};
+use types::*;
+
+/// A run of glyphs from a `DefineText` character that share a font, size and color.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextRun {
+    pub font_id: CharacterId,
+    /// The font size, in twips.
+    pub height: u16,
+    pub color: Color,
+    /// The position of the start of the baseline, in pixels, relative to the parent of the
+    /// text: the text's matrix has been applied.
+    pub x: f32,
+    pub y: f32,
+    /// The text, with `U+FFFD` for glyphs that have no character code.
+    pub text: String,
+    /// The glyphs of the run, in the text's coordinate space. Each glyph's position is the
+    /// run's start position, before the matrix is applied, plus the preceding advances.
+    pub glyphs: Vec<GlyphEntry>,
+}
+
+/// Returns the text runs of a `DefineText` character, in drawing order.
+///
+/// `fonts` are used to map glyph indices to characters; see `font::embedded_fonts`. Records
+/// are merged with the state of the records before them, as Flash Player does: a record
+/// without an x offset starts where the previous record's glyphs ended. Records with no glyphs
+/// or no font are skipped.
+pub fn text_runs(text: &Text, fonts: &[EmbeddedFont]) -> Vec<TextRun> {
+    let mut runs = local_text_runs(text, fonts);
+    for run in &mut runs {
+        let (x, y) = text.matrix.transform_point(run.x, run.y);
+        run.x = x;
+        run.y = y;
+    }
+    runs
+}
+
+/// Returns the text runs of a `DefineText` character, positioned in the text's coordinate
+/// space instead of its parent's.
+pub fn local_text_runs(text: &Text, fonts: &[EmbeddedFont]) -> Vec<TextRun> {
+    let mut runs = vec![];
+    let mut font_id = None;
+    let mut height = 0;
+    let mut color = Color {
+        r: 0,
+        g: 0,
+        b: 0,
+        a: 255,
+    };
+    let (mut x, mut y) = (0.0, 0.0);
+    for record in &text.records {
+        if let Some(id) = record.font_id {
+            font_id = Some(id);
+        }
+        if let Some(record_height) = record.height {
+            height = record_height;
+        }
+        if let Some(ref record_color) = record.color {
+            color = record_color.clone();
+        }
+        if let Some(x_offset) = record.x_offset {
+            x = x_offset;
+        }
+        if let Some(y_offset) = record.y_offset {
+            y = y_offset;
+        }
+
+        let font_id = match font_id {
+            Some(id) if !record.glyphs.is_empty() => id,
+            _ => continue,
+        };
+        let font = fonts.iter().find(|font| font.font.id == font_id);
+        let text_string = record
+            .glyphs
+            .iter()
+            .map(|entry| {
+                font.and_then(|font| {
+                    let glyph = font.font.glyphs.get(entry.index as usize)?;
+                    code_to_char(&font.font, glyph.code)
+                })
+                .unwrap_or('\u{fffd}')
+            })
+            .collect();
+        runs.push(TextRun {
+            font_id,
+            height,
+            color: color.clone(),
+            x,
+            y,
+            text: text_string,
+            glyphs: record.glyphs.clone(),
+        });
+        x += record.glyphs.iter().map(|entry| entry.advance).sum::<i32>() as f32 / 20.0;
+    }
+    runs
+}
+
+/// Returns the text runs of every `DefineText` character in a tag list, keyed by character ID.
+pub fn static_text_runs(tags: &[Tag]) -> Vec<(CharacterId, Vec<TextRun>)> {
+    let fonts = embedded_fonts(tags);
+    tags.iter()
+        .filter_map(|tag| match *tag {
+            Tag::DefineText(ref text) => Some((text.id, text_runs(text, &fonts))),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glyph(code: u16) -> Glyph {
+        Glyph {
+            shape_records: vec![],
+            code,
+            advance: None,
+            bounds: None,
+        }
+    }
+
+    fn entry(index: u32, advance: i32) -> GlyphEntry {
+        GlyphEntry { index, advance }
+    }
+
+    #[test]
+    fn extract_static_text() {
+        let red = Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let tags = vec![
+            Tag::DefineFont(Box::new(FontV1 {
+                id: 1,
+                glyphs: vec![vec![], vec![]],
+            })),
+            Tag::DefineFontInfo(Box::new(FontInfo {
+                id: 1,
+                version: 1,
+                name: "Arial".to_string(),
+                is_small_text: false,
+                is_shift_jis: false,
+                is_ansi: true,
+                is_bold: false,
+                is_italic: false,
+                language: Language::Unknown,
+                code_table: vec![72, 105],
+            })),
+            Tag::DefineFont2(Box::new(Font {
+                version: 2,
+                id: 2,
+                name: "Verdana".to_string(),
+                language: Language::Latin,
+                layout: None,
+                glyphs: vec![glyph(0x263a)],
+                is_small_text: false,
+                is_shift_jis: false,
+                is_ansi: false,
+                is_bold: false,
+                is_italic: false,
+            })),
+            Tag::DefineText(Box::new(Text {
+                id: 3,
+                bounds: Rectangle {
+                    x_min: 0.0,
+                    x_max: 100.0,
+                    y_min: 0.0,
+                    y_max: 100.0,
+                },
+                matrix: Matrix {
+                    translate_x: 10.0,
+                    translate_y: 5.0,
+                    ..Matrix::new()
+                },
+                records: vec![
+                    TextRecord {
+                        font_id: Some(1),
+                        color: Some(red.clone()),
+                        x_offset: Some(2.0),
+                        y_offset: Some(12.0),
+                        height: Some(240),
+                        glyphs: vec![entry(0, 100), entry(1, 60)],
+                    },
+                    TextRecord {
+                        font_id: Some(2),
+                        color: None,
+                        x_offset: None,
+                        y_offset: None,
+                        height: Some(480),
+                        glyphs: vec![entry(0, 200), entry(5, 200)],
+                    },
+                    TextRecord {
+                        font_id: Some(1),
+                        color: None,
+                        x_offset: Some(0.0),
+                        y_offset: Some(30.0),
+                        height: None,
+                        glyphs: vec![entry(1, 60)],
+                    },
+                ],
+            })),
+        ];
+
+        let texts = static_text_runs(&tags);
+        assert_eq!(texts.len(), 1);
+        assert_eq!(texts[0].0, 3);
+        let runs = &texts[0].1;
+        let summary: Vec<_> = runs
+            .iter()
+            .map(|run| (run.font_id, run.height, run.x, run.y, run.text.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1, 240, 12.0, 17.0, "Hi"),
+                (2, 480, 20.0, 17.0, "\u{263a}\u{fffd}"),
+                (1, 480, 10.0, 35.0, "i"),
+            ]
+        );
+        assert!(runs.iter().all(|run| run.color == red));
+    }
+}
@@ -0,0 +1,204 @@
+//! Playback of timeline tags into the display list shown on each frame.
+use std::collections::BTreeMap;
+use types::*;
+
+/// A character instance on the display list.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DisplayObject {
+    pub depth: Depth,
+    pub character_id: CharacterId,
+    pub matrix: Matrix,
+    pub color_transform: ColorTransform,
+    pub ratio: Option<u16>,
+    pub name: Option<String>,
+    pub clip_depth: Option<Depth>,
+    pub filters: Vec<Filter>,
+    pub blend_mode: BlendMode,
+    /// The index of the frame on which this instance was placed. A `Replace` keeps the
+    /// instance, while placing a new character at the depth creates a new one.
+    pub place_frame: usize,
+}
+
+impl DisplayObject {
+    fn new(depth: Depth, character_id: CharacterId, place_frame: usize) -> DisplayObject {
+        DisplayObject {
+            depth,
+            character_id,
+            matrix: Matrix::new(),
+            color_transform: ColorTransform::new(),
+            ratio: None,
+            name: None,
+            clip_depth: None,
+            filters: vec![],
+            blend_mode: BlendMode::Normal,
+            place_frame,
+        }
+    }
+
+    /// Applies the properties set by a `PlaceObject` tag.
+    ///
+    /// `PlaceObject3` cannot distinguish an empty filter list or a `Normal` blend mode from
+    /// properties that are not set, so these leave the current values unchanged.
+    fn apply(&mut self, place_object: &PlaceObject) {
+        if let Some(matrix) = place_object.matrix {
+            self.matrix = matrix;
+        }
+        if let Some(color_transform) = place_object.color_transform {
+            self.color_transform = color_transform;
+        }
+        if let Some(ratio) = place_object.ratio {
+            self.ratio = Some(ratio);
+        }
+        if let Some(ref name) = place_object.name {
+            self.name = Some(name.clone());
+        }
+        if let Some(clip_depth) = place_object.clip_depth {
+            self.clip_depth = Some(clip_depth);
+        }
+        if !place_object.filters.is_empty() {
+            self.filters = place_object.filters.clone();
+        }
+        if place_object.blend_mode != BlendMode::Normal {
+            self.blend_mode = place_object.blend_mode;
+        }
+    }
+}
+
+/// The display lists of every frame of a timeline.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Timeline {
+    frames: Vec<Vec<DisplayObject>>,
+}
+
+impl Timeline {
+    /// Plays through the tags of a timeline, such as `Swf::tags` or `Sprite::tags`.
+    ///
+    /// Each `ShowFrame` tag ends a frame; tags after the last `ShowFrame` are ignored, as they
+    /// are by Flash Player. Placing a character at a depth that is in use replaces the instance
+    /// at that depth, while `Modify` and `Replace` actions on an empty depth are ignored.
+    pub fn new(tags: &[Tag]) -> Timeline {
+        let mut frames = vec![];
+        let mut display_list: BTreeMap<Depth, DisplayObject> = BTreeMap::new();
+        for tag in tags {
+            match *tag {
+                Tag::PlaceObject(ref place_object) => {
+                    let depth = place_object.depth;
+                    match place_object.action {
+                        PlaceObjectAction::Place(id) => {
+                            let mut object = DisplayObject::new(depth, id, frames.len());
+                            object.apply(place_object);
+                            display_list.insert(depth, object);
+                        }
+                        PlaceObjectAction::Modify => {
+                            if let Some(object) = display_list.get_mut(&depth) {
+                                object.apply(place_object);
+                            }
+                        }
+                        PlaceObjectAction::Replace(id) => {
+                            if let Some(object) = display_list.get_mut(&depth) {
+                                object.character_id = id;
+                                object.apply(place_object);
+                            }
+                        }
+                    }
+                }
+                Tag::RemoveObject { depth, .. } => {
+                    display_list.remove(&depth);
+                }
+                Tag::ShowFrame => frames.push(display_list.values().cloned().collect()),
+                _ => (),
+            }
+        }
+        Timeline { frames }
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns the display list of a frame, counting from 0, in depth order.
+    pub fn frame(&self, frame: usize) -> Option<&[DisplayObject]> {
+        self.frames.get(frame).map(|objects| &objects[..])
+    }
+
+    /// Returns the display lists of all frames, in order.
+    pub fn frames(&self) -> ::std::slice::Iter<'_, Vec<DisplayObject>> {
+        self.frames.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_data;
+
+    fn place(action: PlaceObjectAction, depth: Depth, matrix: Option<Matrix>) -> Tag {
+        Tag::PlaceObject(Box::new(PlaceObject {
+            matrix,
+            ..test_data::place_object(action, depth)
+        }))
+    }
+
+    fn translate(x: f32) -> Option<Matrix> {
+        Some(Matrix {
+            translate_x: x,
+            ..Matrix::new()
+        })
+    }
+
+    #[test]
+    fn play_timeline() {
+        let mut named = place(PlaceObjectAction::Place(2), 1, None);
+        if let Tag::PlaceObject(ref mut place_object) = named {
+            place_object.name = Some("child".to_string());
+            place_object.blend_mode = BlendMode::Multiply;
+        }
+        let tags = vec![
+            place(PlaceObjectAction::Place(1), 2, translate(10.0)),
+            named,
+            Tag::ShowFrame,
+            place(PlaceObjectAction::Modify, 2, translate(20.0)),
+            place(PlaceObjectAction::Replace(3), 1, None),
+            place(PlaceObjectAction::Modify, 5, translate(30.0)),
+            Tag::ShowFrame,
+            Tag::RemoveObject {
+                depth: 2,
+                character_id: None,
+            },
+            place(PlaceObjectAction::Place(4), 1, None),
+            Tag::ShowFrame,
+            Tag::RemoveObject {
+                depth: 1,
+                character_id: None,
+            },
+        ];
+        let timeline = Timeline::new(&tags);
+        assert_eq!(timeline.num_frames(), 3);
+
+        let frame = timeline.frame(0).unwrap();
+        assert_eq!(
+            frame
+                .iter()
+                .map(|object| (object.depth, object.character_id))
+                .collect::<Vec<_>>(),
+            vec![(1, 2), (2, 1)]
+        );
+        assert_eq!(frame[1].matrix.translate_x, 10.0);
+
+        let frame = timeline.frame(1).unwrap();
+        assert_eq!(frame.len(), 2);
+        assert_eq!(frame[0].character_id, 3);
+        assert_eq!(frame[0].name, Some("child".to_string()));
+        assert_eq!(frame[0].blend_mode, BlendMode::Multiply);
+        assert_eq!(frame[0].place_frame, 0);
+        assert_eq!(frame[1].matrix.translate_x, 20.0);
+
+        let frame = timeline.frame(2).unwrap();
+        assert_eq!(frame.len(), 1);
+        assert_eq!(frame[0].character_id, 4);
+        assert_eq!(frame[0].name, None);
+        assert_eq!(frame[0].place_frame, 2);
+        assert_eq!(timeline.frame(3), None);
+        assert_eq!(timeline.frames().count(), 3);
+    }
+}
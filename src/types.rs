@@ -1,4 +1,6 @@
 use std::collections::HashSet;
+use std::f32::consts::PI;
+use std::ops::Mul;
 
 #[derive(Debug, PartialEq)]
 pub struct Swf {
@@ -34,7 +36,7 @@ pub struct Color {
     pub a: u8,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub struct ColorTransform {
     pub r_multiply: f32,
     pub g_multiply: f32,
@@ -59,6 +61,23 @@ impl ColorTransform {
             a_add: 0,
         }
     }
+
+    /// Applies the color transform to a color.
+    ///
+    /// Like Flash, the multipliers are applied as 8.8 fixed-point values and each channel is
+    /// clamped to 0-255.
+    pub fn transform_color(&self, color: &Color) -> Color {
+        fn channel(value: u8, multiply: f32, add: i16) -> u8 {
+            let value = ((i32::from(value) * to_fixed8(multiply)) >> 8) + i32::from(add);
+            value.clamp(0, 255) as u8
+        }
+        Color {
+            r: channel(color.r, self.r_multiply, self.r_add),
+            g: channel(color.g, self.g_multiply, self.g_add),
+            b: channel(color.b, self.b_multiply, self.b_add),
+            a: channel(color.a, self.a_multiply, self.a_add),
+        }
+    }
 }
 
 impl Default for ColorTransform {
@@ -67,7 +86,49 @@ impl Default for ColorTransform {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+/// Concatenates two color transforms.
+///
+/// `a * b` is the color transform that applies `b` followed by `a`, such as a parent clip's
+/// transform (`a`) combined with a child's (`b`).
+impl Mul for ColorTransform {
+    type Output = ColorTransform;
+
+    fn mul(self, rhs: ColorTransform) -> ColorTransform {
+        fn multiply(a: f32, b: f32) -> f32 {
+            let value = (to_fixed8(a) * to_fixed8(b)) >> 8;
+            value.clamp(i32::from(i16::MIN), i32::from(i16::MAX)) as f32 / 256.0
+        }
+        fn add(a_multiply: f32, a_add: i16, b_add: i16) -> i16 {
+            let value = ((i32::from(b_add) * to_fixed8(a_multiply)) >> 8) + i32::from(a_add);
+            value.clamp(i32::from(i16::MIN), i32::from(i16::MAX)) as i16
+        }
+        ColorTransform {
+            r_multiply: multiply(self.r_multiply, rhs.r_multiply),
+            g_multiply: multiply(self.g_multiply, rhs.g_multiply),
+            b_multiply: multiply(self.b_multiply, rhs.b_multiply),
+            a_multiply: multiply(self.a_multiply, rhs.a_multiply),
+            r_add: add(self.r_multiply, self.r_add, rhs.r_add),
+            g_add: add(self.g_multiply, self.g_add, rhs.g_add),
+            b_add: add(self.b_multiply, self.b_add, rhs.b_add),
+            a_add: add(self.a_multiply, self.a_add, rhs.a_add),
+        }
+    }
+}
+
+/// Applies a color transform to a color.
+impl Mul<&Color> for ColorTransform {
+    type Output = Color;
+
+    fn mul(self, rhs: &Color) -> Color {
+        self.transform_color(rhs)
+    }
+}
+
+fn to_fixed8(n: f32) -> i32 {
+    (n * 256.0).round() as i32
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Matrix {
     pub translate_x: f32,
     pub translate_y: f32,
@@ -88,6 +149,90 @@ impl Matrix {
             rotate_skew_1: 0f32,
         }
     }
+
+    /// Transforms a point, such as a shape coordinate, by this matrix.
+    pub fn transform_point(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            self.scale_x * x + self.rotate_skew_1 * y + self.translate_x,
+            self.rotate_skew_0 * x + self.scale_y * y + self.translate_y,
+        )
+    }
+
+    /// Returns the inverse of this matrix, or `None` if the matrix is not invertible.
+    ///
+    /// The result is rounded to the precision of an SWF `MATRIX`.
+    pub fn invert(&self) -> Option<Matrix> {
+        let det = self.scale_x * self.scale_y - self.rotate_skew_0 * self.rotate_skew_1;
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        let scale_x = self.scale_y / det;
+        let rotate_skew_0 = -self.rotate_skew_0 / det;
+        let rotate_skew_1 = -self.rotate_skew_1 / det;
+        let scale_y = self.scale_x / det;
+        Some(
+            Matrix {
+                translate_x: -(scale_x * self.translate_x + rotate_skew_1 * self.translate_y),
+                translate_y: -(rotate_skew_0 * self.translate_x + scale_y * self.translate_y),
+                scale_x,
+                scale_y,
+                rotate_skew_0,
+                rotate_skew_1,
+            }
+            .rounded(),
+        )
+    }
+
+    /// Splits the matrix into translation, scale, rotation and skew.
+    pub fn decompose(&self) -> MatrixComponents {
+        let skew_y = self.rotate_skew_0.atan2(self.scale_x);
+        let skew_x = (-self.rotate_skew_1).atan2(self.scale_y);
+        let mut skew = skew_x - skew_y;
+        if skew > PI {
+            skew -= 2.0 * PI;
+        } else if skew <= -PI {
+            skew += 2.0 * PI;
+        }
+        MatrixComponents {
+            translate_x: self.translate_x,
+            translate_y: self.translate_y,
+            scale_x: (self.scale_x * self.scale_x + self.rotate_skew_0 * self.rotate_skew_0).sqrt(),
+            scale_y: (self.scale_y * self.scale_y + self.rotate_skew_1 * self.rotate_skew_1).sqrt(),
+            rotation: skew_y,
+            skew,
+        }
+    }
+
+    /// Builds a matrix from translation, scale, rotation and skew.
+    ///
+    /// The result is rounded to the precision of an SWF `MATRIX`.
+    pub fn compose(components: &MatrixComponents) -> Matrix {
+        let skew_x = components.rotation + components.skew;
+        Matrix {
+            translate_x: components.translate_x,
+            translate_y: components.translate_y,
+            scale_x: components.scale_x * components.rotation.cos(),
+            rotate_skew_0: components.scale_x * components.rotation.sin(),
+            rotate_skew_1: -components.scale_y * skew_x.sin(),
+            scale_y: components.scale_y * skew_x.cos(),
+        }
+        .rounded()
+    }
+
+    /// Rounds the matrix to 16.16 fixed-point scale and rotation and whole-twip translation,
+    /// the precision used by Flash.
+    fn rounded(self) -> Matrix {
+        let fixed16 = |n: f32| (f64::from(n) * 65536.0).round() as f32 / 65536.0;
+        let twips = |n: f32| (n * 20.0).round() / 20.0;
+        Matrix {
+            translate_x: twips(self.translate_x),
+            translate_y: twips(self.translate_y),
+            scale_x: fixed16(self.scale_x),
+            scale_y: fixed16(self.scale_y),
+            rotate_skew_0: fixed16(self.rotate_skew_0),
+            rotate_skew_1: fixed16(self.rotate_skew_1),
+        }
+    }
 }
 
 impl Default for Matrix {
@@ -96,6 +241,41 @@ impl Default for Matrix {
     }
 }
 
+/// Concatenates two matrices.
+///
+/// `a * b` is the matrix that applies `b` followed by `a`, such as a parent clip's matrix
+/// (`a`) combined with a child's (`b`). The result is rounded to the precision of an SWF
+/// `MATRIX`.
+impl Mul for Matrix {
+    type Output = Matrix;
+
+    fn mul(self, rhs: Matrix) -> Matrix {
+        let (translate_x, translate_y) = self.transform_point(rhs.translate_x, rhs.translate_y);
+        Matrix {
+            translate_x,
+            translate_y,
+            scale_x: self.scale_x * rhs.scale_x + self.rotate_skew_1 * rhs.rotate_skew_0,
+            rotate_skew_0: self.rotate_skew_0 * rhs.scale_x + self.scale_y * rhs.rotate_skew_0,
+            rotate_skew_1: self.scale_x * rhs.rotate_skew_1 + self.rotate_skew_1 * rhs.scale_y,
+            scale_y: self.rotate_skew_0 * rhs.rotate_skew_1 + self.scale_y * rhs.scale_y,
+        }
+        .rounded()
+    }
+}
+
+/// The components of a `Matrix`, as shown in the Flash authoring tool's transform panel.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct MatrixComponents {
+    pub translate_x: f32,
+    pub translate_y: f32,
+    pub scale_x: f32,
+    pub scale_y: f32,
+    /// Rotation of the x-axis, in radians.
+    pub rotation: f32,
+    /// Additional rotation of the y-axis relative to the x-axis, in radians.
+    pub skew: f32,
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Language {
     Unknown,
@@ -943,3 +1123,173 @@ pub struct DoAbc {
     pub is_lazy_initialize: bool,
     pub data: Vec<u8>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_matrix_eq(a: &Matrix, b: &Matrix) {
+        let fields = |m: &Matrix| {
+            [
+                m.translate_x,
+                m.translate_y,
+                m.scale_x,
+                m.scale_y,
+                m.rotate_skew_0,
+                m.rotate_skew_1,
+            ]
+        };
+        for (x, y) in fields(a).iter().zip(fields(b).iter()) {
+            assert!((x - y).abs() < 0.001, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn matrix_multiply() {
+        let translate = Matrix {
+            translate_x: 10.0,
+            translate_y: 20.0,
+            ..Matrix::new()
+        };
+        let scale = Matrix {
+            scale_x: 2.0,
+            scale_y: 3.0,
+            ..Matrix::new()
+        };
+        // Scale first, then translate.
+        assert_eq!((translate * scale).transform_point(1.0, 1.0), (12.0, 23.0));
+        // Translate first, then scale.
+        assert_eq!((scale * translate).transform_point(1.0, 1.0), (22.0, 63.0));
+        assert_eq!(Matrix::new() * scale, scale);
+    }
+
+    #[test]
+    fn matrix_skew_order() {
+        // rotate_skew_0 moves y by x; rotate_skew_1 moves x by y.
+        let m = Matrix {
+            rotate_skew_0: 0.5,
+            rotate_skew_1: 0.25,
+            ..Matrix::new()
+        };
+        assert_eq!(m.transform_point(4.0, 0.0), (4.0, 2.0));
+        assert_eq!(m.transform_point(0.0, 4.0), (1.0, 4.0));
+    }
+
+    #[test]
+    fn matrix_invert() {
+        let m = Matrix {
+            translate_x: 15.0,
+            translate_y: -5.0,
+            scale_x: 2.0,
+            scale_y: 0.5,
+            rotate_skew_0: 0.25,
+            rotate_skew_1: -1.0,
+        };
+        let inverse = m.invert().unwrap();
+        assert_matrix_eq(&(m * inverse), &Matrix::new());
+        let (x, y) = m.transform_point(3.0, 7.0);
+        let (x, y) = inverse.transform_point(x, y);
+        assert!((x - 3.0).abs() < 0.01 && (y - 7.0).abs() < 0.01);
+
+        let singular = Matrix {
+            scale_x: 0.0,
+            ..Matrix::new()
+        };
+        assert_eq!(singular.invert(), None);
+    }
+
+    #[test]
+    fn matrix_rounding() {
+        let m = Matrix {
+            scale_x: 1.0 / 3.0,
+            translate_x: 0.123,
+            ..Matrix::new()
+        } * Matrix::new();
+        assert_eq!(m.scale_x, 21845.0 / 65536.0);
+        assert_eq!(m.translate_x, 0.1);
+    }
+
+    #[test]
+    fn matrix_decompose() {
+        let components = MatrixComponents {
+            translate_x: 5.0,
+            translate_y: 6.0,
+            scale_x: 2.0,
+            scale_y: 0.5,
+            rotation: PI / 6.0,
+            skew: PI / 12.0,
+        };
+        let m = Matrix::compose(&components);
+        let decomposed = m.decompose();
+        assert_eq!(decomposed.translate_x, 5.0);
+        assert!((decomposed.scale_x - 2.0).abs() < 0.001);
+        assert!((decomposed.scale_y - 0.5).abs() < 0.001);
+        assert!((decomposed.rotation - PI / 6.0).abs() < 0.001);
+        assert!((decomposed.skew - PI / 12.0).abs() < 0.001);
+        assert_matrix_eq(&Matrix::compose(&decomposed), &m);
+
+        // A horizontal flip is represented with a skew of 180 degrees.
+        let flip = Matrix {
+            scale_x: -1.0,
+            ..Matrix::new()
+        };
+        assert_matrix_eq(&Matrix::compose(&flip.decompose()), &flip);
+    }
+
+    #[test]
+    fn color_transform_apply() {
+        let color = Color {
+            r: 200,
+            g: 100,
+            b: 50,
+            a: 255,
+        };
+        let color_transform = ColorTransform {
+            r_multiply: 0.5,
+            g_multiply: 2.0,
+            b_multiply: 1.0,
+            a_multiply: 0.5,
+            r_add: 10,
+            g_add: 0,
+            b_add: -100,
+            a_add: 0,
+        };
+        assert_eq!(
+            color_transform.transform_color(&color),
+            Color {
+                r: 110,
+                g: 200,
+                b: 0,
+                a: 127,
+            }
+        );
+        assert_eq!(ColorTransform::new() * &color, color);
+    }
+
+    #[test]
+    fn color_transform_concat() {
+        let parent = ColorTransform {
+            r_multiply: 0.5,
+            r_add: 20,
+            ..ColorTransform::new()
+        };
+        let child = ColorTransform {
+            r_multiply: 0.5,
+            r_add: 40,
+            ..ColorTransform::new()
+        };
+        let combined = parent * child;
+        assert_eq!(combined.r_multiply, 0.25);
+        assert_eq!(combined.r_add, 40);
+        let color = Color {
+            r: 200,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        assert_eq!(
+            combined.transform_color(&color),
+            parent.transform_color(&child.transform_color(&color))
+        );
+    }
+}
@@ -68,6 +68,11 @@ pub fn write_swf<W: Write>(swf: &Swf, mut output: W) -> Result<()> {
     Ok(())
 }
 
+/// Writes a single tag, including its header, as it is encoded in an SWF of the given version.
+pub fn write_tag<W: Write>(tag: &Tag, version: u8, output: W) -> Result<()> {
+    Writer::new(output, version).write_tag(tag)
+}
+
 pub trait SwfWrite<W: Write> {
     fn get_inner(&mut self) -> &mut W;
 